@@ -183,20 +183,19 @@ fn main() {
       .expect("An error occurred while downloading pico extras!");
   }
 
+  let toolchain_bin = |name: &str| {
+    toolchain_path
+      .join("bin")
+      .join(format!("arm-none-eabi-{name}{}", env::consts::EXE_SUFFIX))
+  };
   let mut cmake_config = cmake::Config::new(&current_dir);
 
   // Ninja
   cmake_config.define("CMAKE_MAKE_PROGRAM", ninja_path);
 
   // Compiler
-  cmake_config.define(
-    "CMAKE_C_COMPILER",
-    toolchain_path.join("bin").join("arm-none-eabi-gcc.exe"),
-  );
-  cmake_config.define(
-    "CMAKE_CXX_COMPILER",
-    toolchain_path.join("bin").join("arm-none-eabi-g++.exe"),
-  );
+  cmake_config.define("CMAKE_C_COMPILER", toolchain_bin("gcc"));
+  cmake_config.define("CMAKE_CXX_COMPILER", toolchain_bin("g++"));
 
   cmake_config.define("PICO_COMPILER", "pico_arm_gcc");
   cmake_config.define("PICO_SDK_PATH", &sdk_dir);
@@ -227,6 +226,11 @@ fn main() {
     cmake_config.define("ENABLE_STDIO_SEMIHOSTING", "");
   }
 
+  // Let allocation failures reach Rust instead of panicking in C
+  if env::var("CARGO_FEATURE_ALLOC").is_ok() {
+    cmake_config.define("DISABLE_MALLOC_PANIC", "");
+  }
+
   // Output
   cmake_config.out_dir(build_dir);
 
@@ -237,7 +241,6 @@ fn main() {
   let sdk_build_dir = dst.join("build");
 
   println!("cargo:rustc-link-search=native={}", sdk_build_dir.display());
-  println!("cargo:rustc-link-lib=static=pico-sdk");
 
   let raw_build_info = fs::read(sdk_build_dir.join("build_info.toml"))
     .expect("An error occurred while reading build_info.toml");
@@ -245,20 +248,18 @@ fn main() {
     toml::from_str(str::from_utf8(&raw_build_info).expect("Invalid bytes in build_info.toml"))
       .expect("An error occurred while parsing build_info.toml");
 
+  // `static inline` functions have no symbol to link against, so bindgen emits
+  // a C file that re-exports each of them as `<name>__extern`.
+  let extern_path = out_dir.join("pico-sdk-extern");
+
   let bindings = bindgen::Builder::default()
     .raw_line("#![allow(non_upper_case_globals, non_camel_case_types, non_snake_case)]")
     .use_core()
     .header(current_dir.join("pico-sdk.h").display().to_string())
-    .header_contents(
-      "_cyw43",
-      if board == "pico_w" {
-        "#include \"pico/cyw43_arch.h\""
-      } else {
-        ""
-      },
-    )
     .generate_comments(true)
     .generate_inline_functions(true)
+    .wrap_static_fns(true)
+    .wrap_static_fns_path(&extern_path)
     .disable_untagged_union()
     .prepend_enum_name(false)
     .layout_tests(false)
@@ -273,7 +274,7 @@ fn main() {
         .display()
     ))
     .clang_args(build_info.include_dirs.iter().map(|dir| format!("-I{dir}")))
-    .clang_args(build_info.compile_definitions)
+    .clang_args(&build_info.compile_definitions)
     .generate()
     .expect("Unable to generate bindings");
 
@@ -281,6 +282,32 @@ fn main() {
     .write_to_file(project_dir.join("src").join("pico_sdk.rs"))
     .expect("Couldn't write bindings!");
 
+  let mut extern_build = cc::Build::new();
+
+  extern_build
+    .compiler(toolchain_bin("gcc"))
+    .archiver(toolchain_bin("ar"))
+    .file(extern_path.with_extension("c"))
+    .include(&current_dir)
+    .include(toolchain_path.join("include"))
+    .includes(&build_info.include_dirs)
+    .flag("-mcpu=cortex-m0plus")
+    .warnings(false);
+
+  for flag in build_info
+    .compile_definitions
+    .iter()
+    .chain(&build_info.compile_options)
+    .filter(|flag| !flag.is_empty())
+  {
+    extern_build.flag(flag);
+  }
+
+  extern_build.compile("pico-sdk-extern");
+
+  // Must come after `pico-sdk-extern` since the wrappers call into the SDK
+  println!("cargo:rustc-link-lib=static=pico-sdk");
+
   for link_flag in build_info.link_flags {
     println!("cargo:rustc-link-arg={link_flag}");
   }
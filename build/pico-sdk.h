@@ -1,5 +1,11 @@
 #include "malloc.h"
 #include "pico/stdlib.h"
+#include "pico/stdio/driver.h"
+#include "pico/multicore.h"
+#include "pico/critical_section.h"
+#include "pico/mutex.h"
+#include "pico/sem.h"
+#include "pico/util/queue.h"
 #include "hardware/adc.h"
 #include "hardware/spi.h"
 #include "hardware/i2c.h"
@@ -7,6 +13,7 @@
 #include "hardware/divider.h"
 #include "hardware/clocks.h"
 #include "hardware/dma.h"
+#include "hardware/flash.h"
 #include "hardware/gpio.h"
 #include "hardware/interp.h"
 #include "hardware/irq.h"
@@ -20,3 +27,7 @@
 #include "hardware/rtc.h"
 #include "hardware/vreg.h"
 #include "hardware/watchdog.h"
+
+#if PICO_CYW43_SUPPORTED
+#include "pico/cyw43_arch.h"
+#endif
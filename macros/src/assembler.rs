@@ -0,0 +1,1268 @@
+//! PIO assembler
+//!
+//! Assembles the `pioasm` language into instructions, independently of the
+//! proc macro machinery so it can be tested on the host. Errors carry the
+//! line and column range of the offending token.
+//!
+//! Instructions are encoded by [`PioInstruction`] of pico-sdk-pio, the same
+//! encoder pico-sdk-sys uses at runtime.
+
+use pico_sdk_pio::{
+  InSource, JmpCondition, MovDestination, MovOperation, MovSource, OutDestination, PioInstruction,
+  SetDestination, SideSet, WaitSource,
+};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Maximum number of instructions in a program
+pub const MAX_PROGRAM_SIZE: usize = 32;
+
+/// An assembly error at `line` (0-based), covering `columns`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+  pub line: usize,
+  pub columns: (usize, usize),
+  pub message: String,
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}:{}: {}",
+      self.line + 1,
+      self.columns.0 + 1,
+      self.message
+    )
+  }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// An assembled program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+  pub name: Option<String>,
+  pub instructions: Vec<u16>,
+  pub origin: Option<u8>,
+  pub wrap_target: u8,
+  pub wrap: u8,
+  pub side_set: SideSet,
+  /// Labels and defines marked `public`
+  pub public_symbols: Vec<(String, i64)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+  Ident(String),
+  Number(i64),
+  Directive(String),
+  Symbol(&'static str),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+  kind: Kind,
+  line: usize,
+  columns: (usize, usize),
+}
+
+impl Token {
+  fn error(&self, message: impl Into<String>) -> Error {
+    Error {
+      line: self.line,
+      columns: self.columns,
+      message: message.into(),
+    }
+  }
+
+  fn is_symbol(&self, symbol: &str) -> bool {
+    matches!(self.kind, Kind::Symbol(kind) if kind == symbol)
+  }
+
+  /// Lower case identifier, `None` for other tokens
+  fn keyword(&self) -> Option<String> {
+    match &self.kind {
+      Kind::Ident(ident) => Some(ident.to_ascii_lowercase()),
+      _ => None,
+    }
+  }
+}
+
+/// Symbols, longest first so `::` isn't read as two `:`
+const SYMBOLS: [&str; 20] = [
+  "::", "--", "!=", "<<", ">>", ":", ",", "[", "]", "(", ")", "!", "~", "+", "-", "*", "/", "|",
+  "&", "^",
+];
+
+/// Splits a line into tokens, stopping at comments.
+fn tokenize(line: usize, text: &str) -> Result<Vec<Token>> {
+  let bytes = text.as_bytes();
+  let mut tokens = Vec::new();
+  let mut i = 0;
+
+  while i < bytes.len() {
+    let c = bytes[i];
+    let start = i;
+
+    if c.is_ascii_whitespace() {
+      i += 1;
+      continue;
+    }
+
+    if c == b';' || text[i..].starts_with("//") {
+      break;
+    }
+
+    let kind = if c.is_ascii_alphabetic() || c == b'_' || c == b'.' {
+      i += 1;
+
+      while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+        i += 1;
+      }
+
+      if c == b'.' {
+        Kind::Directive(text[start + 1..i].to_ascii_lowercase())
+      } else {
+        Kind::Ident(text[start..i].to_string())
+      }
+    } else if c.is_ascii_digit() {
+      while i < bytes.len() && bytes[i].is_ascii_alphanumeric() {
+        i += 1;
+      }
+
+      let literal = &text[start..i];
+      let parsed = if let Some(hex) = literal.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+      } else if let Some(binary) = literal.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+      } else {
+        literal.parse()
+      };
+
+      Kind::Number(parsed.map_err(|_| Error {
+        line,
+        columns: (start, i),
+        message: format!("invalid number `{literal}`"),
+      })?)
+    } else if let Some(symbol) = SYMBOLS.iter().find(|s| text[i..].starts_with(**s)) {
+      i += symbol.len();
+      Kind::Symbol(symbol)
+    } else {
+      let len = text[i..].chars().next().map_or(1, char::len_utf8);
+
+      return Err(Error {
+        line,
+        columns: (start, start + len),
+        message: format!("unexpected character `{}`", &text[i..i + len]),
+      });
+    };
+
+    tokens.push(Token {
+      kind,
+      line,
+      columns: (start, i),
+    });
+  }
+
+  Ok(tokens)
+}
+
+/// Tokens of one line with a cursor
+struct Line<'a> {
+  tokens: &'a [Token],
+  position: usize,
+  line: usize,
+  end: usize,
+}
+
+impl<'a> Line<'a> {
+  fn peek(&self) -> Option<&'a Token> {
+    self.tokens.get(self.position)
+  }
+
+  fn next(&mut self) -> Option<&'a Token> {
+    let token = self.tokens.get(self.position);
+    self.position += 1;
+    token
+  }
+
+  fn is_done(&self) -> bool {
+    self.position >= self.tokens.len()
+  }
+
+  fn error_at_end(&self, message: impl Into<String>) -> Error {
+    Error {
+      line: self.line,
+      columns: (self.end, self.end + 1),
+      message: message.into(),
+    }
+  }
+
+  fn expect(&mut self, what: &str) -> Result<&'a Token> {
+    self
+      .next()
+      .ok_or_else(|| self.error_at_end(format!("expected {what}")))
+  }
+
+  fn eat_symbol(&mut self, symbol: &str) -> bool {
+    if self.peek().is_some_and(|token| token.is_symbol(symbol)) {
+      self.position += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn eat_keyword(&mut self, keyword: &str) -> bool {
+    if self.peek().and_then(Token::keyword).as_deref() == Some(keyword) {
+      self.position += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn skip_comma(&mut self) {
+    self.eat_symbol(",");
+  }
+
+  fn expect_end(&mut self) -> Result<()> {
+    match self.peek() {
+      Some(token) => Err(token.error("unexpected token")),
+      None => Ok(()),
+    }
+  }
+}
+
+/// An expression which may refer to labels defined later
+#[derive(Debug, Clone)]
+enum Expr {
+  Number(i64),
+  Symbol(Token, String),
+  Negate(Box<Expr>),
+  Invert(Box<Expr>),
+  Reverse(Box<Expr>),
+  Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+fn binary_precedence(token: &Token) -> Option<(&'static str, u8)> {
+  match token.kind {
+    Kind::Symbol(symbol @ ("|" | "^")) => Some((symbol, 1)),
+    Kind::Symbol(symbol @ "&") => Some((symbol, 2)),
+    Kind::Symbol(symbol @ ("<<" | ">>")) => Some((symbol, 3)),
+    Kind::Symbol(symbol @ ("+" | "-")) => Some((symbol, 4)),
+    Kind::Symbol(symbol @ ("*" | "/")) => Some((symbol, 5)),
+    _ => None,
+  }
+}
+
+/// Parses a value: a number, a symbol or a parenthesized expression.
+fn parse_value(line: &mut Line) -> Result<Expr> {
+  let token = line.expect("a value")?;
+
+  match &token.kind {
+    Kind::Number(number) => Ok(Expr::Number(*number)),
+    Kind::Ident(ident) => Ok(Expr::Symbol(token.clone(), ident.clone())),
+    Kind::Symbol("-") => Ok(Expr::Negate(Box::new(parse_value(line)?))),
+    Kind::Symbol("(") => {
+      let expr = parse_expr(line, 0)?;
+
+      if !line.eat_symbol(")") {
+        return Err(match line.peek() {
+          Some(token) => token.error("expected `)`"),
+          None => line.error_at_end("expected `)`"),
+        });
+      }
+
+      Ok(expr)
+    }
+    _ => Err(token.error("expected a value")),
+  }
+}
+
+/// Parses an expression with binary operators binding tighter than
+/// `min_precedence`.
+fn parse_expr(line: &mut Line, min_precedence: u8) -> Result<Expr> {
+  let mut left = match line.peek() {
+    Some(token) if token.is_symbol("::") => {
+      line.next();
+      Expr::Reverse(Box::new(parse_expr(line, 6)?))
+    }
+    Some(token) if token.is_symbol("!") || token.is_symbol("~") => {
+      line.next();
+      Expr::Invert(Box::new(parse_expr(line, 6)?))
+    }
+    _ => parse_value(line)?,
+  };
+
+  while let Some((operator, precedence)) = line.peek().and_then(binary_precedence) {
+    if precedence <= min_precedence {
+      break;
+    }
+
+    line.next();
+    let right = parse_expr(line, precedence)?;
+    left = Expr::Binary(operator, Box::new(left), Box::new(right));
+  }
+
+  Ok(left)
+}
+
+/// Symbols visible to expressions
+struct Symbols {
+  values: HashMap<String, i64>,
+}
+
+impl Symbols {
+  fn evaluate(&self, expr: &Expr) -> Result<i64> {
+    Ok(match expr {
+      Expr::Number(number) => *number,
+      Expr::Symbol(token, name) => *self
+        .values
+        .get(name)
+        .ok_or_else(|| token.error(format!("undefined symbol `{name}`")))?,
+      Expr::Negate(value) => -self.evaluate(value)?,
+      Expr::Invert(value) => !self.evaluate(value)?,
+      Expr::Reverse(value) => (self.evaluate(value)? as u32).reverse_bits() as i64,
+      Expr::Binary(operator, left, right) => {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+
+        match *operator {
+          "+" => left.wrapping_add(right),
+          "-" => left.wrapping_sub(right),
+          "*" => left.wrapping_mul(right),
+          "/" => left.checked_div(right).unwrap_or(0),
+          "|" => left | right,
+          "&" => left & right,
+          "^" => left ^ right,
+          "<<" => left.wrapping_shl(right as u32),
+          ">>" => left.wrapping_shr(right as u32),
+          _ => unreachable!(),
+        }
+      }
+    })
+  }
+}
+
+/// Span of an expression for error reporting
+fn expr_token(expr: &Expr) -> Option<&Token> {
+  match expr {
+    Expr::Symbol(token, _) => Some(token),
+    Expr::Negate(value) | Expr::Invert(value) | Expr::Reverse(value) => expr_token(value),
+    Expr::Binary(_, left, right) => expr_token(left).or_else(|| expr_token(right)),
+    Expr::Number(_) => None,
+  }
+}
+
+/// An operand that is resolved once all labels are known
+#[derive(Debug, Clone)]
+struct Operand {
+  expr: Expr,
+  line: usize,
+  columns: (usize, usize),
+}
+
+impl Operand {
+  fn parse(line: &mut Line) -> Result<Self> {
+    let start = line.peek().map_or(line.end, |token| token.columns.0);
+    let expr = parse_expr(line, 0)?;
+    let end = line.tokens[line.position - 1].columns.1;
+
+    Ok(Self {
+      expr,
+      line: line.line,
+      columns: (start, end),
+    })
+  }
+
+  fn error(&self, message: impl Into<String>) -> Error {
+    Error {
+      line: self.line,
+      columns: self.columns,
+      message: message.into(),
+    }
+  }
+
+  /// Evaluates the operand and checks it is in `0..=max`.
+  fn resolve(&self, symbols: &Symbols, max: i64, what: &str) -> Result<u16> {
+    let value = symbols.evaluate(&self.expr).map_err(|error| {
+      expr_token(&self.expr).map_or(error.clone(), |token| token.error(error.message.clone()))
+    })?;
+
+    if !(0..=max).contains(&value) {
+      return Err(self.error(format!("{what} must be from 0 to {max}, found {value}")));
+    }
+
+    Ok(value as u16)
+  }
+}
+
+/// An instruction waiting for label resolution
+#[derive(Debug, Clone)]
+struct Pending {
+  /// Instruction with its operand still 0, `None` for a raw `.word`
+  instruction: Option<PioInstruction>,
+  /// Operand resolved once all labels are known, and its maximum
+  operand: Option<(Operand, i64, &'static str)>,
+  side: Option<Operand>,
+  delay: Option<Operand>,
+  /// Token of the mnemonic, for side-set errors
+  token: Token,
+}
+
+#[derive(Default)]
+struct ProgramBuilder {
+  name: Option<String>,
+  instructions: Vec<Pending>,
+  origin: Option<u8>,
+  wrap_target: Option<u8>,
+  wrap: Option<u8>,
+  side_set: SideSet,
+  side_set_defined: bool,
+  labels: HashMap<String, i64>,
+  defines: HashMap<String, i64>,
+  public: Vec<String>,
+}
+
+/// Assembles `source`, which may contain several `.program`s.
+pub fn assemble(source: &str) -> Result<Vec<Program>> {
+  let mut programs = Vec::new();
+  let mut current: Option<ProgramBuilder> = None;
+  let mut global_defines = HashMap::new();
+  let mut in_code_block = false;
+
+  for (number, text) in source.lines().enumerate() {
+    let trimmed = text.trim_start();
+
+    // Blocks of C code for pioasm's output are ignored
+    if in_code_block {
+      in_code_block = !trimmed.starts_with("%}");
+      continue;
+    }
+
+    if trimmed.starts_with('%') {
+      in_code_block = !trimmed.contains("%}");
+      continue;
+    }
+
+    let tokens = tokenize(number, text)?;
+    let mut line = Line {
+      tokens: &tokens,
+      position: 0,
+      line: number,
+      end: text.trim_end().len(),
+    };
+
+    if line.is_done() {
+      continue;
+    }
+
+    if let Some(Kind::Directive(directive)) = line.peek().map(|token| &token.kind) {
+      let token = line.next().unwrap();
+
+      if directive == "program" {
+        let name = match line.expect("a program name")? {
+          Token {
+            kind: Kind::Ident(name),
+            ..
+          } => name.clone(),
+          token => return Err(token.error("expected a program name")),
+        };
+        line.expect_end()?;
+
+        if let Some(program) = current.take() {
+          programs.push(program.finish()?);
+        }
+
+        current = Some(ProgramBuilder {
+          name: Some(name),
+          defines: global_defines.clone(),
+          ..Default::default()
+        });
+        continue;
+      }
+
+      if directive == "define" && current.is_none() {
+        let (name, value) = parse_define(&mut line, &global_defines)?;
+        global_defines.insert(name, value);
+        continue;
+      }
+
+      let program = current.get_or_insert_with(|| ProgramBuilder {
+        defines: global_defines.clone(),
+        ..Default::default()
+      });
+      program.directive(directive, token, &mut line)?;
+      continue;
+    }
+
+    let program = current.get_or_insert_with(|| ProgramBuilder {
+      defines: global_defines.clone(),
+      ..Default::default()
+    });
+    program.statement(&mut line)?;
+  }
+
+  if let Some(program) = current {
+    programs.push(program.finish()?);
+  }
+
+  Ok(programs)
+}
+
+fn parse_define(line: &mut Line, defines: &HashMap<String, i64>) -> Result<(String, i64)> {
+  line.eat_keyword("public");
+
+  let name = match line.expect("a name")? {
+    Token {
+      kind: Kind::Ident(name),
+      ..
+    } => name.clone(),
+    token => return Err(token.error("expected a name")),
+  };
+  let operand = Operand::parse(line)?;
+  line.expect_end()?;
+
+  let symbols = Symbols {
+    values: defines.clone(),
+  };
+  let value = symbols.evaluate(&operand.expr)?;
+
+  Ok((name, value))
+}
+
+impl ProgramBuilder {
+  /// Address of the next instruction
+  fn address(&self) -> u8 {
+    self.instructions.len() as u8
+  }
+
+  fn push(&mut self, pending: Pending) -> Result<()> {
+    if self.instructions.len() == MAX_PROGRAM_SIZE {
+      let message = format!("programs are limited to {MAX_PROGRAM_SIZE} instructions");
+      return Err(pending.token.error(message));
+    }
+
+    self.instructions.push(pending);
+
+    Ok(())
+  }
+
+  fn directive(&mut self, directive: &str, token: &Token, line: &mut Line) -> Result<()> {
+    match directive {
+      "origin" => {
+        let origin = Operand::parse(line)?.resolve(&self.symbols(), 31, "origin")?;
+        self.origin = Some(origin as u8);
+      }
+      "side_set" => {
+        if !self.instructions.is_empty() {
+          return Err(token.error("`.side_set` must come before the instructions"));
+        }
+
+        let bits = Operand::parse(line)?.resolve(&self.symbols(), 5, "side-set bit count")? as u8;
+        let optional = line.eat_keyword("opt");
+        let pindirs = line.eat_keyword("pindirs");
+
+        if bits + optional as u8 > 5 {
+          return Err(token.error("optional side-set is limited to 4 bits"));
+        }
+
+        self.side_set = SideSet::new(bits, optional, pindirs);
+        self.side_set_defined = true;
+      }
+      "wrap_target" => {
+        if self.wrap_target.is_some() {
+          return Err(token.error("`.wrap_target` was already set"));
+        }
+
+        self.wrap_target = Some(self.address());
+      }
+      "wrap" => {
+        if self.wrap.is_some() {
+          return Err(token.error("`.wrap` was already set"));
+        }
+
+        if self.instructions.is_empty() {
+          return Err(token.error("`.wrap` must follow an instruction"));
+        }
+
+        self.wrap = Some(self.instructions.len() as u8 - 1);
+      }
+      "define" => {
+        let public = line.peek().and_then(Token::keyword).as_deref() == Some("public");
+        let (name, value) = parse_define(line, &self.defines)?;
+
+        if public {
+          self.public.push(name.clone());
+        }
+
+        self.defines.insert(name, value);
+      }
+      "word" => {
+        let operand = Operand::parse(line)?;
+        self.push(Pending {
+          instruction: None,
+          operand: Some((operand, u16::MAX as i64, "word")),
+          side: None,
+          delay: None,
+          token: token.clone(),
+        })?;
+      }
+      // Options for other languages don't affect the program
+      "lang_opt" | "pio_version" => {
+        line.position = line.tokens.len();
+      }
+      // Rejected rather than dropped, since the program would run with a
+      // different configuration than the source asks for
+      "fifo" | "clock_div" | "in" | "out" | "set" | "mov_status" => {
+        return Err(token.error(format!(
+          "`.{directive}` isn't supported, configure the state machine with `PioConfig` instead"
+        )));
+      }
+      _ => return Err(token.error(format!("unknown directive `.{directive}`"))),
+    }
+
+    line.expect_end()
+  }
+
+  /// Parses a line with an optional label and instruction.
+  fn statement(&mut self, line: &mut Line) -> Result<()> {
+    let public = line.peek().and_then(Token::keyword).as_deref() == Some("public")
+      && line
+        .tokens
+        .get(line.position + 2)
+        .is_some_and(|token| token.is_symbol(":"));
+
+    if public {
+      line.next();
+    }
+
+    if line
+      .tokens
+      .get(line.position + 1)
+      .is_some_and(|token| token.is_symbol(":"))
+    {
+      let token = line.next().unwrap();
+      let Kind::Ident(name) = &token.kind else {
+        return Err(token.error("expected a label name"));
+      };
+
+      if self.labels.contains_key(name) || self.defines.contains_key(name) {
+        return Err(token.error(format!("`{name}` is already defined")));
+      }
+
+      self.labels.insert(name.clone(), self.address() as i64);
+
+      if public {
+        self.public.push(name.clone());
+      }
+
+      line.next();
+    }
+
+    if line.is_done() {
+      return Ok(());
+    }
+
+    let token = line.next().unwrap();
+    let Some(mnemonic) = token.keyword() else {
+      return Err(token.error("expected an instruction"));
+    };
+    let mut pending = self.instruction(&mnemonic, token, line)?;
+
+    loop {
+      if line.eat_keyword("side") || line.eat_keyword("sideset") {
+        if pending.side.is_some() {
+          return Err(line.tokens[line.position - 1].error("side-set was already given"));
+        }
+
+        pending.side = Some(Operand::parse(line)?);
+      } else if line.eat_symbol("[") {
+        if pending.delay.is_some() {
+          return Err(line.tokens[line.position - 1].error("delay was already given"));
+        }
+
+        pending.delay = Some(Operand::parse(line)?);
+
+        if !line.eat_symbol("]") {
+          return Err(line.error_at_end("expected `]`"));
+        }
+      } else {
+        break;
+      }
+    }
+
+    line.expect_end()?;
+    self.push(pending)
+  }
+
+  fn instruction(&self, mnemonic: &str, token: &Token, line: &mut Line) -> Result<Pending> {
+    let mut pending = Pending {
+      instruction: None,
+      operand: None,
+      side: None,
+      delay: None,
+      token: token.clone(),
+    };
+
+    let instruction = match mnemonic {
+      "nop" => PioInstruction::NOP,
+      "jmp" => {
+        let condition = if line.eat_symbol("!") {
+          match line.expect("a condition")?.keyword().as_deref() {
+            Some("x") => JmpCondition::XZero,
+            Some("y") => JmpCondition::YZero,
+            Some("osre") => JmpCondition::OsrNotEmpty,
+            _ => return Err(line.tokens[line.position - 1].error("expected `x`, `y` or `osre`")),
+          }
+        } else {
+          let next_is = |offset: usize, symbol: &str| {
+            line
+              .tokens
+              .get(line.position + offset)
+              .is_some_and(|token| token.is_symbol(symbol))
+          };
+
+          match line.peek().and_then(Token::keyword).as_deref() {
+            Some("x") if next_is(1, "--") => {
+              line.position += 2;
+              JmpCondition::XDecrement
+            }
+            Some("y") if next_is(1, "--") => {
+              line.position += 2;
+              JmpCondition::YDecrement
+            }
+            Some("x") if next_is(1, "!=") => {
+              line.position += 2;
+
+              if !line.eat_keyword("y") {
+                return Err(line.error_at_end("expected `y`"));
+              }
+
+              JmpCondition::XNotEqualY
+            }
+            Some("pin") => {
+              line.position += 1;
+              JmpCondition::Pin
+            }
+            _ => JmpCondition::Always,
+          }
+        };
+
+        line.skip_comma();
+        pending.operand = Some((Operand::parse(line)?, 31, "jump target"));
+
+        PioInstruction::Jmp {
+          condition,
+          address: 0,
+        }
+      }
+      "wait" => {
+        let polarity = Operand::parse(line)?.resolve(&self.symbols(), 1, "polarity")?;
+        let source_token = line.expect("`gpio`, `pin` or `irq`")?;
+        let source = match source_token.keyword().as_deref() {
+          Some("gpio") => WaitSource::Gpio,
+          Some("pin") => WaitSource::Pin,
+          Some("irq") => WaitSource::Irq,
+          _ => return Err(source_token.error("expected `gpio`, `pin` or `irq`")),
+        };
+
+        line.skip_comma();
+        let index = Operand::parse(line)?;
+        let is_irq = source == WaitSource::Irq;
+        let relative = is_irq && line.eat_keyword("rel");
+        pending.operand = Some((index, if is_irq { 7 } else { 31 }, "index"));
+
+        PioInstruction::Wait {
+          polarity: polarity == 1,
+          source,
+          index: 0,
+          relative,
+        }
+      }
+      "in" => {
+        let target = line.expect("a source")?;
+        let source = match target.keyword().as_deref() {
+          Some("pins") => InSource::Pins,
+          Some("x") => InSource::X,
+          Some("y") => InSource::Y,
+          Some("null") => InSource::Null,
+          Some("isr") => InSource::Isr,
+          Some("osr") => InSource::Osr,
+          _ => return Err(target.error("invalid in target")),
+        };
+
+        PioInstruction::In {
+          source,
+          bit_count: self.bit_count(line)?,
+        }
+      }
+      "out" => {
+        let target = line.expect("a source")?;
+        let destination = match target.keyword().as_deref() {
+          Some("pins") => OutDestination::Pins,
+          Some("x") => OutDestination::X,
+          Some("y") => OutDestination::Y,
+          Some("null") => OutDestination::Null,
+          Some("pindirs") => OutDestination::Pindirs,
+          Some("pc") => OutDestination::Pc,
+          Some("isr") => OutDestination::Isr,
+          Some("exec") => OutDestination::Exec,
+          _ => return Err(target.error("invalid out target")),
+        };
+
+        PioInstruction::Out {
+          destination,
+          bit_count: self.bit_count(line)?,
+        }
+      }
+      "push" | "pull" => {
+        let mut conditional = false;
+        let mut block = true;
+        let condition = if mnemonic == "push" {
+          "iffull"
+        } else {
+          "ifempty"
+        };
+
+        loop {
+          if line.eat_keyword(condition) {
+            conditional = true;
+          } else if line.eat_keyword("block") {
+            block = true;
+          } else if line.eat_keyword("noblock") {
+            block = false;
+          } else {
+            break;
+          }
+        }
+
+        if mnemonic == "push" {
+          PioInstruction::Push {
+            if_full: conditional,
+            block,
+          }
+        } else {
+          PioInstruction::Pull {
+            if_empty: conditional,
+            block,
+          }
+        }
+      }
+      "mov" => {
+        let destination = line.expect("a destination")?;
+        let destination = match destination.keyword().as_deref() {
+          Some("pins") => MovDestination::Pins,
+          Some("x") => MovDestination::X,
+          Some("y") => MovDestination::Y,
+          Some("exec") => MovDestination::Exec,
+          Some("pc") => MovDestination::Pc,
+          Some("isr") => MovDestination::Isr,
+          Some("osr") => MovDestination::Osr,
+          _ => return Err(destination.error("invalid mov destination")),
+        };
+
+        line.skip_comma();
+
+        let operation = if line.eat_symbol("!") || line.eat_symbol("~") {
+          MovOperation::Invert
+        } else if line.eat_symbol("::") {
+          MovOperation::Reverse
+        } else {
+          MovOperation::None
+        };
+        let source = line.expect("a source")?;
+        let source = match source.keyword().as_deref() {
+          Some("pins") => MovSource::Pins,
+          Some("x") => MovSource::X,
+          Some("y") => MovSource::Y,
+          Some("null") => MovSource::Null,
+          Some("status") => MovSource::Status,
+          Some("isr") => MovSource::Isr,
+          Some("osr") => MovSource::Osr,
+          _ => return Err(source.error("invalid mov source")),
+        };
+
+        PioInstruction::Mov {
+          destination,
+          operation,
+          source,
+        }
+      }
+      "irq" => {
+        let clear = line.eat_keyword("clear");
+        let wait = !clear && line.eat_keyword("wait");
+
+        if !clear && !wait {
+          let _ = line.eat_keyword("set") || line.eat_keyword("nowait");
+        }
+
+        let index = Operand::parse(line)?;
+        let relative = line.eat_keyword("rel");
+        pending.operand = Some((index, 7, "IRQ index"));
+
+        PioInstruction::Irq {
+          clear,
+          wait,
+          index: 0,
+          relative,
+        }
+      }
+      "set" => {
+        let destination = line.expect("a destination")?;
+        let destination = match destination.keyword().as_deref() {
+          Some("pins") => SetDestination::Pins,
+          Some("x") => SetDestination::X,
+          Some("y") => SetDestination::Y,
+          Some("pindirs") => SetDestination::Pindirs,
+          _ => return Err(destination.error("invalid set destination")),
+        };
+
+        line.skip_comma();
+        pending.operand = Some((Operand::parse(line)?, 31, "value"));
+
+        PioInstruction::Set {
+          destination,
+          data: 0,
+        }
+      }
+      _ => return Err(token.error(format!("unknown instruction `{mnemonic}`"))),
+    };
+
+    pending.instruction = Some(instruction);
+
+    Ok(pending)
+  }
+
+  /// Parses the bit count of `in` and `out`.
+  fn bit_count(&self, line: &mut Line) -> Result<u8> {
+    line.skip_comma();
+    let count = Operand::parse(line)?;
+    let value = count.resolve(&self.symbols(), 32, "bit count")?;
+
+    if value == 0 {
+      return Err(count.error("bit count must be from 1 to 32"));
+    }
+
+    Ok(value as u8)
+  }
+
+  fn symbols(&self) -> Symbols {
+    let mut values = self.defines.clone();
+    values.extend(
+      self
+        .labels
+        .iter()
+        .map(|(name, value)| (name.clone(), *value)),
+    );
+
+    Symbols { values }
+  }
+
+  fn finish(self) -> Result<Program> {
+    let symbols = self.symbols();
+    let side_set = self.side_set;
+    let mut instructions = Vec::with_capacity(self.instructions.len());
+
+    for pending in &self.instructions {
+      let value = match &pending.operand {
+        Some((operand, max, what)) => operand.resolve(&symbols, *max, what)?,
+        None => 0,
+      };
+
+      let Some(instruction) = pending.instruction else {
+        instructions.push(value);
+        continue;
+      };
+
+      let delay = match &pending.delay {
+        Some(delay) => delay.resolve(&symbols, side_set.max_delay() as i64, "delay")? as u8,
+        None => 0,
+      };
+
+      let side = match &pending.side {
+        Some(side) if !self.side_set_defined || side_set.bits() == 0 => {
+          return Err(side.error("side-set used without `.side_set`"));
+        }
+        Some(side) => {
+          let max = (1 << side_set.bits()) - 1;
+          Some(side.resolve(&symbols, max, "side-set value")? as u8)
+        }
+        None if side_set.bits() > 0 && !side_set.is_optional() => {
+          return Err(pending.token.error("instruction needs a side-set value"));
+        }
+        None => None,
+      };
+
+      // Operands were checked against their ranges, so this can't panic
+      let instruction = with_operand(instruction, value as u8);
+      instructions.push(instruction.encode_with(side_set, side, delay));
+    }
+
+    if instructions.is_empty() {
+      return Err(Error {
+        line: 0,
+        columns: (0, 0),
+        message: "program has no instructions".into(),
+      });
+    }
+
+    let last = instructions.len() as u8 - 1;
+    let public_symbols = self
+      .public
+      .iter()
+      .map(|name| (name.clone(), symbols.values[name]))
+      .collect();
+
+    Ok(Program {
+      name: self.name,
+      instructions,
+      origin: self.origin,
+      wrap_target: self.wrap_target.unwrap_or(0).min(last),
+      wrap: self.wrap.unwrap_or(last),
+      side_set,
+      public_symbols,
+    })
+  }
+}
+
+/// Fills in the operand of `instruction`, which was resolved after parsing.
+fn with_operand(instruction: PioInstruction, value: u8) -> PioInstruction {
+  match instruction {
+    PioInstruction::Jmp { condition, .. } => PioInstruction::Jmp {
+      condition,
+      address: value,
+    },
+    PioInstruction::Wait {
+      polarity,
+      source,
+      relative,
+      ..
+    } => PioInstruction::Wait {
+      polarity,
+      source,
+      index: value,
+      relative,
+    },
+    PioInstruction::Irq {
+      clear,
+      wait,
+      relative,
+      ..
+    } => PioInstruction::Irq {
+      clear,
+      wait,
+      index: value,
+      relative,
+    },
+    PioInstruction::Set { destination, .. } => PioInstruction::Set {
+      destination,
+      data: value,
+    },
+    instruction => instruction,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pico_sdk_pio::*;
+
+  fn assemble_one(source: &str) -> Program {
+    let mut programs = assemble(source).unwrap();
+    assert_eq!(programs.len(), 1);
+    programs.remove(0)
+  }
+
+  fn assemble_error(source: &str) -> Error {
+    assemble(source).unwrap_err()
+  }
+
+  #[test]
+  fn parses_instructions() {
+    let program = assemble_one(
+      "
+      start:
+        jmp start
+        jmp !x start
+        jmp x-- start
+        jmp !y, start
+        jmp y-- start
+        jmp x!=y start
+        jmp pin start
+        jmp !osre start
+        wait 1 gpio 5
+        wait 0 pin 2
+        wait 1 irq 3 rel
+        in pins, 32
+        in osr, 8
+        out pc, 5
+        out exec, 16
+        push
+        push iffull noblock
+        pull ifempty block
+        pull noblock
+        mov x, !y
+        mov isr, ::osr
+        mov pins, status
+        irq 1
+        irq wait 2 rel
+        irq clear 3
+        set pindirs, 31
+        nop
+      ",
+    );
+
+    assert_eq!(
+      program.instructions,
+      [
+        pio_encode_jmp(0),
+        pio_encode_jmp_not_x(0),
+        pio_encode_jmp_x_dec(0),
+        pio_encode_jmp_not_y(0),
+        pio_encode_jmp_y_dec(0),
+        pio_encode_jmp_x_ne_y(0),
+        pio_encode_jmp_pin(0),
+        pio_encode_jmp_not_osre(0),
+        pio_encode_wait_gpio(true, 5),
+        pio_encode_wait_pin(false, 2),
+        pio_encode_wait_irq(true, true, 3),
+        pio_encode_in(InSource::Pins, 32),
+        pio_encode_in(InSource::Osr, 8),
+        pio_encode_out(OutDestination::Pc, 5),
+        pio_encode_out(OutDestination::Exec, 16),
+        pio_encode_push(false, true),
+        pio_encode_push(true, false),
+        pio_encode_pull(true, true),
+        pio_encode_pull(false, false),
+        pio_encode_mov_not(MovDestination::X, MovSource::Y),
+        pio_encode_mov_reverse(MovDestination::Isr, MovSource::Osr),
+        pio_encode_mov(MovDestination::Pins, MovSource::Status),
+        pio_encode_irq_set(false, 1),
+        pio_encode_irq_wait(true, 2),
+        pio_encode_irq_clear(false, 3),
+        pio_encode_set(SetDestination::Pindirs, 31),
+        pio_encode_nop(),
+      ]
+    );
+  }
+
+  #[test]
+  fn encodes_side_set_and_delay() {
+    let program = assemble_one(
+      "
+      .side_set 1 opt
+        nop side 1 [3]
+        nop [7]
+      .wrap_target
+        set x, 1 side 0
+      .wrap
+        nop
+      ",
+    );
+
+    assert_eq!(program.instructions, [0xbb42, 0xa742, 0xf021, 0xa042]);
+    assert_eq!((program.wrap_target, program.wrap), (2, 2));
+    assert!(program.side_set.is_optional());
+
+    let program = assemble_one(".side_set 2 pindirs\nnop side 3 [7]\n");
+    assert_eq!(program.instructions, [0xbf42]);
+    assert!(program.side_set.is_pindirs());
+  }
+
+  #[test]
+  fn handles_ws2812() {
+    let program = assemble_one(
+      "
+      .program ws2812
+      .side_set 1
+
+      .define public T1 2
+      .define public T2 5
+      .define public T3 3
+
+      .wrap_target
+      bitloop:
+          out x, 1       side 0 [T3 - 1] ; Side-set still takes place when instruction stalls
+          jmp !x do_zero side 1 [T1 - 1] ; Branch on the bit we shifted out. Positive pulse
+      do_one:
+          jmp  bitloop   side 1 [T2 - 1] ; Continue driving high, for a long pulse
+      do_zero:
+          nop            side 0 [T2 - 1] ; Or drive low, for a short pulse
+      .wrap
+
+      % c-sdk {
+      void ws2812_program_init(PIO pio, uint sm, uint offset, uint pin) {}
+      %}
+      ",
+    );
+
+    assert_eq!(program.name.as_deref(), Some("ws2812"));
+    assert_eq!(program.instructions, [0x6221, 0x1123, 0x1400, 0xa442]);
+    assert_eq!((program.wrap_target, program.wrap), (0, 3));
+    assert_eq!(program.public_symbols.len(), 3);
+  }
+
+  #[test]
+  fn evaluates_expressions() {
+    let program = assemble_one(".define N 3\nset x, (N * 2 + 1)\nset y, (1 << N)\n.origin 4\n");
+    assert_eq!(program.instructions, [0xe027, 0xe048]);
+    assert_eq!(program.origin, Some(4));
+  }
+
+  #[test]
+  fn splits_programs() {
+    let programs = assemble(".program a\nnop\n.program b\nset x, 1\nset y, 2\n").unwrap();
+    assert_eq!(programs.len(), 2);
+    assert_eq!(programs[1].name.as_deref(), Some("b"));
+    assert_eq!(programs[1].instructions.len(), 2);
+  }
+
+  #[test]
+  fn reports_errors_with_columns() {
+    let error = assemble_error("nop\n  jmp nowhere\n");
+    assert_eq!((error.line, error.columns), (1, (6, 13)));
+    assert!(error.message.contains("nowhere"));
+
+    let error = assemble_error("set x, 32\n");
+    assert_eq!((error.line, error.columns), (0, (7, 9)));
+
+    let error = assemble_error("fly x\n");
+    assert_eq!(error.columns, (0, 3));
+
+    assert!(assemble_error(".side_set 1\nnop\n")
+      .message
+      .contains("side-set"));
+    assert!(assemble_error("nop side 1\n").message.contains(".side_set"));
+    assert!(assemble_error(".side_set 4\nnop side 1 [2]\n")
+      .message
+      .contains("delay"));
+    assert!(assemble_error("x: nop\nx: nop\n")
+      .message
+      .contains("already"));
+    assert!(assemble_error(&"nop\n".repeat(33)).message.contains("32"));
+  }
+
+  #[test]
+  fn rejects_config_directives() {
+    let error = assemble_error(".program a\n.fifo txrx\nnop\n");
+    assert_eq!(error.line, 1);
+    assert!(error.message.contains("`.fifo`"));
+
+    for directive in [
+      ".clock_div 2",
+      ".in 8 left",
+      ".out 1",
+      ".set 2",
+      ".mov_status irq set 3",
+    ] {
+      assert!(assemble_error(&format!("{directive}\nnop\n"))
+        .message
+        .contains("PioConfig"));
+    }
+
+    assert!(assemble(".pio_version 0\n.lang_opt python out_init 1\nnop\n").is_ok());
+  }
+}
@@ -0,0 +1,199 @@
+//! Procedural macros for [pico-sdk-sys](https://docs.rs/pico-sdk-sys)
+//!
+//! [`pio_asm!`] and [`include_pio!`] assemble PIO programs at compile time
+//! into a `pico_sdk_sys::PioProgram`, so the `pioasm` tool isn't needed.
+//!
+//! The program carries its wrap and side-set settings, from which
+//! `LoadedProgram::config` builds the default state machine configuration.
+//! Directives configuring anything else, such as `.fifo`, `.clock_div` or
+//! `.in`, are rejected; set those on the `PioConfig` instead.
+
+mod assembler;
+
+use assembler::Program;
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use std::path::PathBuf;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, LitStr, Token};
+
+/// Assembles a PIO program.
+///
+/// The source is given as string literals, either one per line or a single
+/// literal with several lines. Errors point at the literal of the offending
+/// line.
+///
+/// ```ignore
+/// static BLINK: PioProgram<2> = pio_asm!(
+///   ".side_set 1",
+///   "loop:",
+///   "  nop side 0 [31]",
+///   "  jmp loop side 1 [31]",
+/// );
+/// ```
+#[proc_macro]
+pub fn pio_asm(input: TokenStream) -> TokenStream {
+  let literals = parse_macro_input!(input with Punctuated::<LitStr, Token![,]>::parse_terminated);
+  let mut source = String::new();
+  // Literal and line within it of every source line
+  let mut origins = Vec::new();
+
+  for (index, literal) in literals.iter().enumerate() {
+    let value = literal.value();
+
+    for (line, text) in value.lines().enumerate() {
+      source.push_str(text);
+      source.push('\n');
+      origins.push((index, line));
+    }
+  }
+
+  let programs = match assembler::assemble(&source) {
+    Ok(programs) => programs,
+    Err(error) => {
+      let (index, line) = origins.get(error.line).copied().unwrap_or_default();
+      let message = if literals[index].value().lines().count() > 1 {
+        format!(
+          "line {}, column {}: {}",
+          line + 1,
+          error.columns.0 + 1,
+          error.message
+        )
+      } else {
+        format!("column {}: {}", error.columns.0 + 1, error.message)
+      };
+
+      return syn::Error::new(literals[index].span(), message)
+        .to_compile_error()
+        .into();
+    }
+  };
+
+  match programs.as_slice() {
+    [program] => expand(program, None).into(),
+    [] => syn::Error::new(proc_macro2::Span::call_site(), "no PIO program given")
+      .to_compile_error()
+      .into(),
+    _ => syn::Error::new(
+      proc_macro2::Span::call_site(),
+      "use `include_pio!` for several programs",
+    )
+    .to_compile_error()
+    .into(),
+  }
+}
+
+struct IncludeArgs {
+  path: LitStr,
+  name: Option<LitStr>,
+}
+
+impl Parse for IncludeArgs {
+  fn parse(input: ParseStream) -> syn::Result<Self> {
+    let path = input.parse()?;
+    let name = if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+      Some(input.parse()?)
+    } else {
+      None
+    };
+
+    input.parse::<Option<Token![,]>>()?;
+
+    Ok(Self { path, name })
+  }
+}
+
+/// Assembles a PIO program from a `.pio` file.
+///
+/// The path is relative to the crate root. If the file has several
+/// `.program`s, the name of one must be given.
+///
+/// ```ignore
+/// static WS2812: PioProgram<4> = include_pio!("src/ws2812.pio", "ws2812");
+/// ```
+#[proc_macro]
+pub fn include_pio(input: TokenStream) -> TokenStream {
+  let IncludeArgs { path, name } = parse_macro_input!(input as IncludeArgs);
+  let error =
+    |span, message: String| TokenStream::from(syn::Error::new(span, message).to_compile_error());
+
+  let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+  let full_path = PathBuf::from(root).join(path.value());
+  let source = match std::fs::read_to_string(&full_path) {
+    Ok(source) => source,
+    Err(err) => {
+      return error(
+        path.span(),
+        format!("can't read {}: {err}", full_path.display()),
+      )
+    }
+  };
+
+  let programs = match assembler::assemble(&source) {
+    Ok(programs) => programs,
+    Err(err) => return error(path.span(), format!("{}:{err}", path.value())),
+  };
+
+  let program = match &name {
+    Some(name) => programs
+      .iter()
+      .find(|program| program.name.as_deref() == Some(&name.value())),
+    None if programs.len() == 1 => programs.first(),
+    None => {
+      return error(
+        path.span(),
+        "the file has several programs, name one".into(),
+      )
+    }
+  };
+
+  match program {
+    Some(program) => expand(program, Some(full_path.to_string_lossy().into_owned())).into(),
+    None => error(
+      name.map_or(path.span(), |name| name.span()),
+      "no such program".into(),
+    ),
+  }
+}
+
+/// Generates the `PioProgram` expression for `program`.
+fn expand(program: &Program, tracked_path: Option<String>) -> TokenStream2 {
+  let instructions = &program.instructions;
+  let len = instructions.len();
+  let origin = match program.origin {
+    Some(origin) => quote!(::core::option::Option::Some(#origin)),
+    None => quote!(::core::option::Option::None),
+  };
+  let wrap_target = program.wrap_target;
+  let wrap = program.wrap;
+  let side_set_bits = program.side_set.bits();
+  let side_set_optional = program.side_set.is_optional();
+  let side_set_pindirs = program.side_set.is_pindirs();
+  let symbols = program.public_symbols.iter().map(|(name, value)| {
+    let value = *value as i32;
+    quote!((#name, #value))
+  });
+  // Rebuilds the crate when the file changes
+  let track = tracked_path.map(|path| {
+    quote!(
+      const _: &[u8] = include_bytes!(#path);
+    )
+  });
+
+  quote! {
+    {
+      #track
+
+      ::pico_sdk_sys::PioProgram::<#len>::new(
+        [#(#instructions),*],
+        #origin,
+        #wrap_target,
+        #wrap,
+        ::pico_sdk_sys::SideSet::new(#side_set_bits, #side_set_optional, #side_set_pindirs),
+        &[#(#symbols),*],
+      )
+    }
+  }
+}
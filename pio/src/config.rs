@@ -0,0 +1,69 @@
+//! State machine configuration shared with the simulator
+
+/// Bits of the delay/side-set field of instructions
+pub(crate) const DELAY_SIDE_SET_BITS: u8 = 5;
+
+/// Side-set settings of a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SideSet {
+  bits: u8,
+  optional: bool,
+  pindirs: bool,
+}
+
+impl SideSet {
+  /// No side-set, leaving 5 bits for delays
+  pub const NONE: Self = Self::new(0, false, false);
+
+  /// `bits` side-set pins, which are set on every instruction unless
+  /// `optional`, driving pin directions instead of values if `pindirs`.
+  pub const fn new(bits: u8, optional: bool, pindirs: bool) -> Self {
+    assert!(
+      bits + optional as u8 <= DELAY_SIDE_SET_BITS,
+      "side-set is limited to 5 bits"
+    );
+
+    Self {
+      bits,
+      optional,
+      pindirs,
+    }
+  }
+
+  /// Number of side-set pins
+  pub const fn bits(&self) -> u8 {
+    self.bits
+  }
+
+  /// Returns `true` if instructions may omit the side-set value.
+  pub const fn is_optional(&self) -> bool {
+    self.optional
+  }
+
+  /// Returns `true` if side-set drives pin directions.
+  pub const fn is_pindirs(&self) -> bool {
+    self.pindirs
+  }
+
+  /// Bits of the delay/side-set field of each instruction used for side-set
+  pub const fn field_bits(&self) -> u8 {
+    self.bits + self.optional as u8
+  }
+
+  /// Longest delay instructions can have
+  pub const fn max_delay(&self) -> u8 {
+    (1 << (DELAY_SIDE_SET_BITS - self.field_bits())) - 1
+  }
+}
+
+/// How the TX and RX FIFOs of a state machine are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FifoJoin {
+  /// 4 words in each direction
+  #[default]
+  None,
+  /// 8 words for TX, no RX FIFO
+  Tx,
+  /// 8 words for RX, no TX FIFO
+  Rx,
+}
@@ -0,0 +1,1110 @@
+//! PIO instruction encoding
+//!
+//! [`PioInstruction`] models the 9 PIO instructions, encodes them in `const`
+//! contexts and decodes and disassembles instruction words. The `pio_encode_*`
+//! functions mirror `hardware/pio_instructions.h` of the SDK, whose functions
+//! are all `static inline` and so have no bindings.
+//!
+//! ```ignore
+//! // Jump a stopped state machine to the start of its program
+//! sm.exec(pio_encode_jmp(offset));
+//!
+//! const BLINK: [u16; 2] = [
+//!   PioInstruction::Set { destination: SetDestination::Pins, data: 1 }.encode_with(SideSet::NONE, None, 31),
+//!   PioInstruction::Set { destination: SetDestination::Pins, data: 0 }.encode_with(SideSet::NONE, None, 31),
+//! ];
+//! ```
+
+use crate::config::{SideSet, DELAY_SIDE_SET_BITS};
+use core::fmt;
+
+const OPCODE_JMP: u16 = 0x0000;
+const OPCODE_WAIT: u16 = 0x2000;
+const OPCODE_IN: u16 = 0x4000;
+const OPCODE_OUT: u16 = 0x6000;
+const OPCODE_PUSH: u16 = 0x8000;
+const OPCODE_PULL: u16 = 0x8080;
+const OPCODE_MOV: u16 = 0xa000;
+const OPCODE_IRQ: u16 = 0xc000;
+const OPCODE_SET: u16 = 0xe000;
+
+const DELAY_SIDE_SET_LSB: u32 = 8;
+
+/// Index bit of `wait irq` and `irq` selecting the IRQ relative to the state
+/// machine
+const IRQ_RELATIVE: u16 = 0x10;
+
+/// Condition of a `jmp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum JmpCondition {
+  /// Always jumps
+  Always = 0,
+  /// `!x`, jumps if X is zero
+  XZero = 1,
+  /// `x--`, jumps if X is non-zero before decrementing it
+  XDecrement = 2,
+  /// `!y`, jumps if Y is zero
+  YZero = 3,
+  /// `y--`, jumps if Y is non-zero before decrementing it
+  YDecrement = 4,
+  /// `x!=y`, jumps if X and Y differ
+  XNotEqualY = 5,
+  /// `pin`, jumps if the `jmp pin` is high
+  Pin = 6,
+  /// `!osre`, jumps if the output shift register isn't empty
+  OsrNotEmpty = 7,
+}
+
+/// What a `wait` waits for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum WaitSource {
+  /// A GPIO, by its number
+  Gpio = 0,
+  /// An input pin, relative to the `in` base
+  Pin = 1,
+  /// A PIO IRQ flag, which is cleared when waiting for 1
+  Irq = 2,
+}
+
+/// Source of an `in`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum InSource {
+  Pins = 0,
+  X = 1,
+  Y = 2,
+  Null = 3,
+  Isr = 6,
+  Osr = 7,
+}
+
+/// Destination of an `out`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum OutDestination {
+  Pins = 0,
+  X = 1,
+  Y = 2,
+  Null = 3,
+  Pindirs = 4,
+  Pc = 5,
+  Isr = 6,
+  /// Executes the shifted out bits as an instruction
+  Exec = 7,
+}
+
+/// Destination of a `mov`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum MovDestination {
+  Pins = 0,
+  X = 1,
+  Y = 2,
+  /// Executes the source as an instruction
+  Exec = 4,
+  Pc = 5,
+  Isr = 6,
+  Osr = 7,
+}
+
+/// Operation applied by a `mov`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum MovOperation {
+  None = 0,
+  /// `!`, inverts every bit
+  Invert = 1,
+  /// `::`, reverses the bit order
+  Reverse = 2,
+}
+
+/// Source of a `mov`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum MovSource {
+  Pins = 0,
+  X = 1,
+  Y = 2,
+  Null = 3,
+  /// All ones or all zeros, depending on the FIFO level selected by
+  /// `STATUS_SEL`
+  Status = 5,
+  Isr = 6,
+  Osr = 7,
+}
+
+/// Destination of a `set`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum SetDestination {
+  Pins = 0,
+  X = 1,
+  Y = 2,
+  Pindirs = 4,
+}
+
+/// A PIO instruction, without delay and side-set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PioInstruction {
+  /// Jumps to the absolute `address` if `condition` holds
+  Jmp {
+    condition: JmpCondition,
+    address: u8,
+  },
+  /// Stalls until `source` number `index` has level `polarity`
+  Wait {
+    polarity: bool,
+    source: WaitSource,
+    index: u8,
+    /// For [`WaitSource::Irq`], adds the state machine number to `index`
+    relative: bool,
+  },
+  /// Shifts `bit_count` (1 to 32) bits into the ISR
+  In { source: InSource, bit_count: u8 },
+  /// Shifts `bit_count` (1 to 32) bits out of the OSR
+  Out {
+    destination: OutDestination,
+    bit_count: u8,
+  },
+  /// Pushes the ISR to the RX FIFO
+  Push {
+    /// Only pushes once the autopush threshold is reached
+    if_full: bool,
+    /// Stalls while the RX FIFO is full
+    block: bool,
+  },
+  /// Pulls the TX FIFO into the OSR
+  Pull {
+    /// Only pulls once the autopull threshold is reached
+    if_empty: bool,
+    /// Stalls while the TX FIFO is empty, or else copies X
+    block: bool,
+  },
+  /// Copies `source` to `destination`, applying `operation`
+  Mov {
+    destination: MovDestination,
+    operation: MovOperation,
+    source: MovSource,
+  },
+  /// Sets or clears IRQ flag `index`
+  Irq {
+    /// Clears the flag instead of setting it
+    clear: bool,
+    /// Stalls until the flag is cleared again
+    wait: bool,
+    index: u8,
+    /// Adds the state machine number to `index`
+    relative: bool,
+  },
+  /// Writes `data` (0 to 31) to `destination`
+  Set {
+    destination: SetDestination,
+    data: u8,
+  },
+}
+
+impl PioInstruction {
+  /// `mov y, y`, which does nothing
+  pub const NOP: Self = Self::Mov {
+    destination: MovDestination::Y,
+    operation: MovOperation::None,
+    source: MovSource::Y,
+  };
+
+  /// Encodes the instruction without delay and side-set.
+  ///
+  /// Panics if an operand is out of range.
+  pub const fn encode(self) -> u16 {
+    match self {
+      Self::Jmp { condition, address } => {
+        assert!(address < 32, "jmp address out of range");
+
+        OPCODE_JMP | (condition as u16) << 5 | address as u16
+      }
+      Self::Wait {
+        polarity,
+        source,
+        index,
+        relative,
+      } => {
+        let index = match source {
+          WaitSource::Irq => irq_index(index, relative),
+          _ => {
+            assert!(index < 32, "wait index out of range");
+            assert!(!relative, "only wait irq can be relative");
+
+            index as u16
+          }
+        };
+
+        OPCODE_WAIT | (polarity as u16) << 7 | (source as u16) << 5 | index
+      }
+      Self::In { source, bit_count } => {
+        OPCODE_IN | (source as u16) << 5 | bit_count_field(bit_count)
+      }
+      Self::Out {
+        destination,
+        bit_count,
+      } => OPCODE_OUT | (destination as u16) << 5 | bit_count_field(bit_count),
+      Self::Push { if_full, block } => OPCODE_PUSH | (if_full as u16) << 6 | (block as u16) << 5,
+      Self::Pull { if_empty, block } => OPCODE_PULL | (if_empty as u16) << 6 | (block as u16) << 5,
+      Self::Mov {
+        destination,
+        operation,
+        source,
+      } => OPCODE_MOV | (destination as u16) << 5 | (operation as u16) << 3 | source as u16,
+      Self::Irq {
+        clear,
+        wait,
+        index,
+        relative,
+      } => OPCODE_IRQ | (clear as u16) << 6 | (wait as u16) << 5 | irq_index(index, relative),
+      Self::Set { destination, data } => {
+        assert!(data < 32, "set data out of range");
+
+        OPCODE_SET | (destination as u16) << 5 | data as u16
+      }
+    }
+  }
+
+  /// Encodes the instruction with `side` (if any) and `delay` for a program
+  /// with `side_set`.
+  ///
+  /// Panics if `side` or `delay` don't fit, or `side` is missing but not
+  /// optional.
+  pub const fn encode_with(self, side_set: SideSet, side: Option<u8>, delay: u8) -> u16 {
+    assert!(delay <= side_set.max_delay(), "delay too long for side-set");
+
+    let mut field = delay as u16;
+
+    match side {
+      Some(side) => {
+        assert!(side_set.bits() > 0, "the program has no side-set");
+        assert!(side < 1 << side_set.bits(), "side-set value out of range");
+
+        field |= (side as u16) << (DELAY_SIDE_SET_BITS - side_set.field_bits());
+
+        if side_set.is_optional() {
+          field |= 1 << (DELAY_SIDE_SET_BITS - 1);
+        }
+      }
+      None => assert!(
+        side_set.bits() == 0 || side_set.is_optional(),
+        "side-set value required"
+      ),
+    }
+
+    self.encode() | field << DELAY_SIDE_SET_LSB
+  }
+
+  /// Decodes the instruction of `word`, ignoring delay and side-set.
+  ///
+  /// Returns `None` for reserved encodings.
+  pub const fn decode(word: u16) -> Option<Self> {
+    let operand = (word >> 5) & 0x7;
+    let index = (word & 0x1f) as u8;
+
+    let instruction = match word & 0xe000 {
+      OPCODE_JMP => Self::Jmp {
+        condition: match operand {
+          0 => JmpCondition::Always,
+          1 => JmpCondition::XZero,
+          2 => JmpCondition::XDecrement,
+          3 => JmpCondition::YZero,
+          4 => JmpCondition::YDecrement,
+          5 => JmpCondition::XNotEqualY,
+          6 => JmpCondition::Pin,
+          _ => JmpCondition::OsrNotEmpty,
+        },
+        address: index,
+      },
+      OPCODE_WAIT => {
+        let source = match operand & 0x3 {
+          0 => WaitSource::Gpio,
+          1 => WaitSource::Pin,
+          2 => WaitSource::Irq,
+          _ => return None,
+        };
+        let is_irq = matches!(source, WaitSource::Irq);
+
+        if is_irq && index & 0x08 != 0 {
+          return None;
+        }
+
+        Self::Wait {
+          polarity: word & 0x80 != 0,
+          source,
+          index: if is_irq { index & 0x7 } else { index },
+          relative: is_irq && word & IRQ_RELATIVE != 0,
+        }
+      }
+      OPCODE_IN => Self::In {
+        source: match operand {
+          0 => InSource::Pins,
+          1 => InSource::X,
+          2 => InSource::Y,
+          3 => InSource::Null,
+          6 => InSource::Isr,
+          7 => InSource::Osr,
+          _ => return None,
+        },
+        bit_count: bit_count(index),
+      },
+      OPCODE_OUT => Self::Out {
+        destination: match operand {
+          0 => OutDestination::Pins,
+          1 => OutDestination::X,
+          2 => OutDestination::Y,
+          3 => OutDestination::Null,
+          4 => OutDestination::Pindirs,
+          5 => OutDestination::Pc,
+          6 => OutDestination::Isr,
+          _ => OutDestination::Exec,
+        },
+        bit_count: bit_count(index),
+      },
+      OPCODE_PUSH => {
+        if word & 0x1f != 0 {
+          return None;
+        }
+
+        if word & 0x80 == 0 {
+          Self::Push {
+            if_full: word & 0x40 != 0,
+            block: word & 0x20 != 0,
+          }
+        } else {
+          Self::Pull {
+            if_empty: word & 0x40 != 0,
+            block: word & 0x20 != 0,
+          }
+        }
+      }
+      OPCODE_MOV => Self::Mov {
+        destination: match operand {
+          0 => MovDestination::Pins,
+          1 => MovDestination::X,
+          2 => MovDestination::Y,
+          4 => MovDestination::Exec,
+          5 => MovDestination::Pc,
+          6 => MovDestination::Isr,
+          7 => MovDestination::Osr,
+          _ => return None,
+        },
+        operation: match (word >> 3) & 0x3 {
+          0 => MovOperation::None,
+          1 => MovOperation::Invert,
+          2 => MovOperation::Reverse,
+          _ => return None,
+        },
+        source: match word & 0x7 {
+          0 => MovSource::Pins,
+          1 => MovSource::X,
+          2 => MovSource::Y,
+          3 => MovSource::Null,
+          5 => MovSource::Status,
+          6 => MovSource::Isr,
+          7 => MovSource::Osr,
+          _ => return None,
+        },
+      },
+      OPCODE_IRQ => {
+        if word & 0x88 != 0 {
+          return None;
+        }
+
+        Self::Irq {
+          clear: word & 0x40 != 0,
+          wait: word & 0x20 != 0,
+          index: index & 0x7,
+          relative: word & IRQ_RELATIVE != 0,
+        }
+      }
+      _ => Self::Set {
+        destination: match operand {
+          0 => SetDestination::Pins,
+          1 => SetDestination::X,
+          2 => SetDestination::Y,
+          4 => SetDestination::Pindirs,
+          _ => return None,
+        },
+        data: index,
+      },
+    };
+
+    Some(instruction)
+  }
+}
+
+impl fmt::Display for PioInstruction {
+  /// Formats the instruction as `pioasm` source.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match *self {
+      Self::Jmp { condition, address } => {
+        let condition = match condition {
+          JmpCondition::Always => "",
+          JmpCondition::XZero => "!x, ",
+          JmpCondition::XDecrement => "x--, ",
+          JmpCondition::YZero => "!y, ",
+          JmpCondition::YDecrement => "y--, ",
+          JmpCondition::XNotEqualY => "x!=y, ",
+          JmpCondition::Pin => "pin, ",
+          JmpCondition::OsrNotEmpty => "!osre, ",
+        };
+
+        write!(f, "jmp {condition}{address}")
+      }
+      Self::Wait {
+        polarity,
+        source,
+        index,
+        relative,
+      } => {
+        let source = match source {
+          WaitSource::Gpio => "gpio",
+          WaitSource::Pin => "pin",
+          WaitSource::Irq => "irq",
+        };
+
+        write!(f, "wait {} {source} {index}", polarity as u8)?;
+
+        if relative {
+          f.write_str(" rel")?;
+        }
+
+        Ok(())
+      }
+      Self::In { source, bit_count } => {
+        let source = match source {
+          InSource::Pins => "pins",
+          InSource::X => "x",
+          InSource::Y => "y",
+          InSource::Null => "null",
+          InSource::Isr => "isr",
+          InSource::Osr => "osr",
+        };
+
+        write!(f, "in {source}, {bit_count}")
+      }
+      Self::Out {
+        destination,
+        bit_count,
+      } => {
+        let destination = match destination {
+          OutDestination::Pins => "pins",
+          OutDestination::X => "x",
+          OutDestination::Y => "y",
+          OutDestination::Null => "null",
+          OutDestination::Pindirs => "pindirs",
+          OutDestination::Pc => "pc",
+          OutDestination::Isr => "isr",
+          OutDestination::Exec => "exec",
+        };
+
+        write!(f, "out {destination}, {bit_count}")
+      }
+      Self::Push { if_full, block } => {
+        f.write_str("push")?;
+
+        if if_full {
+          f.write_str(" iffull")?;
+        }
+
+        f.write_str(if block { " block" } else { " noblock" })
+      }
+      Self::Pull { if_empty, block } => {
+        f.write_str("pull")?;
+
+        if if_empty {
+          f.write_str(" ifempty")?;
+        }
+
+        f.write_str(if block { " block" } else { " noblock" })
+      }
+      Self::NOP => f.write_str("nop"),
+      Self::Mov {
+        destination,
+        operation,
+        source,
+      } => {
+        let destination = match destination {
+          MovDestination::Pins => "pins",
+          MovDestination::X => "x",
+          MovDestination::Y => "y",
+          MovDestination::Exec => "exec",
+          MovDestination::Pc => "pc",
+          MovDestination::Isr => "isr",
+          MovDestination::Osr => "osr",
+        };
+        let operation = match operation {
+          MovOperation::None => "",
+          MovOperation::Invert => "!",
+          MovOperation::Reverse => "::",
+        };
+        let source = match source {
+          MovSource::Pins => "pins",
+          MovSource::X => "x",
+          MovSource::Y => "y",
+          MovSource::Null => "null",
+          MovSource::Status => "status",
+          MovSource::Isr => "isr",
+          MovSource::Osr => "osr",
+        };
+
+        write!(f, "mov {destination}, {operation}{source}")
+      }
+      Self::Irq {
+        clear,
+        wait,
+        index,
+        relative,
+      } => {
+        let mode = match (clear, wait) {
+          (true, _) => "clear",
+          (false, true) => "wait",
+          (false, false) => "nowait",
+        };
+
+        write!(f, "irq {mode} {index}")?;
+
+        if relative {
+          f.write_str(" rel")?;
+        }
+
+        Ok(())
+      }
+      Self::Set { destination, data } => {
+        let destination = match destination {
+          SetDestination::Pins => "pins",
+          SetDestination::X => "x",
+          SetDestination::Y => "y",
+          SetDestination::Pindirs => "pindirs",
+        };
+
+        write!(f, "set {destination}, {data}")
+      }
+    }
+  }
+}
+
+/// An instruction word split into instruction, side-set value and delay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedInstruction {
+  pub instruction: PioInstruction,
+  pub side: Option<u8>,
+  pub delay: u8,
+}
+
+impl DecodedInstruction {
+  /// Decodes `word` of a program with `side_set`.
+  ///
+  /// Returns `None` for reserved encodings.
+  pub const fn decode(word: u16, side_set: SideSet) -> Option<Self> {
+    let instruction = match PioInstruction::decode(word) {
+      Some(instruction) => instruction,
+      None => return None,
+    };
+    let field = (word >> DELAY_SIDE_SET_LSB) & 0x1f;
+    let delay_bits = DELAY_SIDE_SET_BITS - side_set.field_bits();
+    let side = if side_set.bits() == 0 || (side_set.is_optional() && field & 0x10 == 0) {
+      None
+    } else {
+      Some(((field >> delay_bits) & ((1 << side_set.bits()) - 1)) as u8)
+    };
+
+    Some(Self {
+      instruction,
+      side,
+      delay: (field & ((1 << delay_bits) - 1)) as u8,
+    })
+  }
+
+  /// Encodes the instruction for a program with `side_set`.
+  pub const fn encode(&self, side_set: SideSet) -> u16 {
+    self
+      .instruction
+      .encode_with(side_set, self.side, self.delay)
+  }
+}
+
+impl fmt::Display for DecodedInstruction {
+  /// Formats the instruction as `pioasm` source.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.instruction)?;
+
+    if let Some(side) = self.side {
+      write!(f, " side {side}")?;
+    }
+
+    if self.delay > 0 {
+      write!(f, " [{}]", self.delay)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Writes `pioasm` source for `instructions` of a program with `side_set`,
+/// one numbered line per instruction.
+///
+/// Reserved encodings are written as `.word`s.
+pub fn disassemble<W: fmt::Write>(
+  out: &mut W,
+  instructions: &[u16],
+  side_set: SideSet,
+) -> fmt::Result {
+  if side_set.bits() > 0 {
+    write!(out, ".side_set {}", side_set.bits())?;
+
+    if side_set.is_optional() {
+      out.write_str(" opt")?;
+    }
+
+    if side_set.is_pindirs() {
+      out.write_str(" pindirs")?;
+    }
+
+    out.write_char('\n')?;
+  }
+
+  for (address, &word) in instructions.iter().enumerate() {
+    match DecodedInstruction::decode(word, side_set) {
+      Some(decoded) => writeln!(out, "  {decoded} ; {address}")?,
+      None => writeln!(out, "  .word {word:#06x} ; {address}")?,
+    }
+  }
+
+  Ok(())
+}
+
+/// Delay field for `cycles` extra cycles, to be ORed into an instruction
+pub const fn pio_encode_delay(cycles: u32) -> u16 {
+  assert!(cycles <= 31, "delay out of range");
+
+  (cycles as u16) << DELAY_SIDE_SET_LSB
+}
+
+/// Side-set field for `value` with `sideset_bit_count` non-optional bits
+pub const fn pio_encode_sideset(sideset_bit_count: u32, value: u32) -> u16 {
+  assert!(
+    sideset_bit_count >= 1 && sideset_bit_count <= 5,
+    "side-set count must be 1 to 5"
+  );
+  assert!(
+    value < 1 << sideset_bit_count,
+    "side-set value out of range"
+  );
+
+  (value as u16) << (13 - sideset_bit_count)
+}
+
+/// Side-set field for `value` with `sideset_bit_count` optional bits
+pub const fn pio_encode_sideset_opt(sideset_bit_count: u32, value: u32) -> u16 {
+  assert!(
+    sideset_bit_count >= 1 && sideset_bit_count <= 4,
+    "optional side-set count must be 1 to 4"
+  );
+  assert!(
+    value < 1 << sideset_bit_count,
+    "side-set value out of range"
+  );
+
+  0x1000 | (value as u16) << (12 - sideset_bit_count)
+}
+
+const fn jmp(condition: JmpCondition, addr: u32) -> u16 {
+  assert!(addr < 32, "jmp address out of range");
+
+  PioInstruction::Jmp {
+    condition,
+    address: addr as u8,
+  }
+  .encode()
+}
+
+/// `jmp addr`
+pub const fn pio_encode_jmp(addr: u32) -> u16 {
+  jmp(JmpCondition::Always, addr)
+}
+
+/// `jmp !x, addr`
+pub const fn pio_encode_jmp_not_x(addr: u32) -> u16 {
+  jmp(JmpCondition::XZero, addr)
+}
+
+/// `jmp x--, addr`
+pub const fn pio_encode_jmp_x_dec(addr: u32) -> u16 {
+  jmp(JmpCondition::XDecrement, addr)
+}
+
+/// `jmp !y, addr`
+pub const fn pio_encode_jmp_not_y(addr: u32) -> u16 {
+  jmp(JmpCondition::YZero, addr)
+}
+
+/// `jmp y--, addr`
+pub const fn pio_encode_jmp_y_dec(addr: u32) -> u16 {
+  jmp(JmpCondition::YDecrement, addr)
+}
+
+/// `jmp x!=y, addr`
+pub const fn pio_encode_jmp_x_ne_y(addr: u32) -> u16 {
+  jmp(JmpCondition::XNotEqualY, addr)
+}
+
+/// `jmp pin, addr`
+pub const fn pio_encode_jmp_pin(addr: u32) -> u16 {
+  jmp(JmpCondition::Pin, addr)
+}
+
+/// `jmp !osre, addr`
+pub const fn pio_encode_jmp_not_osre(addr: u32) -> u16 {
+  jmp(JmpCondition::OsrNotEmpty, addr)
+}
+
+const fn wait(polarity: bool, source: WaitSource, index: u32, relative: bool) -> u16 {
+  assert!(index < 32, "wait index out of range");
+
+  PioInstruction::Wait {
+    polarity,
+    source,
+    index: index as u8,
+    relative,
+  }
+  .encode()
+}
+
+/// `wait <polarity> gpio <gpio>`
+pub const fn pio_encode_wait_gpio(polarity: bool, gpio: u32) -> u16 {
+  wait(polarity, WaitSource::Gpio, gpio, false)
+}
+
+/// `wait <polarity> pin <pin>`
+pub const fn pio_encode_wait_pin(polarity: bool, pin: u32) -> u16 {
+  wait(polarity, WaitSource::Pin, pin, false)
+}
+
+/// `wait <polarity> irq <irq> [rel]`
+pub const fn pio_encode_wait_irq(polarity: bool, relative: bool, irq: u32) -> u16 {
+  wait(polarity, WaitSource::Irq, irq, relative)
+}
+
+/// `in <src>, <count>`
+pub const fn pio_encode_in(src: InSource, count: u32) -> u16 {
+  assert!(count >= 1 && count <= 32, "bit count must be 1 to 32");
+
+  PioInstruction::In {
+    source: src,
+    bit_count: count as u8,
+  }
+  .encode()
+}
+
+/// `out <dest>, <count>`
+pub const fn pio_encode_out(dest: OutDestination, count: u32) -> u16 {
+  assert!(count >= 1 && count <= 32, "bit count must be 1 to 32");
+
+  PioInstruction::Out {
+    destination: dest,
+    bit_count: count as u8,
+  }
+  .encode()
+}
+
+/// `push [iffull] block|noblock`
+pub const fn pio_encode_push(if_full: bool, block: bool) -> u16 {
+  PioInstruction::Push { if_full, block }.encode()
+}
+
+/// `pull [ifempty] block|noblock`
+pub const fn pio_encode_pull(if_empty: bool, block: bool) -> u16 {
+  PioInstruction::Pull { if_empty, block }.encode()
+}
+
+/// `mov <dest>, <src>`
+pub const fn pio_encode_mov(dest: MovDestination, src: MovSource) -> u16 {
+  PioInstruction::Mov {
+    destination: dest,
+    operation: MovOperation::None,
+    source: src,
+  }
+  .encode()
+}
+
+/// `mov <dest>, !<src>`
+pub const fn pio_encode_mov_not(dest: MovDestination, src: MovSource) -> u16 {
+  PioInstruction::Mov {
+    destination: dest,
+    operation: MovOperation::Invert,
+    source: src,
+  }
+  .encode()
+}
+
+/// `mov <dest>, ::<src>`
+pub const fn pio_encode_mov_reverse(dest: MovDestination, src: MovSource) -> u16 {
+  PioInstruction::Mov {
+    destination: dest,
+    operation: MovOperation::Reverse,
+    source: src,
+  }
+  .encode()
+}
+
+const fn irq(clear: bool, wait: bool, relative: bool, irq: u32) -> u16 {
+  assert!(irq < 8, "PIOs only have 8 IRQ flags");
+
+  PioInstruction::Irq {
+    clear,
+    wait,
+    index: irq as u8,
+    relative,
+  }
+  .encode()
+}
+
+/// `irq set <irq> [rel]`
+pub const fn pio_encode_irq_set(relative: bool, irq_index: u32) -> u16 {
+  irq(false, false, relative, irq_index)
+}
+
+/// `irq wait <irq> [rel]`
+pub const fn pio_encode_irq_wait(relative: bool, irq_index: u32) -> u16 {
+  irq(false, true, relative, irq_index)
+}
+
+/// `irq clear <irq> [rel]`
+pub const fn pio_encode_irq_clear(relative: bool, irq_index: u32) -> u16 {
+  irq(true, false, relative, irq_index)
+}
+
+/// `set <dest>, <value>`
+pub const fn pio_encode_set(dest: SetDestination, value: u32) -> u16 {
+  assert!(value < 32, "set value out of range");
+
+  PioInstruction::Set {
+    destination: dest,
+    data: value as u8,
+  }
+  .encode()
+}
+
+/// `nop`, encoded as `mov y, y`
+pub const fn pio_encode_nop() -> u16 {
+  PioInstruction::NOP.encode()
+}
+
+const fn bit_count_field(bit_count: u8) -> u16 {
+  assert!(
+    bit_count > 0 && bit_count <= 32,
+    "bit count must be 1 to 32"
+  );
+
+  (bit_count & 0x1f) as u16
+}
+
+const fn bit_count(field: u8) -> u8 {
+  if field == 0 {
+    32
+  } else {
+    field
+  }
+}
+
+const fn irq_index(index: u8, relative: bool) -> u16 {
+  assert!(index < 8, "PIOs only have 8 IRQ flags");
+
+  if relative {
+    IRQ_RELATIVE | index as u16
+  } else {
+    index as u16
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn disassembly(instructions: &[u16], side_set: SideSet) -> String {
+    let mut out = String::new();
+    disassemble(&mut out, instructions, side_set).unwrap();
+    out
+  }
+
+  #[test]
+  fn matches_pioasm() {
+    // squarewave.pio
+    assert_eq!(
+      [
+        pio_encode_set(SetDestination::Pindirs, 1),
+        pio_encode_set(SetDestination::Pins, 1) | pio_encode_delay(1),
+        pio_encode_set(SetDestination::Pins, 0),
+        pio_encode_jmp(1),
+      ],
+      [0xe081, 0xe101, 0xe000, 0x0001]
+    );
+
+    // ws2812.pio, with `.side_set 1`
+    assert_eq!(
+      [
+        pio_encode_out(OutDestination::X, 1) | pio_encode_sideset(1, 0) | pio_encode_delay(2),
+        pio_encode_jmp_not_x(3) | pio_encode_sideset(1, 1) | pio_encode_delay(1),
+        pio_encode_jmp(0) | pio_encode_sideset(1, 1) | pio_encode_delay(4),
+        pio_encode_nop() | pio_encode_sideset(1, 0) | pio_encode_delay(4),
+      ],
+      [0x6221, 0x1123, 0x1400, 0xa442]
+    );
+
+    // uart_tx.pio, with `.side_set 1 opt`
+    assert_eq!(
+      [
+        pio_encode_pull(false, true) | pio_encode_sideset_opt(1, 1) | pio_encode_delay(7),
+        pio_encode_set(SetDestination::X, 7) | pio_encode_sideset_opt(1, 0) | pio_encode_delay(7),
+        pio_encode_out(OutDestination::Pins, 1),
+        pio_encode_jmp_x_dec(2) | pio_encode_delay(6),
+      ],
+      [0x9fa0, 0xf727, 0x6001, 0x0642]
+    );
+
+    // uart_rx.pio
+    assert_eq!(
+      [
+        pio_encode_wait_pin(false, 0),
+        pio_encode_set(SetDestination::X, 7) | pio_encode_delay(10),
+        pio_encode_in(InSource::Pins, 1),
+        pio_encode_jmp_x_dec(2) | pio_encode_delay(6),
+        pio_encode_jmp_pin(8),
+        pio_encode_irq_set(true, 4),
+        pio_encode_wait_pin(true, 0),
+        pio_encode_jmp(0),
+        pio_encode_push(false, true),
+      ],
+      [0x2020, 0xea27, 0x4001, 0x0642, 0x00c8, 0xc014, 0x20a0, 0x0000, 0x8020]
+    );
+  }
+
+  #[test]
+  fn encodes_every_instruction() {
+    assert_eq!(
+      [
+        pio_encode_jmp_not_y(0),
+        pio_encode_jmp_y_dec(0),
+        pio_encode_jmp_x_ne_y(0),
+        pio_encode_jmp_not_osre(0),
+        pio_encode_wait_gpio(true, 5),
+        pio_encode_wait_irq(true, true, 3),
+        pio_encode_in(InSource::Pins, 32),
+        pio_encode_in(InSource::Osr, 8),
+        pio_encode_out(OutDestination::Pc, 5),
+        pio_encode_out(OutDestination::Exec, 16),
+        pio_encode_push(true, false),
+        pio_encode_pull(true, true),
+        pio_encode_pull(false, false),
+        pio_encode_mov_not(MovDestination::X, MovSource::Y),
+        pio_encode_mov_reverse(MovDestination::Isr, MovSource::Osr),
+        pio_encode_mov(MovDestination::Pins, MovSource::Status),
+        pio_encode_irq_wait(true, 2),
+        pio_encode_irq_clear(false, 3),
+        pio_encode_set(SetDestination::Pindirs, 31),
+      ],
+      [
+        0x0060, 0x0080, 0x00a0, 0x00e0, 0x2085, 0x20d3, 0x4000, 0x40e8, 0x60a5, 0x60f0, 0x8040,
+        0x80e0, 0x8080, 0xa02a, 0xa0d7, 0xa005, 0xc032, 0xc043, 0xe09f,
+      ]
+    );
+
+    assert_eq!(pio_encode_sideset(2, 3) | pio_encode_nop(), 0xb842);
+    assert_eq!(
+      PioInstruction::NOP.encode_with(SideSet::new(2, false, true), Some(3), 7),
+      0xbf42
+    );
+    assert_eq!(
+      PioInstruction::NOP.encode_with(SideSet::new(1, true, false), Some(1), 3),
+      0xbb42
+    );
+  }
+
+  #[test]
+  fn encodes_in_const_context() {
+    const PROGRAM: [u16; 2] = [
+      PioInstruction::Set {
+        destination: SetDestination::Pins,
+        data: 1,
+      }
+      .encode_with(SideSet::NONE, None, 31),
+      pio_encode_jmp(0),
+    ];
+
+    assert_eq!(PROGRAM, [0xff01, 0x0000]);
+  }
+
+  #[test]
+  fn round_trips_every_word() {
+    let side_sets = [
+      SideSet::NONE,
+      SideSet::new(1, false, false),
+      SideSet::new(1, true, false),
+      SideSet::new(2, true, true),
+      SideSet::new(5, false, false),
+      SideSet::new(4, true, false),
+    ];
+
+    for side_set in side_sets {
+      for word in 0..=u16::MAX {
+        let Some(decoded) = DecodedInstruction::decode(word, side_set) else {
+          continue;
+        };
+
+        // Without the enable bit, the side-set bits of optional side-sets
+        // are ignored
+        let ignored = if side_set.is_optional() && decoded.side.is_none() {
+          (((1u16 << side_set.bits()) - 1) << (12 - side_set.bits())) & 0x0f00
+        } else {
+          0
+        };
+
+        assert_eq!(
+          decoded.encode(side_set),
+          word & !ignored,
+          "{word:#06x} {side_set:?}"
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn rejects_reserved_encodings() {
+    for word in [
+      0x2060, 0x20c8, 0x4080, 0x40a0, 0x8001, 0x8081, 0xa060, 0xa018, 0xa004, 0xc080, 0xc008,
+      0xe060, 0xe0a0,
+    ] {
+      assert_eq!(PioInstruction::decode(word), None, "{word:#06x}");
+    }
+  }
+
+  #[test]
+  #[should_panic(expected = "delay too long for side-set")]
+  fn rejects_long_delays() {
+    PioInstruction::NOP.encode_with(SideSet::new(2, false, false), Some(0), 8);
+  }
+
+  #[test]
+  fn disassembles() {
+    assert_eq!(
+      disassembly(&[0x6221, 0x1123, 0x1400, 0xa442], SideSet::new(1, false, false)),
+      ".side_set 1\n  out x, 1 side 0 [2] ; 0\n  jmp !x, 3 side 1 [1] ; 1\n  jmp 0 side 1 [4] ; 2\n  nop side 0 [4] ; 3\n"
+    );
+
+    assert_eq!(
+      disassembly(&[0x9fa0, 0xf727, 0x6001, 0x0642], SideSet::new(1, true, false)),
+      ".side_set 1 opt\n  pull block side 1 [7] ; 0\n  set x, 7 side 0 [7] ; 1\n  out pins, 1 ; 2\n  jmp x--, 2 [6] ; 3\n"
+    );
+
+    assert_eq!(
+      disassembly(&[0x20d3, 0xc014, 0xa0d7, 0x8040, 0xc043, 0xa060], SideSet::NONE),
+      "  wait 1 irq 3 rel ; 0\n  irq nowait 4 rel ; 1\n  mov isr, ::osr ; 2\n  push iffull noblock ; 3\n  irq clear 3 ; 4\n  .word 0xa060 ; 5\n"
+    );
+  }
+}
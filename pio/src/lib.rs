@@ -0,0 +1,19 @@
+//! PIO programs and instruction encoding for
+//! [pico-sdk-sys](https://docs.rs/pico-sdk-sys)
+//!
+//! This crate has no hardware dependencies, so it builds and is tested on the
+//! host. pico-sdk-sys re-exports all of it. The `sim` feature adds a
+//! simulator to test PIO programs on the host, which needs `std`.
+
+#![cfg_attr(not(any(test, feature = "sim")), no_std)]
+
+mod config;
+mod instructions;
+mod program;
+pub mod programs;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+
+pub use config::*;
+pub use instructions::*;
+pub use program::*;
@@ -0,0 +1,119 @@
+//! Assembled PIO programs
+
+use crate::config::SideSet;
+
+/// Number of instructions in a PIO's memory
+pub const PIO_INSTRUCTION_COUNT: usize = 32;
+
+/// An assembled PIO program of `N` instructions
+#[derive(Debug, Clone, Copy)]
+pub struct PioProgram<const N: usize> {
+  instructions: [u16; N],
+  origin: Option<u8>,
+  wrap_target: u8,
+  wrap: u8,
+  side_set: SideSet,
+  symbols: &'static [(&'static str, i32)],
+}
+
+impl<const N: usize> PioProgram<N> {
+  /// Program with `instructions` that must be loaded at `origin`, if given,
+  /// and `public` labels and defines in `symbols`.
+  ///
+  /// `wrap_target` and `wrap` are relative to the start of the program.
+  pub const fn new(
+    instructions: [u16; N],
+    origin: Option<u8>,
+    wrap_target: u8,
+    wrap: u8,
+    side_set: SideSet,
+    symbols: &'static [(&'static str, i32)],
+  ) -> Self {
+    assert!(
+      N > 0 && N <= PIO_INSTRUCTION_COUNT,
+      "programs have 1 to 32 instructions"
+    );
+    assert!(
+      (wrap_target as usize) < N && (wrap as usize) < N,
+      "wrap outside the program"
+    );
+
+    Self {
+      instructions,
+      origin,
+      wrap_target,
+      wrap,
+      side_set,
+      symbols,
+    }
+  }
+
+  /// Encoded instructions
+  pub const fn instructions(&self) -> &[u16; N] {
+    &self.instructions
+  }
+
+  /// Offset the program must be loaded at, if any
+  pub const fn origin(&self) -> Option<u8> {
+    self.origin
+  }
+
+  /// First instruction after wrapping, relative to the start of the program
+  pub const fn wrap_target(&self) -> u8 {
+    self.wrap_target
+  }
+
+  /// Last instruction before wrapping, relative to the start of the program
+  pub const fn wrap(&self) -> u8 {
+    self.wrap
+  }
+
+  /// Side-set settings
+  pub const fn side_set(&self) -> SideSet {
+    self.side_set
+  }
+
+  /// Value of the `public` label or define `name`
+  pub const fn symbol(&self, name: &str) -> Option<i32> {
+    let mut index = 0;
+
+    while index < self.symbols.len() {
+      let (symbol, value) = self.symbols[index];
+
+      if symbol.len() == name.len() {
+        let mut byte = 0;
+
+        while byte < name.len() && symbol.as_bytes()[byte] == name.as_bytes()[byte] {
+          byte += 1;
+        }
+
+        if byte == name.len() {
+          return Some(value);
+        }
+      }
+
+      index += 1;
+    }
+
+    None
+  }
+
+  /// Instructions with the addresses of `jmp`s moved to `offset`, as loaded
+  /// by `pio_add_program`.
+  pub const fn relocated(&self, offset: u8) -> [u16; N] {
+    let mut instructions = self.instructions;
+    let mut index = 0;
+
+    while index < N {
+      let instruction = instructions[index];
+
+      if instruction & 0xe000 == 0 {
+        instructions[index] = (instruction & !0x1f) | ((instruction + offset as u16) & 0x1f);
+      }
+
+      index += 1;
+    }
+
+    instructions
+  }
+}
@@ -0,0 +1,583 @@
+//! Programs of the PIO drivers
+//!
+//! The programs follow the SDK examples and are built with the `const`
+//! encoder, so they don't need the `pio-asm` feature. The pioasm source of
+//! each instruction is in the comment next to it.
+
+use crate::config::SideSet;
+use crate::instructions::*;
+use crate::program::PioProgram;
+
+/// Cycles of the WS2812 bit phases
+const WS2812_T1: u32 = 2;
+const WS2812_T2: u32 = 5;
+const WS2812_T3: u32 = 3;
+
+/// Cycles per bit of [`WS2812`]
+pub const WS2812_CYCLES_PER_BIT: u32 = WS2812_T1 + WS2812_T2 + WS2812_T3;
+
+/// WS2812 (NeoPixel) output on the side-set pin, shifting out the top 24 or
+/// 32 bits of each word with autopull
+pub const WS2812: PioProgram<4> = PioProgram::new(
+  [
+    // bitloop: out x, 1 side 0 [T3 - 1]
+    pio_encode_out(OutDestination::X, 1)
+      | pio_encode_sideset(1, 0)
+      | pio_encode_delay(WS2812_T3 - 1),
+    // jmp !x do_zero side 1 [T1 - 1]
+    pio_encode_jmp_not_x(3) | pio_encode_sideset(1, 1) | pio_encode_delay(WS2812_T1 - 1),
+    // do_one: jmp bitloop side 1 [T2 - 1]
+    pio_encode_jmp(0) | pio_encode_sideset(1, 1) | pio_encode_delay(WS2812_T2 - 1),
+    // do_zero: nop side 0 [T2 - 1]
+    pio_encode_nop() | pio_encode_sideset(1, 0) | pio_encode_delay(WS2812_T2 - 1),
+  ],
+  None,
+  0,
+  3,
+  SideSet::new(1, false, false),
+  &[
+    ("T1", WS2812_T1 as i32),
+    ("T2", WS2812_T2 as i32),
+    ("T3", WS2812_T3 as i32),
+  ],
+);
+
+const QUADRATURE_DECREMENT: u32 = 14;
+const QUADRATURE_UPDATE: u32 = 15;
+const QUADRATURE_INCREMENT: u32 = 21;
+
+/// Quadrature encoder counter from `quadrature_encoder.pio` of the SDK
+/// examples, with phase A on the `in` base and phase B on the pin after it
+///
+/// Counts in Y and keeps pushing the count without blocking. It jumps to a
+/// table entry for the previous and current phase levels, so it must be
+/// loaded at 0.
+pub const QUADRATURE_ENCODER: PioProgram<24> = PioProgram::new(
+  [
+    // From 00: update, decrement, increment, update
+    pio_encode_jmp(QUADRATURE_UPDATE),
+    pio_encode_jmp(QUADRATURE_DECREMENT),
+    pio_encode_jmp(QUADRATURE_INCREMENT),
+    pio_encode_jmp(QUADRATURE_UPDATE),
+    // From 01: increment, update, update, decrement
+    pio_encode_jmp(QUADRATURE_INCREMENT),
+    pio_encode_jmp(QUADRATURE_UPDATE),
+    pio_encode_jmp(QUADRATURE_UPDATE),
+    pio_encode_jmp(QUADRATURE_DECREMENT),
+    // From 10: decrement, update, update, increment
+    pio_encode_jmp(QUADRATURE_DECREMENT),
+    pio_encode_jmp(QUADRATURE_UPDATE),
+    pio_encode_jmp(QUADRATURE_UPDATE),
+    pio_encode_jmp(QUADRATURE_INCREMENT),
+    // From 11: update, increment, then decrement and update in place
+    pio_encode_jmp(QUADRATURE_UPDATE),
+    pio_encode_jmp(QUADRATURE_INCREMENT),
+    // decrement: jmp y--, update
+    pio_encode_jmp_y_dec(QUADRATURE_UPDATE),
+    // update: mov isr, y
+    pio_encode_mov(MovDestination::Isr, MovSource::Y),
+    // push noblock
+    pio_encode_push(false, false),
+    // out isr, 2
+    pio_encode_out(OutDestination::Isr, 2),
+    // in pins, 2
+    pio_encode_in(InSource::Pins, 2),
+    // mov osr, isr
+    pio_encode_mov(MovDestination::Osr, MovSource::Isr),
+    // mov pc, isr
+    pio_encode_mov(MovDestination::Pc, MovSource::Isr),
+    // increment: mov y, ~y
+    pio_encode_mov_not(MovDestination::Y, MovSource::Y),
+    // jmp y--, increment_cont
+    pio_encode_jmp_y_dec(QUADRATURE_INCREMENT + 2),
+    // increment_cont: mov y, ~y
+    pio_encode_mov_not(MovDestination::Y, MovSource::Y),
+  ],
+  Some(0),
+  QUADRATURE_UPDATE as u8,
+  23,
+  SideSet::NONE,
+  &[],
+);
+
+/// Longest loop of [`QUADRATURE_ENCODER`], which limits the step rate to the
+/// state machine clock divided by this
+pub const QUADRATURE_ENCODER_CYCLES_PER_SAMPLE: u32 = 10;
+
+/// I2S output from `audio_i2s.pio` of pico-extras, with BCLK on the side-set
+/// base and LRCLK on the pin after it
+///
+/// Each word holds the right sample in the top and the left sample in the
+/// bottom 16 bits, which is a little-endian pair of 16-bit samples as
+/// pico-extras packs them. Execution starts at `entry_point`.
+pub const I2S_OUT: PioProgram<8> = PioProgram::new(
+  [
+    // bitloop1: out pins, 1 side 0b10
+    pio_encode_out(OutDestination::Pins, 1) | pio_encode_sideset(2, 0b10),
+    // jmp x-- bitloop1 side 0b11
+    pio_encode_jmp_x_dec(0) | pio_encode_sideset(2, 0b11),
+    // out pins, 1 side 0b00
+    pio_encode_out(OutDestination::Pins, 1) | pio_encode_sideset(2, 0b00),
+    // set x, 14 side 0b01
+    pio_encode_set(SetDestination::X, 14) | pio_encode_sideset(2, 0b01),
+    // bitloop0: out pins, 1 side 0b00
+    pio_encode_out(OutDestination::Pins, 1) | pio_encode_sideset(2, 0b00),
+    // jmp x-- bitloop0 side 0b01
+    pio_encode_jmp_x_dec(4) | pio_encode_sideset(2, 0b01),
+    // out pins, 1 side 0b10
+    pio_encode_out(OutDestination::Pins, 1) | pio_encode_sideset(2, 0b10),
+    // public entry_point: set x, 14 side 0b11
+    pio_encode_set(SetDestination::X, 14) | pio_encode_sideset(2, 0b11),
+  ],
+  None,
+  0,
+  7,
+  SideSet::new(2, false, false),
+  &[("entry_point", 7)],
+);
+
+/// Cycles per stereo frame of [`I2S_OUT`]
+pub const I2S_OUT_CYCLES_PER_FRAME: u32 = 64;
+
+/// 8N1 UART transmitter from `uart_tx.pio` of the SDK examples, on the `out`
+/// and side-set pin
+///
+/// Sends the low 8 bits of each word, at 8 cycles per bit.
+pub const UART_TX: PioProgram<4> = PioProgram::new(
+  [
+    // pull side 1 [7]
+    pio_encode_pull(false, true) | pio_encode_sideset_opt(1, 1) | pio_encode_delay(7),
+    // set x, 7 side 0 [7]
+    pio_encode_set(SetDestination::X, 7) | pio_encode_sideset_opt(1, 0) | pio_encode_delay(7),
+    // bitloop: out pins, 1
+    pio_encode_out(OutDestination::Pins, 1),
+    // jmp x-- bitloop [6]
+    pio_encode_jmp_x_dec(2) | pio_encode_delay(6),
+  ],
+  None,
+  0,
+  3,
+  SideSet::new(1, true, false),
+  &[],
+);
+
+/// 8N1 UART receiver from `uart_rx.pio` of the SDK examples, on the `in` and
+/// `jmp` pin
+///
+/// Pushes each byte into the top 8 bits of a word, at 8 cycles per bit. On a
+/// framing error or break, it raises relative IRQ 4 and waits for the line to
+/// go idle.
+pub const UART_RX: PioProgram<9> = PioProgram::new(
+  [
+    // start: wait 0 pin 0
+    pio_encode_wait_pin(false, 0),
+    // set x, 7 [10]
+    pio_encode_set(SetDestination::X, 7) | pio_encode_delay(10),
+    // bitloop: in pins, 1
+    pio_encode_in(InSource::Pins, 1),
+    // jmp x-- bitloop [6]
+    pio_encode_jmp_x_dec(2) | pio_encode_delay(6),
+    // jmp pin good_stop
+    pio_encode_jmp_pin(8),
+    // irq 4 rel
+    pio_encode_irq_set(true, 4),
+    // wait 1 pin 0
+    pio_encode_wait_pin(true, 0),
+    // jmp start
+    pio_encode_jmp(0),
+    // good_stop: push
+    pio_encode_push(false, true),
+  ],
+  None,
+  0,
+  8,
+  SideSet::NONE,
+  &[],
+);
+
+/// Cycles per bit of [`UART_TX`] and [`UART_RX`]
+pub const UART_CYCLES_PER_BIT: u32 = 8;
+
+/// SPI with clock phase 0 from `spi.pio` of the SDK examples, with SCK on the
+/// side-set pin, MOSI on the `out` pin and MISO on the `in` pin
+///
+/// The frame size is the autopull and autopush threshold.
+pub const SPI_CPHA0: PioProgram<2> = PioProgram::new(
+  [
+    // out pins, 1 side 0 [1]
+    pio_encode_out(OutDestination::Pins, 1) | pio_encode_sideset(1, 0) | pio_encode_delay(1),
+    // in pins, 1 side 1 [1]
+    pio_encode_in(InSource::Pins, 1) | pio_encode_sideset(1, 1) | pio_encode_delay(1),
+  ],
+  None,
+  0,
+  1,
+  SideSet::new(1, false, false),
+  &[],
+);
+
+/// SPI with clock phase 1 from `spi.pio` of the SDK examples, with the pins
+/// of [`SPI_CPHA0`]
+pub const SPI_CPHA1: PioProgram<3> = PioProgram::new(
+  [
+    // out x, 1 side 0
+    pio_encode_out(OutDestination::X, 1) | pio_encode_sideset(1, 0),
+    // mov pins, x side 1 [1]
+    pio_encode_mov(MovDestination::Pins, MovSource::X)
+      | pio_encode_sideset(1, 1)
+      | pio_encode_delay(1),
+    // in pins, 1 side 0
+    pio_encode_in(InSource::Pins, 1) | pio_encode_sideset(1, 0),
+  ],
+  None,
+  0,
+  2,
+  SideSet::new(1, false, false),
+  &[],
+);
+
+/// Cycles per bit of [`SPI_CPHA0`] and [`SPI_CPHA1`]
+pub const SPI_CYCLES_PER_BIT: u32 = 4;
+
+/// Free-running clock on the side-set pin, toggling every cycle
+pub const PIXEL_CLOCK: PioProgram<2> = PioProgram::new(
+  [
+    // nop side 1
+    pio_encode_nop() | pio_encode_sideset(1, 1),
+    // nop side 0
+    pio_encode_nop() | pio_encode_sideset(1, 0),
+  ],
+  None,
+  0,
+  1,
+  SideSet::new(1, false, false),
+  &[],
+);
+
+/// [`PIXEL_CLOCK`] on a differential pair, with the inverted clock on the
+/// pin after the side-set base, as used by the clock lane of DVI
+pub const PIXEL_CLOCK_DIFFERENTIAL: PioProgram<2> = PioProgram::new(
+  [
+    // nop side 0b01
+    pio_encode_nop() | pio_encode_sideset(2, 0b01),
+    // nop side 0b10
+    pio_encode_nop() | pio_encode_sideset(2, 0b10),
+  ],
+  None,
+  0,
+  1,
+  SideSet::new(2, false, false),
+  &[],
+);
+
+/// Cycles per period of [`PIXEL_CLOCK`] and [`PIXEL_CLOCK_DIFFERENTIAL`]
+pub const PIXEL_CLOCK_CYCLES_PER_PERIOD: u32 = 2;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::FifoJoin;
+  use crate::sim::{PioSim, SimConfig};
+
+  #[test]
+  fn matches_pioasm() {
+    assert_eq!(WS2812.instructions(), &[0x6221, 0x1123, 0x1400, 0xa442]);
+    assert_eq!(UART_TX.instructions(), &[0x9fa0, 0xf727, 0x6001, 0x0642]);
+    assert_eq!(
+      UART_RX.instructions(),
+      &[0x2020, 0xea27, 0x4001, 0x0642, 0x00c8, 0xc014, 0x20a0, 0x0000, 0x8020]
+    );
+    assert_eq!(SPI_CPHA0.instructions(), &[0x6101, 0x5101]);
+    assert_eq!(SPI_CPHA1.instructions(), &[0x6021, 0xb101, 0x4001]);
+    assert_eq!(
+      I2S_OUT.instructions(),
+      &[0x7001, 0x1840, 0x6001, 0xe82e, 0x6001, 0x0844, 0x7001, 0xf82e]
+    );
+    assert_eq!(
+      QUADRATURE_ENCODER.instructions(),
+      &[
+        0x000f, 0x000e, 0x0015, 0x000f, 0x0015, 0x000f, 0x000f, 0x000e, 0x000e, 0x000f, 0x000f,
+        0x0015, 0x000f, 0x0015, 0x008f, 0xa0c2, 0x8000, 0x60c2, 0x4002, 0xa0e6, 0xa0a6, 0xa04a,
+        0x0097, 0xa04a,
+      ]
+    );
+  }
+
+  #[test]
+  fn drives_ws2812() {
+    let mut sim = PioSim::new();
+    let config = SimConfig {
+      side_set_base: 2,
+      out_shift_right: false,
+      autopull: true,
+      pull_threshold: 24,
+      fifo_join: FifoJoin::Tx,
+      ..sim.load_program(5, &WS2812)
+    };
+
+    sim.init(0, 5, config);
+    sim.set_pindirs(2, 1, true);
+    sim.put(0, 0x80_01_ff << 8);
+    sim.set_enabled(0, true);
+    sim.start_trace();
+    sim.run(WS2812_CYCLES_PER_BIT as u64 * 24 + 3);
+
+    // High for 7 cycles for ones and 2 for zeros, in 10 cycle bits
+    let high: Vec<usize> = sim
+      .take_trace()
+      .pulses(2)
+      .into_iter()
+      .filter_map(|(level, cycles)| level.then_some(cycles))
+      .collect();
+    let expected: Vec<usize> = (0..24)
+      .rev()
+      .map(|bit| if (0x80_01_ff >> bit) & 1 != 0 { 7 } else { 2 })
+      .collect();
+    assert_eq!(high, expected);
+  }
+
+  /// Levels of phases A and B, where counting up B leads A
+  const QUADRATURE_STEPS: [u32; 4] = [0b00, 0b10, 0b11, 0b01];
+
+  #[test]
+  fn counts_quadrature_steps() {
+    let mut sim = PioSim::new();
+    let config = SimConfig {
+      in_base: 6,
+      in_shift_right: false,
+      fifo_join: FifoJoin::Rx,
+      ..sim.load_program(0, &QUADRATURE_ENCODER)
+    };
+
+    sim.init(0, QUADRATURE_ENCODER.wrap_target(), config);
+    sim.set_enabled(0, true);
+
+    let count = |sim: &mut PioSim| {
+      while sim.get(0).is_some() {}
+
+      sim.run(QUADRATURE_ENCODER_CYCLES_PER_SAMPLE as u64 * 2);
+      sim.get(0).unwrap() as i32
+    };
+    let mut step = 0;
+
+    for _ in 0..9 {
+      step += 1;
+      sim.set_inputs(QUADRATURE_STEPS[step % 4] << 6);
+      sim.run(QUADRATURE_ENCODER_CYCLES_PER_SAMPLE as u64);
+    }
+
+    assert_eq!(count(&mut sim), 9);
+
+    for _ in 0..13 {
+      step += 3;
+      sim.set_inputs(QUADRATURE_STEPS[step % 4] << 6);
+      sim.run(QUADRATURE_ENCODER_CYCLES_PER_SAMPLE as u64);
+    }
+
+    assert_eq!(count(&mut sim), -4);
+
+    // Skipping a step is invalid and ignored
+    sim.set_inputs(QUADRATURE_STEPS[(step + 2) % 4] << 6);
+    assert_eq!(count(&mut sim), -4);
+  }
+
+  fn uart_config(sim: &mut PioSim) -> (SimConfig, SimConfig) {
+    let tx = SimConfig {
+      out_base: 3,
+      out_count: 1,
+      side_set_base: 3,
+      fifo_join: FifoJoin::Tx,
+      ..sim.load_program(0, &UART_TX)
+    };
+    let rx = SimConfig {
+      in_base: 3,
+      jmp_pin: 3,
+      fifo_join: FifoJoin::Rx,
+      ..sim.load_program(UART_TX.instructions().len() as u8, &UART_RX)
+    };
+
+    (tx, rx)
+  }
+
+  #[test]
+  fn loops_uart_back() {
+    let mut sim = PioSim::new();
+    let (tx, rx) = uart_config(&mut sim);
+
+    sim.init(0, 0, tx);
+    sim.init(1, rx.wrap_target, rx);
+    sim.set_pins(1 << 3, 1 << 3);
+    sim.set_pindirs(3, 1, true);
+    sim.set_enabled(0, true);
+    sim.set_enabled(1, true);
+
+    for byte in b"PIO\x00\xff" {
+      sim.put(0, *byte as u32);
+    }
+
+    sim.run(UART_CYCLES_PER_BIT as u64 * 10 * 6);
+
+    let received: Vec<u8> = std::iter::from_fn(|| sim.get(1))
+      .map(|word| (word >> 24) as u8)
+      .collect();
+    assert_eq!(received, b"PIO\x00\xff");
+    assert_eq!(sim.irq_flags(), 0);
+  }
+
+  #[test]
+  fn detects_uart_breaks() {
+    let mut sim = PioSim::new();
+    let (_, rx) = uart_config(&mut sim);
+
+    sim.init(2, rx.wrap_target, rx);
+    sim.set_inputs(1 << 3);
+    sim.set_enabled(2, true);
+    sim.run(10);
+
+    sim.set_inputs(0);
+    sim.run(UART_CYCLES_PER_BIT as u64 * 12);
+
+    // IRQ 4 relative to state machine 2
+    assert_eq!(sim.irq_flags(), 1 << 6);
+    assert_eq!(sim.rx_level(2), 0);
+  }
+
+  fn loop_spi_back<const N: usize>(program: &PioProgram<N>) {
+    let mut sim = PioSim::new();
+    let config = SimConfig {
+      side_set_base: 10,
+      out_base: 11,
+      out_count: 1,
+      in_base: 11,
+      out_shift_right: false,
+      autopull: true,
+      pull_threshold: 8,
+      in_shift_right: false,
+      autopush: true,
+      push_threshold: 8,
+      ..sim.load_program(0, program)
+    };
+
+    sim.init(0, 0, config);
+    sim.set_pindirs(10, 2, true);
+    sim.set_enabled(0, true);
+
+    for byte in [0xa5u32, 0x3c] {
+      sim.put(0, byte << 24);
+    }
+
+    sim.start_trace();
+    sim.run(SPI_CYCLES_PER_BIT as u64 * 16 + 4);
+
+    assert_eq!(sim.get(0), Some(0xa5));
+    assert_eq!(sim.get(0), Some(0x3c));
+
+    let clock = sim.take_trace().pulses(10);
+    assert_eq!(clock.iter().filter(|&&(level, _)| level).count(), 16);
+    assert!(clock.iter().all(|&(level, cycles)| !level || cycles == 2));
+  }
+
+  #[test]
+  fn loops_spi_back() {
+    loop_spi_back(&SPI_CPHA0);
+    loop_spi_back(&SPI_CPHA1);
+  }
+
+  #[test]
+  fn outputs_i2s() {
+    let mut sim = PioSim::new();
+    let config = SimConfig {
+      out_base: 20,
+      out_count: 1,
+      side_set_base: 21,
+      out_shift_right: false,
+      autopull: true,
+      fifo_join: FifoJoin::Tx,
+      ..sim.load_program(0, &I2S_OUT)
+    };
+    let (left, right) = (0x7ffe, 0x8001);
+    let frame = right << 16 | left;
+
+    sim.init(0, I2S_OUT.symbol("entry_point").unwrap() as u8, config);
+    sim.set_pindirs(20, 3, true);
+    sim.put(0, frame);
+    sim.put(0, 0);
+    sim.set_enabled(0, true);
+    sim.start_trace();
+    sim.run(I2S_OUT_CYCLES_PER_FRAME as u64 + 4);
+
+    // Data is sampled on rising BCLK edges, with LRCLK low for the left
+    // channel, one bit ahead of the data
+    let trace = sim.take_trace();
+    let samples: Vec<(bool, bool)> = trace
+      .edges(21)
+      .into_iter()
+      .filter(|&index| trace.samples()[index] & (1 << 21) != 0)
+      .map(|index| {
+        let levels = trace.samples()[index];
+        (levels & (1 << 20) != 0, levels & (1 << 22) != 0)
+      })
+      .take(32)
+      .collect();
+
+    let data: u32 = samples
+      .iter()
+      .fold(0, |word, &(data, _)| word << 1 | data as u32);
+    assert_eq!(data, frame);
+
+    let lrclk: Vec<bool> = samples.iter().map(|&(_, lrclk)| lrclk).collect();
+    assert_eq!(lrclk[..15], [true; 15]);
+    assert_eq!(lrclk[15..31], [false; 16]);
+    assert!(lrclk[31]);
+
+    // Each bit belongs to the channel LRCLK selected one edge earlier, the
+    // entry point starts with LRCLK high
+    let mut slots = (0, 0);
+    let mut selected = true;
+
+    for &(data, lrclk) in &samples {
+      let slot = if selected { &mut slots.1 } else { &mut slots.0 };
+      *slot = *slot << 1 | data as u32;
+      selected = lrclk;
+    }
+
+    assert_eq!(slots, (left, right));
+  }
+
+  #[test]
+  fn runs_pixel_clocks() {
+    let mut sim = PioSim::new();
+    let single = sim.load_program(0, &PIXEL_CLOCK);
+    let differential = sim.load_program(2, &PIXEL_CLOCK_DIFFERENTIAL);
+
+    sim.init(
+      0,
+      0,
+      SimConfig {
+        side_set_base: 0,
+        ..single
+      },
+    );
+    sim.init(
+      1,
+      2,
+      SimConfig {
+        side_set_base: 1,
+        ..differential
+      },
+    );
+    sim.set_pindirs(0, 3, true);
+    sim.set_enabled(0, true);
+    sim.set_enabled(1, true);
+    sim.start_trace();
+    sim.run(20);
+
+    let trace = sim.take_trace();
+    assert_eq!(trace.pulses(0), [(true, 1), (false, 1)].repeat(10));
+    assert_eq!(trace.pulses(1), [(true, 1), (false, 1)].repeat(10));
+    assert!(trace
+      .samples()
+      .iter()
+      .all(|levels| (levels >> 1) & 0b11 == 0b01 || (levels >> 1) & 0b11 == 0b10));
+  }
+}
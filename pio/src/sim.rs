@@ -0,0 +1,1377 @@
+//! PIO simulator
+//!
+//! [`PioSim`] runs programs on a model of one PIO block, so PIO programs can be
+//! tested on the host with `cargo test`. It models the instruction memory, the
+//! 4 state machines with their scratch and shift registers, FIFOs, autopush
+//! and autopull, side-set, delays, wrap and clock dividers, the 8 IRQ flags
+//! and the levels and directions of the 32 GPIOs.
+//!
+//! Every call to [`PioSim::step`] is one system clock cycle. The pin levels
+//! after each cycle can be recorded into a [`Trace`] to assert on timing.
+//!
+//! ```ignore
+//! let mut sim = PioSim::new();
+//! let config = SimConfig { set_base: 2, set_count: 1, wrap: 1, ..SimConfig::default() };
+//!
+//! sim.load(0, &[pio_encode_set(SetDestination::Pins, 1), pio_encode_set(SetDestination::Pins, 0)]);
+//! sim.init(0, 0, config);
+//! sim.set_pindirs(2, 1, true);
+//! sim.set_enabled(0, true);
+//!
+//! sim.start_trace();
+//! sim.run(4);
+//! assert_eq!(sim.take_trace().pulses(2), [(true, 1), (false, 1), (true, 1), (false, 1)]);
+//! ```
+//!
+//! Not modelled are the 2-cycle input synchronizers, the jitter of fractional
+//! clock dividers, which are spread evenly instead, `OUT_STICKY` and
+//! `INLINE_OUT_EN`, and the FIFO debug flags.
+
+use crate::config::{FifoJoin, SideSet};
+use crate::instructions::*;
+use crate::program::{PioProgram, PIO_INSTRUCTION_COUNT};
+use std::collections::VecDeque;
+
+const NUM_STATE_MACHINES: usize = 4;
+const FIFO_DEPTH: usize = 4;
+
+/// Configuration of a simulated state machine
+///
+/// [`SimConfig::default`] matches `pio_get_default_sm_config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimConfig {
+  /// Integer part of the clock divider, where 0 divides by 65536
+  pub clkdiv_int: u16,
+  /// Fractional part of the clock divider, in 1/256ths
+  pub clkdiv_frac: u8,
+  /// Absolute address execution wraps to
+  pub wrap_target: u8,
+  /// Absolute address execution wraps from
+  pub wrap: u8,
+  pub side_set: SideSet,
+  pub side_set_base: u8,
+  pub out_base: u8,
+  pub out_count: u8,
+  pub set_base: u8,
+  pub set_count: u8,
+  pub in_base: u8,
+  pub jmp_pin: u8,
+  pub in_shift_right: bool,
+  pub autopush: bool,
+  /// Bits in the ISR for autopush and `push iffull`, 1 to 32
+  pub push_threshold: u8,
+  pub out_shift_right: bool,
+  pub autopull: bool,
+  /// Bits shifted out of the OSR for autopull and `pull ifempty`, 1 to 32
+  pub pull_threshold: u8,
+  pub fifo_join: FifoJoin,
+  /// Compares the RX instead of the TX FIFO level for `mov x, status`
+  pub status_rx: bool,
+  /// `mov x, status` is all ones while the FIFO level is below this
+  pub status_level: u8,
+}
+
+impl Default for SimConfig {
+  fn default() -> Self {
+    Self {
+      clkdiv_int: 1,
+      clkdiv_frac: 0,
+      wrap_target: 0,
+      wrap: 31,
+      side_set: SideSet::NONE,
+      side_set_base: 0,
+      out_base: 0,
+      out_count: 0,
+      set_base: 0,
+      set_count: 0,
+      in_base: 0,
+      jmp_pin: 0,
+      in_shift_right: true,
+      autopush: false,
+      push_threshold: 32,
+      out_shift_right: true,
+      autopull: false,
+      pull_threshold: 32,
+      fifo_join: FifoJoin::None,
+      status_rx: false,
+      status_level: 0,
+    }
+  }
+}
+
+impl SimConfig {
+  /// Configuration for `program` loaded at `offset`, with the SDK's defaults
+  /// and the wrap and side-set settings of the program.
+  pub fn for_program<const N: usize>(program: &PioProgram<N>, offset: u8) -> Self {
+    Self {
+      wrap_target: offset + program.wrap_target(),
+      wrap: offset + program.wrap(),
+      side_set: program.side_set(),
+      ..Self::default()
+    }
+  }
+
+  /// Decodes the `CLKDIV`, `EXECCTRL`, `SHIFTCTRL` and `PINCTRL` register
+  /// values of a state machine, such as those of a `pio_sm_config`.
+  pub fn from_registers(clkdiv: u32, execctrl: u32, shiftctrl: u32, pinctrl: u32) -> Self {
+    let field = |register: u32, lsb: u32, bits: u32| ((register >> lsb) & ((1 << bits) - 1)) as u8;
+    let threshold = |value: u8| if value == 0 { 32 } else { value };
+
+    let optional = execctrl & (1 << 30) != 0;
+    let side_set_bits = field(pinctrl, 29, 3).saturating_sub(optional as u8);
+
+    Self {
+      clkdiv_int: (clkdiv >> 16) as u16,
+      clkdiv_frac: field(clkdiv, 8, 8),
+      wrap_target: field(execctrl, 7, 5),
+      wrap: field(execctrl, 12, 5),
+      side_set: SideSet::new(side_set_bits, optional, execctrl & (1 << 29) != 0),
+      side_set_base: field(pinctrl, 10, 5),
+      out_base: field(pinctrl, 0, 5),
+      out_count: field(pinctrl, 20, 6),
+      set_base: field(pinctrl, 5, 5),
+      set_count: field(pinctrl, 26, 3),
+      in_base: field(pinctrl, 15, 5),
+      jmp_pin: field(execctrl, 24, 5),
+      in_shift_right: shiftctrl & (1 << 18) != 0,
+      autopush: shiftctrl & (1 << 16) != 0,
+      push_threshold: threshold(field(shiftctrl, 20, 5)),
+      out_shift_right: shiftctrl & (1 << 19) != 0,
+      autopull: shiftctrl & (1 << 17) != 0,
+      pull_threshold: threshold(field(shiftctrl, 25, 5)),
+      fifo_join: match (shiftctrl & (1 << 30) != 0, shiftctrl & (1 << 31) != 0) {
+        (true, _) => FifoJoin::Tx,
+        (false, true) => FifoJoin::Rx,
+        (false, false) => FifoJoin::None,
+      },
+      status_rx: execctrl & (1 << 4) != 0,
+      status_level: field(execctrl, 0, 4),
+    }
+  }
+
+  /// Clock divider in 1/256ths
+  fn divider(&self) -> u32 {
+    let integer = if self.clkdiv_int == 0 {
+      65536
+    } else {
+      self.clkdiv_int as u32
+    };
+
+    integer * 256 + self.clkdiv_frac as u32
+  }
+
+  fn tx_depth(&self) -> usize {
+    match self.fifo_join {
+      FifoJoin::None => FIFO_DEPTH,
+      FifoJoin::Tx => FIFO_DEPTH * 2,
+      FifoJoin::Rx => 0,
+    }
+  }
+
+  fn rx_depth(&self) -> usize {
+    match self.fifo_join {
+      FifoJoin::None => FIFO_DEPTH,
+      FifoJoin::Tx => 0,
+      FifoJoin::Rx => FIFO_DEPTH * 2,
+    }
+  }
+}
+
+/// What executing an instruction did
+enum Outcome {
+  /// Completed, continuing with the next instruction
+  Next,
+  /// Completed, continuing at an address
+  Jump(u8),
+  /// Completed, executing an instruction word next
+  Exec(u16),
+  /// Didn't complete and runs again on the next cycle
+  Stall,
+}
+
+#[derive(Debug, Clone, Default)]
+struct StateMachine {
+  config: SimConfig,
+  enabled: bool,
+  pc: u8,
+  x: u32,
+  y: u32,
+  isr: u32,
+  /// Bits shifted into the ISR
+  isr_count: u8,
+  osr: u32,
+  /// Bits shifted out of the OSR, where 32 is empty
+  osr_count: u8,
+  tx: VecDeque<u32>,
+  rx: VecDeque<u32>,
+  /// Delay cycles left of the last instruction
+  delay: u8,
+  /// Instruction to run instead of the one at the program counter
+  exec: Option<u16>,
+  /// Set once an `irq wait` has raised its flag
+  irq_waiting: bool,
+  /// Clock divider accumulator in 1/256ths
+  divider: u32,
+}
+
+impl StateMachine {
+  /// Clears the state like `SM_RESTART`, `CLKDIV_RESTART` and clearing the
+  /// FIFOs.
+  fn reset(&mut self) {
+    self.isr = 0;
+    self.isr_count = 0;
+    self.osr = 0;
+    self.osr_count = 32;
+    self.tx.clear();
+    self.rx.clear();
+    self.delay = 0;
+    self.exec = None;
+    self.irq_waiting = false;
+    self.restart_clock();
+  }
+
+  /// Makes the divider tick on the next cycle.
+  fn restart_clock(&mut self) {
+    self.divider = self.config.divider() - 256;
+  }
+
+  fn next_pc(&self) -> u8 {
+    if self.pc == self.config.wrap {
+      self.config.wrap_target
+    } else {
+      (self.pc + 1) % PIO_INSTRUCTION_COUNT as u8
+    }
+  }
+
+  fn shift_in(&mut self, data: u32, bit_count: u8) {
+    let data = data & mask(bit_count);
+
+    self.isr = match (bit_count, self.config.in_shift_right) {
+      (32, _) => data,
+      (_, true) => (self.isr >> bit_count) | data << (32 - bit_count),
+      (_, false) => (self.isr << bit_count) | data,
+    };
+    self.isr_count = (self.isr_count + bit_count).min(32);
+  }
+
+  fn shift_out(&mut self, bit_count: u8) -> u32 {
+    let data = match (bit_count, self.config.out_shift_right) {
+      (32, _) => self.osr,
+      (_, true) => self.osr & mask(bit_count),
+      (_, false) => self.osr >> (32 - bit_count),
+    };
+
+    self.osr = match (bit_count, self.config.out_shift_right) {
+      (32, _) => 0,
+      (_, true) => self.osr >> bit_count,
+      (_, false) => self.osr << bit_count,
+    };
+    self.osr_count = (self.osr_count + bit_count).min(32);
+
+    data
+  }
+
+  fn is_osr_empty(&self) -> bool {
+    self.osr_count >= self.config.pull_threshold
+  }
+
+  fn pull(&mut self) -> bool {
+    match self.tx.pop_front() {
+      Some(word) => {
+        self.osr = word;
+        self.osr_count = 0;
+        true
+      }
+      None => false,
+    }
+  }
+
+  fn is_rx_full(&self) -> bool {
+    self.rx.len() >= self.config.rx_depth()
+  }
+
+  fn push(&mut self) {
+    if !self.is_rx_full() {
+      self.rx.push_back(self.isr);
+    }
+
+    self.isr = 0;
+    self.isr_count = 0;
+  }
+}
+
+/// Levels and directions of the GPIOs
+#[derive(Debug, Clone, Copy, Default)]
+struct Gpio {
+  /// Levels driven from outside
+  inputs: u32,
+  /// Levels driven by the PIO
+  outputs: u32,
+  /// Pins driven by the PIO
+  output_enables: u32,
+}
+
+impl Gpio {
+  fn levels(&self) -> u32 {
+    (self.outputs & self.output_enables) | (self.inputs & !self.output_enables)
+  }
+}
+
+/// A simulated PIO block
+#[derive(Debug, Clone)]
+pub struct PioSim {
+  instructions: [u16; PIO_INSTRUCTION_COUNT],
+  state_machines: [StateMachine; NUM_STATE_MACHINES],
+  irq: u8,
+  gpio: Gpio,
+  cycle: u64,
+  trace: Option<Trace>,
+}
+
+impl Default for PioSim {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl PioSim {
+  /// PIO with empty instruction memory and stopped state machines.
+  pub fn new() -> Self {
+    let mut sim = Self {
+      instructions: [0; PIO_INSTRUCTION_COUNT],
+      state_machines: Default::default(),
+      irq: 0,
+      gpio: Gpio::default(),
+      cycle: 0,
+      trace: None,
+    };
+
+    for sm in &mut sim.state_machines {
+      sm.config = SimConfig::default();
+      sm.reset();
+    }
+
+    sim
+  }
+
+  /// Writes `instructions` to the instruction memory at `offset`.
+  ///
+  /// Jumps in the instructions must already be relocated to `offset`.
+  pub fn load(&mut self, offset: u8, instructions: &[u16]) {
+    assert!(
+      offset as usize + instructions.len() <= PIO_INSTRUCTION_COUNT,
+      "program doesn't fit"
+    );
+
+    self.instructions[offset as usize..][..instructions.len()].copy_from_slice(instructions);
+  }
+
+  /// Loads `program` at `offset`, like `pio_add_program_at_offset`, and
+  /// returns its default configuration.
+  pub fn load_program<const N: usize>(&mut self, offset: u8, program: &PioProgram<N>) -> SimConfig {
+    if let Some(origin) = program.origin() {
+      assert_eq!(origin, offset, "program must be loaded at its origin");
+    }
+
+    self.load(offset, &program.relocated(offset));
+
+    SimConfig::for_program(program, offset)
+  }
+
+  /// Instruction memory
+  pub fn instructions(&self) -> &[u16; PIO_INSTRUCTION_COUNT] {
+    &self.instructions
+  }
+
+  fn sm(&self, sm: u8) -> &StateMachine {
+    &self.state_machines[sm as usize]
+  }
+
+  fn sm_mut(&mut self, sm: u8) -> &mut StateMachine {
+    &mut self.state_machines[sm as usize]
+  }
+
+  /// Stops state machine `sm`, applies `config`, clears its state and FIFOs
+  /// and jumps to `pc`, like `pio_sm_init`.
+  pub fn init(&mut self, sm: u8, pc: u8, config: SimConfig) {
+    let state = self.sm_mut(sm);
+
+    state.enabled = false;
+    state.config = config;
+    state.reset();
+    state.pc = pc % PIO_INSTRUCTION_COUNT as u8;
+  }
+
+  /// Applies `config` while `sm` keeps its state.
+  pub fn set_config(&mut self, sm: u8, config: SimConfig) {
+    self.sm_mut(sm).config = config;
+  }
+
+  /// Starts or stops `sm`.
+  pub fn set_enabled(&mut self, sm: u8, enabled: bool) {
+    self.sm_mut(sm).enabled = enabled;
+  }
+
+  /// Returns `true` while `sm` is running.
+  pub fn is_enabled(&self, sm: u8) -> bool {
+    self.sm(sm).enabled
+  }
+
+  /// Clears the shift registers, delays and stalls of `sm`, like
+  /// `pio_sm_restart`.
+  pub fn restart(&mut self, sm: u8) {
+    let state = self.sm_mut(sm);
+
+    state.isr = 0;
+    state.isr_count = 0;
+    state.osr_count = 32;
+    state.delay = 0;
+    state.exec = None;
+    state.irq_waiting = false;
+  }
+
+  /// Executes `instruction` on `sm` immediately, like `pio_sm_exec`.
+  ///
+  /// If it stalls, it completes once `sm` runs.
+  pub fn exec(&mut self, sm: u8, instruction: u16) {
+    let levels = self.gpio.levels();
+
+    self.run_instruction(sm as usize, instruction, true, levels);
+  }
+
+  /// Writes `word` to the TX FIFO of `sm`, returning `false` if it is full.
+  pub fn put(&mut self, sm: u8, word: u32) -> bool {
+    let state = self.sm_mut(sm);
+
+    if state.tx.len() >= state.config.tx_depth() {
+      return false;
+    }
+
+    state.tx.push_back(word);
+    true
+  }
+
+  /// Reads a word from the RX FIFO of `sm`.
+  pub fn get(&mut self, sm: u8) -> Option<u32> {
+    self.sm_mut(sm).rx.pop_front()
+  }
+
+  /// Number of words in the TX FIFO of `sm`
+  pub fn tx_level(&self, sm: u8) -> usize {
+    self.sm(sm).tx.len()
+  }
+
+  /// Number of words in the RX FIFO of `sm`
+  pub fn rx_level(&self, sm: u8) -> usize {
+    self.sm(sm).rx.len()
+  }
+
+  /// Program counter of `sm`
+  pub fn pc(&self, sm: u8) -> u8 {
+    self.sm(sm).pc
+  }
+
+  /// X register of `sm`
+  pub fn x(&self, sm: u8) -> u32 {
+    self.sm(sm).x
+  }
+
+  /// Y register of `sm`
+  pub fn y(&self, sm: u8) -> u32 {
+    self.sm(sm).y
+  }
+
+  /// Input shift register of `sm`
+  pub fn isr(&self, sm: u8) -> u32 {
+    self.sm(sm).isr
+  }
+
+  /// Output shift register of `sm`
+  pub fn osr(&self, sm: u8) -> u32 {
+    self.sm(sm).osr
+  }
+
+  /// IRQ flags, one bit each
+  pub fn irq_flags(&self) -> u8 {
+    self.irq
+  }
+
+  /// Sets the IRQ flags in `mask`, like writing `IRQ_FORCE`.
+  pub fn force_irq(&mut self, mask: u8) {
+    self.irq |= mask;
+  }
+
+  /// Clears the IRQ flags in `mask`, like writing `IRQ`.
+  pub fn clear_irq(&mut self, mask: u8) {
+    self.irq &= !mask;
+  }
+
+  /// Drives `pin` from outside, where it isn't an output.
+  pub fn set_input(&mut self, pin: u8, level: bool) {
+    self.gpio.inputs = with_bit(self.gpio.inputs, pin, level);
+  }
+
+  /// Drives all pins from outside, where they aren't outputs.
+  pub fn set_inputs(&mut self, levels: u32) {
+    self.gpio.inputs = levels;
+  }
+
+  /// Levels of all pins, from the PIO where it drives them
+  pub fn pins(&self) -> u32 {
+    self.gpio.levels()
+  }
+
+  /// Level of `pin`
+  pub fn pin(&self, pin: u8) -> bool {
+    self.pins() & (1 << pin) != 0
+  }
+
+  /// Output levels of the PIO, also for pins it doesn't drive
+  pub fn outputs(&self) -> u32 {
+    self.gpio.outputs
+  }
+
+  /// Pins the PIO drives
+  pub fn output_enables(&self) -> u32 {
+    self.gpio.output_enables
+  }
+
+  /// Sets the direction of `count` pins starting at `base`, like
+  /// `pio_sm_set_consecutive_pindirs`.
+  pub fn set_pindirs(&mut self, base: u8, count: u8, output: bool) {
+    let values = if output { u32::MAX } else { 0 };
+
+    write_pins(&mut self.gpio.output_enables, base, count, values);
+  }
+
+  /// Sets the output levels of the pins in `mask`, like
+  /// `pio_sm_set_pins_with_mask`.
+  pub fn set_pins(&mut self, values: u32, mask: u32) {
+    self.gpio.outputs = (self.gpio.outputs & !mask) | (values & mask);
+  }
+
+  /// Number of cycles run
+  pub fn cycle(&self) -> u64 {
+    self.cycle
+  }
+
+  /// Records the pin levels after every following cycle.
+  pub fn start_trace(&mut self) {
+    self.trace = Some(Trace {
+      start: self.cycle,
+      samples: Vec::new(),
+    });
+  }
+
+  /// Stops recording and returns the recorded pin levels.
+  pub fn take_trace(&mut self) -> Trace {
+    self.trace.take().unwrap_or_default()
+  }
+
+  /// Runs one system clock cycle.
+  pub fn step(&mut self) {
+    // All state machines see the pins as they were before the cycle
+    let levels = self.gpio.levels();
+
+    for index in 0..NUM_STATE_MACHINES {
+      let sm = &mut self.state_machines[index];
+
+      if !sm.enabled {
+        continue;
+      }
+
+      sm.divider += 256;
+
+      if sm.divider < sm.config.divider() {
+        continue;
+      }
+
+      sm.divider -= sm.config.divider();
+      self.clock(index, levels);
+    }
+
+    self.cycle += 1;
+
+    if let Some(trace) = &mut self.trace {
+      trace.samples.push(self.gpio.levels());
+    }
+  }
+
+  /// Runs `cycles` system clock cycles.
+  pub fn run(&mut self, cycles: u64) {
+    for _ in 0..cycles {
+      self.step();
+    }
+  }
+
+  /// Runs until `done` returns `true`, for at most `max_cycles` cycles.
+  ///
+  /// Returns `false` on timeout.
+  pub fn run_until(&mut self, max_cycles: u64, mut done: impl FnMut(&Self) -> bool) -> bool {
+    for _ in 0..max_cycles {
+      if done(self) {
+        return true;
+      }
+
+      self.step();
+    }
+
+    done(self)
+  }
+
+  /// Runs one cycle of state machine `index`.
+  fn clock(&mut self, index: usize, levels: u32) {
+    let sm = &mut self.state_machines[index];
+
+    // Autopull refills the OSR in the background
+    if sm.config.autopull && sm.is_osr_empty() {
+      sm.pull();
+    }
+
+    if sm.delay > 0 {
+      sm.delay -= 1;
+      return;
+    }
+
+    match sm.exec.take() {
+      Some(instruction) => self.run_instruction(index, instruction, true, levels),
+      None => {
+        let instruction = self.instructions[sm.pc as usize];
+
+        self.run_instruction(index, instruction, false, levels);
+      }
+    }
+  }
+
+  /// Runs `word` on state machine `index`, where `forced` instructions
+  /// don't advance the program counter.
+  fn run_instruction(&mut self, index: usize, word: u16, forced: bool, levels: u32) {
+    let side_set = self.state_machines[index].config.side_set;
+    // Reserved encodings are treated as `nop`
+    let decoded = DecodedInstruction::decode(word, side_set).unwrap_or(DecodedInstruction {
+      instruction: PioInstruction::NOP,
+      side: None,
+      delay: 0,
+    });
+
+    let outcome = self.execute(index, decoded.instruction, levels);
+
+    // Side-set takes effect even if the instruction stalls, and takes
+    // priority over pins written by the instruction itself
+    if let Some(side) = decoded.side {
+      let config = &self.state_machines[index].config;
+      let (base, bits) = (config.side_set_base, side_set.bits());
+      let target = if side_set.is_pindirs() {
+        &mut self.gpio.output_enables
+      } else {
+        &mut self.gpio.outputs
+      };
+
+      write_pins(target, base, bits, side as u32);
+    }
+
+    let sm = &mut self.state_machines[index];
+
+    match outcome {
+      Outcome::Stall => sm.exec = forced.then_some(word),
+      Outcome::Next => {
+        if !forced {
+          sm.pc = sm.next_pc();
+        }
+
+        sm.delay = decoded.delay;
+      }
+      Outcome::Jump(address) => {
+        sm.pc = address % PIO_INSTRUCTION_COUNT as u8;
+        sm.delay = decoded.delay;
+      }
+      // The delay of the executing instruction is ignored
+      Outcome::Exec(instruction) => {
+        if !forced {
+          sm.pc = sm.next_pc();
+        }
+
+        sm.exec = Some(instruction);
+      }
+    }
+  }
+
+  fn execute(&mut self, index: usize, instruction: PioInstruction, levels: u32) -> Outcome {
+    let Self {
+      state_machines,
+      irq,
+      gpio,
+      ..
+    } = self;
+    let sm = &mut state_machines[index];
+    let config = sm.config;
+
+    match instruction {
+      PioInstruction::Jmp { condition, address } => {
+        let taken = match condition {
+          JmpCondition::Always => true,
+          JmpCondition::XZero => sm.x == 0,
+          JmpCondition::XDecrement => {
+            let taken = sm.x != 0;
+            sm.x = sm.x.wrapping_sub(1);
+            taken
+          }
+          JmpCondition::YZero => sm.y == 0,
+          JmpCondition::YDecrement => {
+            let taken = sm.y != 0;
+            sm.y = sm.y.wrapping_sub(1);
+            taken
+          }
+          JmpCondition::XNotEqualY => sm.x != sm.y,
+          JmpCondition::Pin => bit(levels, config.jmp_pin),
+          JmpCondition::OsrNotEmpty => !sm.is_osr_empty(),
+        };
+
+        if taken {
+          Outcome::Jump(address)
+        } else {
+          Outcome::Next
+        }
+      }
+      PioInstruction::Wait {
+        polarity,
+        source,
+        index: source_index,
+        relative,
+      } => {
+        let level = match source {
+          WaitSource::Gpio => bit(levels, source_index),
+          WaitSource::Pin => bit(levels, (config.in_base + source_index) % 32),
+          WaitSource::Irq => bit(*irq as u32, irq_flag(source_index, relative, index)),
+        };
+
+        if level != polarity {
+          return Outcome::Stall;
+        }
+
+        // Waiting for an IRQ flag to be set clears it
+        if source == WaitSource::Irq && polarity {
+          *irq &= !(1 << irq_flag(source_index, relative, index));
+        }
+
+        Outcome::Next
+      }
+      PioInstruction::In { source, bit_count } => {
+        let data = match source {
+          InSource::Pins => levels.rotate_right(config.in_base as u32),
+          InSource::X => sm.x,
+          InSource::Y => sm.y,
+          InSource::Null => 0,
+          InSource::Isr => sm.isr,
+          InSource::Osr => sm.osr,
+        };
+        let pushes = config.autopush && sm.isr_count + bit_count >= config.push_threshold;
+
+        if pushes && sm.is_rx_full() {
+          return Outcome::Stall;
+        }
+
+        sm.shift_in(data, bit_count);
+
+        if pushes {
+          sm.push();
+        }
+
+        Outcome::Next
+      }
+      PioInstruction::Out {
+        destination,
+        bit_count,
+      } => {
+        if config.autopull && sm.is_osr_empty() && !sm.pull() {
+          return Outcome::Stall;
+        }
+
+        let data = sm.shift_out(bit_count);
+
+        match destination {
+          OutDestination::Pins => {
+            write_pins(&mut gpio.outputs, config.out_base, config.out_count, data)
+          }
+          OutDestination::X => sm.x = data,
+          OutDestination::Y => sm.y = data,
+          OutDestination::Null => {}
+          OutDestination::Pindirs => write_pins(
+            &mut gpio.output_enables,
+            config.out_base,
+            config.out_count,
+            data,
+          ),
+          OutDestination::Pc => return Outcome::Jump(data as u8),
+          OutDestination::Isr => {
+            sm.isr = data;
+            sm.isr_count = bit_count;
+          }
+          OutDestination::Exec => return Outcome::Exec(data as u16),
+        }
+
+        Outcome::Next
+      }
+      PioInstruction::Push { if_full, block } => {
+        if if_full && sm.isr_count < config.push_threshold {
+          return Outcome::Next;
+        }
+
+        if block && sm.is_rx_full() {
+          return Outcome::Stall;
+        }
+
+        sm.push();
+        Outcome::Next
+      }
+      PioInstruction::Pull { if_empty, block } => {
+        // With autopull, `pull` only refills an empty OSR
+        if (if_empty || config.autopull) && !sm.is_osr_empty() {
+          return Outcome::Next;
+        }
+
+        if !sm.pull() {
+          if block {
+            return Outcome::Stall;
+          }
+
+          sm.osr = sm.x;
+          sm.osr_count = 0;
+        }
+
+        Outcome::Next
+      }
+      PioInstruction::Mov {
+        destination,
+        operation,
+        source,
+      } => {
+        let data = match source {
+          MovSource::Pins => levels.rotate_right(config.in_base as u32),
+          MovSource::X => sm.x,
+          MovSource::Y => sm.y,
+          MovSource::Null => 0,
+          MovSource::Status => {
+            let level = if config.status_rx {
+              sm.rx.len()
+            } else {
+              sm.tx.len()
+            };
+
+            if level < config.status_level as usize {
+              u32::MAX
+            } else {
+              0
+            }
+          }
+          MovSource::Isr => sm.isr,
+          MovSource::Osr => sm.osr,
+        };
+        let data = match operation {
+          MovOperation::None => data,
+          MovOperation::Invert => !data,
+          MovOperation::Reverse => data.reverse_bits(),
+        };
+
+        match destination {
+          MovDestination::Pins => {
+            write_pins(&mut gpio.outputs, config.out_base, config.out_count, data)
+          }
+          MovDestination::X => sm.x = data,
+          MovDestination::Y => sm.y = data,
+          MovDestination::Exec => return Outcome::Exec(data as u16),
+          MovDestination::Pc => return Outcome::Jump(data as u8),
+          MovDestination::Isr => {
+            sm.isr = data;
+            sm.isr_count = 0;
+          }
+          MovDestination::Osr => {
+            sm.osr = data;
+            sm.osr_count = 0;
+          }
+        }
+
+        Outcome::Next
+      }
+      PioInstruction::Irq {
+        clear,
+        wait,
+        index: flag_index,
+        relative,
+      } => {
+        let flag = 1 << irq_flag(flag_index, relative, index);
+
+        if clear {
+          *irq &= !flag;
+          return Outcome::Next;
+        }
+
+        if !wait {
+          *irq |= flag;
+          return Outcome::Next;
+        }
+
+        // Raises the flag once, then waits for it to be cleared
+        if !sm.irq_waiting {
+          *irq |= flag;
+          sm.irq_waiting = true;
+          return Outcome::Stall;
+        }
+
+        if *irq & flag != 0 {
+          return Outcome::Stall;
+        }
+
+        sm.irq_waiting = false;
+        Outcome::Next
+      }
+      PioInstruction::Set { destination, data } => {
+        let data = data as u32;
+
+        match destination {
+          SetDestination::Pins => {
+            write_pins(&mut gpio.outputs, config.set_base, config.set_count, data)
+          }
+          SetDestination::X => sm.x = data,
+          SetDestination::Y => sm.y = data,
+          SetDestination::Pindirs => write_pins(
+            &mut gpio.output_enables,
+            config.set_base,
+            config.set_count,
+            data,
+          ),
+        }
+
+        Outcome::Next
+      }
+    }
+  }
+}
+
+/// Pin levels recorded after every cycle
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+  start: u64,
+  samples: Vec<u32>,
+}
+
+impl Trace {
+  /// Cycle the recording started at
+  pub fn start(&self) -> u64 {
+    self.start
+  }
+
+  /// Levels of all pins after each cycle
+  pub fn samples(&self) -> &[u32] {
+    &self.samples
+  }
+
+  /// Number of recorded cycles
+  pub fn len(&self) -> usize {
+    self.samples.len()
+  }
+
+  /// Returns `true` if no cycles were recorded.
+  pub fn is_empty(&self) -> bool {
+    self.samples.is_empty()
+  }
+
+  /// Levels of `pin` after each cycle
+  pub fn levels(&self, pin: u8) -> impl Iterator<Item = bool> + '_ {
+    self.samples.iter().map(move |&levels| bit(levels, pin))
+  }
+
+  /// Levels of `pin` as runs of `(level, cycles)`
+  pub fn pulses(&self, pin: u8) -> Vec<(bool, usize)> {
+    let mut pulses: Vec<(bool, usize)> = Vec::new();
+
+    for level in self.levels(pin) {
+      match pulses.last_mut() {
+        Some((last, cycles)) if *last == level => *cycles += 1,
+        _ => pulses.push((level, 1)),
+      }
+    }
+
+    pulses
+  }
+
+  /// Indices of the samples where `pin` changed level
+  pub fn edges(&self, pin: u8) -> Vec<usize> {
+    let levels: Vec<bool> = self.levels(pin).collect();
+
+    (1..levels.len())
+      .filter(|&index| levels[index] != levels[index - 1])
+      .collect()
+  }
+}
+
+/// Writes the low `count` bits of `data` to the pins from `base`, wrapping
+/// around after pin 31.
+fn write_pins(target: &mut u32, base: u8, count: u8, data: u32) {
+  for offset in 0..count.min(32) {
+    *target = with_bit(*target, (base + offset) % 32, bit(data, offset));
+  }
+}
+
+fn with_bit(value: u32, bit: u8, level: bool) -> u32 {
+  if level {
+    value | 1 << bit
+  } else {
+    value & !(1 << bit)
+  }
+}
+
+fn bit(value: u32, bit: u8) -> bool {
+  value & (1 << bit) != 0
+}
+
+fn mask(bit_count: u8) -> u32 {
+  u32::MAX >> (32 - bit_count as u32)
+}
+
+/// IRQ flag `index` for state machine `sm`, where relative indices add `sm` to
+/// the low 2 bits.
+fn irq_flag(index: u8, relative: bool, sm: usize) -> u8 {
+  if relative {
+    (index & 0x4) | ((index + sm as u8) & 0x3)
+  } else {
+    index
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Runs `program` from address 0 on state machine 0 with `config`.
+  fn start(program: &[u16], config: SimConfig) -> PioSim {
+    let mut sim = PioSim::new();
+
+    sim.load(0, program);
+    sim.init(0, 0, config);
+    sim.set_enabled(0, true);
+    sim
+  }
+
+  fn traced(sim: &mut PioSim, cycles: u64) -> Trace {
+    sim.start_trace();
+    sim.run(cycles);
+    sim.take_trace()
+  }
+
+  const SQUAREWAVE: [u16; 4] = [
+    0xe081, // set pindirs, 1
+    0xe101, // set pins, 1 [1]
+    0xe000, // set pins, 0
+    0x0001, // jmp 1
+  ];
+
+  #[test]
+  fn runs_squarewave() {
+    let config = SimConfig {
+      set_base: 3,
+      set_count: 1,
+      ..SimConfig::default()
+    };
+    let mut sim = start(&SQUAREWAVE, config);
+
+    sim.step();
+    assert_eq!(sim.output_enables(), 1 << 3);
+
+    let trace = traced(&mut sim, 12);
+    assert_eq!(
+      trace.pulses(3),
+      [
+        (true, 2),
+        (false, 2),
+        (true, 2),
+        (false, 2),
+        (true, 2),
+        (false, 2)
+      ]
+    );
+    assert_eq!(trace.edges(3), [2, 4, 6, 8, 10]);
+    assert_eq!(trace.start(), 1);
+  }
+
+  #[test]
+  fn divides_clock() {
+    let config = SimConfig {
+      set_base: 0,
+      set_count: 1,
+      clkdiv_int: 2,
+      clkdiv_frac: 128,
+      ..SimConfig::default()
+    };
+    let mut sim = start(&SQUAREWAVE, config);
+
+    sim.run(3);
+
+    // Each instruction cycle takes 2.5 system cycles on average
+    let trace = traced(&mut sim, 100);
+    let high: usize = trace.levels(0).filter(|&level| level).count();
+    assert_eq!(high, 50);
+    let pulses = trace.pulses(0);
+    assert!(pulses[1..pulses.len() - 1]
+      .iter()
+      .all(|&(_, cycles)| cycles == 4 || cycles == 5 || cycles == 6));
+  }
+
+  #[test]
+  fn side_set_overrides_set() {
+    let config = SimConfig {
+      side_set: SideSet::new(1, false, false),
+      side_set_base: 0,
+      set_base: 0,
+      set_count: 2,
+      ..SimConfig::default()
+    };
+    // set pins, 3 side 0
+    let mut sim = start(&[0xe003], config);
+
+    sim.step();
+    assert_eq!(sim.outputs() & 0b11, 0b10);
+  }
+
+  #[test]
+  fn drives_ws2812() {
+    // ws2812.pio with T1 = 2, T2 = 5 and T3 = 3
+    let program = [0x6221, 0x1123, 0x1400, 0xa442];
+    let config = SimConfig {
+      side_set: SideSet::new(1, false, false),
+      side_set_base: 7,
+      wrap: 3,
+      out_shift_right: false,
+      autopull: true,
+      pull_threshold: 24,
+      ..SimConfig::default()
+    };
+    let mut sim = start(&program, config);
+
+    sim.set_pindirs(7, 1, true);
+    assert!(sim.put(0, 0xa5_00_00 << 8));
+
+    let trace = traced(&mut sim, 240 + 20);
+    let mut expected = vec![(false, 3)];
+
+    for bit in (0..24).rev() {
+      if (0xa5_00_00 >> bit) & 1 != 0 {
+        expected.extend([(true, 7), (false, 3)]);
+      } else {
+        expected.extend([(true, 2), (false, 8)]);
+      }
+    }
+
+    // The out stalls low once the FIFO runs dry
+    let mut pulses = trace.pulses(7);
+    let idle = pulses.pop().unwrap();
+    let (_, last) = expected.pop().unwrap();
+    assert_eq!(pulses, expected);
+    assert_eq!(idle.1 - last, 17);
+    assert_eq!(sim.pc(0), 0);
+  }
+
+  #[test]
+  fn transmits_uart() {
+    // uart_tx.pio with `.side_set 1 opt`
+    let program = [0x9fa0, 0xf727, 0x6001, 0x0642];
+    let config = SimConfig {
+      side_set: SideSet::new(1, true, false),
+      wrap: 3,
+      out_count: 1,
+      ..SimConfig::default()
+    };
+    let mut sim = start(&program, config);
+
+    sim.set_pindirs(0, 1, true);
+    sim.run(10);
+    assert!(sim.pin(0));
+
+    sim.put(0, 0x55);
+
+    let trace = traced(&mut sim, 8 * 11);
+    let mut expected = vec![(false, 8)];
+
+    for bit in 0..8 {
+      expected.push(((0x55 >> bit) & 1 != 0, 8));
+    }
+
+    expected.push((true, 8));
+    assert_eq!(trace.pulses(0)[1..], expected[..]);
+  }
+
+  #[test]
+  fn receives_with_autopush() {
+    // in pins, 1 ; jmp 0
+    let program = [0x4001, 0x0000];
+    let config = SimConfig {
+      in_base: 4,
+      in_shift_right: false,
+      autopush: true,
+      push_threshold: 8,
+      ..SimConfig::default()
+    };
+    let mut sim = start(&program, config);
+
+    // One bit every 2 cycles, most significant first
+    for bit in (0..8).rev() {
+      sim.set_input(4, (0xc3 >> bit) & 1 != 0);
+      sim.run(2);
+    }
+
+    assert_eq!(sim.get(0), Some(0xc3));
+    assert_eq!(sim.rx_level(0), 0);
+    assert_eq!(sim.isr(0), 0);
+  }
+
+  #[test]
+  fn stalls_on_full_rx_fifo() {
+    // in x, 32 with autopush
+    let program = [0x4020];
+    let config = SimConfig {
+      autopush: true,
+      wrap: 0,
+      ..SimConfig::default()
+    };
+    let mut sim = start(&program, config);
+
+    sim.run(10);
+    assert_eq!(sim.rx_level(0), 4);
+    assert_eq!(sim.pc(0), 0);
+  }
+
+  #[test]
+  fn synchronizes_with_irqs() {
+    let mut sim = PioSim::new();
+
+    // State machine 0: irq wait 0 rel ; set x, 1
+    sim.load(
+      0,
+      &[
+        pio_encode_irq_wait(true, 0),
+        pio_encode_set(SetDestination::X, 1),
+      ],
+    );
+    // State machine 1: wait 1 irq 0 [7] ; set y, 1
+    sim.load(
+      2,
+      &[
+        pio_encode_wait_irq(true, false, 0) | pio_encode_delay(7),
+        pio_encode_set(SetDestination::Y, 1),
+      ],
+    );
+
+    sim.init(
+      0,
+      0,
+      SimConfig {
+        wrap_target: 1,
+        wrap: 1,
+        ..SimConfig::default()
+      },
+    );
+    sim.init(
+      1,
+      2,
+      SimConfig {
+        wrap_target: 3,
+        wrap: 3,
+        ..SimConfig::default()
+      },
+    );
+    sim.set_enabled(0, true);
+
+    sim.run(5);
+    assert_eq!(sim.irq_flags(), 1);
+    assert_eq!(sim.x(0), 0);
+
+    sim.set_enabled(1, true);
+    sim.run(3);
+    assert_eq!(sim.irq_flags(), 0);
+    assert_eq!(sim.x(0), 1);
+    assert_eq!(sim.y(1), 0);
+
+    // 7 delay cycles after the wait
+    sim.run(5);
+    assert_eq!(sim.y(1), 0);
+    sim.run(1);
+    assert_eq!(sim.y(1), 1);
+  }
+
+  #[test]
+  fn executes_out_exec() {
+    // out exec, 16 ; jmp 0
+    let program = [0x60f0, 0x0000];
+    let config = SimConfig {
+      autopull: true,
+      ..SimConfig::default()
+    };
+    let mut sim = start(&program, config);
+
+    sim.put(
+      0,
+      pio_encode_set(SetDestination::Y, 7) as u32
+        | (pio_encode_set(SetDestination::X, 3) as u32) << 16,
+    );
+    sim.run(5);
+
+    assert_eq!((sim.x(0), sim.y(0)), (3, 7));
+  }
+
+  #[test]
+  fn forces_instructions() {
+    let mut sim = PioSim::new();
+
+    sim.exec(0, pio_encode_set(SetDestination::X, 9));
+    sim.exec(0, pio_encode_jmp(5));
+    assert_eq!((sim.x(0), sim.pc(0)), (9, 5));
+
+    // A stalled pull completes once the state machine runs
+    sim.exec(0, pio_encode_pull(false, true));
+    sim.put(0, 42);
+    sim.set_enabled(0, true);
+    sim.step();
+    assert_eq!((sim.osr(0), sim.pc(0)), (42, 5));
+  }
+
+  #[test]
+  fn decodes_registers() {
+    let config = SimConfig::from_registers(
+      3 << 16 | 64 << 8,
+      1 << 30 | 1 << 29 | 9 << 24 | 20 << 12 | 10 << 7 | 1 << 4 | 2,
+      1 << 30 | 8 << 25 | 16 << 20 | 1 << 18 | 1 << 17,
+      3 << 29 | 2 << 26 | 8 << 20 | 4 << 15 | 6 << 10 | 5 << 5 | 1,
+    );
+
+    assert_eq!(
+      config,
+      SimConfig {
+        clkdiv_int: 3,
+        clkdiv_frac: 64,
+        wrap_target: 10,
+        wrap: 20,
+        side_set: SideSet::new(2, true, true),
+        side_set_base: 6,
+        out_base: 1,
+        out_count: 8,
+        set_base: 5,
+        set_count: 2,
+        in_base: 4,
+        jmp_pin: 9,
+        in_shift_right: true,
+        autopush: false,
+        push_threshold: 16,
+        out_shift_right: false,
+        autopull: true,
+        pull_threshold: 8,
+        fifo_join: FifoJoin::Tx,
+        status_rx: true,
+        status_level: 2,
+      }
+    );
+    assert_eq!(
+      SimConfig::from_registers(1 << 16, 31 << 12, 3 << 18, 0),
+      SimConfig::default()
+    );
+  }
+}
@@ -0,0 +1,365 @@
+//! ADC bindings
+//!
+//! [`Adc`] owns the ADC. Its inputs are GPIO26–29, which must be put into
+//! [`Analog`] mode first, and the internal [`TemperatureSensor`].
+//!
+//! ```ignore
+//! let pins = Pins::take().unwrap();
+//! let input = pins.gpio26.into_analog();
+//! let mut adc = Adc::new().unwrap();
+//! let sensor = adc.enable_temperature_sensor();
+//!
+//! println!("{} V", adc.read_volts(&input));
+//! println!("{} °C", adc.read_temperature(&sensor));
+//! ```
+//!
+//! Besides one-shot conversions, the ADC can sample continuously into its
+//! 4 entry FIFO, raise [ADC_IRQ_FIFO](pico_sdk::ADC_IRQ_FIFO) when the FIFO
+//! reaches a threshold, or stream samples into a buffer with
+//! [`Adc::start_capture`].
+
+use crate::gpio::{Analog, Pin};
+use crate::{claim, pico_sdk};
+use core::ffi::c_void;
+use core::ptr;
+use pico_sdk::adc_hw_t;
+
+pub const ADC_PTR: *mut adc_hw_t = 0x4004c000u32 as _;
+
+/// Resolution of a conversion in bits
+pub const ADC_BITS: u32 = 12;
+
+/// Input connected to the temperature sensor
+const TEMPERATURE_SENSOR_INPUT: u8 = 4;
+
+/// Number of `clk_adc` cycles a conversion takes
+const CYCLES_PER_SAMPLE: u32 = 96;
+
+const ADC_FCS_UNDER_BITS: u32 = 0x400;
+const ADC_FCS_OVER_BITS: u32 = 0x800;
+
+static mut ADC_CLAIMED: bool = false;
+
+/// An ADC input
+pub trait AdcChannel {
+  /// Input index selected with `adc_select_input`
+  fn input(&self) -> u8;
+}
+
+macro_rules! adc_pins {
+  ($($pin:literal => $input:literal),+) => {
+    $(
+      impl<Mode> Pin<$pin, Mode> {
+        /// Connects the pin to the ADC.
+        pub fn into_analog(self) -> Pin<$pin, Analog> {
+          unsafe {
+            pico_sdk::adc_gpio_init(Self::ID);
+            Pin::steal()
+          }
+        }
+      }
+
+      impl AdcChannel for Pin<$pin, Analog> {
+        fn input(&self) -> u8 {
+          $input
+        }
+      }
+    )+
+  };
+}
+
+adc_pins!(26 => 0, 27 => 1, 28 => 2, 29 => 3);
+
+/// The internal temperature sensor
+///
+/// Obtained with [`Adc::enable_temperature_sensor`].
+pub struct TemperatureSensor {
+  _private: (),
+}
+
+impl AdcChannel for TemperatureSensor {
+  fn input(&self) -> u8 {
+    TEMPERATURE_SENSOR_INPUT
+  }
+}
+
+/// Converts a temperature sensor voltage to degrees Celsius.
+pub fn volts_to_celsius(volts: f32) -> f32 {
+  27.0 - (volts - 0.706) / 0.001721
+}
+
+/// The ADC
+pub struct Adc {
+  reference_volts: f32,
+}
+
+impl Adc {
+  /// Resets and claims the ADC.
+  ///
+  /// Returns `None` if it is already in use.
+  pub fn new() -> Option<Self> {
+    if !unsafe { claim::claim(ptr::addr_of_mut!(ADC_CLAIMED)) } {
+      return None;
+    }
+
+    unsafe { pico_sdk::adc_init() };
+
+    Some(Self {
+      reference_volts: 3.3,
+    })
+  }
+
+  /// Stops the ADC and releases it.
+  pub fn free(self) {
+    unsafe {
+      pico_sdk::adc_run(false);
+      pico_sdk::adc_set_temp_sensor_enabled(false);
+      claim::unclaim(ptr::addr_of_mut!(ADC_CLAIMED));
+    }
+  }
+
+  /// Sets the voltage of `ADC_VREF`, used by [`Adc::read_volts`]. Defaults to
+  /// 3.3 V.
+  pub fn set_reference_voltage(&mut self, volts: f32) {
+    self.reference_volts = volts;
+  }
+
+  /// Converts a raw sample to volts.
+  pub fn to_volts(&self, raw: u16) -> f32 {
+    raw as f32 * self.reference_volts / (1 << ADC_BITS) as f32
+  }
+
+  /// Powers the temperature sensor on.
+  pub fn enable_temperature_sensor(&mut self) -> TemperatureSensor {
+    unsafe { pico_sdk::adc_set_temp_sensor_enabled(true) };
+
+    TemperatureSensor { _private: () }
+  }
+
+  /// Powers the temperature sensor off.
+  pub fn disable_temperature_sensor(&mut self, sensor: TemperatureSensor) {
+    let _ = sensor;
+    unsafe { pico_sdk::adc_set_temp_sensor_enabled(false) }
+  }
+
+  /// Selects the input used by the next conversion.
+  pub fn select<C: AdcChannel + ?Sized>(&mut self, channel: &C) {
+    unsafe { pico_sdk::adc_select_input(channel.input() as _) }
+  }
+
+  /// Performs a single conversion of `channel`.
+  pub fn read<C: AdcChannel + ?Sized>(&mut self, channel: &C) -> u16 {
+    self.select(channel);
+    unsafe { pico_sdk::adc_read() }
+  }
+
+  /// Performs a single conversion of `channel` and returns it in volts.
+  pub fn read_volts<C: AdcChannel + ?Sized>(&mut self, channel: &C) -> f32 {
+    let raw = self.read(channel);
+    self.to_volts(raw)
+  }
+
+  /// Measures the chip temperature in degrees Celsius.
+  pub fn read_temperature(&mut self, sensor: &TemperatureSensor) -> f32 {
+    volts_to_celsius(self.read_volts(sensor))
+  }
+
+  /// Samples `channels` in turn during free-running sampling, starting with
+  /// the first one. An empty slice disables round-robin.
+  pub fn set_round_robin(&mut self, channels: &[&dyn AdcChannel]) {
+    let mask = channels
+      .iter()
+      .fold(0, |mask, channel| mask | 1 << channel.input());
+
+    if let Some(first) = channels.first() {
+      self.select(*first);
+    }
+
+    unsafe { pico_sdk::adc_set_round_robin(mask) }
+  }
+
+  /// Sets the rate of free-running sampling and returns the actual rate.
+  ///
+  /// Rates above 500 kS/s run the ADC back-to-back.
+  pub fn set_sample_rate(&mut self, hz: u32) -> u32 {
+    let clk_adc = unsafe { pico_sdk::clock_get_hz(pico_sdk::clk_adc) } as u64;
+    // A sample is taken every `1 + div` cycles, `div` is 16.8 fixed point
+    let div = (clk_adc * 256 / hz.max(1) as u64)
+      .saturating_sub(256)
+      .min(0xff_ffff) as u32;
+    let div = if div < (CYCLES_PER_SAMPLE - 1) << 8 {
+      0
+    } else {
+      div
+    };
+
+    self.set_clkdiv(div);
+
+    if div == 0 {
+      (clk_adc / CYCLES_PER_SAMPLE as u64) as u32
+    } else {
+      (clk_adc * 256 / (div as u64 + 256)) as u32
+    }
+  }
+
+  /// Sets the raw 16.8 fixed point clock divider, see
+  /// [`Adc::set_sample_rate`].
+  pub fn set_clkdiv(&mut self, div: u32) {
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!((*ADC_PTR).div), div) }
+  }
+
+  /// Starts or stops free-running sampling.
+  pub fn run(&mut self, run: bool) {
+    unsafe { pico_sdk::adc_run(run) }
+  }
+
+  /// Writes conversions to the FIFO, asserting the FIFO interrupt and DMA
+  /// request once it holds `threshold` (1 to 4) samples.
+  pub fn enable_fifo(&mut self, threshold: u8) {
+    assert!((1..=4).contains(&threshold), "The ADC FIFO holds 4 samples");
+
+    unsafe { pico_sdk::adc_fifo_setup(true, true, threshold as _, false, false) }
+  }
+
+  /// Stops writing conversions to the FIFO and empties it.
+  pub fn disable_fifo(&mut self) {
+    unsafe {
+      pico_sdk::adc_fifo_setup(false, false, 0, false, false);
+      pico_sdk::adc_fifo_drain();
+    }
+  }
+
+  /// Enables or disables [ADC_IRQ_FIFO](pico_sdk::ADC_IRQ_FIFO).
+  pub fn set_irq_enabled(&mut self, enabled: bool) {
+    unsafe { pico_sdk::adc_irq_set_enabled(enabled) }
+  }
+
+  /// Number of samples in the FIFO
+  pub fn fifo_level(&self) -> u8 {
+    unsafe { pico_sdk::adc_fifo_get_level() }
+  }
+
+  /// Returns `true` if the FIFO overflowed and samples were lost since the
+  /// last call.
+  pub fn take_fifo_overrun(&mut self) -> bool {
+    unsafe {
+      let fcs = ptr::addr_of_mut!((*ADC_PTR).fcs);
+      let value = ptr::read_volatile(fcs);
+
+      // The flag is cleared by writing 1, leave the underflow flag alone
+      ptr::write_volatile(fcs, value & !ADC_FCS_UNDER_BITS);
+
+      value & ADC_FCS_OVER_BITS != 0
+    }
+  }
+
+  /// Pops a sample from the FIFO, `None` if it is empty.
+  pub fn read_fifo(&mut self) -> Option<u16> {
+    unsafe { (!pico_sdk::adc_fifo_is_empty()).then(|| pico_sdk::adc_fifo_get()) }
+  }
+
+  /// Waits for a sample and pops it from the FIFO.
+  pub fn read_fifo_blocking(&mut self) -> u16 {
+    unsafe { pico_sdk::adc_fifo_get_blocking() }
+  }
+
+  /// Discards all samples in the FIFO.
+  pub fn drain_fifo(&mut self) {
+    unsafe { pico_sdk::adc_fifo_drain() }
+  }
+
+  /// Samples continuously into `buffer` with DMA, using the current input,
+  /// round-robin and sample rate settings.
+  ///
+  /// Returns `None` if no DMA channel is available.
+  pub fn start_capture(&mut self, buffer: &'static mut [u16]) -> Option<AdcCapture<'_>> {
+    let channel = unsafe { pico_sdk::dma_claim_unused_channel(false) };
+
+    if channel < 0 {
+      return None;
+    }
+
+    let channel = channel as u32;
+
+    unsafe {
+      self.run(false);
+      self.enable_fifo(1);
+      self.drain_fifo();
+
+      let mut config = pico_sdk::dma_channel_get_default_config(channel);
+      pico_sdk::channel_config_set_transfer_data_size(&mut config, pico_sdk::DMA_SIZE_16);
+      pico_sdk::channel_config_set_read_increment(&mut config, false);
+      pico_sdk::channel_config_set_write_increment(&mut config, true);
+      pico_sdk::channel_config_set_dreq(&mut config, pico_sdk::DREQ_ADC);
+      pico_sdk::dma_channel_configure(
+        channel,
+        &config,
+        buffer.as_mut_ptr() as *mut c_void,
+        ptr::addr_of!((*ADC_PTR).fifo) as *const c_void,
+        buffer.len() as u32,
+        true,
+      );
+    }
+
+    self.run(true);
+
+    Some(AdcCapture {
+      adc: self,
+      buffer,
+      channel,
+      running: true,
+    })
+  }
+
+  /// Fills `buffer` with samples like [`Adc::start_capture`] and waits until
+  /// it is full.
+  pub fn capture(&mut self, buffer: &'static mut [u16]) -> Option<&'static mut [u16]> {
+    Some(self.start_capture(buffer)?.wait())
+  }
+}
+
+/// A running DMA capture started with [`Adc::start_capture`]
+///
+/// Dropping the capture stops it.
+pub struct AdcCapture<'a> {
+  adc: &'a mut Adc,
+  buffer: &'static mut [u16],
+  channel: u32,
+  running: bool,
+}
+
+impl AdcCapture<'_> {
+  /// Returns `true` once the buffer is full.
+  pub fn is_done(&self) -> bool {
+    !unsafe { pico_sdk::dma_channel_is_busy(self.channel) }
+  }
+
+  /// Waits until the buffer is full and returns it.
+  pub fn wait(mut self) -> &'static mut [u16] {
+    unsafe { pico_sdk::dma_channel_wait_for_finish_blocking(self.channel) };
+
+    self.stop();
+
+    core::mem::take(&mut self.buffer)
+  }
+
+  fn stop(&mut self) {
+    self.running = false;
+    self.adc.run(false);
+
+    unsafe {
+      pico_sdk::dma_channel_abort(self.channel);
+      pico_sdk::dma_channel_unclaim(self.channel);
+    }
+
+    self.adc.disable_fifo();
+  }
+}
+
+impl Drop for AdcCapture<'_> {
+  fn drop(&mut self) {
+    if self.running {
+      self.stop();
+    }
+  }
+}
@@ -1,18 +1,122 @@
 use crate::pico_sdk;
 use core::alloc::{GlobalAlloc, Layout};
-use core::ffi;
+use core::{ffi, ptr};
+
+/// Alignment guaranteed by newlib's `malloc`
+const MALLOC_ALIGNMENT: usize = 8;
+
+static mut FAILED_ALLOCATIONS: usize = 0;
+
+/// Heap usage reported by [Allocator::stats]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HeapStats {
+  /// Bytes obtained from the system with `sbrk`
+  pub arena: usize,
+  /// Bytes in use by allocations
+  pub used: usize,
+  /// Bytes available for allocations without growing the arena
+  pub free: usize,
+  /// Number of free chunks
+  pub free_chunks: usize,
+  /// Number of allocations that failed since boot
+  pub failed_allocations: usize,
+}
 
 /// The global allocator type.
+///
+/// Allocations go through newlib's `malloc`. Layouts aligned to more than 8
+/// bytes are served by `memalign`.
 #[derive(Default)]
 pub struct Allocator;
 
+impl Allocator {
+  /// Returns the current heap usage.
+  pub fn stats() -> HeapStats {
+    let info = unsafe { pico_sdk::mallinfo() };
+
+    HeapStats {
+      arena: info.arena,
+      used: info.uordblks,
+      free: info.fordblks,
+      free_chunks: info.ordblks,
+      failed_allocations: with_malloc_lock(|| unsafe { FAILED_ALLOCATIONS }),
+    }
+  }
+
+  /// Returns the number of bytes that can be used through `ptr`, which may be
+  /// more than requested.
+  ///
+  /// # Safety
+  ///
+  /// `ptr` must be allocated by this allocator and not freed.
+  pub unsafe fn usable_size(ptr: *mut u8) -> usize {
+    pico_sdk::malloc_usable_size(ptr as *mut ffi::c_void)
+  }
+}
+
+fn with_malloc_lock<T>(f: impl FnOnce() -> T) -> T {
+  unsafe {
+    let reent = pico_sdk::_impure_ptr;
+    pico_sdk::__malloc_lock(reent);
+    let result = f();
+    pico_sdk::__malloc_unlock(reent);
+
+    result
+  }
+}
+
+fn checked(ptr: *mut ffi::c_void) -> *mut u8 {
+  if ptr.is_null() {
+    with_malloc_lock(|| unsafe { FAILED_ALLOCATIONS += 1 });
+  }
+
+  ptr as *mut u8
+}
+
 unsafe impl GlobalAlloc for Allocator {
   unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-    pico_sdk::malloc(layout.size() as u32) as *mut u8
+    checked(if layout.align() <= MALLOC_ALIGNMENT {
+      pico_sdk::malloc(layout.size() as u32)
+    } else {
+      pico_sdk::memalign(layout.align() as u32, layout.size() as u32)
+    })
+  }
+
+  unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+    if layout.align() <= MALLOC_ALIGNMENT {
+      checked(pico_sdk::calloc(1, layout.size() as u32))
+    } else {
+      let ptr = self.alloc(layout);
+
+      if !ptr.is_null() {
+        ptr::write_bytes(ptr, 0, layout.size());
+      }
+
+      ptr
+    }
   }
+
   unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+    // `free` also releases blocks returned by `memalign`
     pico_sdk::free(ptr as *mut ffi::c_void);
   }
+
+  unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    if layout.align() <= MALLOC_ALIGNMENT {
+      return checked(pico_sdk::realloc(ptr as *mut ffi::c_void, new_size as u32));
+    }
+
+    // `realloc` may move the block to an address with weaker alignment
+    let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+    let new_ptr = self.alloc(new_layout);
+
+    if !new_ptr.is_null() {
+      ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+      self.dealloc(ptr, layout);
+    }
+
+    new_ptr
+  }
 }
 
 /// The static global allocator.
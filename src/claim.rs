@@ -0,0 +1,59 @@
+//! Claiming of peripherals, cooperative for those the SDK doesn't track itself
+
+use crate::pico_sdk;
+use core::ptr;
+
+/// Marks `flag` as claimed.
+///
+/// Returns `false` if it was already claimed. The hardware claim spin lock is
+/// held during the update, so this is safe to call from both cores.
+pub(crate) unsafe fn claim(flag: *mut bool) -> bool {
+  let token = pico_sdk::hw_claim_lock();
+  let claimed = ptr::replace(flag, true);
+  pico_sdk::hw_claim_unlock(token);
+
+  !claimed
+}
+
+/// Releases a flag which was claimed with [claim].
+pub(crate) unsafe fn unclaim(flag: *mut bool) {
+  let token = pico_sdk::hw_claim_lock();
+  ptr::write(flag, false);
+  pico_sdk::hw_claim_unlock(token);
+}
+
+/// Claims index `index` of an SDK claim bitfield.
+///
+/// The SDK panics when claiming an index twice and has no way to test and
+/// claim a given one atomically, so this claims the lowest unused index
+/// through `claim_unused` until it reaches `index`, then releases the lower
+/// ones again through `unclaim`. Each step is atomic, also against C code
+/// using the SDK. Returns `false` if `index` is in use.
+pub(crate) fn claim_index(
+  index: u32,
+  mut claim_unused: impl FnMut() -> i32,
+  mut unclaim: impl FnMut(u32),
+) -> bool {
+  let mut passed = 0u32;
+
+  let claimed = loop {
+    let next = claim_unused();
+
+    if next < 0 {
+      break false;
+    } else if next as u32 > index {
+      unclaim(next as u32);
+      break false;
+    } else if next as u32 == index {
+      break true;
+    }
+
+    passed |= 1 << next;
+  };
+
+  (0..index)
+    .filter(|i| passed & 1 << i != 0)
+    .for_each(unclaim);
+
+  claimed
+}
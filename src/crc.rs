@@ -0,0 +1,104 @@
+//! CRC with the DMA sniffer
+//!
+//! The DMA sniffer observes the data of one channel and computes a CRC-32,
+//! CRC-16-CCITT, parity or sum over it. [`DmaCrc`] runs a transfer from a
+//! buffer to a dummy register with sniffing enabled to compute a [`Crc`]
+//! without the CPU.
+//!
+//! ```ignore
+//! let mut dma = DmaChannel::new().unwrap();
+//!
+//! assert_eq!(DmaCrc::new(Crc::crc32()).compute(&mut dma, b"123456789"), 0xcbf43926);
+//! ```
+//!
+//! [`Crc`] from pico-sdk-util models the sniffer bit for bit and is tested on
+//! the host. It is used whenever the sniffer is busy.
+
+use crate::claim;
+use crate::dma::{DmaChannel, DmaConfig, DmaRegister, DMA_PTR, DREQ_FORCE};
+use core::ptr;
+use pico_sdk_util::Crc;
+
+const SNIFF_CTRL_EN: u32 = 1 << 0;
+const SNIFF_CTRL_DMACH_LSB: u32 = 1;
+const SNIFF_CTRL_CALC_LSB: u32 = 5;
+const SNIFF_CTRL_OUT_REV: u32 = 1 << 10;
+const SNIFF_CTRL_OUT_INV: u32 = 1 << 11;
+
+static mut SNIFFER_CLAIMED: bool = false;
+
+/// Transfers are written here and discarded
+static mut SINK: u8 = 0;
+
+/// A checksum computed by the DMA sniffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaCrc {
+  crc: Crc,
+}
+
+impl DmaCrc {
+  /// Computes `crc` with the sniffer.
+  pub const fn new(crc: Crc) -> Self {
+    Self { crc }
+  }
+
+  /// The checksum computed
+  pub const fn crc(&self) -> &Crc {
+    &self.crc
+  }
+
+  /// Computes the checksum of `data` with the sniffer, using `dma` for the
+  /// transfer.
+  ///
+  /// Falls back to [`Crc::compute`] if the sniffer is in use.
+  pub fn compute(&self, dma: &mut DmaChannel, data: &[u8]) -> u32 {
+    if !unsafe { claim::claim(ptr::addr_of_mut!(SNIFFER_CLAIMED)) } {
+      return self.crc.compute(data);
+    }
+
+    let reversed = self.crc.is_output_reversed();
+    let mut ctrl = SNIFF_CTRL_EN
+      | dma.channel() << SNIFF_CTRL_DMACH_LSB
+      | (self.crc.algorithm() as u32) << SNIFF_CTRL_CALC_LSB;
+
+    if reversed {
+      ctrl |= SNIFF_CTRL_OUT_REV;
+    }
+
+    if self.crc.is_output_inverted() {
+      ctrl |= SNIFF_CTRL_OUT_INV;
+    }
+
+    // The sniffer doesn't care where the data goes
+    let sink = unsafe { DmaRegister::new(ptr::addr_of_mut!(SINK), DREQ_FORCE) };
+
+    let result = unsafe {
+      ptr::write_volatile(ptr::addr_of_mut!((*DMA_PTR).sniff_data), self.crc.start());
+      ptr::write_volatile(ptr::addr_of_mut!((*DMA_PTR).sniff_ctrl), ctrl);
+
+      dma.transfer_blocking(&DmaConfig::new().sniff(true), data, sink);
+
+      let result = ptr::read_volatile(ptr::addr_of!((*DMA_PTR).sniff_data));
+      ptr::write_volatile(ptr::addr_of_mut!((*DMA_PTR).sniff_ctrl), 0);
+      claim::unclaim(ptr::addr_of_mut!(SNIFFER_CLAIMED));
+
+      result
+    };
+
+    // The sniffer reverses and inverts all 32 bits as they are read, which
+    // moves a reversed 16 bit result into the top half
+    let shift = 32 - self.crc.algorithm().width();
+
+    if reversed {
+      result >> shift
+    } else {
+      result & u32::MAX >> shift
+    }
+  }
+}
+
+impl From<Crc> for DmaCrc {
+  fn from(crc: Crc) -> Self {
+    Self::new(crc)
+  }
+}
@@ -0,0 +1,848 @@
+//! DMA bindings
+//!
+//! A [`DmaChannel`] owns one of the 12 DMA channels. Transfers are described
+//! by a [`DmaConfig`] and move words between a [`DmaRead`] source and a
+//! [`DmaWrite`] destination: slices of memory, or peripheral registers such as
+//! [`DmaRegister::uart_tx`], which also select the DREQ pacing the transfer.
+//!
+//! ```ignore
+//! static mut MESSAGE: [u8; 5] = *b"hello";
+//!
+//! let mut dma = DmaChannel::new().unwrap();
+//! let message = unsafe { &*ptr::addr_of!(MESSAGE) };
+//! let transfer = dma.start(&DmaConfig::new(), message, DmaRegister::uart_tx(0));
+//!
+//! transfer.finish();
+//! ```
+//!
+//! Transfers started with [`DmaChannel::start`] own their buffers, which
+//! must be `'static`. Borrowed buffers can be used within
+//! [`DmaChannel::scope`], which stops the transfer before returning, so DMA
+//! can't outlive the memory it uses.
+
+use crate::claim;
+use crate::pico_sdk;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+use pico_sdk::{dma_channel_hw_t, dma_hw_t};
+
+pub const DMA_PTR: *mut dma_hw_t = 0x50000000u32 as _;
+
+/// Number of DMA channels
+pub const NUM_DMA_CHANNELS: u32 = 12;
+
+/// DREQ of DMA timer 0, the others follow
+pub const DREQ_DMA_TIMER0: u32 = 0x3b;
+
+/// Transfers as fast as possible, without pacing
+pub const DREQ_FORCE: u32 = 0x3f;
+
+const CTRL_EN: u32 = 1 << 0;
+const CTRL_HIGH_PRIORITY: u32 = 1 << 1;
+const CTRL_DATA_SIZE_LSB: u32 = 2;
+const CTRL_INCR_READ: u32 = 1 << 4;
+const CTRL_INCR_WRITE: u32 = 1 << 5;
+const CTRL_RING_SIZE_LSB: u32 = 6;
+const CTRL_RING_SEL: u32 = 1 << 10;
+const CTRL_CHAIN_TO_LSB: u32 = 11;
+const CTRL_TREQ_SEL_LSB: u32 = 15;
+const CTRL_IRQ_QUIET: u32 = 1 << 21;
+const CTRL_BSWAP: u32 = 1 << 22;
+const CTRL_SNIFF_EN: u32 = 1 << 23;
+const CTRL_BUSY: u32 = 1 << 24;
+
+const SPI0_BASE: u32 = 0x4003c000;
+const SPI1_BASE: u32 = 0x40040000;
+const SPI_SSPDR_OFFSET: u32 = 0x08;
+const UART0_BASE: u32 = 0x40034000;
+const UART1_BASE: u32 = 0x40038000;
+const I2C0_BASE: u32 = 0x40044000;
+const I2C1_BASE: u32 = 0x40048000;
+const I2C_DATA_CMD_OFFSET: u32 = 0x10;
+const ADC_FIFO: u32 = 0x4004c00c;
+const PWM_CC: u32 = 0x4005000c;
+const PWM_SLICE_STRIDE: u32 = 0x14;
+
+/// Size of the words moved by a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum DataSize {
+  Byte = pico_sdk::DMA_SIZE_8,
+  HalfWord = pico_sdk::DMA_SIZE_16,
+  Word = pico_sdk::DMA_SIZE_32,
+}
+
+/// A type DMA can move in one transfer
+///
+/// # Safety
+///
+/// `SIZE` must match the size of the type, and any bit pattern must be valid.
+pub unsafe trait DmaWord: Copy {
+  const SIZE: DataSize;
+}
+
+unsafe impl DmaWord for u8 {
+  const SIZE: DataSize = DataSize::Byte;
+}
+
+unsafe impl DmaWord for i8 {
+  const SIZE: DataSize = DataSize::Byte;
+}
+
+unsafe impl DmaWord for u16 {
+  const SIZE: DataSize = DataSize::HalfWord;
+}
+
+unsafe impl DmaWord for i16 {
+  const SIZE: DataSize = DataSize::HalfWord;
+}
+
+unsafe impl DmaWord for u32 {
+  const SIZE: DataSize = DataSize::Word;
+}
+
+unsafe impl DmaWord for i32 {
+  const SIZE: DataSize = DataSize::Word;
+}
+
+/// Address, length and pacing of one side of a transfer
+#[derive(Debug, Clone, Copy)]
+pub struct DmaEndpoint {
+  addr: u32,
+  len: Option<u32>,
+  dreq: Option<u32>,
+}
+
+impl DmaEndpoint {
+  /// `len` words of memory at `addr`, which advance after each transfer
+  pub const fn memory(addr: u32, len: u32) -> Self {
+    Self {
+      addr,
+      len: Some(len),
+      dreq: None,
+    }
+  }
+
+  /// A peripheral register at `addr`, paced by `dreq`
+  pub const fn register(addr: u32, dreq: u32) -> Self {
+    Self {
+      addr,
+      len: None,
+      dreq: Some(dreq),
+    }
+  }
+}
+
+/// Source of a transfer
+///
+/// # Safety
+///
+/// The endpoint must stay valid for reads of `Word` while `self` exists and
+/// isn't moved, even if `self` is moved into a [`Transfer`].
+pub unsafe trait DmaRead {
+  type Word: DmaWord;
+
+  fn read_endpoint(&self) -> DmaEndpoint;
+}
+
+/// Destination of a transfer
+///
+/// # Safety
+///
+/// The endpoint must stay valid for writes of `Word` while `self` exists,
+/// even if `self` is moved into a [`Transfer`].
+pub unsafe trait DmaWrite {
+  type Word: DmaWord;
+
+  fn write_endpoint(&mut self) -> DmaEndpoint;
+}
+
+unsafe impl<W: DmaWord> DmaRead for &[W] {
+  type Word = W;
+
+  fn read_endpoint(&self) -> DmaEndpoint {
+    DmaEndpoint::memory(self.as_ptr() as u32, self.len() as u32)
+  }
+}
+
+unsafe impl<W: DmaWord> DmaRead for &mut [W] {
+  type Word = W;
+
+  fn read_endpoint(&self) -> DmaEndpoint {
+    DmaEndpoint::memory(self.as_ptr() as u32, self.len() as u32)
+  }
+}
+
+unsafe impl<W: DmaWord, const N: usize> DmaRead for &[W; N] {
+  type Word = W;
+
+  fn read_endpoint(&self) -> DmaEndpoint {
+    DmaEndpoint::memory(self.as_ptr() as u32, N as u32)
+  }
+}
+
+unsafe impl<W: DmaWord, const N: usize> DmaRead for &mut [W; N] {
+  type Word = W;
+
+  fn read_endpoint(&self) -> DmaEndpoint {
+    DmaEndpoint::memory(self.as_ptr() as u32, N as u32)
+  }
+}
+
+unsafe impl<W: DmaWord> DmaWrite for &mut [W] {
+  type Word = W;
+
+  fn write_endpoint(&mut self) -> DmaEndpoint {
+    DmaEndpoint::memory(self.as_mut_ptr() as u32, self.len() as u32)
+  }
+}
+
+unsafe impl<W: DmaWord, const N: usize> DmaWrite for &mut [W; N] {
+  type Word = W;
+
+  fn write_endpoint(&mut self) -> DmaEndpoint {
+    DmaEndpoint::memory(self.as_mut_ptr() as u32, N as u32)
+  }
+}
+
+/// A peripheral data register, accessed with words of type `W`
+#[derive(Debug)]
+pub struct DmaRegister<W> {
+  addr: u32,
+  dreq: u32,
+  _word: PhantomData<W>,
+}
+
+impl<W: DmaWord> DmaRegister<W> {
+  /// Register at `addr`, paced by `dreq`.
+  ///
+  /// # Safety
+  ///
+  /// `addr` must be a register that DMA can access with words of type `W`.
+  pub unsafe fn new(addr: *mut W, dreq: u32) -> Self {
+    Self {
+      addr: addr as u32,
+      dreq,
+      _word: PhantomData,
+    }
+  }
+
+  const fn at(addr: u32, dreq: u32) -> Self {
+    Self {
+      addr,
+      dreq,
+      _word: PhantomData,
+    }
+  }
+
+  /// Data register of SPI `index`, paced by its TX FIFO
+  pub const fn spi_tx(index: u8) -> Self {
+    Self::at(
+      [SPI0_BASE, SPI1_BASE][index as usize] + SPI_SSPDR_OFFSET,
+      pico_sdk::DREQ_SPI0_TX + 2 * index as u32,
+    )
+  }
+
+  /// Data register of SPI `index`, paced by its RX FIFO
+  pub const fn spi_rx(index: u8) -> Self {
+    Self::at(
+      [SPI0_BASE, SPI1_BASE][index as usize] + SPI_SSPDR_OFFSET,
+      pico_sdk::DREQ_SPI0_RX + 2 * index as u32,
+    )
+  }
+
+  /// Data register of UART `index`, paced by its TX FIFO
+  pub const fn uart_tx(index: u8) -> Self {
+    Self::at(
+      [UART0_BASE, UART1_BASE][index as usize],
+      pico_sdk::DREQ_UART0_TX + 2 * index as u32,
+    )
+  }
+
+  /// Data register of UART `index`, paced by its RX FIFO
+  pub const fn uart_rx(index: u8) -> Self {
+    Self::at(
+      [UART0_BASE, UART1_BASE][index as usize],
+      pico_sdk::DREQ_UART0_RX + 2 * index as u32,
+    )
+  }
+
+  /// Data and command register of I2C `index`, paced by its TX FIFO
+  pub const fn i2c_tx(index: u8) -> Self {
+    Self::at(
+      [I2C0_BASE, I2C1_BASE][index as usize] + I2C_DATA_CMD_OFFSET,
+      pico_sdk::DREQ_I2C0_TX + 2 * index as u32,
+    )
+  }
+
+  /// Data and command register of I2C `index`, paced by its RX FIFO
+  pub const fn i2c_rx(index: u8) -> Self {
+    Self::at(
+      [I2C0_BASE, I2C1_BASE][index as usize] + I2C_DATA_CMD_OFFSET,
+      pico_sdk::DREQ_I2C0_RX + 2 * index as u32,
+    )
+  }
+
+  /// Result FIFO of the ADC, see [`Adc::enable_fifo`](crate::Adc::enable_fifo)
+  pub const fn adc_fifo() -> Self {
+    Self::at(ADC_FIFO, pico_sdk::DREQ_ADC)
+  }
+
+  /// Compare register of PWM `slice`, paced by the counter wrapping
+  pub const fn pwm_cc(slice: u8) -> Self {
+    assert!(
+      slice < crate::NUM_PWM_SLICES,
+      "RP2040 only has 8 PWM slices"
+    );
+
+    Self::at(
+      PWM_CC + slice as u32 * PWM_SLICE_STRIDE,
+      pico_sdk::DREQ_PWM_WRAP0 + slice as u32,
+    )
+  }
+}
+
+unsafe impl<W: DmaWord> DmaRead for DmaRegister<W> {
+  type Word = W;
+
+  fn read_endpoint(&self) -> DmaEndpoint {
+    DmaEndpoint::register(self.addr, self.dreq)
+  }
+}
+
+unsafe impl<W: DmaWord> DmaWrite for DmaRegister<W> {
+  type Word = W;
+
+  fn write_endpoint(&mut self) -> DmaEndpoint {
+    DmaEndpoint::register(self.addr, self.dreq)
+  }
+}
+
+/// Settings of a transfer
+///
+/// Settings left unset are derived from the source and destination: the data
+/// size from the word type, increments from whether a side is memory, the
+/// DREQ from the peripheral register and the count from the memory length.
+#[derive(Debug, Clone, Copy)]
+pub struct DmaConfig {
+  ctrl: u32,
+  data_size: Option<DataSize>,
+  read_increment: Option<bool>,
+  write_increment: Option<bool>,
+  dreq: Option<u32>,
+  chain_to: Option<u32>,
+  count: Option<u32>,
+  trigger: bool,
+}
+
+impl DmaConfig {
+  /// Default settings, which start the transfer right away.
+  pub const fn new() -> Self {
+    Self {
+      ctrl: CTRL_EN,
+      data_size: None,
+      read_increment: None,
+      write_increment: None,
+      dreq: None,
+      chain_to: None,
+      count: None,
+      trigger: true,
+    }
+  }
+
+  const fn with_flag(mut self, flag: u32, enabled: bool) -> Self {
+    if enabled {
+      self.ctrl |= flag;
+    } else {
+      self.ctrl &= !flag;
+    }
+
+    self
+  }
+
+  /// Sets the size of each word.
+  pub const fn data_size(mut self, size: DataSize) -> Self {
+    self.data_size = Some(size);
+    self
+  }
+
+  /// Advances the read address after each word.
+  pub const fn read_increment(mut self, increment: bool) -> Self {
+    self.read_increment = Some(increment);
+    self
+  }
+
+  /// Advances the write address after each word.
+  pub const fn write_increment(mut self, increment: bool) -> Self {
+    self.write_increment = Some(increment);
+    self
+  }
+
+  /// Paces the transfer with `dreq`, such as
+  /// [DREQ_PIO0_TX0](pico_sdk::DREQ_PIO0_TX0), [`DmaTimer::dreq`] or
+  /// [`DREQ_FORCE`].
+  pub const fn dreq(mut self, dreq: u32) -> Self {
+    assert!(dreq <= DREQ_FORCE, "invalid DREQ");
+
+    self.dreq = Some(dreq);
+    self
+  }
+
+  /// Wraps the read address on a `1 << size_bits` byte boundary; the buffer
+  /// must be aligned to its size.
+  pub const fn ring_read(self, size_bits: u8) -> Self {
+    self.ring(size_bits, false)
+  }
+
+  /// Wraps the write address on a `1 << size_bits` byte boundary; the buffer
+  /// must be aligned to its size.
+  pub const fn ring_write(self, size_bits: u8) -> Self {
+    self.ring(size_bits, true)
+  }
+
+  const fn ring(mut self, size_bits: u8, write: bool) -> Self {
+    assert!(
+      size_bits > 0 && size_bits < 16,
+      "ring size must be 1 to 15 bits"
+    );
+
+    self.ctrl &= !(0xf << CTRL_RING_SIZE_LSB);
+    self.ctrl |= (size_bits as u32) << CTRL_RING_SIZE_LSB;
+    self.with_flag(CTRL_RING_SEL, write)
+  }
+
+  /// Triggers channel `channel` when the transfer completes.
+  pub const fn chain_to(mut self, channel: u32) -> Self {
+    assert!(
+      channel < NUM_DMA_CHANNELS,
+      "RP2040 only has 12 DMA channels"
+    );
+
+    self.chain_to = Some(channel);
+    self
+  }
+
+  /// Reverses the byte order of each half word or word.
+  pub const fn byte_swap(self, enabled: bool) -> Self {
+    self.with_flag(CTRL_BSWAP, enabled)
+  }
+
+  /// Feeds the transferred data to the sniffer.
+  pub const fn sniff(self, enabled: bool) -> Self {
+    self.with_flag(CTRL_SNIFF_EN, enabled)
+  }
+
+  /// Gives the channel priority over channels without it.
+  pub const fn high_priority(self, enabled: bool) -> Self {
+    self.with_flag(CTRL_HIGH_PRIORITY, enabled)
+  }
+
+  /// Only raises the interrupt when a null trigger is written, for control
+  /// block chains.
+  pub const fn irq_quiet(self, enabled: bool) -> Self {
+    self.with_flag(CTRL_IRQ_QUIET, enabled)
+  }
+
+  /// Number of words to transfer, needed if neither side is memory.
+  pub const fn count(mut self, count: u32) -> Self {
+    self.count = Some(count);
+    self
+  }
+
+  /// Only configures the channel if `false`, so it can be started by
+  /// [`Transfer::trigger`] or by a channel chaining to it.
+  pub const fn trigger(mut self, trigger: bool) -> Self {
+    self.trigger = trigger;
+    self
+  }
+
+  /// Control register value for `channel`, using the defaults of the SDK for
+  /// unset settings.
+  pub const fn ctrl(&self, channel: u32) -> u32 {
+    let data_size = match self.data_size {
+      Some(size) => size,
+      None => DataSize::Word,
+    };
+    let read_increment = match self.read_increment {
+      Some(increment) => increment,
+      None => true,
+    };
+    let write_increment = match self.write_increment {
+      Some(increment) => increment,
+      None => false,
+    };
+    let dreq = match self.dreq {
+      Some(dreq) => dreq,
+      None => DREQ_FORCE,
+    };
+    let chain_to = match self.chain_to {
+      Some(chain_to) => chain_to,
+      None => channel,
+    };
+
+    self.ctrl
+      | (data_size as u32) << CTRL_DATA_SIZE_LSB
+      | if read_increment { CTRL_INCR_READ } else { 0 }
+      | if write_increment { CTRL_INCR_WRITE } else { 0 }
+      | chain_to << CTRL_CHAIN_TO_LSB
+      | dreq << CTRL_TREQ_SEL_LSB
+  }
+
+  /// Fills in the unset settings from a source and destination of `size`
+  /// words.
+  fn resolve(mut self, size: DataSize, read: DmaEndpoint, write: DmaEndpoint) -> (Self, u32) {
+    assert!(
+      self.data_size.is_none() || self.data_size == Some(size),
+      "data size doesn't match the word type"
+    );
+
+    self.data_size = Some(size);
+    self.read_increment = self.read_increment.or(Some(read.len.is_some()));
+    self.write_increment = self.write_increment.or(Some(write.len.is_some()));
+    self.dreq = self.dreq.or(write.dreq).or(read.dreq);
+
+    let count = match (self.count, read.len, write.len) {
+      (Some(count), ..) => count,
+      (None, Some(read), Some(write)) => read.min(write),
+      (None, Some(len), None) | (None, None, Some(len)) => len,
+      (None, None, None) => panic!("transfer count needed between registers"),
+    };
+
+    (self, count)
+  }
+}
+
+impl Default for DmaConfig {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// A claimed DMA channel
+pub struct DmaChannel {
+  channel: u32,
+}
+
+impl DmaChannel {
+  /// Claims an unused channel.
+  ///
+  /// Returns `None` if all channels are in use.
+  pub fn new() -> Option<Self> {
+    let channel = unsafe { pico_sdk::dma_claim_unused_channel(false) };
+
+    if channel < 0 {
+      return None;
+    }
+
+    Some(Self {
+      channel: channel as u32,
+    })
+  }
+
+  /// Claims channel `channel`.
+  ///
+  /// Returns `None` if it is already in use.
+  pub fn with_channel(channel: u32) -> Option<Self> {
+    assert!(
+      channel < NUM_DMA_CHANNELS,
+      "RP2040 only has 12 DMA channels"
+    );
+
+    let claimed = claim::claim_index(
+      channel,
+      || unsafe { pico_sdk::dma_claim_unused_channel(false) },
+      |index| unsafe { pico_sdk::dma_channel_unclaim(index) },
+    );
+
+    claimed.then_some(Self { channel })
+  }
+
+  /// Number of the channel
+  pub fn channel(&self) -> u32 {
+    self.channel
+  }
+
+  /// Returns the registers of the channel.
+  pub fn hw(&self) -> *mut dma_channel_hw_t {
+    unsafe { ptr::addr_of_mut!((*DMA_PTR).ch[self.channel as usize]) }
+  }
+
+  /// Returns `true` while a transfer is running.
+  pub fn is_busy(&self) -> bool {
+    unsafe { ptr::read_volatile(ptr::addr_of!((*self.hw()).al1_ctrl)) & CTRL_BUSY != 0 }
+  }
+
+  /// Number of words left in the current transfer
+  pub fn remaining(&self) -> u32 {
+    unsafe { ptr::read_volatile(ptr::addr_of!((*self.hw()).transfer_count)) }
+  }
+
+  /// Stops the current transfer and waits until it has stopped.
+  pub fn abort(&mut self) {
+    let mask = 1 << self.channel;
+
+    // RP2040-E13: aborting raises the completion interrupt, so it is masked
+    // during the abort and cleared before being unmasked again
+    let enabled = unsafe {
+      [
+        ptr::read_volatile(ptr::addr_of!((*DMA_PTR).inte0)) & mask != 0,
+        ptr::read_volatile(ptr::addr_of!((*DMA_PTR).inte1)) & mask != 0,
+      ]
+    };
+    self.set_irq_enabled(0, false);
+    self.set_irq_enabled(1, false);
+
+    unsafe {
+      ptr::write_volatile(ptr::addr_of_mut!((*DMA_PTR).abort), mask);
+
+      while ptr::read_volatile(ptr::addr_of!((*DMA_PTR).abort)) & mask != 0 {}
+    }
+
+    self.clear_irq();
+    self.set_irq_enabled(0, enabled[0]);
+    self.set_irq_enabled(1, enabled[1]);
+
+    compiler_fence(Ordering::SeqCst);
+  }
+
+  /// Waits until the current transfer completes.
+  pub fn wait(&self) {
+    while self.is_busy() {}
+
+    compiler_fence(Ordering::SeqCst);
+  }
+
+  /// Triggers the channel with its current configuration.
+  pub fn trigger(&mut self) {
+    compiler_fence(Ordering::SeqCst);
+
+    unsafe {
+      ptr::write_volatile(
+        ptr::addr_of_mut!((*DMA_PTR).multi_channel_trigger),
+        1 << self.channel,
+      )
+    }
+  }
+
+  /// Routes the completion interrupt of the channel to `DMA_IRQ_0` or
+  /// `DMA_IRQ_1`.
+  pub fn set_irq_enabled(&mut self, irq: u8, enabled: bool) {
+    let inte = match irq {
+      0 => unsafe { ptr::addr_of_mut!((*DMA_PTR).inte0) },
+      1 => unsafe { ptr::addr_of_mut!((*DMA_PTR).inte1) },
+      _ => panic!("DMA only has 2 IRQs"),
+    };
+
+    unsafe {
+      let mask = ptr::read_volatile(inte) & !(1 << self.channel);
+      ptr::write_volatile(inte, mask | (enabled as u32) << self.channel);
+    }
+  }
+
+  /// Returns `true` if the channel completed a transfer since the interrupt
+  /// was last cleared.
+  pub fn is_irq_pending(&self) -> bool {
+    unsafe { ptr::read_volatile(ptr::addr_of!((*DMA_PTR).intr)) & (1 << self.channel) != 0 }
+  }
+
+  /// Clears the interrupt of the channel.
+  pub fn clear_irq(&mut self) {
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!((*DMA_PTR).intr), 1 << self.channel) }
+  }
+
+  /// Configures the channel and starts it unless `config` disables the
+  /// trigger.
+  ///
+  /// The data size and increments are taken from `config`, and it must set
+  /// the count.
+  ///
+  /// # Safety
+  ///
+  /// The addresses must stay valid for the whole transfer.
+  pub unsafe fn start_raw(&mut self, config: &DmaConfig, write_addr: u32, read_addr: u32) {
+    let count = config.count.expect("transfer count not set");
+    let hw = self.hw();
+
+    compiler_fence(Ordering::SeqCst);
+
+    ptr::write_volatile(ptr::addr_of_mut!((*hw).read_addr), read_addr);
+    ptr::write_volatile(ptr::addr_of_mut!((*hw).write_addr), write_addr);
+    ptr::write_volatile(ptr::addr_of_mut!((*hw).transfer_count), count);
+
+    if config.trigger {
+      ptr::write_volatile(
+        ptr::addr_of_mut!((*hw).ctrl_trig),
+        config.ctrl(self.channel),
+      );
+    } else {
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).al1_ctrl), config.ctrl(self.channel));
+    }
+  }
+
+  fn configure<R, W>(&mut self, config: &DmaConfig, from: &R, to: &mut W)
+  where
+    R: DmaRead,
+    W: DmaWrite<Word = R::Word>,
+  {
+    let read = from.read_endpoint();
+    let write = to.write_endpoint();
+    let (config, count) = config.resolve(R::Word::SIZE, read, write);
+
+    unsafe { self.start_raw(&config.count(count), write.addr, read.addr) }
+  }
+
+  /// Starts a transfer from `from` to `to`, which it owns until it is
+  /// complete.
+  pub fn start<R, W>(&mut self, config: &DmaConfig, from: R, mut to: W) -> Transfer<'_, R, W>
+  where
+    R: DmaRead + 'static,
+    W: DmaWrite<Word = R::Word> + 'static,
+  {
+    self.configure(config, &from, &mut to);
+
+    Transfer {
+      channel: self,
+      buffers: Some((from, to)),
+    }
+  }
+
+  /// Starts a transfer from borrowed buffers and passes it to `f`.
+  ///
+  /// The transfer is aborted if it is still running when `f` returns.
+  pub fn scope<R, W, T>(
+    &mut self,
+    config: &DmaConfig,
+    from: R,
+    mut to: W,
+    f: impl FnOnce(&mut Transfer<'_, R, W>) -> T,
+  ) -> T
+  where
+    R: DmaRead,
+    W: DmaWrite<Word = R::Word>,
+  {
+    self.configure(config, &from, &mut to);
+
+    let mut transfer = Transfer {
+      channel: self,
+      buffers: Some((from, to)),
+    };
+
+    f(&mut transfer)
+  }
+
+  /// Transfers from `from` to `to` and waits until it is complete.
+  pub fn transfer_blocking<R, W>(&mut self, config: &DmaConfig, from: R, to: W)
+  where
+    R: DmaRead,
+    W: DmaWrite<Word = R::Word>,
+  {
+    self.scope(&config.trigger(true), from, to, |transfer| transfer.wait());
+  }
+}
+
+impl Drop for DmaChannel {
+  fn drop(&mut self) {
+    self.abort();
+    self.set_irq_enabled(0, false);
+    self.set_irq_enabled(1, false);
+
+    unsafe { pico_sdk::dma_channel_unclaim(self.channel) }
+  }
+}
+
+/// A transfer started by [`DmaChannel::start`] or [`DmaChannel::scope`]
+///
+/// Dropping the transfer aborts it.
+pub struct Transfer<'a, R, W> {
+  channel: &'a mut DmaChannel,
+  buffers: Option<(R, W)>,
+}
+
+impl<R, W> Transfer<'_, R, W> {
+  /// Returns `true` once all words were transferred.
+  pub fn is_done(&self) -> bool {
+    !self.channel.is_busy()
+  }
+
+  /// Number of words left
+  pub fn remaining(&self) -> u32 {
+    self.channel.remaining()
+  }
+
+  /// Starts a transfer configured without trigger.
+  pub fn trigger(&mut self) {
+    self.channel.trigger();
+  }
+
+  /// Waits until the transfer completes.
+  pub fn wait(&mut self) {
+    self.channel.wait();
+  }
+
+  /// Stops the transfer.
+  pub fn abort(&mut self) {
+    self.channel.abort();
+  }
+
+  /// Waits until the transfer completes and returns the buffers.
+  pub fn finish(mut self) -> (R, W) {
+    self.channel.wait();
+
+    self.buffers.take().unwrap()
+  }
+}
+
+impl<R, W> Drop for Transfer<'_, R, W> {
+  fn drop(&mut self) {
+    if self.buffers.is_some() {
+      self.channel.abort();
+    }
+  }
+}
+
+/// One of the 4 DMA pacing timers
+pub struct DmaTimer {
+  timer: u32,
+}
+
+impl DmaTimer {
+  /// Claims an unused timer.
+  ///
+  /// Returns `None` if all timers are in use.
+  pub fn new() -> Option<Self> {
+    let timer = unsafe { pico_sdk::dma_claim_unused_timer(false) };
+
+    if timer < 0 {
+      return None;
+    }
+
+    Some(Self {
+      timer: timer as u32,
+    })
+  }
+
+  /// Makes the timer request a transfer `numerator / denominator` times per
+  /// `clk_sys` cycle.
+  pub fn set_fraction(&mut self, numerator: u16, denominator: u16) {
+    let value = (numerator as u32) << 16 | denominator as u32;
+
+    unsafe {
+      ptr::write_volatile(
+        ptr::addr_of_mut!((*DMA_PTR).timer[self.timer as usize]),
+        value,
+      )
+    }
+  }
+
+  /// DREQ of the timer, see [`DmaConfig::dreq`]
+  pub fn dreq(&self) -> u32 {
+    DREQ_DMA_TIMER0 + self.timer
+  }
+}
+
+impl Drop for DmaTimer {
+  fn drop(&mut self) {
+    unsafe { pico_sdk::dma_timer_unclaim(self.timer) }
+  }
+}
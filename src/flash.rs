@@ -0,0 +1,277 @@
+//! Flash programming
+//!
+//! [`Flash`] erases and programs the external QSPI flash, which is also where
+//! the firmware executes from. While the flash is being written it can't be
+//! read through XIP, so every operation runs with interrupts disabled and
+//! the other core paused through [`lockout_other_core`]. If the other core is
+//! running, it must have called [`enable_lockout_victim`], or else writes fail
+//! with [`FlashError::OtherCoreRunning`].
+//!
+//! Offsets are relative to the start of flash. Erasing works on
+//! [FLASH_SECTOR_SIZE](pico_sdk::FLASH_SECTOR_SIZE) sectors and programming
+//! on [FLASH_PAGE_SIZE](pico_sdk::FLASH_PAGE_SIZE) pages.
+//!
+//! ```ignore
+//! let mut flash = Flash::new().unwrap();
+//! let offset = flash.size() - FLASH_SECTOR_SIZE;
+//!
+//! flash.erase(offset, FLASH_SECTOR_SIZE).unwrap();
+//! flash.program(offset, &[0x42; 256]).unwrap();
+//! assert_eq!(flash.read(offset, 1).unwrap(), [0x42]);
+//! ```
+//!
+//! [`enable_lockout_victim`]: crate::enable_lockout_victim
+
+use crate::multicore::{core1_is_running, lockout_other_core};
+use crate::{claim, pico_sdk};
+use core::{ptr, slice};
+use pico_sdk::{FLASH_PAGE_SIZE, FLASH_SECTOR_SIZE, FLASH_UNIQUE_ID_SIZE_BYTES};
+use pico_sdk_util::KvFlash;
+
+/// Address flash is mapped at through XIP
+const XIP_BASE: u32 = 0x10000000;
+
+/// Serial flash command reading the manufacturer and device ID
+const FLASH_CMD_READ_JEDEC_ID: u8 = 0x9f;
+
+static mut FLASH_CLAIMED: bool = false;
+
+extern "C" {
+  /// End of the firmware image, provided by the SDK's linker scripts
+  static __flash_binary_end: u8;
+}
+
+/// Errors reported by [`Flash`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashError {
+  /// The offset or length isn't a multiple of the sector or page size
+  Unaligned,
+  /// The range doesn't fit into the flash
+  OutOfBounds,
+  /// The other core is running but can't be paused, because it didn't call
+  /// [`enable_lockout_victim`]
+  OtherCoreRunning,
+}
+
+/// Runs `f` with interrupts disabled and the other core paused if it is
+/// running.
+fn with_xip_disabled<R>(f: impl FnOnce() -> R) -> Result<R, FlashError> {
+  let other_core = unsafe { pico_sdk::get_core_num() } ^ 1;
+  let run = || unsafe {
+    let saved_irq = pico_sdk::save_and_disable_interrupts();
+    let result = f();
+    pico_sdk::restore_interrupts(saved_irq);
+
+    result
+  };
+
+  if other_core == 1 && !core1_is_running() {
+    Ok(run())
+  } else if unsafe { pico_sdk::multicore_lockout_victim_is_initialized(other_core) } {
+    Ok(lockout_other_core(run))
+  } else {
+    Err(FlashError::OtherCoreRunning)
+  }
+}
+
+/// The onboard flash
+pub struct Flash {
+  _private: (),
+}
+
+impl Flash {
+  /// Claims the flash.
+  ///
+  /// Returns `None` if it is already in use.
+  pub fn new() -> Option<Self> {
+    if !unsafe { claim::claim(ptr::addr_of_mut!(FLASH_CLAIMED)) } {
+      return None;
+    }
+
+    Some(Self { _private: () })
+  }
+
+  /// Releases the flash.
+  pub fn free(self) {
+    unsafe { claim::unclaim(ptr::addr_of_mut!(FLASH_CLAIMED)) }
+  }
+
+  /// Size of the flash in bytes, as configured by the board
+  pub fn size(&self) -> u32 {
+    pico_sdk::PICO_FLASH_SIZE_BYTES
+  }
+
+  /// Offset of the first byte after the firmware
+  ///
+  /// Everything from the first sector at or after this offset is free for
+  /// data.
+  pub fn firmware_end(&self) -> u32 {
+    ptr::addr_of!(__flash_binary_end) as u32 - XIP_BASE
+  }
+
+  fn check(&self, offset: u32, len: usize, align: u32) -> Result<(), FlashError> {
+    if offset % align != 0 || len as u32 % align != 0 {
+      return Err(FlashError::Unaligned);
+    }
+
+    match offset.checked_add(len as u32) {
+      Some(end) if end <= self.size() => Ok(()),
+      _ => Err(FlashError::OutOfBounds),
+    }
+  }
+
+  /// Returns `len` bytes at `offset` through XIP.
+  pub fn read(&self, offset: u32, len: usize) -> Result<&[u8], FlashError> {
+    self.check(offset, len, 1)?;
+
+    Ok(unsafe { slice::from_raw_parts((XIP_BASE + offset) as *const u8, len) })
+  }
+
+  /// Erases `len` bytes at `offset` to `0xff`.
+  ///
+  /// Both must be multiples of the sector size.
+  pub fn erase(&mut self, offset: u32, len: u32) -> Result<(), FlashError> {
+    self.check(offset, len as usize, FLASH_SECTOR_SIZE)?;
+
+    with_xip_disabled(|| unsafe { pico_sdk::flash_range_erase(offset, len as usize) })
+  }
+
+  /// Erases sector `index`.
+  pub fn erase_sector(&mut self, index: u32) -> Result<(), FlashError> {
+    self.erase(index * FLASH_SECTOR_SIZE, FLASH_SECTOR_SIZE)
+  }
+
+  /// Programs `data` at `offset`, which must have been erased.
+  ///
+  /// Both must be multiples of the page size. `data` may be located in
+  /// flash, it is copied to RAM one page at a time.
+  pub fn program(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError> {
+    self.check(offset, data.len(), FLASH_PAGE_SIZE)?;
+
+    let mut page = [0; FLASH_PAGE_SIZE as usize];
+
+    for (index, chunk) in data.chunks(page.len()).enumerate() {
+      page.copy_from_slice(chunk);
+
+      let page_offset = offset + index as u32 * FLASH_PAGE_SIZE;
+      with_xip_disabled(|| unsafe {
+        pico_sdk::flash_range_program(page_offset, page.as_ptr(), page.len())
+      })?;
+    }
+
+    Ok(())
+  }
+
+  /// Programs page `index`, which must have been erased.
+  pub fn program_page(
+    &mut self,
+    index: u32,
+    data: &[u8; FLASH_PAGE_SIZE as usize],
+  ) -> Result<(), FlashError> {
+    self.program(index * FLASH_PAGE_SIZE, data)
+  }
+
+  /// Reads the unique 64-bit ID of the flash chip.
+  pub fn unique_id(&mut self) -> Result<[u8; FLASH_UNIQUE_ID_SIZE_BYTES as usize], FlashError> {
+    let mut id = [0; FLASH_UNIQUE_ID_SIZE_BYTES as usize];
+    with_xip_disabled(|| unsafe { pico_sdk::flash_get_unique_id(id.as_mut_ptr()) })?;
+
+    Ok(id)
+  }
+
+  /// Reads the JEDEC manufacturer ID, memory type and capacity of the flash
+  /// chip as `0x00MMTTCC`.
+  pub fn jedec_id(&mut self) -> Result<u32, FlashError> {
+    let tx = [FLASH_CMD_READ_JEDEC_ID, 0, 0, 0];
+    let mut rx = [0; 4];
+    with_xip_disabled(|| unsafe {
+      pico_sdk::flash_do_cmd(tx.as_ptr(), rx.as_mut_ptr(), tx.len())
+    })?;
+
+    Ok(u32::from_be_bytes([0, rx[1], rx[2], rx[3]]))
+  }
+}
+
+/// Sectors of [`Flash`] reserved for a [`KvStore`](crate::KvStore)
+///
+/// Writes may start anywhere: the rest of each page is programmed with
+/// `0xff`, which leaves the bytes already written unchanged.
+pub struct FlashRegion<'a> {
+  flash: &'a mut Flash,
+  offset: u32,
+  sectors: u32,
+}
+
+impl<'a> FlashRegion<'a> {
+  /// Reserves `sectors` sectors starting at `offset`, which must be a
+  /// multiple of the sector size.
+  pub fn new(flash: &'a mut Flash, offset: u32, sectors: u32) -> Result<Self, FlashError> {
+    let len = sectors
+      .checked_mul(FLASH_SECTOR_SIZE)
+      .ok_or(FlashError::OutOfBounds)?;
+    flash.check(offset, len as usize, FLASH_SECTOR_SIZE)?;
+
+    Ok(Self {
+      flash,
+      offset,
+      sectors,
+    })
+  }
+}
+
+impl FlashRegion<'_> {
+  /// Checks that `len` bytes at `offset` lie inside the region.
+  fn check(&self, offset: u32, len: usize) -> Result<(), FlashError> {
+    match offset.checked_add(len as u32) {
+      Some(end) if end <= self.sectors * FLASH_SECTOR_SIZE => Ok(()),
+      _ => Err(FlashError::OutOfBounds),
+    }
+  }
+}
+
+impl KvFlash for FlashRegion<'_> {
+  type Error = FlashError;
+
+  const SECTOR_SIZE: u32 = FLASH_SECTOR_SIZE;
+
+  fn sector_count(&self) -> u32 {
+    self.sectors
+  }
+
+  fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), FlashError> {
+    self.check(offset, buf.len())?;
+    buf.copy_from_slice(self.flash.read(self.offset + offset, buf.len())?);
+
+    Ok(())
+  }
+
+  fn erase(&mut self, index: u32) -> Result<(), FlashError> {
+    if index >= self.sectors {
+      return Err(FlashError::OutOfBounds);
+    }
+
+    self
+      .flash
+      .erase_sector(self.offset / FLASH_SECTOR_SIZE + index)
+  }
+
+  fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError> {
+    self.check(offset, data.len())?;
+
+    let mut address = self.offset + offset;
+    let mut data = data;
+
+    while !data.is_empty() {
+      let start = (address % FLASH_PAGE_SIZE) as usize;
+      let len = data.len().min(FLASH_PAGE_SIZE as usize - start);
+      let mut page = [0xff; FLASH_PAGE_SIZE as usize];
+      page[start..start + len].copy_from_slice(&data[..len]);
+
+      self.flash.program(address - start as u32, &page)?;
+      address += len as u32;
+      data = &data[len..];
+    }
+
+    Ok(())
+  }
+}
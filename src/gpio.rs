@@ -1,91 +1,326 @@
 //! GPIO bindings
+//!
+//! Besides the raw SDK functions, this module provides a typestate [`Pin`] API.
+//! Each of the 30 user GPIOs is represented by a zero-sized `Pin<N, Mode>`
+//! which can only be obtained once through [`Pins::take`].
+//!
+//! ```ignore
+//! let pins = Pins::take().unwrap();
+//! let mut led = pins.gpio25.into_output();
+//!
+//! led.set_high();
+//! ```
 
-use crate::pico_sdk;
-use pico_sdk::gpio_set_pulls;
+use crate::{claim, pico_sdk};
+use core::marker::PhantomData;
 use pico_sdk::sio_hw_t;
 
 pub const SIO_PTR: *mut sio_hw_t = 0xd0000000u32 as _;
 
-/// Set a number of GPIOs to output
-pub unsafe fn gpio_set_dir_out_masked(mask: u32) {
-  (*SIO_PTR).gpio_oe_set = mask;
+/// Number of user GPIOs on RP2040
+pub const NUM_PINS: u8 = 30;
+
+static mut PINS_TAKEN: bool = false;
+
+/// Pin that hasn't been configured through this API yet
+pub struct Disabled;
+
+/// Pin whose input buffer, output driver and pulls are disabled
+pub struct HighZ;
+
+/// Pin driven by SIO as an input
+pub struct Input;
+
+/// Pin driven by SIO as an output
+pub struct Output;
+
+/// Pin connected to the ADC, with its digital input and pulls disabled
+pub struct Analog;
+
+/// Pin connected to a peripheral selected by `F`
+pub struct Function<F: FunctionKind>(PhantomData<F>);
+
+/// A peripheral that can be selected with [`Pin::into_function`]
+pub trait FunctionKind {
+  const FUNCTION: pico_sdk::gpio_function;
 }
 
-/// Set a number of GPIOs to input
-pub unsafe fn gpio_set_dir_in_masked(mask: u32) {
-  (*SIO_PTR).gpio_oe_clr = mask;
+macro_rules! functions {
+  ($($(#[$meta:meta])* $name:ident = $function:ident;)+) => {
+    $(
+      $(#[$meta])*
+      pub struct $name;
+
+      impl FunctionKind for $name {
+        const FUNCTION: pico_sdk::gpio_function = pico_sdk::$function;
+      }
+    )+
+  };
 }
 
-/// Set a single GPIO direction
-pub unsafe fn gpio_set_dir(gpio: u32, out: u32) {
-  let mask = 1u32 << gpio;
+functions! {
+  /// SPI function
+  FunctionSpi = GPIO_FUNC_SPI;
+  /// UART function
+  FunctionUart = GPIO_FUNC_UART;
+  /// I2C function
+  FunctionI2c = GPIO_FUNC_I2C;
+  /// PWM function
+  FunctionPwm = GPIO_FUNC_PWM;
+  /// PIO0 function
+  FunctionPio0 = GPIO_FUNC_PIO0;
+  /// PIO1 function
+  FunctionPio1 = GPIO_FUNC_PIO1;
+  /// Clock input/output function
+  FunctionGpck = GPIO_FUNC_GPCK;
+  /// USB function
+  FunctionUsb = GPIO_FUNC_USB;
+}
 
-  if out != 0 {
-    gpio_set_dir_out_masked(mask);
-  } else {
-    gpio_set_dir_in_masked(mask)
-  }
+/// Pull resistor configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pull {
+  #[default]
+  None,
+  Up,
+  Down,
+  /// Both pulls enabled, which keeps the last driven level
+  BusKeep,
 }
 
-/// Check if a specific GPIO direction is OUT
-pub unsafe fn gpio_is_dir_out(gpio: u32) -> u32 {
-  (*SIO_PTR).gpio_oe & (1 << gpio)
+/// Output slew rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u32)]
+pub enum SlewRate {
+  #[default]
+  Slow = pico_sdk::GPIO_SLEW_RATE_SLOW,
+  Fast = pico_sdk::GPIO_SLEW_RATE_FAST,
 }
 
-/// Get a specific GPIO direction
-///
-/// Returns `1` for out, `0` for in
-pub unsafe fn gpio_get_dir(gpio: u32) -> u32 {
-  gpio_is_dir_out(gpio)
+/// Output drive strength
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u32)]
+pub enum DriveStrength {
+  _2mA = pico_sdk::GPIO_DRIVE_STRENGTH_2MA,
+  #[default]
+  _4mA = pico_sdk::GPIO_DRIVE_STRENGTH_4MA,
+  _8mA = pico_sdk::GPIO_DRIVE_STRENGTH_8MA,
+  _12mA = pico_sdk::GPIO_DRIVE_STRENGTH_12MA,
 }
 
-/// Drive high every GPIO appearing in mask
-pub unsafe fn gpio_set_mask(mask: u32) {
-  (*SIO_PTR).gpio_set = mask;
+/// A single GPIO in `Mode`
+///
+/// Pins are zero-sized and can only be created by [`Pins::take`] or
+/// [`Pin::steal`], so owning one guarantees exclusive access to the GPIO.
+pub struct Pin<const N: u8, Mode> {
+  _mode: PhantomData<Mode>,
 }
 
-/// Drive low every GPIO appearing in mask
-pub unsafe fn gpio_clr_mask(mask: u32) {
-  (*SIO_PTR).gpio_clr = mask;
+impl<const N: u8, Mode> Pin<N, Mode> {
+  /// GPIO number of the pin
+  pub const ID: u32 = {
+    assert!(N < NUM_PINS, "RP2040 only has 30 GPIOs");
+    N as u32
+  };
+
+  /// Creates a pin without checking whether it is already owned.
+  ///
+  /// # Safety
+  ///
+  /// The caller must make sure that no other `Pin<N, _>` exists and that the
+  /// GPIO is actually in `Mode`.
+  pub const unsafe fn steal() -> Self {
+    let _ = Self::ID;
+
+    Self { _mode: PhantomData }
+  }
+
+  /// GPIO number of the pin
+  pub const fn id(&self) -> u32 {
+    Self::ID
+  }
+
+  /// Enables or disables the pull resistors
+  pub fn set_pull(&mut self, pull: Pull) {
+    let (up, down) = match pull {
+      Pull::None => (false, false),
+      Pull::Up => (true, false),
+      Pull::Down => (false, true),
+      Pull::BusKeep => (true, true),
+    };
+
+    unsafe { pico_sdk::gpio_set_pulls(Self::ID, up, down) }
+  }
+
+  /// Puts the pin into high-impedance mode.
+  pub fn into_high_z(self) -> Pin<N, HighZ> {
+    unsafe {
+      pico_sdk::gpio_deinit(Self::ID);
+      pico_sdk::gpio_disable_pulls(Self::ID);
+      pico_sdk::gpio_set_input_enabled(Self::ID, false);
+      Pin::steal()
+    }
+  }
+
+  /// Configures the pin as a SIO input.
+  pub fn into_input(self) -> Pin<N, Input> {
+    unsafe {
+      pico_sdk::gpio_init(Self::ID);
+      pico_sdk::gpio_set_input_enabled(Self::ID, true);
+      Pin::steal()
+    }
+  }
+
+  /// Configures the pin as a SIO input with the given pull resistors.
+  pub fn into_input_with_pull(self, pull: Pull) -> Pin<N, Input> {
+    let mut pin = self.into_input();
+    pin.set_pull(pull);
+
+    pin
+  }
+
+  /// Configures the pin as a SIO output which is initially driven low.
+  pub fn into_output(self) -> Pin<N, Output> {
+    unsafe {
+      pico_sdk::gpio_init(Self::ID);
+      pico_sdk::gpio_set_dir(Self::ID, true);
+      Pin::steal()
+    }
+  }
+
+  /// Configures the pin as a SIO output which is initially driven high.
+  pub fn into_output_high(self) -> Pin<N, Output> {
+    unsafe {
+      pico_sdk::gpio_init(Self::ID);
+      pico_sdk::gpio_put(Self::ID, true);
+      pico_sdk::gpio_set_dir(Self::ID, true);
+      Pin::steal()
+    }
+  }
+
+  /// Connects the pin to the peripheral selected by `F`.
+  pub fn into_function<F: FunctionKind>(self) -> Pin<N, Function<F>> {
+    unsafe {
+      pico_sdk::gpio_set_input_enabled(Self::ID, true);
+      pico_sdk::gpio_set_function(Self::ID, F::FUNCTION);
+      Pin::steal()
+    }
+  }
 }
 
-/// Drive a single GPIO high/low
-pub unsafe fn gpio_put(gpio: u32, value: u32) {
-  let mask = 1u32 << gpio;
+impl<const N: u8> Pin<N, Input> {
+  /// Returns `true` if the pad reads high.
+  pub fn is_high(&self) -> bool {
+    unsafe { pico_sdk::gpio_get(Self::ID) }
+  }
+
+  /// Returns `true` if the pad reads low.
+  pub fn is_low(&self) -> bool {
+    !self.is_high()
+  }
 
-  if value != 0 {
-    gpio_set_mask(mask);
-  } else {
-    gpio_clr_mask(mask);
+  /// Enables or disables the schmitt trigger on the input.
+  pub fn set_hysteresis(&mut self, enabled: bool) {
+    unsafe { pico_sdk::gpio_set_input_hysteresis_enabled(Self::ID, enabled) }
   }
 }
 
-/// Drive all pins simultaneously
-pub unsafe fn gpio_put_all(value: u32) {
-  (*SIO_PTR).gpio_out = value;
+impl<const N: u8> Pin<N, Output> {
+  /// Drives the pin high.
+  pub fn set_high(&mut self) {
+    unsafe { pico_sdk::gpio_set_mask(1 << Self::ID) }
+  }
+
+  /// Drives the pin low.
+  pub fn set_low(&mut self) {
+    unsafe { pico_sdk::gpio_clr_mask(1 << Self::ID) }
+  }
+
+  /// Drives the pin to `value`.
+  pub fn set_level(&mut self, value: bool) {
+    unsafe { pico_sdk::gpio_put(Self::ID, value) }
+  }
+
+  /// Inverts the output level.
+  pub fn toggle(&mut self) {
+    unsafe { pico_sdk::gpio_xor_mask(1 << Self::ID) }
+  }
+
+  /// Returns `true` if the pin is currently driven high.
+  ///
+  /// This is the level assigned by software, not the level read back from the
+  /// pad.
+  pub fn is_set_high(&self) -> bool {
+    unsafe { pico_sdk::gpio_get_out_level(Self::ID) }
+  }
+
+  /// Returns `true` if the pin is currently driven low.
+  pub fn is_set_low(&self) -> bool {
+    !self.is_set_high()
+  }
+
+  /// Sets the output slew rate.
+  pub fn set_slew_rate(&mut self, slew_rate: SlewRate) {
+    unsafe { pico_sdk::gpio_set_slew_rate(Self::ID, slew_rate as _) }
+  }
+
+  /// Sets the output drive strength.
+  pub fn set_drive_strength(&mut self, drive_strength: DriveStrength) {
+    unsafe { pico_sdk::gpio_set_drive_strength(Self::ID, drive_strength as _) }
+  }
 }
 
-/// Determine whether a GPIO is currently driven high or low
-///
-/// This function returns the high/low output level most recently assigned to a
-/// GPIO via [gpio_put](gpio_put) or similar. This is the value that is
-/// presented outward to the IO muxing, *not* the input level back from the pad
-/// (which can be read using [gpio_put](gpio_put)).
-///
-/// To avoid races, this function must not be used for read-modify-write
-/// sequences when driving GPIOs -- instead functions like [gpio_put](gpio_put)
-/// should be used to atomically update GPIOs. This accessor is intended for
-/// debug use only.
-pub unsafe fn gpio_get_out_level(gpio: u32) -> bool {
-  ((*SIO_PTR).gpio_out & (1 << gpio)) > 0
+impl<const N: u8, F: FunctionKind> Pin<N, Function<F>> {
+  /// Sets the output slew rate.
+  pub fn set_slew_rate(&mut self, slew_rate: SlewRate) {
+    unsafe { pico_sdk::gpio_set_slew_rate(Self::ID, slew_rate as _) }
+  }
+
+  /// Sets the output drive strength.
+  pub fn set_drive_strength(&mut self, drive_strength: DriveStrength) {
+    unsafe { pico_sdk::gpio_set_drive_strength(Self::ID, drive_strength as _) }
+  }
 }
 
-/// Set specified GPIO to be pulled up.
-pub unsafe fn gpio_pull_up(gpio: u32) {
-  gpio_set_pulls(gpio, true, false)
+macro_rules! pins {
+  ($($field:ident: $n:literal),+ $(,)?) => {
+    /// All user GPIOs
+    pub struct Pins {
+      $(pub $field: Pin<$n, Disabled>,)+
+    }
+
+    impl Pins {
+      /// Takes ownership of all pins.
+      ///
+      /// Returns `None` if the pins have already been taken.
+      pub fn take() -> Option<Self> {
+        if unsafe { claim::claim(core::ptr::addr_of_mut!(PINS_TAKEN)) } {
+          Some(unsafe { Self::steal() })
+        } else {
+          None
+        }
+      }
+
+      /// Creates all pins without checking whether they are already owned.
+      ///
+      /// # Safety
+      ///
+      /// The caller must make sure that none of the pins are used elsewhere.
+      pub unsafe fn steal() -> Self {
+        Self {
+          $($field: Pin::steal(),)+
+        }
+      }
+    }
+  };
 }
 
-/// Set specified GPIO to be pulled down.
-pub unsafe fn gpio_pull_down(gpio: u32) {
-  gpio_set_pulls(gpio, false, true)
+pins! {
+  gpio0: 0, gpio1: 1, gpio2: 2, gpio3: 3, gpio4: 4,
+  gpio5: 5, gpio6: 6, gpio7: 7, gpio8: 8, gpio9: 9,
+  gpio10: 10, gpio11: 11, gpio12: 12, gpio13: 13, gpio14: 14,
+  gpio15: 15, gpio16: 16, gpio17: 17, gpio18: 18, gpio19: 19,
+  gpio20: 20, gpio21: 21, gpio22: 22, gpio23: 23, gpio24: 24,
+  gpio25: 25, gpio26: 26, gpio27: 27, gpio28: 28, gpio29: 29,
 }
@@ -0,0 +1,205 @@
+//! [`embedded-hal`](embedded_hal) trait implementations
+
+use crate::gpio::{Input, Output, Pin};
+use crate::i2c::{I2c, I2cAddress, I2cError, I2cOperation, I2cPins};
+use crate::pico_sdk;
+use crate::spi::{Spi, SpiDevice, SpiPins, SpiWord};
+use core::convert::Infallible;
+use embedded_hal::{delay, digital, i2c, spi};
+
+impl<const N: u8> digital::ErrorType for Pin<N, Input> {
+  type Error = Infallible;
+}
+
+impl<const N: u8> digital::InputPin for Pin<N, Input> {
+  fn is_high(&mut self) -> Result<bool, Self::Error> {
+    Ok(Pin::is_high(self))
+  }
+
+  fn is_low(&mut self) -> Result<bool, Self::Error> {
+    Ok(Pin::is_low(self))
+  }
+}
+
+impl<const N: u8> digital::ErrorType for Pin<N, Output> {
+  type Error = Infallible;
+}
+
+impl<const N: u8> digital::OutputPin for Pin<N, Output> {
+  fn set_low(&mut self) -> Result<(), Self::Error> {
+    Pin::set_low(self);
+    Ok(())
+  }
+
+  fn set_high(&mut self) -> Result<(), Self::Error> {
+    Pin::set_high(self);
+    Ok(())
+  }
+}
+
+impl<const N: u8> digital::StatefulOutputPin for Pin<N, Output> {
+  fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+    Ok(Pin::is_set_high(self))
+  }
+
+  fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+    Ok(Pin::is_set_low(self))
+  }
+
+  fn toggle(&mut self) -> Result<(), Self::Error> {
+    Pin::toggle(self);
+    Ok(())
+  }
+}
+
+impl<P: SpiPins> spi::ErrorType for Spi<P> {
+  type Error = Infallible;
+}
+
+impl<P: SpiPins, W: SpiWord> spi::SpiBus<W> for Spi<P> {
+  fn read(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+    Spi::read(self, W::default(), words);
+    Ok(())
+  }
+
+  fn write(&mut self, words: &[W]) -> Result<(), Self::Error> {
+    Spi::write(self, words);
+    Ok(())
+  }
+
+  fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
+    Spi::transfer(self, read, write);
+    Ok(())
+  }
+
+  fn transfer_in_place(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+    Spi::transfer_in_place(self, words);
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), Self::Error> {
+    Spi::flush(self);
+    Ok(())
+  }
+}
+
+impl<P: SpiPins, const CS: u8> spi::ErrorType for SpiDevice<'_, P, CS> {
+  type Error = Infallible;
+}
+
+impl<P: SpiPins, W: SpiWord, const CS: u8> spi::SpiDevice<W> for SpiDevice<'_, P, CS> {
+  fn transaction(&mut self, operations: &mut [spi::Operation<'_, W>]) -> Result<(), Self::Error> {
+    self.transaction(|bus| {
+      for operation in operations {
+        match operation {
+          spi::Operation::Read(words) => bus.read(W::default(), words),
+          spi::Operation::Write(words) => bus.write(words),
+          spi::Operation::Transfer(read, write) => bus.transfer(read, write),
+          spi::Operation::TransferInPlace(words) => bus.transfer_in_place(words),
+          spi::Operation::DelayNs(ns) => {
+            bus.flush();
+            delay::DelayNs::delay_ns(&mut BusyDelay, *ns);
+          }
+        }
+      }
+    });
+
+    Ok(())
+  }
+}
+
+impl i2c::Error for I2cError {
+  fn kind(&self) -> i2c::ErrorKind {
+    match self {
+      I2cError::AddressNack => i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Address),
+      I2cError::DataNack => i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Data),
+      I2cError::ArbitrationLost => i2c::ErrorKind::ArbitrationLoss,
+      I2cError::Timeout | I2cError::EmptyOperation | I2cError::Abort(_) => i2c::ErrorKind::Other,
+    }
+  }
+}
+
+impl<P: I2cPins> i2c::ErrorType for I2c<P> {
+  type Error = I2cError;
+}
+
+fn i2c_transaction<P: I2cPins>(
+  i2c: &mut I2c<P>,
+  address: I2cAddress,
+  operations: &mut [i2c::Operation<'_>],
+) -> Result<(), I2cError> {
+  let is_empty = |operation: &i2c::Operation<'_>| match operation {
+    i2c::Operation::Read(buffer) => buffer.is_empty(),
+    i2c::Operation::Write(bytes) => bytes.is_empty(),
+  };
+
+  if operations.is_empty() || operations.iter().any(is_empty) {
+    return Err(I2cError::EmptyOperation);
+  }
+
+  i2c.transaction_iter(
+    address,
+    operations.iter_mut().map(|operation| match operation {
+      i2c::Operation::Read(buffer) => I2cOperation::Read(buffer),
+      i2c::Operation::Write(bytes) => I2cOperation::Write(bytes),
+    }),
+  )
+}
+
+impl<P: I2cPins> i2c::I2c<i2c::SevenBitAddress> for I2c<P> {
+  fn transaction(
+    &mut self,
+    address: u8,
+    operations: &mut [i2c::Operation<'_>],
+  ) -> Result<(), Self::Error> {
+    i2c_transaction(self, I2cAddress::SevenBit(address), operations)
+  }
+}
+
+impl<P: I2cPins> i2c::I2c<i2c::TenBitAddress> for I2c<P> {
+  fn transaction(
+    &mut self,
+    address: u16,
+    operations: &mut [i2c::Operation<'_>],
+  ) -> Result<(), Self::Error> {
+    i2c_transaction(self, I2cAddress::TenBit(address), operations)
+  }
+}
+
+/// Busy-waiting delay which is safe to use in interrupt handlers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BusyDelay;
+
+impl delay::DelayNs for BusyDelay {
+  fn delay_ns(&mut self, ns: u32) {
+    unsafe { pico_sdk::busy_wait_us_32(ns.div_ceil(1000)) }
+  }
+
+  fn delay_us(&mut self, us: u32) {
+    unsafe { pico_sdk::busy_wait_us_32(us) }
+  }
+
+  fn delay_ms(&mut self, ms: u32) {
+    unsafe { pico_sdk::busy_wait_ms(ms) }
+  }
+}
+
+/// Delay which puts the core to sleep until the default alarm pool wakes it up
+///
+/// Must not be used in interrupt handlers, use [`BusyDelay`] there instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Delay;
+
+impl delay::DelayNs for Delay {
+  fn delay_ns(&mut self, ns: u32) {
+    unsafe { pico_sdk::sleep_us(ns.div_ceil(1000) as u64) }
+  }
+
+  fn delay_us(&mut self, us: u32) {
+    unsafe { pico_sdk::sleep_us(us as u64) }
+  }
+
+  fn delay_ms(&mut self, ms: u32) {
+    unsafe { pico_sdk::sleep_ms(ms) }
+  }
+}
@@ -0,0 +1,168 @@
+//! Pure Rust heap allocator
+//!
+//! Enabled with the `rust-heap` feature as an alternative to newlib's
+//! `malloc`, which holds `__malloc_lock` and can't be used from interrupt
+//! handlers. [`RustHeap`] keeps one [`Heap`] arena per core. Each arena is
+//! guarded by a hardware spin lock with interrupts disabled, so allocating and
+//! freeing is safe from both cores and from interrupt handlers.
+//!
+//! The feature doesn't register [`RustHeap`] by itself. Declare it as the
+//! global allocator and give it memory before the first allocation:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static HEAP: RustHeap = RustHeap::new();
+//!
+//! unsafe { HEAP.init_from_sbrk(32 * 1024, 16 * 1024) };
+//! ```
+//!
+//! The arenas are [`Heap`]s from pico-sdk-util, which is tested on the host.
+
+use crate::pico_sdk;
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ffi::{c_int, c_void};
+use core::mem::MaybeUninit;
+use core::ptr::{self, NonNull};
+use pico_sdk_util::{ArenaStats, Heap};
+
+extern "C" {
+  fn _sbrk(incr: c_int) -> *mut c_void;
+}
+
+/// An arena of [`RustHeap`] and the spin lock guarding it
+struct Arena {
+  heap: UnsafeCell<Heap>,
+  lock: UnsafeCell<*mut pico_sdk::spin_lock_t>,
+}
+
+impl Arena {
+  const fn new() -> Self {
+    Self {
+      heap: UnsafeCell::new(Heap::empty()),
+      lock: UnsafeCell::new(ptr::null_mut()),
+    }
+  }
+
+  fn with<T>(&self, f: impl FnOnce(&mut Heap) -> T) -> Option<T> {
+    unsafe {
+      let lock = *self.lock.get();
+
+      if lock.is_null() {
+        return None;
+      }
+
+      let saved_irq = pico_sdk::spin_lock_blocking(lock);
+      let result = f(&mut *self.heap.get());
+      pico_sdk::spin_unlock(lock, saved_irq);
+
+      Some(result)
+    }
+  }
+}
+
+/// Global allocator with a [`Heap`] per core
+///
+/// Allocations are served from the arena of the calling core first and fall
+/// back to the other arena. Memory can be freed from either core.
+pub struct RustHeap {
+  arenas: [Arena; 2],
+  failure_hook: UnsafeCell<Option<fn(Layout)>>,
+}
+
+unsafe impl Sync for RustHeap {}
+
+impl RustHeap {
+  /// Creates an allocator without memory; call one of the `init` functions
+  /// before allocating.
+  pub const fn new() -> Self {
+    Self {
+      arenas: [Arena::new(), Arena::new()],
+      failure_hook: UnsafeCell::new(None),
+    }
+  }
+
+  /// Gives each core its own memory region.
+  ///
+  /// # Safety
+  ///
+  /// Must be called once, before anything is allocated and while only one
+  /// core is running.
+  pub unsafe fn init(
+    &self,
+    core0: &'static mut [MaybeUninit<u8>],
+    core1: &'static mut [MaybeUninit<u8>],
+  ) {
+    for (arena, region) in self.arenas.iter().zip([core0, core1]) {
+      (*arena.heap.get()).init(region.as_mut_ptr() as *mut u8, region.len());
+      *arena.lock.get() = pico_sdk::spin_lock_init(pico_sdk::spin_lock_claim_unused(true) as u32);
+    }
+  }
+
+  /// Takes the arenas from the linker-provided heap between the end of
+  /// `.bss` and the stack, through the same `sbrk` newlib uses.
+  ///
+  /// Memory taken here is no longer available to `malloc`.
+  ///
+  /// # Safety
+  ///
+  /// Same as [`RustHeap::init`].
+  pub unsafe fn init_from_sbrk(&self, core0_size: usize, core1_size: usize) {
+    let start = _sbrk((core0_size + core1_size) as c_int);
+
+    assert!(start as isize != -1, "Not enough memory for the heap");
+
+    let start = start as *mut MaybeUninit<u8>;
+    self.init(
+      core::slice::from_raw_parts_mut(start, core0_size),
+      core::slice::from_raw_parts_mut(start.add(core0_size), core1_size),
+    );
+  }
+
+  /// Sets the function called with the layout of every failed allocation.
+  ///
+  /// The hook may run in interrupt context and must not allocate.
+  pub fn set_failure_hook(&self, hook: Option<fn(Layout)>) {
+    unsafe { ptr::write_volatile(self.failure_hook.get(), hook) }
+  }
+
+  /// Returns the usage statistics of `core`'s arena.
+  pub fn stats(&self, core: usize) -> ArenaStats {
+    self.arenas[core]
+      .with(|heap| heap.stats())
+      .unwrap_or_default()
+  }
+}
+
+impl Default for RustHeap {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+unsafe impl GlobalAlloc for RustHeap {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let core = pico_sdk::get_core_num() as usize;
+
+    for arena in [&self.arenas[core], &self.arenas[core ^ 1]] {
+      if let Some(Some(ptr)) = arena.with(|heap| heap.alloc(layout)) {
+        return ptr.as_ptr();
+      }
+    }
+
+    if let Some(hook) = ptr::read_volatile(self.failure_hook.get()) {
+      hook(layout);
+    }
+
+    ptr::null_mut()
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    for arena in &self.arenas {
+      if (*arena.heap.get()).contains(ptr) {
+        arena.with(|heap| heap.dealloc(NonNull::new_unchecked(ptr), layout));
+        return;
+      }
+    }
+  }
+}
@@ -0,0 +1,815 @@
+//! I2C bindings
+//!
+//! [`I2c`] owns one of the two I2C instances together with its SDA and SCL
+//! pins. As controller it runs transactions of reads and writes joined by
+//! repeated starts, with 7 or 10 bit addresses, and reports why a transfer
+//! failed through [`I2cError`]. [`I2c::into_target`] turns it into an
+//! interrupt-driven [`I2cTarget`], which passes the bus events to an
+//! [`I2cTargetHandler`] such as an [`I2cRegisterMap`].
+//!
+//! ```ignore
+//! let pins = Pins::take().unwrap();
+//! let mut i2c = I2c::new(
+//!   (pins.gpio4.into_function(), pins.gpio5.into_function()),
+//!   I2cConfig::default(),
+//! )
+//! .unwrap();
+//!
+//! for address in i2c.scan() {
+//!   println!("found {address:#04x}");
+//! }
+//!
+//! let mut id = [0];
+//! i2c.write_read(0x68, &[0x75], &mut id)?;
+//! ```
+
+use crate::gpio::{Function, FunctionI2c, Pin};
+use crate::sync::timeout_time;
+use crate::{claim, pico_sdk};
+use core::marker::PhantomData;
+use core::ptr;
+use core::time::Duration;
+use pico_sdk::{absolute_time_t, i2c_hw_t, i2c_inst_t};
+
+pub const I2C0_PTR: *mut i2c_hw_t = 0x40044000u32 as _;
+pub const I2C1_PTR: *mut i2c_hw_t = 0x40048000u32 as _;
+
+const IC_CON_MASTER_MODE: u32 = 1 << 0;
+const IC_CON_10BITADDR_SLAVE: u32 = 1 << 3;
+const IC_CON_10BITADDR_MASTER: u32 = 1 << 4;
+const IC_CON_SLAVE_DISABLE: u32 = 1 << 6;
+const IC_CON_RX_FIFO_FULL_HLD_CTRL: u32 = 1 << 9;
+const IC_ENABLE_ENABLE: u32 = 1 << 0;
+const IC_ENABLE_ABORT: u32 = 1 << 1;
+const IC_DATA_CMD_CMD: u32 = 1 << 8;
+const IC_DATA_CMD_STOP: u32 = 1 << 9;
+const IC_DATA_CMD_RESTART: u32 = 1 << 10;
+const IC_INTR_RX_FULL: u32 = 1 << 2;
+const IC_INTR_TX_EMPTY: u32 = 1 << 4;
+const IC_INTR_RD_REQ: u32 = 1 << 5;
+const IC_INTR_TX_ABRT: u32 = 1 << 6;
+const IC_INTR_STOP_DET: u32 = 1 << 9;
+const IC_INTR_START_DET: u32 = 1 << 10;
+const IC_TX_ABRT_ADDR_NOACK: u32 = 0b111;
+const IC_TX_ABRT_TXDATA_NOACK: u32 = 1 << 3;
+const IC_TX_ABRT_ARB_LOST: u32 = 1 << 12;
+
+/// Depth of the TX and RX FIFOs
+const FIFO_DEPTH: u32 = 16;
+
+static mut I2C_CLAIMED: [bool; 2] = [false; 2];
+
+/// Handlers of the targets, for the interrupt handlers
+static mut TARGETS: [Option<TargetState>; 2] = [None, None];
+
+struct TargetState {
+  handler: *mut dyn I2cTargetHandler,
+  in_transfer: bool,
+}
+
+/// One of the I2C instances
+pub trait I2cInstance {
+  const HW: *mut i2c_hw_t;
+  const IRQ: u32;
+  const INDEX: usize;
+
+  /// Returns the SDK instance.
+  fn inst() -> *mut i2c_inst_t;
+}
+
+/// I2C0 instance
+pub struct I2c0;
+
+/// I2C1 instance
+pub struct I2c1;
+
+impl I2cInstance for I2c0 {
+  const HW: *mut i2c_hw_t = I2C0_PTR;
+  const IRQ: u32 = pico_sdk::I2C0_IRQ;
+  const INDEX: usize = 0;
+
+  fn inst() -> *mut i2c_inst_t {
+    ptr::addr_of_mut!(pico_sdk::i2c0_inst)
+  }
+}
+
+impl I2cInstance for I2c1 {
+  const HW: *mut i2c_hw_t = I2C1_PTR;
+  const IRQ: u32 = pico_sdk::I2C1_IRQ;
+  const INDEX: usize = 1;
+
+  fn inst() -> *mut i2c_inst_t {
+    ptr::addr_of_mut!(pico_sdk::i2c1_inst)
+  }
+}
+
+/// A pin which can be used as I2C data
+pub trait I2cSdaPin {
+  type Instance: I2cInstance;
+}
+
+/// A pin which can be used as I2C clock
+pub trait I2cSclPin {
+  type Instance: I2cInstance;
+}
+
+macro_rules! i2c_pins {
+  ($($instance:ident: sda = [$($sda:literal),+], scl = [$($scl:literal),+];)+) => {
+    $(
+      $(impl I2cSdaPin for Pin<$sda, Function<FunctionI2c>> {
+        type Instance = $instance;
+      })+
+      $(impl I2cSclPin for Pin<$scl, Function<FunctionI2c>> {
+        type Instance = $instance;
+      })+
+    )+
+  };
+}
+
+i2c_pins! {
+  I2c0: sda = [0, 4, 8, 12, 16, 20, 24, 28], scl = [1, 5, 9, 13, 17, 21, 25, 29];
+  I2c1: sda = [2, 6, 10, 14, 18, 22, 26], scl = [3, 7, 11, 15, 19, 23, 27];
+}
+
+/// An SDA and SCL pin pair belonging to the same I2C instance
+///
+/// The bus needs pull-ups, either external ones or those of the pins.
+pub trait I2cPins {
+  type Instance: I2cInstance;
+}
+
+impl<SDA: I2cSdaPin, SCL: I2cSclPin<Instance = SDA::Instance>> I2cPins for (SDA, SCL) {
+  type Instance = SDA::Instance;
+}
+
+/// Address of a target
+///
+/// `u8` converts to a 7 bit address, 10 bit addresses must be given as
+/// [`I2cAddress::TenBit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I2cAddress {
+  SevenBit(u8),
+  TenBit(u16),
+}
+
+impl I2cAddress {
+  /// Returns `true` for 7 bit addresses reserved by the I2C specification,
+  /// `0x00` to `0x07` and `0x78` to `0x7f`.
+  pub const fn is_reserved(self) -> bool {
+    match self {
+      I2cAddress::SevenBit(address) => address & 0x78 == 0 || address & 0x78 == 0x78,
+      I2cAddress::TenBit(_) => false,
+    }
+  }
+
+  const fn bits(self) -> u32 {
+    match self {
+      I2cAddress::SevenBit(address) => {
+        assert!(address < 0x80, "7 bit address out of range");
+        address as u32
+      }
+      I2cAddress::TenBit(address) => {
+        assert!(address < 0x400, "10 bit address out of range");
+        address as u32
+      }
+    }
+  }
+}
+
+impl From<u8> for I2cAddress {
+  fn from(address: u8) -> Self {
+    I2cAddress::SevenBit(address)
+  }
+}
+
+/// Speed and timeout of an [`I2c`]
+///
+/// The default is 100 kHz without a timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2cConfig {
+  pub baudrate: u32,
+  /// Longest time a transaction may take, for example while a target
+  /// stretches the clock
+  pub timeout: Option<Duration>,
+}
+
+impl Default for I2cConfig {
+  fn default() -> Self {
+    Self {
+      baudrate: 100_000,
+      timeout: None,
+    }
+  }
+}
+
+/// Errors reported by controller transfers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I2cError {
+  /// No target acknowledged the address
+  AddressNack,
+  /// The target didn't acknowledge a data byte
+  DataNack,
+  /// Another controller won the bus
+  ArbitrationLost,
+  /// The transaction didn't complete within the timeout
+  Timeout,
+  /// The transaction has no operations or an empty one, and the controller
+  /// can't address a target without transferring data
+  EmptyOperation,
+  /// The transfer was aborted for another reason, given by the raw value of
+  /// `IC_TX_ABRT_SOURCE`
+  Abort(u32),
+}
+
+impl I2cError {
+  fn from_abort_source(source: u32) -> Self {
+    if source & IC_TX_ABRT_ADDR_NOACK != 0 {
+      I2cError::AddressNack
+    } else if source & IC_TX_ABRT_TXDATA_NOACK != 0 {
+      I2cError::DataNack
+    } else if source & IC_TX_ABRT_ARB_LOST != 0 {
+      I2cError::ArbitrationLost
+    } else {
+      I2cError::Abort(source)
+    }
+  }
+}
+
+/// A read or write in a transaction
+#[derive(Debug, PartialEq, Eq)]
+pub enum I2cOperation<'a> {
+  Read(&'a mut [u8]),
+  Write(&'a [u8]),
+}
+
+impl I2cOperation<'_> {
+  fn is_read(&self) -> bool {
+    matches!(self, I2cOperation::Read(_))
+  }
+
+  fn is_empty(&self) -> bool {
+    match self {
+      I2cOperation::Read(buffer) => buffer.is_empty(),
+      I2cOperation::Write(bytes) => bytes.is_empty(),
+    }
+  }
+}
+
+/// An I2C instance and its pins, as controller
+pub struct I2c<P: I2cPins> {
+  pins: P,
+  baudrate: u32,
+  timeout: Option<Duration>,
+}
+
+impl<P: I2cPins> I2c<P> {
+  /// Initializes the I2C instance of `pins` with `config`.
+  ///
+  /// Returns `None` if the instance is already in use.
+  pub fn new(pins: P, config: I2cConfig) -> Option<Self> {
+    if !unsafe { claim::claim(ptr::addr_of_mut!(I2C_CLAIMED[P::Instance::INDEX])) } {
+      return None;
+    }
+
+    let baudrate = unsafe { pico_sdk::i2c_init(P::Instance::inst(), config.baudrate) };
+
+    Some(Self {
+      pins,
+      baudrate,
+      timeout: config.timeout,
+    })
+  }
+
+  /// Disables the I2C and returns its pins.
+  pub fn free(self) -> P {
+    unsafe {
+      pico_sdk::i2c_deinit(P::Instance::inst());
+      claim::unclaim(ptr::addr_of_mut!(I2C_CLAIMED[P::Instance::INDEX]));
+    }
+
+    self.pins
+  }
+
+  /// Returns the underlying SDK instance.
+  pub fn as_ptr(&self) -> *mut i2c_inst_t {
+    P::Instance::inst()
+  }
+
+  fn hw(&self) -> *mut i2c_hw_t {
+    P::Instance::HW
+  }
+
+  /// Actual baudrate, which may differ slightly from the requested one
+  pub fn baudrate(&self) -> u32 {
+    self.baudrate
+  }
+
+  /// Changes the baudrate and returns the actual baudrate.
+  pub fn set_baudrate(&mut self, baudrate: u32) -> u32 {
+    self.baudrate = unsafe { pico_sdk::i2c_set_baudrate(self.as_ptr(), baudrate) };
+    self.baudrate
+  }
+
+  /// Changes the timeout of transactions.
+  pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+    self.timeout = timeout;
+  }
+
+  /// Writes `bytes` to the target at `address`.
+  pub fn write(&mut self, address: impl Into<I2cAddress>, bytes: &[u8]) -> Result<(), I2cError> {
+    self.transaction(address, &mut [I2cOperation::Write(bytes)])
+  }
+
+  /// Reads into `buffer` from the target at `address`.
+  pub fn read(
+    &mut self,
+    address: impl Into<I2cAddress>,
+    buffer: &mut [u8],
+  ) -> Result<(), I2cError> {
+    self.transaction(address, &mut [I2cOperation::Read(buffer)])
+  }
+
+  /// Writes `bytes` and reads into `buffer` after a repeated start, as used
+  /// to read registers.
+  pub fn write_read(
+    &mut self,
+    address: impl Into<I2cAddress>,
+    bytes: &[u8],
+    buffer: &mut [u8],
+  ) -> Result<(), I2cError> {
+    self.transaction(
+      address,
+      &mut [I2cOperation::Write(bytes), I2cOperation::Read(buffer)],
+    )
+  }
+
+  /// Runs `operations` as one transaction, ended by a stop.
+  ///
+  /// Adjacent operations of the same kind are sent as one, others are joined
+  /// with a repeated start. The controller can't address a target without
+  /// transferring data, so empty operations fail with
+  /// [`I2cError::EmptyOperation`] before the bus is touched.
+  pub fn transaction(
+    &mut self,
+    address: impl Into<I2cAddress>,
+    operations: &mut [I2cOperation<'_>],
+  ) -> Result<(), I2cError> {
+    if operations.is_empty() || operations.iter().any(I2cOperation::is_empty) {
+      return Err(I2cError::EmptyOperation);
+    }
+
+    self.transaction_iter(
+      address.into(),
+      operations.iter_mut().map(|operation| match operation {
+        I2cOperation::Read(buffer) => I2cOperation::Read(buffer),
+        I2cOperation::Write(bytes) => I2cOperation::Write(bytes),
+      }),
+    )
+  }
+
+  /// Runs `operations`, which the caller checked to be neither empty nor
+  /// contain empty operations.
+  pub(crate) fn transaction_iter<'a>(
+    &mut self,
+    address: I2cAddress,
+    operations: impl Iterator<Item = I2cOperation<'a>>,
+  ) -> Result<(), I2cError> {
+    let deadline = self.timeout.map(timeout_time);
+    let mut operations = operations.peekable();
+    let mut was_read = None;
+
+    self.set_target_address(address);
+
+    while let Some(operation) = operations.next() {
+      let restart = was_read.is_some_and(|was_read| was_read != operation.is_read());
+      let stop = operations.peek().is_none();
+      was_read = Some(operation.is_read());
+
+      let result = match operation {
+        I2cOperation::Read(buffer) => self.read_bytes(buffer, restart, stop, deadline),
+        I2cOperation::Write(bytes) => self.write_bytes(bytes, restart, stop, deadline),
+      };
+
+      if result == Err(I2cError::Timeout) {
+        self.abort_transfer();
+      }
+
+      result?;
+    }
+
+    Ok(())
+  }
+
+  /// Returns the 7 bit addresses which acknowledge a read, skipping the
+  /// reserved ones.
+  ///
+  /// Each target found is read once, which some devices act on.
+  pub fn scan(&mut self) -> impl Iterator<Item = u8> + '_ {
+    (0..0x80)
+      .filter(|&address| !I2cAddress::SevenBit(address).is_reserved())
+      .filter(move |&address| self.read(address, &mut [0]).is_ok())
+  }
+
+  fn set_target_address(&mut self, address: I2cAddress) {
+    let hw = self.hw();
+
+    unsafe {
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).enable), 0);
+
+      let con = ptr::read_volatile(ptr::addr_of!((*hw).con)) & !IC_CON_10BITADDR_MASTER;
+      let ten_bit = match address {
+        I2cAddress::SevenBit(_) => 0,
+        I2cAddress::TenBit(_) => IC_CON_10BITADDR_MASTER,
+      };
+
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).con), con | ten_bit);
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).tar), address.bits());
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).enable), 1);
+
+      // A stop left over from a previous read would end the next write early
+      ptr::read_volatile(ptr::addr_of!((*hw).clr_stop_det));
+    }
+  }
+
+  /// Waits until `done` returns `true` or `deadline` is reached.
+  fn wait_until(
+    &self,
+    deadline: Option<absolute_time_t>,
+    mut done: impl FnMut(*mut i2c_hw_t) -> bool,
+  ) -> Result<(), I2cError> {
+    while !done(self.hw()) {
+      if deadline.is_some_and(|deadline| unsafe { pico_sdk::time_reached(deadline) }) {
+        return Err(I2cError::Timeout);
+      }
+
+      unsafe { pico_sdk::tight_loop_contents() };
+    }
+
+    Ok(())
+  }
+
+  /// Waits for the stop ending a transfer, and clears it.
+  fn wait_stop(&self, deadline: Option<absolute_time_t>) -> Result<(), I2cError> {
+    self.wait_until(deadline, |hw| unsafe {
+      ptr::read_volatile(ptr::addr_of!((*hw).raw_intr_stat)) & IC_INTR_STOP_DET != 0
+    })?;
+
+    unsafe { ptr::read_volatile(ptr::addr_of!((*self.hw()).clr_stop_det)) };
+
+    Ok(())
+  }
+
+  /// Aborts a transfer which timed out, so the controller flushes its TX
+  /// FIFO and releases the bus with a stop.
+  fn abort_transfer(&self) {
+    let hw = self.hw();
+    // The bus may still be stuck, so give the abort another timeout
+    let deadline = self.timeout.map(timeout_time);
+
+    unsafe {
+      ptr::write_volatile(
+        ptr::addr_of_mut!((*hw).enable),
+        IC_ENABLE_ENABLE | IC_ENABLE_ABORT,
+      )
+    };
+
+    let _ = self.wait_until(deadline, |hw| unsafe {
+      ptr::read_volatile(ptr::addr_of!((*hw).raw_intr_stat)) & IC_INTR_TX_ABRT != 0
+    });
+    let _ = self.wait_stop(deadline);
+
+    unsafe { ptr::read_volatile(ptr::addr_of!((*hw).clr_tx_abrt)) };
+  }
+
+  /// Returns the reason of an abort, and clears it.
+  fn take_abort(&self) -> Result<(), I2cError> {
+    let hw = self.hw();
+    let source = unsafe { ptr::read_volatile(ptr::addr_of!((*hw).tx_abrt_source)) };
+
+    if source == 0 {
+      return Ok(());
+    }
+
+    // Clearing the abort also clears its source
+    unsafe { ptr::read_volatile(ptr::addr_of!((*hw).clr_tx_abrt)) };
+
+    Err(I2cError::from_abort_source(source))
+  }
+
+  fn command(restart: bool, stop: bool) -> u32 {
+    let mut command = 0;
+
+    if restart {
+      command |= IC_DATA_CMD_RESTART;
+    }
+
+    if stop {
+      command |= IC_DATA_CMD_STOP;
+    }
+
+    command
+  }
+
+  fn write_bytes(
+    &mut self,
+    bytes: &[u8],
+    restart: bool,
+    stop: bool,
+    deadline: Option<absolute_time_t>,
+  ) -> Result<(), I2cError> {
+    let last = bytes.len() - 1;
+
+    for (index, &byte) in bytes.iter().enumerate() {
+      let stop = stop && index == last;
+      let command = Self::command(restart && index == 0, stop) | byte as u32;
+
+      unsafe { ptr::write_volatile(ptr::addr_of_mut!((*self.hw()).data_cmd), command) };
+
+      // TX_EMPTY is only set once the byte has left the shift register, as
+      // i2c_init enables TX_EMPTY_CTRL
+      self.wait_until(deadline, |hw| unsafe {
+        ptr::read_volatile(ptr::addr_of!((*hw).raw_intr_stat)) & IC_INTR_TX_EMPTY != 0
+      })?;
+
+      let result = self.take_abort();
+
+      // The controller sends a stop after an abort by itself
+      if result.is_err() || stop {
+        self.wait_stop(deadline)?;
+      }
+
+      result?;
+    }
+
+    Ok(())
+  }
+
+  fn read_bytes(
+    &mut self,
+    buffer: &mut [u8],
+    restart: bool,
+    stop: bool,
+    deadline: Option<absolute_time_t>,
+  ) -> Result<(), I2cError> {
+    let last = buffer.len() - 1;
+
+    for (index, slot) in buffer.iter_mut().enumerate() {
+      let command = Self::command(restart && index == 0, stop && index == last) | IC_DATA_CMD_CMD;
+
+      self.wait_until(deadline, |hw| unsafe {
+        ptr::read_volatile(ptr::addr_of!((*hw).txflr)) < FIFO_DEPTH
+      })?;
+
+      unsafe { ptr::write_volatile(ptr::addr_of_mut!((*self.hw()).data_cmd), command) };
+
+      self.wait_until(deadline, |hw| unsafe {
+        ptr::read_volatile(ptr::addr_of!((*hw).tx_abrt_source)) != 0
+          || ptr::read_volatile(ptr::addr_of!((*hw).rxflr)) != 0
+      })?;
+
+      let result = self.take_abort();
+
+      // The controller sends a stop after an abort by itself
+      if result.is_err() {
+        self.wait_stop(deadline)?;
+      }
+
+      result?;
+
+      *slot = unsafe { ptr::read_volatile(ptr::addr_of!((*self.hw()).data_cmd)) } as u8;
+    }
+
+    Ok(())
+  }
+
+  /// Switches to target mode at `address`, or back to controller mode.
+  fn set_target_mode(&mut self, address: Option<I2cAddress>) {
+    let hw = self.hw();
+    let modes = IC_CON_MASTER_MODE
+      | IC_CON_SLAVE_DISABLE
+      | IC_CON_10BITADDR_SLAVE
+      | IC_CON_RX_FIFO_FULL_HLD_CTRL;
+
+    unsafe {
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).enable), 0);
+
+      let con = ptr::read_volatile(ptr::addr_of!((*hw).con)) & !modes;
+
+      match address {
+        Some(address) => {
+          let ten_bit = match address {
+            I2cAddress::SevenBit(_) => 0,
+            I2cAddress::TenBit(_) => IC_CON_10BITADDR_SLAVE,
+          };
+
+          ptr::write_volatile(
+            ptr::addr_of_mut!((*hw).con),
+            con | ten_bit | IC_CON_RX_FIFO_FULL_HLD_CTRL,
+          );
+          ptr::write_volatile(ptr::addr_of_mut!((*hw).sar), address.bits());
+        }
+        None => ptr::write_volatile(
+          ptr::addr_of_mut!((*hw).con),
+          con | IC_CON_MASTER_MODE | IC_CON_SLAVE_DISABLE,
+        ),
+      }
+
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).enable), 1);
+    }
+  }
+
+  /// Responds as target at `address`, passing the bus events to `handler`
+  /// from the interrupt handler of the calling core.
+  pub fn into_target<H: I2cTargetHandler + 'static>(
+    mut self,
+    address: impl Into<I2cAddress>,
+    handler: &'static mut H,
+  ) -> I2cTarget<P, H> {
+    let index = P::Instance::INDEX;
+    let handler: *mut H = handler;
+
+    self.set_target_mode(Some(address.into()));
+
+    unsafe {
+      *ptr::addr_of_mut!(TARGETS[index]) = Some(TargetState {
+        handler,
+        in_transfer: false,
+      });
+
+      ptr::write_volatile(
+        ptr::addr_of_mut!((*self.hw()).intr_mask),
+        IC_INTR_RX_FULL | IC_INTR_RD_REQ | IC_INTR_TX_ABRT | IC_INTR_STOP_DET | IC_INTR_START_DET,
+      );
+
+      pico_sdk::irq_set_exclusive_handler(P::Instance::IRQ, Some(target_irq::<P::Instance>));
+      pico_sdk::irq_set_enabled(P::Instance::IRQ, true);
+    }
+
+    I2cTarget {
+      i2c: self,
+      handler,
+      _handler: PhantomData,
+    }
+  }
+}
+
+/// Callbacks of an [`I2cTarget`], run in its interrupt handler
+pub trait I2cTargetHandler: Send {
+  /// The controller wrote `byte`.
+  fn receive(&mut self, byte: u8);
+
+  /// The controller reads the returned byte.
+  fn request(&mut self) -> u8;
+
+  /// The controller ended the transfer with a stop or repeated start.
+  fn finish(&mut self) {}
+}
+
+/// Registers of a device, accessed through an [`I2cRegisterMap`]
+pub trait I2cRegisters: Send {
+  fn read(&mut self, register: u8) -> u8;
+
+  fn write(&mut self, register: u8, value: u8);
+}
+
+/// Memory read and written as registers, with addresses wrapping around
+impl<const N: usize> I2cRegisters for [u8; N] {
+  fn read(&mut self, register: u8) -> u8 {
+    self[register as usize % N]
+  }
+
+  fn write(&mut self, register: u8, value: u8) {
+    self[register as usize % N] = value;
+  }
+}
+
+/// [`I2cTargetHandler`] for the usual register protocol, like the
+/// `slave_mem_i2c` example of the SDK
+///
+/// The first byte written in a transfer selects a register. The bytes written
+/// or read after it access consecutive registers, and a read after a
+/// repeated start continues at the selected register.
+pub struct I2cRegisterMap<R> {
+  registers: R,
+  register: u8,
+  register_written: bool,
+}
+
+impl<R: I2cRegisters> I2cRegisterMap<R> {
+  pub const fn new(registers: R) -> Self {
+    Self {
+      registers,
+      register: 0,
+      register_written: false,
+    }
+  }
+
+  /// Returns the registers.
+  pub fn registers(&mut self) -> &mut R {
+    &mut self.registers
+  }
+}
+
+impl<R: I2cRegisters> I2cTargetHandler for I2cRegisterMap<R> {
+  fn receive(&mut self, byte: u8) {
+    if !self.register_written {
+      self.register = byte;
+      self.register_written = true;
+    } else {
+      self.registers.write(self.register, byte);
+      self.register = self.register.wrapping_add(1);
+    }
+  }
+
+  fn request(&mut self) -> u8 {
+    let value = self.registers.read(self.register);
+
+    self.register = self.register.wrapping_add(1);
+    value
+  }
+
+  fn finish(&mut self) {
+    self.register_written = false;
+  }
+}
+
+/// An I2C instance responding as target, see [`I2c::into_target`]
+pub struct I2cTarget<P: I2cPins, H: 'static> {
+  i2c: I2c<P>,
+  handler: *mut H,
+  _handler: PhantomData<&'static mut H>,
+}
+
+impl<P: I2cPins, H: I2cTargetHandler> I2cTarget<P, H> {
+  /// Runs `f` with the handler while the interrupt is disabled.
+  pub fn with_handler<R>(&mut self, f: impl FnOnce(&mut H) -> R) -> R {
+    unsafe {
+      pico_sdk::irq_set_enabled(P::Instance::IRQ, false);
+      let result = f(&mut *self.handler);
+      pico_sdk::irq_set_enabled(P::Instance::IRQ, true);
+
+      result
+    }
+  }
+
+  /// Stops responding as target and returns the controller and the handler.
+  pub fn free(self) -> (I2c<P>, &'static mut H) {
+    let mut i2c = self.i2c;
+
+    unsafe {
+      pico_sdk::irq_set_enabled(P::Instance::IRQ, false);
+      pico_sdk::irq_remove_handler(P::Instance::IRQ, Some(target_irq::<P::Instance>));
+      ptr::write_volatile(ptr::addr_of_mut!((*i2c.hw()).intr_mask), 0);
+      *ptr::addr_of_mut!(TARGETS[P::Instance::INDEX]) = None;
+    }
+
+    i2c.set_target_mode(None);
+
+    (i2c, unsafe { &mut *self.handler })
+  }
+}
+
+unsafe extern "C" fn target_irq<I: I2cInstance>() {
+  let hw = I::HW;
+  let Some(target) = (*ptr::addr_of_mut!(TARGETS[I::INDEX])).as_mut() else {
+    return;
+  };
+  let handler = &mut *target.handler;
+  let status = ptr::read_volatile(ptr::addr_of!((*hw).intr_stat));
+  let mut finished = false;
+
+  if status & IC_INTR_TX_ABRT != 0 {
+    ptr::read_volatile(ptr::addr_of!((*hw).clr_tx_abrt));
+    finished = true;
+  }
+
+  if status & IC_INTR_START_DET != 0 {
+    ptr::read_volatile(ptr::addr_of!((*hw).clr_start_det));
+    finished = true;
+  }
+
+  if status & IC_INTR_STOP_DET != 0 {
+    ptr::read_volatile(ptr::addr_of!((*hw).clr_stop_det));
+    finished = true;
+  }
+
+  if finished && target.in_transfer {
+    handler.finish();
+    target.in_transfer = false;
+  }
+
+  if status & IC_INTR_RX_FULL != 0 {
+    target.in_transfer = true;
+
+    while ptr::read_volatile(ptr::addr_of!((*hw).rxflr)) != 0 {
+      handler.receive(ptr::read_volatile(ptr::addr_of!((*hw).data_cmd)) as u8);
+    }
+  }
+
+  if status & IC_INTR_RD_REQ != 0 {
+    ptr::read_volatile(ptr::addr_of!((*hw).clr_rd_req));
+    target.in_transfer = true;
+
+    ptr::write_volatile(ptr::addr_of_mut!((*hw).data_cmd), handler.request() as u32);
+  }
+}
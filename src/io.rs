@@ -1,41 +1,32 @@
-pub unsafe fn put_str_raw(value: &str) {
-  for char in value.chars() {
-    crate::pico_sdk::putchar_raw(char as _);
-  }
-}
+//! Standard IO macros over the SDK's stdio
+//!
+//! [`print!`], [`println!`], [`eprint!`], [`eprintln!`] and [`dbg!`] format
+//! without allocating and write through
+//! [putchar_raw](crate::pico_sdk::putchar_raw), so output goes to every
+//! enabled stdio driver (USB, UART, ...). Since the SDK has a single stdio
+//! stream, the `e*` variants write to the same place as their counterparts.
+//! [`flush!`] waits for buffered output with
+//! [stdio_flush](crate::pico_sdk::stdio_flush).
 
-// ::custom_print::define_macros!({ cprint, cprintln, cdbg }, concat, |value: &str| {
-//   unsafe {
-//     $crate::io::put_str_raw(value);
-//   }
-// });
+use crate::pico_sdk;
 
-// ::custom_print::define_macros!({ ceprint, ceprintln }, concat, |value: &str| {
-//   #[cfg(feature = "alloc")]
-//   unsafe {
-//     $crate::io::put_str_raw(format!("error: {value}").as_str());
-//   }
+/// Writes `value` to stdio without CRLF translation.
+pub fn put_str_raw(value: &str) {
+  for byte in value.bytes() {
+    unsafe { pico_sdk::putchar_raw(byte as _) };
+  }
+}
 
-//   #[cfg(not(feature = "alloc"))]
-//   unsafe {
-//     $crate::io::put_str_raw("error: ");
-//     $crate::io::put_str_raw(value);
-//   }
-// });
+::custom_print::define_macros!(
+  #[macro_export] { print, println, eprint, eprintln, dbg },
+  fmt,
+  |value: &str| $crate::put_str_raw(value)
+);
 
-// #[macro_export]
-// macro_rules! flush {
-//   () => {
-//     unsafe { $crate::stdio_flush() }
-//   };
-// }
-// #[macro_export]
-// macro_rules! print { ($($args:tt)*) => { $crate::io::cprint!($($args)*) } }
-// #[macro_export]
-// macro_rules! println { ($($args:tt)*) => { $crate::io::cprintln!($($args)*) } }
-// #[macro_export]
-// macro_rules! eprint { ($($args:tt)*) => { $crate::io::ceprint!($($args)*) } }
-// #[macro_export]
-// macro_rules! eprintln { ($($args:tt)*) => { $crate::io::ceprintln!($($args)*) } }
-// #[macro_export]
-// macro_rules! dbg { ($($args:tt)*) => { $crate::io::cdbg!($($args)*) } }
+/// Flushes any buffered stdio output.
+#[macro_export]
+macro_rules! flush {
+  () => {
+    unsafe { $crate::stdio_flush() }
+  };
+}
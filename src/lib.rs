@@ -7,16 +7,59 @@
 #[cfg(feature = "alloc")]
 extern crate alloc;
 
+mod adc;
+mod claim;
+mod crc;
+mod dma;
+mod flash;
 mod gpio;
+#[cfg(feature = "embedded-hal")]
+mod hal;
+mod i2c;
 #[macro_use]
 mod io;
+mod multicore;
 #[doc(hidden)]
 mod pico_sdk;
+mod pio;
+mod pwm;
+mod queue;
+mod spi;
+mod spin_lock;
+mod stdio;
+mod sync;
+mod uart;
 
 #[doc(hidden)]
-#[cfg(feature = "alloc")]
+#[cfg(all(feature = "alloc", not(feature = "rust-heap")))]
 mod allocator;
+#[cfg(feature = "rust-heap")]
+mod heap;
 
+#[cfg(all(feature = "alloc", not(feature = "rust-heap")))]
+pub use allocator::{Allocator, HeapStats};
+pub use adc::*;
+pub use crc::*;
+pub use dma::*;
+pub use flash::*;
 pub use gpio::*;
+#[cfg(feature = "embedded-hal")]
+pub use hal::*;
+#[cfg(feature = "rust-heap")]
+pub use heap::*;
+pub use i2c::*;
 pub use io::put_str_raw;
+pub use multicore::*;
 pub use pico_sdk::*;
+#[cfg(feature = "pio-asm")]
+pub use pico_sdk_macros::{include_pio, pio_asm};
+pub use pico_sdk_pio::*;
+pub use pico_sdk_util::*;
+pub use pio::*;
+pub use pwm::*;
+pub use queue::*;
+pub use spi::*;
+pub use spin_lock::*;
+pub use stdio::*;
+pub use sync::*;
+pub use uart::*;
@@ -0,0 +1,276 @@
+//! Multicore support
+//!
+//! [`Core1::spawn`] starts the second core with a Rust closure running on its
+//! own [`Stack`]. The cores can talk through the inter-core [`Fifo`], and one
+//! core can pause the other with [`lockout_other_core`], e.g. while writing
+//! to flash.
+//!
+//! ```ignore
+//! static mut CORE1_STACK: Stack<1024> = Stack::new();
+//!
+//! let core1 = Core1::spawn(unsafe { &mut *addr_of_mut!(CORE1_STACK) }, || loop {
+//!   let value: u32 = Fifo::pop();
+//!   Fifo::push(value * 2);
+//! })
+//! .unwrap();
+//!
+//! Fifo::push(21u32);
+//! assert_eq!(Fifo::pop::<u32>(), 42);
+//! ```
+
+use crate::gpio::SIO_PTR;
+use crate::{claim, pico_sdk};
+use core::mem::{self, ManuallyDrop};
+use core::ptr;
+
+static mut CORE1_CLAIMED: bool = false;
+
+/// Stack memory for core 1
+#[repr(C, align(8))]
+pub struct Stack<const WORDS: usize> {
+  memory: [u32; WORDS],
+}
+
+impl<const WORDS: usize> Stack<WORDS> {
+  /// Creates a zeroed stack.
+  pub const fn new() -> Self {
+    Self { memory: [0; WORDS] }
+  }
+}
+
+impl<const WORDS: usize> Default for Stack<WORDS> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+unsafe extern "C" fn core1_entry<F: FnOnce() + Send + 'static>() {
+  // Core 0 sends the address of the closure once the core is running
+  let closure = pico_sdk::multicore_fifo_pop_blocking() as *mut F;
+  let closure = ptr::read(closure);
+  pico_sdk::multicore_fifo_push_blocking(0);
+
+  closure();
+
+  loop {
+    pico_sdk::__wfe();
+  }
+}
+
+/// Returns `true` if core 1 was started with [`Core1::spawn`] and not reset
+/// since.
+pub(crate) fn core1_is_running() -> bool {
+  unsafe { ptr::read_volatile(ptr::addr_of!(CORE1_CLAIMED)) }
+}
+
+/// Handle to the running second core
+pub struct Core1 {
+  _private: (),
+}
+
+impl Core1 {
+  /// Starts core 1 running `entry` on `stack`.
+  ///
+  /// Once `entry` returns, core 1 sleeps until it is reset. Returns `None` if
+  /// core 1 is already running. Must be called from core 0.
+  pub fn spawn<F, const WORDS: usize>(stack: &'static mut Stack<WORDS>, entry: F) -> Option<Self>
+  where
+    F: FnOnce() + Send + 'static,
+  {
+    assert_eq!(
+      unsafe { pico_sdk::get_core_num() },
+      0,
+      "Core 1 can only be spawned from core 0"
+    );
+
+    if !unsafe { claim::claim(ptr::addr_of_mut!(CORE1_CLAIMED)) } {
+      return None;
+    }
+
+    let entry = ManuallyDrop::new(entry);
+
+    unsafe {
+      pico_sdk::multicore_launch_core1_with_stack(
+        Some(core1_entry::<F>),
+        stack.memory.as_mut_ptr(),
+        mem::size_of_val(&stack.memory),
+      );
+      pico_sdk::multicore_fifo_push_blocking(&*entry as *const F as u32);
+      // Wait until core 1 has moved the closure onto its own stack
+      pico_sdk::multicore_fifo_pop_blocking();
+    }
+
+    Some(Self { _private: () })
+  }
+
+  /// Stops core 1 and puts it back into its reset state.
+  pub fn reset(self) {
+    unsafe {
+      pico_sdk::multicore_reset_core1();
+      claim::unclaim(ptr::addr_of_mut!(CORE1_CLAIMED));
+    }
+  }
+}
+
+/// A value which fits into a single FIFO word
+pub trait FifoWord: Copy {
+  fn into_word(self) -> u32;
+  fn from_word(word: u32) -> Self;
+}
+
+macro_rules! fifo_words {
+  ($($ty:ty),+) => {
+    $(impl FifoWord for $ty {
+      fn into_word(self) -> u32 {
+        self as u32
+      }
+
+      fn from_word(word: u32) -> Self {
+        word as Self
+      }
+    })+
+  };
+}
+
+fifo_words!(u8, u16, u32, usize, i8, i16, i32, isize);
+
+impl FifoWord for bool {
+  fn into_word(self) -> u32 {
+    self as u32
+  }
+
+  fn from_word(word: u32) -> Self {
+    word != 0
+  }
+}
+
+impl<T> FifoWord for *const T {
+  fn into_word(self) -> u32 {
+    self as u32
+  }
+
+  fn from_word(word: u32) -> Self {
+    word as Self
+  }
+}
+
+impl<T> FifoWord for *mut T {
+  fn into_word(self) -> u32 {
+    self as u32
+  }
+
+  fn from_word(word: u32) -> Self {
+    word as Self
+  }
+}
+
+/// The inter-core FIFO of the calling core
+///
+/// Each core writes to the other core's RX FIFO, which holds 8 words.
+pub struct Fifo;
+
+impl Fifo {
+  /// Returns `true` if a word can be read.
+  pub fn is_readable() -> bool {
+    unsafe { pico_sdk::multicore_fifo_rvalid() }
+  }
+
+  /// Returns `true` if a word can be written.
+  pub fn is_writable() -> bool {
+    unsafe { pico_sdk::multicore_fifo_wready() }
+  }
+
+  /// Sends `value` to the other core, waiting for space.
+  pub fn push<T: FifoWord>(value: T) {
+    unsafe { pico_sdk::multicore_fifo_push_blocking(value.into_word()) }
+  }
+
+  /// Sends `value` to the other core if there is space, returning it
+  /// otherwise.
+  pub fn try_push<T: FifoWord>(value: T) -> Result<(), T> {
+    if !Self::is_writable() {
+      return Err(value);
+    }
+
+    unsafe {
+      ptr::write_volatile(ptr::addr_of_mut!((*SIO_PTR).fifo_wr), value.into_word());
+      // Wake the other core if it waits for data
+      pico_sdk::__sev();
+    }
+
+    Ok(())
+  }
+
+  /// Sends `value` to the other core, waiting up to `timeout_us`
+  /// microseconds for space.
+  pub fn push_timeout_us<T: FifoWord>(value: T, timeout_us: u64) -> Result<(), T> {
+    if unsafe { pico_sdk::multicore_fifo_push_timeout_us(value.into_word(), timeout_us) } {
+      Ok(())
+    } else {
+      Err(value)
+    }
+  }
+
+  /// Receives a value from the other core, waiting for it.
+  pub fn pop<T: FifoWord>() -> T {
+    T::from_word(unsafe { pico_sdk::multicore_fifo_pop_blocking() })
+  }
+
+  /// Receives a value from the other core, `None` if there is none.
+  pub fn try_pop<T: FifoWord>() -> Option<T> {
+    Self::is_readable()
+      .then(|| T::from_word(unsafe { ptr::read_volatile(ptr::addr_of!((*SIO_PTR).fifo_rd)) }))
+  }
+
+  /// Receives a value from the other core, waiting up to `timeout_us`
+  /// microseconds for it.
+  pub fn pop_timeout_us<T: FifoWord>(timeout_us: u64) -> Option<T> {
+    let mut word = 0;
+
+    unsafe { pico_sdk::multicore_fifo_pop_timeout_us(timeout_us, &mut word) }
+      .then(|| T::from_word(word))
+  }
+
+  /// Discards all received words.
+  pub fn drain() {
+    unsafe { pico_sdk::multicore_fifo_drain() }
+  }
+
+  /// Clears the sticky overflow and underflow flags behind the FIFO
+  /// interrupt.
+  pub fn clear_irq() {
+    unsafe { pico_sdk::multicore_fifo_clear_irq() }
+  }
+}
+
+/// Allows the calling core to be paused by [`lockout_other_core`].
+///
+/// This installs a handler for the core's FIFO interrupt, so the FIFO can no
+/// longer be read by the application on this core.
+pub fn enable_lockout_victim() {
+  unsafe { pico_sdk::multicore_lockout_victim_init() }
+}
+
+/// Runs `f` while the other core spins in its FIFO interrupt handler.
+///
+/// The other core must have called [`enable_lockout_victim`].
+pub fn lockout_other_core<R>(f: impl FnOnce() -> R) -> R {
+  unsafe { pico_sdk::multicore_lockout_start_blocking() };
+  let result = f();
+  unsafe { pico_sdk::multicore_lockout_end_blocking() };
+
+  result
+}
+
+/// Runs `f` like [`lockout_other_core`], giving up if the other core doesn't
+/// pause within `timeout_us` microseconds.
+pub fn try_lockout_other_core<R>(timeout_us: u64, f: impl FnOnce() -> R) -> Option<R> {
+  if !unsafe { pico_sdk::multicore_lockout_start_timeout_us(timeout_us) } {
+    return None;
+  }
+
+  let result = f();
+  unsafe { pico_sdk::multicore_lockout_end_blocking() };
+
+  Some(result)
+}
@@ -4637,7 +4637,13 @@ pub type pico_error_codes = ::core::ffi::c_int;
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct stdio_driver {
-    _unused: [u8; 0],
+    pub out_chars: ::core::option::Option<unsafe extern "C" fn(buf: *const ::core::ffi::c_char, len: ::core::ffi::c_int)>,
+    pub out_flush: ::core::option::Option<unsafe extern "C" fn()>,
+    pub in_chars: ::core::option::Option<unsafe extern "C" fn(buf: *mut ::core::ffi::c_char, len: ::core::ffi::c_int) -> ::core::ffi::c_int>,
+    pub set_chars_available_callback: ::core::option::Option<unsafe extern "C" fn(fn_: ::core::option::Option<unsafe extern "C" fn(arg1: *mut ::core::ffi::c_void)>, param: *mut ::core::ffi::c_void)>,
+    pub next: *mut stdio_driver_t,
+    pub last_ended_with_cr: bool,
+    pub crlf_enabled: bool,
 }
 pub type stdio_driver_t = stdio_driver;
 extern "C" {
@@ -6238,3 +6244,597 @@ extern "C" {
     #[doc = " @brief Returns the number of microseconds before the watchdog will reboot the chip.\n \\ingroup hardware_watchdog\n\n @return The number of microseconds before the watchdog will reboot the chip."]
     pub fn watchdog_get_count() -> u32;
 }
+extern "C" {
+    #[link_name = "gpio_get__extern"]
+    pub fn gpio_get(gpio: uint) -> bool;
+}
+extern "C" {
+    #[link_name = "gpio_get_all__extern"]
+    pub fn gpio_get_all() -> u32;
+}
+extern "C" {
+    #[link_name = "gpio_put__extern"]
+    pub fn gpio_put(gpio: uint, value: bool);
+}
+extern "C" {
+    #[link_name = "gpio_set_mask__extern"]
+    pub fn gpio_set_mask(mask: u32);
+}
+extern "C" {
+    #[link_name = "gpio_clr_mask__extern"]
+    pub fn gpio_clr_mask(mask: u32);
+}
+extern "C" {
+    #[link_name = "gpio_xor_mask__extern"]
+    pub fn gpio_xor_mask(mask: u32);
+}
+extern "C" {
+    #[link_name = "gpio_set_dir__extern"]
+    pub fn gpio_set_dir(gpio: uint, out: bool);
+}
+extern "C" {
+    #[link_name = "gpio_is_dir_out__extern"]
+    pub fn gpio_is_dir_out(gpio: uint) -> bool;
+}
+extern "C" {
+    #[link_name = "gpio_get_out_level__extern"]
+    pub fn gpio_get_out_level(gpio: uint) -> bool;
+}
+extern "C" {
+    #[link_name = "gpio_disable_pulls__extern"]
+    pub fn gpio_disable_pulls(gpio: uint);
+}
+extern "C" {
+    #[link_name = "save_and_disable_interrupts__extern"]
+    pub fn save_and_disable_interrupts() -> u32;
+}
+extern "C" {
+    #[link_name = "restore_interrupts__extern"]
+    pub fn restore_interrupts(status: u32);
+}
+extern "C" {
+    #[link_name = "get_core_num__extern"]
+    pub fn get_core_num() -> uint;
+}
+extern "C" {
+    #[link_name = "__wfe__extern"]
+    pub fn __wfe();
+}
+extern "C" {
+    #[link_name = "__sev__extern"]
+    pub fn __sev();
+}
+extern "C" {
+    #[link_name = "__dmb__extern"]
+    pub fn __dmb();
+}
+extern "C" {
+    #[link_name = "tight_loop_contents__extern"]
+    pub fn tight_loop_contents();
+}
+extern "C" {
+    #[link_name = "get_absolute_time__extern"]
+    pub fn get_absolute_time() -> absolute_time_t;
+}
+extern "C" {
+    #[link_name = "make_timeout_time_us__extern"]
+    pub fn make_timeout_time_us(us: u64) -> absolute_time_t;
+}
+extern "C" {
+    #[link_name = "make_timeout_time_ms__extern"]
+    pub fn make_timeout_time_ms(ms: u32) -> absolute_time_t;
+}
+extern "C" {
+    #[link_name = "time_reached__extern"]
+    pub fn time_reached(t: absolute_time_t) -> bool;
+}
+extern "C" {
+    #[link_name = "spi_is_busy__extern"]
+    pub fn spi_is_busy(spi: *const spi_inst_t) -> bool;
+}
+extern "C" {
+    #[link_name = "spi_is_writable__extern"]
+    pub fn spi_is_writable(spi: *const spi_inst_t) -> bool;
+}
+extern "C" {
+    #[link_name = "spi_is_readable__extern"]
+    pub fn spi_is_readable(spi: *const spi_inst_t) -> bool;
+}
+extern "C" {
+    #[link_name = "uart_is_writable__extern"]
+    pub fn uart_is_writable(uart: *mut uart_inst_t) -> bool;
+}
+extern "C" {
+    #[link_name = "uart_is_readable__extern"]
+    pub fn uart_is_readable(uart: *mut uart_inst_t) -> bool;
+}
+extern "C" {
+    #[link_name = "uart_putc__extern"]
+    pub fn uart_putc(uart: *mut uart_inst_t, c: ::core::ffi::c_char);
+}
+extern "C" {
+    #[link_name = "uart_putc_raw__extern"]
+    pub fn uart_putc_raw(uart: *mut uart_inst_t, c: ::core::ffi::c_char);
+}
+extern "C" {
+    #[link_name = "uart_tx_wait_blocking__extern"]
+    pub fn uart_tx_wait_blocking(uart: *mut uart_inst_t);
+}
+extern "C" {
+    #[link_name = "uart_get_hw__extern"]
+    pub fn uart_get_hw(uart: *mut uart_inst_t) -> *mut uart_hw_t;
+}
+extern "C" {
+    #[link_name = "spin_lock_instance__extern"]
+    pub fn spin_lock_instance(lock_num: uint) -> *mut spin_lock_t;
+}
+extern "C" {
+    #[link_name = "spin_lock_get_num__extern"]
+    pub fn spin_lock_get_num(lock: *mut spin_lock_t) -> uint;
+}
+extern "C" {
+    #[link_name = "spin_lock_blocking__extern"]
+    pub fn spin_lock_blocking(lock: *mut spin_lock_t) -> u32;
+}
+extern "C" {
+    #[link_name = "spin_lock_unsafe_blocking__extern"]
+    pub fn spin_lock_unsafe_blocking(lock: *mut spin_lock_t);
+}
+extern "C" {
+    #[link_name = "spin_unlock__extern"]
+    pub fn spin_unlock(lock: *mut spin_lock_t, saved_irq: u32);
+}
+extern "C" {
+    #[link_name = "spin_unlock_unsafe__extern"]
+    pub fn spin_unlock_unsafe(lock: *mut spin_lock_t);
+}
+extern "C" {
+    #[link_name = "is_spin_locked__extern"]
+    pub fn is_spin_locked(lock: *mut spin_lock_t) -> bool;
+}
+extern "C" {
+    #[link_name = "pwm_gpio_to_slice_num__extern"]
+    pub fn pwm_gpio_to_slice_num(gpio: uint) -> uint;
+}
+extern "C" {
+    #[link_name = "pwm_gpio_to_channel__extern"]
+    pub fn pwm_gpio_to_channel(gpio: uint) -> uint;
+}
+extern "C" {
+    #[link_name = "pwm_get_default_config__extern"]
+    pub fn pwm_get_default_config() -> pwm_config;
+}
+extern "C" {
+    #[link_name = "pwm_init__extern"]
+    pub fn pwm_init(slice_num: uint, c: *mut pwm_config, start: bool);
+}
+extern "C" {
+    #[link_name = "pwm_set_wrap__extern"]
+    pub fn pwm_set_wrap(slice_num: uint, wrap: u16);
+}
+extern "C" {
+    #[link_name = "pwm_set_chan_level__extern"]
+    pub fn pwm_set_chan_level(slice_num: uint, chan: uint, level: u16);
+}
+extern "C" {
+    #[link_name = "pwm_set_both_levels__extern"]
+    pub fn pwm_set_both_levels(slice_num: uint, level_a: u16, level_b: u16);
+}
+extern "C" {
+    #[link_name = "pwm_set_gpio_level__extern"]
+    pub fn pwm_set_gpio_level(gpio: uint, level: u16);
+}
+extern "C" {
+    #[link_name = "pwm_get_counter__extern"]
+    pub fn pwm_get_counter(slice_num: uint) -> u16;
+}
+extern "C" {
+    #[link_name = "pwm_set_counter__extern"]
+    pub fn pwm_set_counter(slice_num: uint, c: u16);
+}
+extern "C" {
+    #[link_name = "pwm_advance_count__extern"]
+    pub fn pwm_advance_count(slice_num: uint);
+}
+extern "C" {
+    #[link_name = "pwm_retard_count__extern"]
+    pub fn pwm_retard_count(slice_num: uint);
+}
+extern "C" {
+    #[link_name = "pwm_set_clkdiv_int_frac__extern"]
+    pub fn pwm_set_clkdiv_int_frac(slice_num: uint, integer: u8, fract: u8);
+}
+extern "C" {
+    #[link_name = "pwm_set_output_polarity__extern"]
+    pub fn pwm_set_output_polarity(slice_num: uint, a: bool, b: bool);
+}
+extern "C" {
+    #[link_name = "pwm_set_clkdiv_mode__extern"]
+    pub fn pwm_set_clkdiv_mode(slice_num: uint, mode: pwm_clkdiv_mode);
+}
+extern "C" {
+    #[link_name = "pwm_set_phase_correct__extern"]
+    pub fn pwm_set_phase_correct(slice_num: uint, phase_correct: bool);
+}
+extern "C" {
+    #[link_name = "pwm_set_enabled__extern"]
+    pub fn pwm_set_enabled(slice_num: uint, enabled: bool);
+}
+extern "C" {
+    #[link_name = "pwm_set_mask_enabled__extern"]
+    pub fn pwm_set_mask_enabled(mask: u32);
+}
+extern "C" {
+    #[link_name = "pwm_set_irq_enabled__extern"]
+    pub fn pwm_set_irq_enabled(slice_num: uint, enabled: bool);
+}
+extern "C" {
+    #[link_name = "pwm_clear_irq__extern"]
+    pub fn pwm_clear_irq(slice_num: uint);
+}
+extern "C" {
+    #[link_name = "pwm_get_irq_status_mask__extern"]
+    pub fn pwm_get_irq_status_mask() -> u32;
+}
+extern "C" {
+    #[link_name = "pwm_get_dreq__extern"]
+    pub fn pwm_get_dreq(slice_num: uint) -> uint;
+}
+extern "C" {
+    #[link_name = "adc_gpio_init__extern"]
+    pub fn adc_gpio_init(gpio: uint);
+}
+extern "C" {
+    #[link_name = "adc_select_input__extern"]
+    pub fn adc_select_input(input: uint);
+}
+extern "C" {
+    #[link_name = "adc_get_selected_input__extern"]
+    pub fn adc_get_selected_input() -> uint;
+}
+extern "C" {
+    #[link_name = "adc_set_round_robin__extern"]
+    pub fn adc_set_round_robin(input_mask: uint);
+}
+extern "C" {
+    #[link_name = "adc_set_temp_sensor_enabled__extern"]
+    pub fn adc_set_temp_sensor_enabled(enable: bool);
+}
+extern "C" {
+    #[link_name = "adc_read__extern"]
+    pub fn adc_read() -> u16;
+}
+extern "C" {
+    #[link_name = "adc_run__extern"]
+    pub fn adc_run(run: bool);
+}
+extern "C" {
+    #[link_name = "adc_set_clkdiv__extern"]
+    pub fn adc_set_clkdiv(clkdiv: f32);
+}
+extern "C" {
+    #[link_name = "adc_fifo_setup__extern"]
+    pub fn adc_fifo_setup(en: bool, dreq_en: bool, dreq_thresh: u16, err_in_fifo: bool, byte_shift: bool);
+}
+extern "C" {
+    #[link_name = "adc_fifo_is_empty__extern"]
+    pub fn adc_fifo_is_empty() -> bool;
+}
+extern "C" {
+    #[link_name = "adc_fifo_get_level__extern"]
+    pub fn adc_fifo_get_level() -> u8;
+}
+extern "C" {
+    #[link_name = "adc_fifo_get__extern"]
+    pub fn adc_fifo_get() -> u16;
+}
+extern "C" {
+    #[link_name = "adc_fifo_get_blocking__extern"]
+    pub fn adc_fifo_get_blocking() -> u16;
+}
+extern "C" {
+    #[link_name = "adc_fifo_drain__extern"]
+    pub fn adc_fifo_drain();
+}
+extern "C" {
+    #[link_name = "adc_irq_set_enabled__extern"]
+    pub fn adc_irq_set_enabled(enabled: bool);
+}
+extern "C" {
+    #[link_name = "dma_channel_get_default_config__extern"]
+    pub fn dma_channel_get_default_config(channel: uint) -> dma_channel_config;
+}
+extern "C" {
+    #[link_name = "channel_config_set_read_increment__extern"]
+    pub fn channel_config_set_read_increment(c: *mut dma_channel_config, incr: bool);
+}
+extern "C" {
+    #[link_name = "channel_config_set_write_increment__extern"]
+    pub fn channel_config_set_write_increment(c: *mut dma_channel_config, incr: bool);
+}
+extern "C" {
+    #[link_name = "channel_config_set_dreq__extern"]
+    pub fn channel_config_set_dreq(c: *mut dma_channel_config, dreq: uint);
+}
+extern "C" {
+    #[link_name = "channel_config_set_transfer_data_size__extern"]
+    pub fn channel_config_set_transfer_data_size(c: *mut dma_channel_config, size: dma_channel_transfer_size);
+}
+extern "C" {
+    #[link_name = "dma_channel_configure__extern"]
+    pub fn dma_channel_configure(channel: uint, config: *const dma_channel_config, write_addr: *mut ::core::ffi::c_void, read_addr: *const ::core::ffi::c_void, transfer_count: uint, trigger: bool);
+}
+extern "C" {
+    #[link_name = "dma_channel_is_busy__extern"]
+    pub fn dma_channel_is_busy(channel: uint) -> bool;
+}
+extern "C" {
+    #[link_name = "dma_channel_wait_for_finish_blocking__extern"]
+    pub fn dma_channel_wait_for_finish_blocking(channel: uint);
+}
+extern "C" {
+    #[link_name = "dma_channel_abort__extern"]
+    pub fn dma_channel_abort(channel: uint);
+}
+extern "C" {
+    pub fn multicore_reset_core1();
+}
+extern "C" {
+    pub fn multicore_launch_core1(entry: ::core::option::Option<unsafe extern "C" fn()>);
+}
+extern "C" {
+    pub fn multicore_launch_core1_with_stack(entry: ::core::option::Option<unsafe extern "C" fn()>, stack_bottom: *mut u32, stack_size_bytes: usize);
+}
+extern "C" {
+    pub fn multicore_fifo_push_blocking(data: u32);
+}
+extern "C" {
+    pub fn multicore_fifo_push_timeout_us(data: u32, timeout_us: u64) -> bool;
+}
+extern "C" {
+    pub fn multicore_fifo_pop_blocking() -> u32;
+}
+extern "C" {
+    pub fn multicore_fifo_pop_timeout_us(timeout_us: u64, out: *mut u32) -> bool;
+}
+extern "C" {
+    pub fn multicore_lockout_victim_init();
+}
+extern "C" {
+    pub fn multicore_lockout_victim_is_initialized(core_num: uint) -> bool;
+}
+extern "C" {
+    pub fn multicore_lockout_start_blocking();
+}
+extern "C" {
+    pub fn multicore_lockout_start_timeout_us(timeout_us: u64) -> bool;
+}
+extern "C" {
+    pub fn multicore_lockout_end_blocking();
+}
+extern "C" {
+    pub fn multicore_lockout_end_timeout_us(timeout_us: u64) -> bool;
+}
+extern "C" {
+    #[link_name = "multicore_fifo_rvalid__extern"]
+    pub fn multicore_fifo_rvalid() -> bool;
+}
+extern "C" {
+    #[link_name = "multicore_fifo_wready__extern"]
+    pub fn multicore_fifo_wready() -> bool;
+}
+extern "C" {
+    #[link_name = "multicore_fifo_drain__extern"]
+    pub fn multicore_fifo_drain();
+}
+extern "C" {
+    #[link_name = "multicore_fifo_clear_irq__extern"]
+    pub fn multicore_fifo_clear_irq();
+}
+extern "C" {
+    #[link_name = "multicore_fifo_get_status__extern"]
+    pub fn multicore_fifo_get_status() -> u32;
+}
+extern "C" {
+    #[link_name = "__mem_fence_acquire__extern"]
+    pub fn __mem_fence_acquire();
+}
+extern "C" {
+    #[link_name = "__mem_fence_release__extern"]
+    pub fn __mem_fence_release();
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct lock_core {
+    pub spin_lock: *mut spin_lock_t,
+}
+pub type lock_core_t = lock_core;
+pub type lock_owner_id_t = i8;
+#[repr(C, packed(4))]
+#[derive(Debug, Copy, Clone)]
+pub struct mutex {
+    pub core: lock_core_t,
+    pub owner: lock_owner_id_t,
+}
+pub type mutex_t = mutex;
+#[repr(C, packed(4))]
+#[derive(Debug, Copy, Clone)]
+pub struct recursive_mutex_t {
+    pub core: lock_core_t,
+    pub owner: lock_owner_id_t,
+    pub enter_count: u8,
+}
+#[repr(C, packed(4))]
+#[derive(Debug, Copy, Clone)]
+pub struct semaphore {
+    pub core: lock_core,
+    pub permits: i16,
+    pub max_permits: i16,
+}
+pub type semaphore_t = semaphore;
+#[repr(C, packed(4))]
+#[derive(Debug, Copy, Clone)]
+pub struct critical_section {
+    pub spin_lock: *mut spin_lock_t,
+    pub save: u32,
+}
+pub type critical_section_t = critical_section;
+extern "C" {
+    pub fn mutex_init(mtx: *mut mutex_t);
+}
+extern "C" {
+    pub fn recursive_mutex_init(mtx: *mut recursive_mutex_t);
+}
+extern "C" {
+    pub fn mutex_enter_blocking(mtx: *mut mutex_t);
+}
+extern "C" {
+    pub fn recursive_mutex_enter_blocking(mtx: *mut recursive_mutex_t);
+}
+extern "C" {
+    pub fn mutex_try_enter(mtx: *mut mutex_t, owner_out: *mut u32) -> bool;
+}
+extern "C" {
+    pub fn mutex_try_enter_block_until(mtx: *mut mutex_t, until: absolute_time_t) -> bool;
+}
+extern "C" {
+    pub fn recursive_mutex_try_enter(mtx: *mut recursive_mutex_t, owner_out: *mut u32) -> bool;
+}
+extern "C" {
+    pub fn mutex_enter_timeout_ms(mtx: *mut mutex_t, timeout_ms: u32) -> bool;
+}
+extern "C" {
+    pub fn recursive_mutex_enter_timeout_ms(mtx: *mut recursive_mutex_t, timeout_ms: u32) -> bool;
+}
+extern "C" {
+    pub fn mutex_enter_timeout_us(mtx: *mut mutex_t, timeout_us: u32) -> bool;
+}
+extern "C" {
+    pub fn recursive_mutex_enter_timeout_us(mtx: *mut recursive_mutex_t, timeout_us: u32) -> bool;
+}
+extern "C" {
+    pub fn mutex_enter_block_until(mtx: *mut mutex_t, until: absolute_time_t) -> bool;
+}
+extern "C" {
+    pub fn recursive_mutex_enter_block_until(mtx: *mut recursive_mutex_t, until: absolute_time_t) -> bool;
+}
+extern "C" {
+    pub fn mutex_exit(mtx: *mut mutex_t);
+}
+extern "C" {
+    pub fn recursive_mutex_exit(mtx: *mut recursive_mutex_t);
+}
+extern "C" {
+    pub fn sem_init(sem: *mut semaphore_t, initial_permits: i16, max_permits: i16);
+}
+extern "C" {
+    pub fn sem_available(sem: *mut semaphore_t) -> ::core::ffi::c_int;
+}
+extern "C" {
+    pub fn sem_release(sem: *mut semaphore_t) -> bool;
+}
+extern "C" {
+    pub fn sem_reset(sem: *mut semaphore_t, permits: i16);
+}
+extern "C" {
+    pub fn sem_acquire_blocking(sem: *mut semaphore_t);
+}
+extern "C" {
+    pub fn sem_acquire_timeout_ms(sem: *mut semaphore_t, timeout_ms: u32) -> bool;
+}
+extern "C" {
+    pub fn sem_acquire_timeout_us(sem: *mut semaphore_t, timeout_us: u32) -> bool;
+}
+extern "C" {
+    pub fn sem_acquire_block_until(sem: *mut semaphore_t, until: absolute_time_t) -> bool;
+}
+extern "C" {
+    pub fn sem_try_acquire(sem: *mut semaphore_t) -> bool;
+}
+extern "C" {
+    pub fn critical_section_init(crit_sec: *mut critical_section_t);
+}
+extern "C" {
+    pub fn critical_section_init_with_lock_num(crit_sec: *mut critical_section_t, lock_num: uint);
+}
+extern "C" {
+    pub fn critical_section_deinit(crit_sec: *mut critical_section_t);
+}
+extern "C" {
+    #[link_name = "critical_section_enter_blocking__extern"]
+    pub fn critical_section_enter_blocking(crit_sec: *mut critical_section_t);
+}
+extern "C" {
+    #[link_name = "critical_section_exit__extern"]
+    pub fn critical_section_exit(crit_sec: *mut critical_section_t);
+}
+extern "C" {
+    #[link_name = "critical_section_is_initialized__extern"]
+    pub fn critical_section_is_initialized(crit_sec: *mut critical_section_t) -> bool;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct queue_t {
+    pub core: lock_core_t,
+    pub data: *mut u8,
+    pub wptr: u16,
+    pub rptr: u16,
+    pub element_size: u16,
+    pub element_count: u16,
+}
+extern "C" {
+    pub fn queue_init_with_spinlock(q: *mut queue_t, element_size: uint, element_count: uint, spinlock_num: uint);
+}
+extern "C" {
+    pub fn queue_free(q: *mut queue_t);
+}
+extern "C" {
+    pub fn queue_try_add(q: *mut queue_t, data: *const ::core::ffi::c_void) -> bool;
+}
+extern "C" {
+    pub fn queue_try_remove(q: *mut queue_t, data: *mut ::core::ffi::c_void) -> bool;
+}
+extern "C" {
+    pub fn queue_try_peek(q: *mut queue_t, data: *mut ::core::ffi::c_void) -> bool;
+}
+extern "C" {
+    pub fn queue_add_blocking(q: *mut queue_t, data: *const ::core::ffi::c_void);
+}
+extern "C" {
+    pub fn queue_remove_blocking(q: *mut queue_t, data: *mut ::core::ffi::c_void);
+}
+extern "C" {
+    pub fn queue_peek_blocking(q: *mut queue_t, data: *mut ::core::ffi::c_void);
+}
+extern "C" {
+    #[link_name = "queue_get_level_unsafe__extern"]
+    pub fn queue_get_level_unsafe(q: *mut queue_t) -> uint;
+}
+extern "C" {
+    #[link_name = "queue_get_level__extern"]
+    pub fn queue_get_level(q: *mut queue_t) -> uint;
+}
+extern "C" {
+    #[link_name = "queue_is_empty__extern"]
+    pub fn queue_is_empty(q: *mut queue_t) -> bool;
+}
+extern "C" {
+    #[link_name = "queue_is_full__extern"]
+    pub fn queue_is_full(q: *mut queue_t) -> bool;
+}
+pub const FLASH_PAGE_SIZE: u32 = 256;
+pub const FLASH_SECTOR_SIZE: u32 = 4096;
+pub const FLASH_BLOCK_SIZE: u32 = 65536;
+pub const FLASH_UNIQUE_ID_SIZE_BYTES: u32 = 8;
+extern "C" {
+    pub fn flash_range_erase(flash_offs: u32, count: usize);
+}
+extern "C" {
+    pub fn flash_range_program(flash_offs: u32, data: *const u8, count: usize);
+}
+extern "C" {
+    pub fn flash_get_unique_id(id_out: *mut u8);
+}
+extern "C" {
+    pub fn flash_do_cmd(txbuf: *const u8, rxbuf: *mut u8, count: usize);
+}
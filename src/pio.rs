@@ -0,0 +1,762 @@
+//! PIO bindings
+//!
+//! [`PioProgram`] holds an assembled program together with its wrap and
+//! side-set settings, as produced by the `pio_asm!` and `include_pio!` macros
+//! of the `pio-asm` feature. A [`StateMachine`] owns one of the 4 state
+//! machines of a PIO and runs a [`LoadedProgram`] with a [`PioConfig`].
+//! Drivers such as [`Ws2812`] and [`PioUartTx`] wrap common programs.
+//!
+//! ```ignore
+//! static WS2812: PioProgram<4> = include_pio!("src/ws2812.pio");
+//!
+//! let mut sm = StateMachine::new(0).unwrap();
+//! let program = sm.load(&WS2812).unwrap();
+//! let config = program.config().side_set_pins(16).out_shift(false, true, 24).frequency(8_000_000);
+//!
+//! sm.set_pindirs(16, 1, true);
+//! sm.init(&program, &config);
+//! sm.start();
+//! sm.put(0xff0000 << 8);
+//! ```
+
+use crate::dma::DmaRegister;
+use crate::{claim, pico_sdk};
+use core::ptr;
+use pico_sdk::{pio_hw_t, pio_program, pio_sm_config, pio_sm_hw_t};
+use pico_sdk_pio::{FifoJoin, PioProgram, SideSet};
+
+mod drivers;
+
+pub use drivers::*;
+
+pub const PIO0_PTR: *mut pio_hw_t = 0x50200000u32 as _;
+pub const PIO1_PTR: *mut pio_hw_t = 0x50300000u32 as _;
+
+/// Number of PIO blocks
+pub const NUM_PIOS: u8 = 2;
+
+/// Number of state machines in each PIO
+pub const NUM_PIO_STATE_MACHINES: u8 = 4;
+
+/// Set while a [`LoadedProgram`] is being added to the PIO
+static mut PROGRAM_LOADING: [bool; NUM_PIOS as usize] = [false; NUM_PIOS as usize];
+
+const CTRL_SM_ENABLE_LSB: u32 = 0;
+const CTRL_SM_RESTART_LSB: u32 = 4;
+const CTRL_CLKDIV_RESTART_LSB: u32 = 8;
+const FDEBUG_TXSTALL_LSB: u32 = 24;
+const FSTAT_RXFULL_LSB: u32 = 0;
+const FSTAT_RXEMPTY_LSB: u32 = 8;
+const FSTAT_TXFULL_LSB: u32 = 16;
+const FSTAT_TXEMPTY_LSB: u32 = 24;
+const CLKDIV_FRAC_LSB: u32 = 8;
+const CLKDIV_INT_LSB: u32 = 16;
+const EXECCTRL_EXEC_STALLED: u32 = 1 << 31;
+const EXECCTRL_SIDE_EN: u32 = 1 << 30;
+const EXECCTRL_SIDE_PINDIR: u32 = 1 << 29;
+const EXECCTRL_JMP_PIN_LSB: u32 = 24;
+const EXECCTRL_OUT_STICKY: u32 = 1 << 17;
+const EXECCTRL_WRAP_TOP_LSB: u32 = 12;
+const EXECCTRL_WRAP_BOTTOM_LSB: u32 = 7;
+const EXECCTRL_STATUS_SEL: u32 = 1 << 4;
+const SHIFTCTRL_FJOIN_RX: u32 = 1 << 31;
+const SHIFTCTRL_FJOIN_TX: u32 = 1 << 30;
+const SHIFTCTRL_PULL_THRESH_LSB: u32 = 25;
+const SHIFTCTRL_PUSH_THRESH_LSB: u32 = 20;
+const SHIFTCTRL_IN_SHIFTDIR: u32 = 1 << 18;
+const SHIFTCTRL_OUT_SHIFTDIR: u32 = 1 << 19;
+const SHIFTCTRL_AUTOPULL: u32 = 1 << 17;
+const SHIFTCTRL_AUTOPUSH: u32 = 1 << 16;
+const PINCTRL_OUT_BASE_LSB: u32 = 0;
+const PINCTRL_SET_BASE_LSB: u32 = 5;
+const PINCTRL_SIDESET_BASE_LSB: u32 = 10;
+const PINCTRL_IN_BASE_LSB: u32 = 15;
+const PINCTRL_OUT_COUNT_LSB: u32 = 20;
+const PINCTRL_SET_COUNT_LSB: u32 = 26;
+const PINCTRL_SIDESET_COUNT_LSB: u32 = 29;
+
+/// Offsets of the atomic set and clear aliases of a register
+const REG_ALIAS_SET: u32 = 0x2000;
+const REG_ALIAS_CLR: u32 = 0x3000;
+const REG_ALIAS_XOR: u32 = 0x1000;
+
+/// Returns the registers of PIO `index`.
+pub const fn pio_ptr(index: u8) -> *mut pio_hw_t {
+  assert!(index < NUM_PIOS, "RP2040 only has 2 PIOs");
+
+  [PIO0_PTR, PIO1_PTR][index as usize]
+}
+
+/// Atomically sets `mask` in `register`.
+unsafe fn set_bits(register: *mut u32, mask: u32) {
+  ptr::write_volatile((register as u32 | REG_ALIAS_SET) as *mut u32, mask);
+}
+
+/// Atomically clears `mask` in `register`.
+unsafe fn clear_bits(register: *mut u32, mask: u32) {
+  ptr::write_volatile((register as u32 | REG_ALIAS_CLR) as *mut u32, mask);
+}
+
+/// Atomically toggles `mask` in `register`.
+unsafe fn toggle_bits(register: *mut u32, mask: u32) {
+  ptr::write_volatile((register as u32 | REG_ALIAS_XOR) as *mut u32, mask);
+}
+
+/// Returns `program` for the SDK, which refers to its instructions.
+fn raw_program<const N: usize>(program: &PioProgram<N>) -> pio_program {
+  pio_program {
+    instructions: program.instructions().as_ptr(),
+    length: N as u8,
+    origin: match program.origin() {
+      Some(origin) => origin as i8,
+      None => -1,
+    },
+  }
+}
+
+/// Returns the divider of `clk_sys` closest to `hz`.
+fn clkdiv_for(hz: u64) -> (u16, u8) {
+  let sys_hz = unsafe { pico_sdk::clock_get_hz(pico_sdk::clk_sys) } as u64;
+  let div256 = (sys_hz * 256 / hz.max(1)).clamp(256, u16::MAX as u64 * 256 + 255);
+
+  ((div256 >> 8) as u16, div256 as u8)
+}
+
+/// Configuration of a state machine, which starts as a program's
+/// [`LoadedProgram::config`]
+#[derive(Debug, Clone, Copy)]
+pub struct PioConfig {
+  config: pio_sm_config,
+}
+
+impl PioConfig {
+  /// Configuration for `program` loaded at `offset`, with the SDK's defaults
+  /// and the wrap and side-set settings of the program.
+  pub const fn for_program<const N: usize>(program: &PioProgram<N>, offset: u8) -> Self {
+    let side_set = program.side_set();
+    let wrap_target = (offset + program.wrap_target()) as u32;
+    let wrap = (offset + program.wrap()) as u32;
+    let mut execctrl = wrap << EXECCTRL_WRAP_TOP_LSB | wrap_target << EXECCTRL_WRAP_BOTTOM_LSB;
+
+    if side_set.is_optional() {
+      execctrl |= EXECCTRL_SIDE_EN;
+    }
+
+    if side_set.is_pindirs() {
+      execctrl |= EXECCTRL_SIDE_PINDIR;
+    }
+
+    Self::from_raw(pio_sm_config {
+      clkdiv: 1 << CLKDIV_INT_LSB,
+      execctrl,
+      shiftctrl: SHIFTCTRL_IN_SHIFTDIR | SHIFTCTRL_OUT_SHIFTDIR,
+      pinctrl: (side_set.field_bits() as u32) << PINCTRL_SIDESET_COUNT_LSB,
+    })
+  }
+
+  /// Wraps an SDK configuration.
+  pub const fn from_raw(config: pio_sm_config) -> Self {
+    Self { config }
+  }
+
+  /// Returns the SDK configuration.
+  pub const fn as_raw(&self) -> &pio_sm_config {
+    &self.config
+  }
+
+  const fn with_pinctrl(mut self, lsb: u32, bits: u32, value: u32) -> Self {
+    assert!(value < 1 << bits, "pin setting out of range");
+
+    self.config.pinctrl &= !(((1 << bits) - 1) << lsb);
+    self.config.pinctrl |= value << lsb;
+    self
+  }
+
+  /// Maps `out` to `count` pins starting at `base`.
+  pub const fn out_pins(self, base: u32, count: u32) -> Self {
+    self
+      .with_pinctrl(PINCTRL_OUT_BASE_LSB, 5, base)
+      .with_pinctrl(PINCTRL_OUT_COUNT_LSB, 6, count)
+  }
+
+  /// Maps `set` to `count` pins starting at `base`, at most 5.
+  pub const fn set_pins(self, base: u32, count: u32) -> Self {
+    assert!(count <= 5, "set can only drive 5 pins");
+
+    self
+      .with_pinctrl(PINCTRL_SET_BASE_LSB, 5, base)
+      .with_pinctrl(PINCTRL_SET_COUNT_LSB, 3, count)
+  }
+
+  /// Maps `in` and `wait pin` to the pins starting at `base`.
+  pub const fn in_pins(self, base: u32) -> Self {
+    self.with_pinctrl(PINCTRL_IN_BASE_LSB, 5, base)
+  }
+
+  /// Maps side-set to the pins starting at `base`.
+  pub const fn side_set_pins(self, base: u32) -> Self {
+    self.with_pinctrl(PINCTRL_SIDESET_BASE_LSB, 5, base)
+  }
+
+  /// Overrides the side-set settings of the program.
+  pub const fn side_set(mut self, side_set: SideSet) -> Self {
+    self.config.execctrl &= !(EXECCTRL_SIDE_EN | EXECCTRL_SIDE_PINDIR);
+
+    if side_set.is_optional() {
+      self.config.execctrl |= EXECCTRL_SIDE_EN;
+    }
+
+    if side_set.is_pindirs() {
+      self.config.execctrl |= EXECCTRL_SIDE_PINDIR;
+    }
+
+    self.with_pinctrl(PINCTRL_SIDESET_COUNT_LSB, 3, side_set.field_bits() as u32)
+  }
+
+  /// Uses `pin` for `jmp pin`.
+  pub const fn jmp_pin(mut self, pin: u32) -> Self {
+    assert!(pin < 32, "pin out of range");
+
+    self.config.execctrl &= !(0x1f << EXECCTRL_JMP_PIN_LSB);
+    self.config.execctrl |= pin << EXECCTRL_JMP_PIN_LSB;
+    self
+  }
+
+  /// Sets the absolute addresses the program wraps from `wrap` to
+  /// `wrap_target`.
+  pub const fn wrap(mut self, wrap_target: u32, wrap: u32) -> Self {
+    assert!(wrap_target < 32 && wrap < 32, "wrap out of range");
+
+    self.config.execctrl &= !(0x1f << EXECCTRL_WRAP_TOP_LSB | 0x1f << EXECCTRL_WRAP_BOTTOM_LSB);
+    self.config.execctrl |= wrap << EXECCTRL_WRAP_TOP_LSB | wrap_target << EXECCTRL_WRAP_BOTTOM_LSB;
+    self
+  }
+
+  /// Divides `clk_sys` by `integer + fraction / 256`.
+  pub const fn clkdiv(mut self, integer: u16, fraction: u8) -> Self {
+    assert!(integer > 0 || fraction == 0, "divider must be at least 1");
+
+    self.config.clkdiv = (integer as u32) << CLKDIV_INT_LSB | (fraction as u32) << CLKDIV_FRAC_LSB;
+    self
+  }
+
+  /// Runs the state machine at `hz` instructions per second, or as close as
+  /// the divider allows.
+  pub fn frequency(self, hz: u32) -> Self {
+    let (integer, fraction) = clkdiv_for(hz as u64);
+
+    self.clkdiv(integer, fraction)
+  }
+
+  /// Sets the shift direction, autopush and threshold of the ISR.
+  pub const fn in_shift(mut self, shift_right: bool, autopush: bool, threshold: u32) -> Self {
+    assert!(
+      threshold > 0 && threshold <= 32,
+      "threshold must be 1 to 32"
+    );
+
+    self.config.shiftctrl &=
+      !(SHIFTCTRL_IN_SHIFTDIR | SHIFTCTRL_AUTOPUSH | 0x1f << SHIFTCTRL_PUSH_THRESH_LSB);
+    self.config.shiftctrl |= (threshold & 0x1f) << SHIFTCTRL_PUSH_THRESH_LSB;
+
+    if shift_right {
+      self.config.shiftctrl |= SHIFTCTRL_IN_SHIFTDIR;
+    }
+
+    if autopush {
+      self.config.shiftctrl |= SHIFTCTRL_AUTOPUSH;
+    }
+
+    self
+  }
+
+  /// Sets the shift direction, autopull and threshold of the OSR.
+  pub const fn out_shift(mut self, shift_right: bool, autopull: bool, threshold: u32) -> Self {
+    assert!(
+      threshold > 0 && threshold <= 32,
+      "threshold must be 1 to 32"
+    );
+
+    self.config.shiftctrl &=
+      !(SHIFTCTRL_OUT_SHIFTDIR | SHIFTCTRL_AUTOPULL | 0x1f << SHIFTCTRL_PULL_THRESH_LSB);
+    self.config.shiftctrl |= (threshold & 0x1f) << SHIFTCTRL_PULL_THRESH_LSB;
+
+    if shift_right {
+      self.config.shiftctrl |= SHIFTCTRL_OUT_SHIFTDIR;
+    }
+
+    if autopull {
+      self.config.shiftctrl |= SHIFTCTRL_AUTOPULL;
+    }
+
+    self
+  }
+
+  /// Combines the FIFOs.
+  pub const fn fifo_join(mut self, join: FifoJoin) -> Self {
+    self.config.shiftctrl &= !(SHIFTCTRL_FJOIN_TX | SHIFTCTRL_FJOIN_RX);
+    self.config.shiftctrl |= match join {
+      FifoJoin::None => 0,
+      FifoJoin::Tx => SHIFTCTRL_FJOIN_TX,
+      FifoJoin::Rx => SHIFTCTRL_FJOIN_RX,
+    };
+    self
+  }
+
+  /// Keeps asserting the last `out` and `set` values on the pins.
+  pub const fn out_sticky(mut self, sticky: bool) -> Self {
+    self.config.execctrl &= !EXECCTRL_OUT_STICKY;
+
+    if sticky {
+      self.config.execctrl |= EXECCTRL_OUT_STICKY;
+    }
+
+    self
+  }
+
+  /// Makes `mov x, status` all ones while the TX FIFO level is below `level`.
+  pub const fn status_tx_below(mut self, level: u32) -> Self {
+    assert!(level < 16, "level out of range");
+
+    self.config.execctrl &= !(EXECCTRL_STATUS_SEL | 0xf);
+    self.config.execctrl |= level;
+    self
+  }
+
+  /// Makes `mov x, status` all ones while the RX FIFO level is below `level`.
+  pub const fn status_rx_below(mut self, level: u32) -> Self {
+    assert!(level < 16, "level out of range");
+
+    self.config.execctrl &= !(EXECCTRL_STATUS_SEL | 0xf);
+    self.config.execctrl |= EXECCTRL_STATUS_SEL | level;
+    self
+  }
+}
+
+/// A program in the instruction memory of a PIO
+///
+/// Dropping it frees the instruction memory, so state machines running it
+/// must be stopped first.
+pub struct LoadedProgram {
+  pio: u8,
+  offset: u8,
+  program: pio_program,
+  config: PioConfig,
+}
+
+impl LoadedProgram {
+  /// Loads `program` into PIO `pio`.
+  ///
+  /// Returns `None` if there isn't enough free space, or the origin of the
+  /// program is in use.
+  pub fn load<const N: usize>(pio: u8, program: &'static PioProgram<N>) -> Option<Self> {
+    let hw = pio_ptr(pio);
+    let raw = raw_program(program);
+    let loading = unsafe { ptr::addr_of_mut!(PROGRAM_LOADING[pio as usize]) };
+
+    // The SDK panics if the program doesn't fit, so nothing may load into
+    // the PIO between the check and adding it
+    let saved_irq = unsafe { pico_sdk::save_and_disable_interrupts() };
+    while !unsafe { claim::claim(loading) } {}
+
+    let offset = unsafe { pico_sdk::pio_can_add_program(hw, &raw) }
+      .then(|| unsafe { pico_sdk::pio_add_program(hw, &raw) } as u8);
+
+    unsafe {
+      claim::unclaim(loading);
+      pico_sdk::restore_interrupts(saved_irq);
+    }
+
+    let offset = offset?;
+
+    Some(Self {
+      pio,
+      offset,
+      program: raw,
+      config: PioConfig::for_program(program, offset),
+    })
+  }
+
+  /// PIO the program is loaded into
+  pub fn pio(&self) -> u8 {
+    self.pio
+  }
+
+  /// Address of the first instruction
+  pub fn offset(&self) -> u8 {
+    self.offset
+  }
+
+  /// Default configuration with the wrap and side-set settings of the
+  /// program
+  pub fn config(&self) -> PioConfig {
+    self.config
+  }
+
+  /// Frees the instruction memory of the program, like dropping it.
+  pub fn unload(self) {}
+}
+
+impl Drop for LoadedProgram {
+  fn drop(&mut self) {
+    unsafe { pico_sdk::pio_remove_program(pio_ptr(self.pio), &self.program, self.offset as u32) }
+  }
+}
+
+/// A claimed PIO state machine
+pub struct StateMachine {
+  pio: u8,
+  sm: u8,
+}
+
+impl StateMachine {
+  /// Claims an unused state machine of PIO `pio`.
+  ///
+  /// Returns `None` if all are in use.
+  pub fn new(pio: u8) -> Option<Self> {
+    let sm = unsafe { pico_sdk::pio_claim_unused_sm(pio_ptr(pio), false) };
+
+    if sm < 0 {
+      return None;
+    }
+
+    Some(Self { pio, sm: sm as u8 })
+  }
+
+  /// Claims state machine `sm` of PIO `pio`.
+  ///
+  /// Returns `None` if it is already in use.
+  pub fn with_index(pio: u8, sm: u8) -> Option<Self> {
+    assert!(
+      sm < NUM_PIO_STATE_MACHINES,
+      "PIOs only have 4 state machines"
+    );
+
+    let claimed = claim::claim_index(
+      sm as u32,
+      || unsafe { pico_sdk::pio_claim_unused_sm(pio_ptr(pio), false) },
+      |index| unsafe { pico_sdk::pio_sm_unclaim(pio_ptr(pio), index) },
+    );
+
+    claimed.then_some(Self { pio, sm })
+  }
+
+  /// PIO of the state machine
+  pub fn pio(&self) -> u8 {
+    self.pio
+  }
+
+  /// Index of the state machine in its PIO
+  pub fn index(&self) -> u8 {
+    self.sm
+  }
+
+  /// Returns the registers of the PIO.
+  pub fn pio_hw(&self) -> *mut pio_hw_t {
+    pio_ptr(self.pio)
+  }
+
+  /// Returns the registers of the state machine.
+  pub fn hw(&self) -> *mut pio_sm_hw_t {
+    unsafe { ptr::addr_of_mut!((*self.pio_hw()).sm[self.sm as usize]) }
+  }
+
+  /// Loads `program` into the PIO of the state machine.
+  pub fn load<const N: usize>(&self, program: &'static PioProgram<N>) -> Option<LoadedProgram> {
+    LoadedProgram::load(self.pio, program)
+  }
+
+  /// Stops the state machine, applies `config`, clears the FIFOs and
+  /// prepares to run `program` from its start.
+  pub fn init(&mut self, program: &LoadedProgram, config: &PioConfig) {
+    self.init_at(program, program.offset, config);
+  }
+
+  /// Like [`StateMachine::init`], but starting at address `pc`.
+  pub fn init_at(&mut self, program: &LoadedProgram, pc: u8, config: &PioConfig) {
+    assert!(
+      program.pio == self.pio,
+      "program is loaded into another PIO"
+    );
+
+    unsafe { pico_sdk::pio_sm_init(self.pio_hw(), self.sm as u32, pc as u32, config.as_raw()) }
+  }
+
+  /// Applies `config` while the state machine keeps its state.
+  pub fn set_config(&mut self, config: &PioConfig) {
+    let hw = self.hw();
+    let config = config.as_raw();
+
+    unsafe {
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).clkdiv), config.clkdiv);
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).execctrl), config.execctrl);
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).shiftctrl), config.shiftctrl);
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).pinctrl), config.pinctrl);
+    }
+  }
+
+  /// Sets the direction of `count` pins starting at `base`.
+  pub fn set_pindirs(&mut self, base: u32, count: u32, output: bool) {
+    unsafe {
+      pico_sdk::pio_sm_set_consecutive_pindirs(self.pio_hw(), self.sm as u32, base, count, output)
+    };
+  }
+
+  /// Drives the pins in `mask` to the levels in `values`.
+  ///
+  /// The state machine must be stopped.
+  pub fn set_pins(&mut self, values: u32, mask: u32) {
+    unsafe { pico_sdk::pio_sm_set_pins_with_mask(self.pio_hw(), self.sm as u32, values, mask) }
+  }
+
+  fn ctrl(&self) -> *mut u32 {
+    unsafe { ptr::addr_of_mut!((*self.pio_hw()).ctrl) }
+  }
+
+  /// Starts executing instructions.
+  pub fn start(&mut self) {
+    unsafe { set_bits(self.ctrl(), 1 << (CTRL_SM_ENABLE_LSB + self.sm as u32)) }
+  }
+
+  /// Stops executing instructions, keeping the state.
+  pub fn stop(&mut self) {
+    unsafe { clear_bits(self.ctrl(), 1 << (CTRL_SM_ENABLE_LSB + self.sm as u32)) }
+  }
+
+  /// Returns `true` while the state machine is running.
+  pub fn is_enabled(&self) -> bool {
+    unsafe { ptr::read_volatile(self.ctrl()) & (1 << (CTRL_SM_ENABLE_LSB + self.sm as u32)) != 0 }
+  }
+
+  /// Clears the shift registers, counters and stalls, without changing the
+  /// program counter or configuration.
+  pub fn restart(&mut self) {
+    unsafe { set_bits(self.ctrl(), 1 << (CTRL_SM_RESTART_LSB + self.sm as u32)) }
+  }
+
+  /// Restarts the clock divider, to keep several state machines in phase.
+  pub fn restart_clock(&mut self) {
+    unsafe { set_bits(self.ctrl(), 1 << (CTRL_CLKDIV_RESTART_LSB + self.sm as u32)) }
+  }
+
+  /// Changes the clock divider while running.
+  pub fn set_clkdiv(&mut self, integer: u16, fraction: u8) {
+    let config = PioConfig::from_raw(pio_sm_config {
+      clkdiv: 0,
+      execctrl: 0,
+      shiftctrl: 0,
+      pinctrl: 0,
+    })
+    .clkdiv(integer, fraction);
+
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!((*self.hw()).clkdiv), config.config.clkdiv) }
+  }
+
+  /// Changes the clock divider while running, to run at `hz` instructions
+  /// per second or as close as the divider allows.
+  pub fn set_frequency(&mut self, hz: u32) {
+    let (integer, fraction) = clkdiv_for(hz as u64);
+
+    self.set_clkdiv(integer, fraction);
+  }
+
+  /// Current program counter
+  pub fn pc(&self) -> u8 {
+    unsafe { ptr::read_volatile(ptr::addr_of!((*self.hw()).addr)) as u8 }
+  }
+
+  /// Executes `instruction` immediately, such as one built with
+  /// [`pio_encode_jmp`](crate::pio_encode_jmp) or
+  /// [`PioInstruction::encode`](crate::PioInstruction::encode).
+  pub fn exec(&mut self, instruction: u16) {
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!((*self.hw()).instr), instruction as u32) }
+  }
+
+  /// Executes `instruction` and waits until it has completed, if it stalls.
+  pub fn exec_blocking(&mut self, instruction: u16) {
+    self.exec(instruction);
+
+    while unsafe { ptr::read_volatile(ptr::addr_of!((*self.hw()).execctrl)) }
+      & EXECCTRL_EXEC_STALLED
+      != 0
+    {}
+  }
+
+  fn fstat(&self, lsb: u32) -> bool {
+    unsafe {
+      ptr::read_volatile(ptr::addr_of!((*self.pio_hw()).fstat)) & (1 << (lsb + self.sm as u32)) != 0
+    }
+  }
+
+  /// Returns `true` if the TX FIFO is full.
+  pub fn is_tx_full(&self) -> bool {
+    self.fstat(FSTAT_TXFULL_LSB)
+  }
+
+  /// Returns `true` if the TX FIFO is empty.
+  pub fn is_tx_empty(&self) -> bool {
+    self.fstat(FSTAT_TXEMPTY_LSB)
+  }
+
+  /// Returns `true` if the RX FIFO is full.
+  pub fn is_rx_full(&self) -> bool {
+    self.fstat(FSTAT_RXFULL_LSB)
+  }
+
+  /// Returns `true` if the RX FIFO is empty.
+  pub fn is_rx_empty(&self) -> bool {
+    self.fstat(FSTAT_RXEMPTY_LSB)
+  }
+
+  /// Number of words in the TX FIFO
+  pub fn tx_level(&self) -> u32 {
+    let flevel = unsafe { ptr::read_volatile(ptr::addr_of!((*self.pio_hw()).flevel)) };
+
+    (flevel >> (self.sm as u32 * 8)) & 0xf
+  }
+
+  /// Number of words in the RX FIFO
+  pub fn rx_level(&self) -> u32 {
+    let flevel = unsafe { ptr::read_volatile(ptr::addr_of!((*self.pio_hw()).flevel)) };
+
+    (flevel >> (self.sm as u32 * 8 + 4)) & 0xf
+  }
+
+  /// Writes `word` to the TX FIFO unless it is full.
+  pub fn try_put(&mut self, word: u32) -> bool {
+    if self.is_tx_full() {
+      return false;
+    }
+
+    unsafe {
+      ptr::write_volatile(
+        ptr::addr_of_mut!((*self.pio_hw()).txf[self.sm as usize]),
+        word,
+      )
+    };
+
+    true
+  }
+
+  /// Waits for space in the TX FIFO and writes `word`.
+  pub fn put(&mut self, word: u32) {
+    while !self.try_put(word) {}
+  }
+
+  /// Reads a word from the RX FIFO unless it is empty.
+  pub fn try_get(&mut self) -> Option<u32> {
+    if self.is_rx_empty() {
+      return None;
+    }
+
+    Some(unsafe { ptr::read_volatile(ptr::addr_of!((*self.pio_hw()).rxf[self.sm as usize])) })
+  }
+
+  /// Waits for a word in the RX FIFO and reads it.
+  pub fn get(&mut self) -> u32 {
+    loop {
+      if let Some(word) = self.try_get() {
+        return word;
+      }
+    }
+  }
+
+  /// Discards the contents of both FIFOs.
+  pub fn clear_fifos(&mut self) {
+    let shiftctrl = unsafe { ptr::addr_of_mut!((*self.hw()).shiftctrl) };
+
+    // Changing the join mode clears the FIFOs
+    unsafe {
+      toggle_bits(shiftctrl, SHIFTCTRL_FJOIN_RX);
+      toggle_bits(shiftctrl, SHIFTCTRL_FJOIN_RX);
+    }
+  }
+
+  /// Empties the TX FIFO by executing `pull`s, which changes the OSR.
+  pub fn drain_tx(&mut self) {
+    unsafe { pico_sdk::pio_sm_drain_tx_fifo(self.pio_hw(), self.sm as u32) }
+  }
+
+  /// DREQ of the TX FIFO, see [`DmaConfig::dreq`](crate::DmaConfig::dreq)
+  pub fn tx_dreq(&self) -> u32 {
+    pico_sdk::DREQ_PIO0_TX0 + self.pio as u32 * 8 + self.sm as u32
+  }
+
+  /// DREQ of the RX FIFO, see [`DmaConfig::dreq`](crate::DmaConfig::dreq)
+  pub fn rx_dreq(&self) -> u32 {
+    pico_sdk::DREQ_PIO0_RX0 + self.pio as u32 * 8 + self.sm as u32
+  }
+
+  /// TX FIFO as a DMA destination
+  pub fn tx_register(&self) -> DmaRegister<u32> {
+    unsafe {
+      DmaRegister::new(
+        ptr::addr_of_mut!((*self.pio_hw()).txf[self.sm as usize]),
+        self.tx_dreq(),
+      )
+    }
+  }
+
+  /// RX FIFO as a DMA source
+  pub fn rx_register(&self) -> DmaRegister<u32> {
+    unsafe {
+      DmaRegister::new(
+        ptr::addr_of_mut!((*self.pio_hw()).rxf[self.sm as usize]),
+        self.rx_dreq(),
+      )
+    }
+  }
+
+  /// Returns `true` if PIO IRQ flag `flag` (0 to 7) is set.
+  pub fn is_irq_set(&self, flag: u8) -> bool {
+    assert!(flag < 8, "PIOs only have 8 IRQ flags");
+
+    unsafe { ptr::read_volatile(ptr::addr_of!((*self.pio_hw()).irq)) & (1 << flag) != 0 }
+  }
+
+  /// Clears PIO IRQ flag `flag`, releasing state machines waiting on it.
+  pub fn clear_irq(&mut self, flag: u8) {
+    assert!(flag < 8, "PIOs only have 8 IRQ flags");
+
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!((*self.pio_hw()).irq), 1 << flag) }
+  }
+
+  /// Sets PIO IRQ flag `flag`.
+  pub fn force_irq(&mut self, flag: u8) {
+    assert!(flag < 8, "PIOs only have 8 IRQ flags");
+
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!((*self.pio_hw()).irq_force), 1 << flag) }
+  }
+
+  /// Routes `source` to the `PIOx_IRQ_0` or `PIOx_IRQ_1` interrupt, such as
+  /// [pis_interrupt0](pico_sdk::pis_interrupt0) or the FIFO sources of this
+  /// state machine.
+  pub fn set_irq_source_enabled(
+    &mut self,
+    irq: u8,
+    source: pico_sdk::pio_interrupt_source,
+    enabled: bool,
+  ) {
+    let inte = match irq {
+      0 => unsafe { ptr::addr_of_mut!((*self.pio_hw()).inte0) },
+      1 => unsafe { ptr::addr_of_mut!((*self.pio_hw()).inte1) },
+      _ => panic!("PIOs only have 2 IRQs"),
+    };
+
+    unsafe {
+      if enabled {
+        set_bits(inte, 1 << source);
+      } else {
+        clear_bits(inte, 1 << source);
+      }
+    }
+  }
+}
+
+impl Drop for StateMachine {
+  fn drop(&mut self) {
+    self.stop();
+
+    unsafe { pico_sdk::pio_sm_unclaim(self.pio_hw(), self.sm as u32) }
+  }
+}
@@ -0,0 +1,632 @@
+//! Ready-made PIO peripherals
+//!
+//! Each driver claims a state machine, loads its program from
+//! [`programs`](pico_sdk_pio::programs) and connects its pins to the PIO. The
+//! program is removed and the state machine freed when the driver is dropped.
+
+use super::{
+  clear_bits, clkdiv_for, pio_ptr, set_bits, LoadedProgram, StateMachine, FDEBUG_TXSTALL_LSB,
+};
+use crate::dma::{DmaChannel, DmaConfig, DmaRegister};
+use crate::pico_sdk;
+use crate::spi::SpiMode;
+use crate::uart::UartError;
+use core::cell::Cell;
+use core::{fmt, ptr};
+use pico_sdk_pio::{programs, FifoJoin, PioProgram};
+
+/// Returns the divider running `cycles` instructions per period of `hz`.
+///
+/// The instruction rate is computed in 64 bits, as it may not fit into a
+/// `u32`.
+fn clkdiv(hz: u32, cycles: u32) -> (u16, u8) {
+  clkdiv_for(hz as u64 * cycles as u64)
+}
+
+/// Claims a state machine of `pio` and loads `program` for it.
+fn claim<const N: usize>(
+  pio: u8,
+  program: &'static PioProgram<N>,
+) -> Option<(StateMachine, LoadedProgram)> {
+  let sm = StateMachine::new(pio)?;
+  let program = sm.load(program)?;
+
+  Some((sm, program))
+}
+
+/// Connects `count` pins starting at `base` to the PIO of `sm`.
+fn init_pins(sm: &mut StateMachine, base: u32, count: u32, output: bool) {
+  for pin in base..base + count {
+    unsafe { pico_sdk::gpio_set_function(pin, pico_sdk::GPIO_FUNC_PIO0 + sm.pio() as u32) }
+  }
+
+  sm.set_pindirs(base, count, output);
+}
+
+/// WS2812 (NeoPixel) LEDs on one pin, at 800 kHz
+///
+/// Colors are sent as words from [`Ws2812::rgb`] or [`Ws2812::rgbw`], one
+/// per LED, either directly or by DMA.
+pub struct Ws2812 {
+  // The state machine is declared first so it stops before the program is
+  // removed
+  sm: StateMachine,
+  _program: LoadedProgram,
+}
+
+impl Ws2812 {
+  /// Bit rate of the LEDs
+  pub const FREQUENCY: u32 = 800_000;
+
+  /// Drives LEDs on `pin` from PIO `pio`, with a white channel if `rgbw`.
+  ///
+  /// Returns `None` if no state machine or instruction memory is free.
+  pub fn new(pio: u8, pin: u32, rgbw: bool) -> Option<Self> {
+    let (mut sm, program) = claim(pio, &programs::WS2812)?;
+    let config = program
+      .config()
+      .side_set_pins(pin)
+      .out_shift(false, true, if rgbw { 32 } else { 24 })
+      .fifo_join(FifoJoin::Tx)
+      .frequency(Self::FREQUENCY * programs::WS2812_CYCLES_PER_BIT);
+
+    init_pins(&mut sm, pin, 1, true);
+    sm.init(&program, &config);
+    sm.start();
+
+    Some(Self {
+      sm,
+      _program: program,
+    })
+  }
+
+  /// Word for an RGB LED
+  pub const fn rgb(r: u8, g: u8, b: u8) -> u32 {
+    (g as u32) << 24 | (r as u32) << 16 | (b as u32) << 8
+  }
+
+  /// Word for an RGBW LED
+  pub const fn rgbw(r: u8, g: u8, b: u8, w: u8) -> u32 {
+    Self::rgb(r, g, b) | w as u32
+  }
+
+  /// Sends the color of the next LED.
+  pub fn put(&mut self, color: u32) {
+    self.sm.put(color);
+  }
+
+  /// Sends the colors of consecutive LEDs.
+  ///
+  /// The LEDs latch the colors once the line stays low for 50 µs.
+  pub fn write(&mut self, colors: &[u32]) {
+    for &color in colors {
+      self.put(color);
+    }
+  }
+
+  /// Sends `colors` with DMA on `channel` and waits until they are in the
+  /// FIFO.
+  pub fn write_dma(&mut self, channel: &mut DmaChannel, colors: &[u32]) {
+    channel.transfer_blocking(&DmaConfig::new(), colors, self.tx_register());
+  }
+
+  /// TX FIFO as a DMA destination for transfers of colors
+  pub fn tx_register(&self) -> DmaRegister<u32> {
+    self.sm.tx_register()
+  }
+}
+
+/// Counter of a quadrature encoder, with phase A on one pin and phase B on
+/// the next
+///
+/// The program must be loaded at address 0 of the PIO, so only one encoder
+/// fits into each PIO.
+pub struct QuadratureEncoder {
+  sm: StateMachine,
+  _program: LoadedProgram,
+}
+
+impl QuadratureEncoder {
+  /// Counts steps on `pin_a` and `pin_a + 1` with PIO `pio`, with pull-ups
+  /// enabled.
+  ///
+  /// Steps faster than `max_step_rate` per second may be missed, `0` runs at
+  /// the full system clock.
+  pub fn new(pio: u8, pin_a: u32, max_step_rate: u32) -> Option<Self> {
+    let (mut sm, program) = claim(pio, &programs::QUADRATURE_ENCODER)?;
+    let mut config = program
+      .config()
+      .in_pins(pin_a)
+      .in_shift(false, false, 32)
+      .fifo_join(FifoJoin::Rx);
+
+    if max_step_rate > 0 {
+      let (integer, fraction) = clkdiv(
+        max_step_rate,
+        programs::QUADRATURE_ENCODER_CYCLES_PER_SAMPLE,
+      );
+      config = config.clkdiv(integer, fraction);
+    }
+
+    init_pins(&mut sm, pin_a, 2, false);
+
+    for pin in pin_a..pin_a + 2 {
+      unsafe { pico_sdk::gpio_set_pulls(pin, true, false) }
+    }
+
+    sm.init(&program, &config);
+    sm.start();
+
+    Some(Self {
+      sm,
+      _program: program,
+    })
+  }
+
+  /// Returns the current count, which goes up when phase B leads.
+  pub fn count(&mut self) -> i32 {
+    // The FIFO holds older counts, the state machine pushes a new one right
+    // after the last is read
+    let mut count = 0;
+
+    for _ in 0..=self.sm.rx_level() {
+      count = self.sm.get();
+    }
+
+    count as i32
+  }
+}
+
+/// I2S output of 16 bit stereo samples, with BCLK on one pin and LRCLK on
+/// the next
+pub struct I2sOutput {
+  sm: StateMachine,
+  _program: LoadedProgram,
+}
+
+impl I2sOutput {
+  /// Outputs data on `data_pin` and the clocks on `clock_pin_base` and
+  /// `clock_pin_base + 1` with PIO `pio`, at `sample_rate` frames per
+  /// second.
+  pub fn new(pio: u8, data_pin: u32, clock_pin_base: u32, sample_rate: u32) -> Option<Self> {
+    let (mut sm, program) = claim(pio, &programs::I2S_OUT)?;
+    let (integer, fraction) = clkdiv(sample_rate, programs::I2S_OUT_CYCLES_PER_FRAME);
+    let config = program
+      .config()
+      .out_pins(data_pin, 1)
+      .side_set_pins(clock_pin_base)
+      .out_shift(false, true, 32)
+      .fifo_join(FifoJoin::Tx)
+      .clkdiv(integer, fraction);
+    let entry_point = programs::I2S_OUT.symbol("entry_point").unwrap() as u8;
+
+    init_pins(&mut sm, data_pin, 1, true);
+    init_pins(&mut sm, clock_pin_base, 2, true);
+    sm.init_at(&program, program.offset() + entry_point, &config);
+    sm.start();
+
+    Some(Self {
+      sm,
+      _program: program,
+    })
+  }
+
+  /// Changes the sample rate.
+  pub fn set_sample_rate(&mut self, sample_rate: u32) {
+    let (integer, fraction) = clkdiv(sample_rate, programs::I2S_OUT_CYCLES_PER_FRAME);
+
+    self.sm.set_clkdiv(integer, fraction);
+  }
+
+  /// Word of a stereo frame, for [`I2sOutput::write`] and DMA
+  pub const fn frame(left: i16, right: i16) -> u32 {
+    (right as u16 as u32) << 16 | left as u16 as u32
+  }
+
+  /// Sends the next stereo frame.
+  pub fn put(&mut self, left: i16, right: i16) {
+    self.sm.put(Self::frame(left, right));
+  }
+
+  /// Sends words of [`I2sOutput::frame`].
+  pub fn write(&mut self, frames: &[u32]) {
+    for &frame in frames {
+      self.sm.put(frame);
+    }
+  }
+
+  /// TX FIFO as a DMA destination for transfers of frames
+  pub fn tx_register(&self) -> DmaRegister<u32> {
+    self.sm.tx_register()
+  }
+}
+
+/// Returns the divider for `baudrate` bits per second.
+fn uart_clkdiv(baudrate: u32) -> (u16, u8) {
+  clkdiv(baudrate, programs::UART_CYCLES_PER_BIT)
+}
+
+/// 8N1 UART transmitter on a state machine, for more UARTs than the 2
+/// hardware ones
+pub struct PioUartTx {
+  sm: StateMachine,
+  _program: LoadedProgram,
+}
+
+impl PioUartTx {
+  /// Transmits on `pin` with PIO `pio` at `baudrate`.
+  pub fn new(pio: u8, pin: u32, baudrate: u32) -> Option<Self> {
+    let (mut sm, program) = claim(pio, &programs::UART_TX)?;
+    let (integer, fraction) = uart_clkdiv(baudrate);
+    let config = program
+      .config()
+      .out_pins(pin, 1)
+      .side_set_pins(pin)
+      .out_shift(true, false, 32)
+      .fifo_join(FifoJoin::Tx)
+      .clkdiv(integer, fraction);
+
+    // Idle high
+    sm.set_pins(1 << pin, 1 << pin);
+    init_pins(&mut sm, pin, 1, true);
+    sm.init(&program, &config);
+    sm.start();
+
+    Some(Self {
+      sm,
+      _program: program,
+    })
+  }
+
+  /// Returns `true` if there is space in the TX FIFO.
+  pub fn is_writable(&self) -> bool {
+    !self.sm.is_tx_full()
+  }
+
+  /// Writes a single byte, blocking until there is space in the TX FIFO.
+  pub fn write_byte(&mut self, byte: u8) {
+    self.sm.put(byte as u32);
+  }
+
+  /// Writes all of `bytes`, blocking until they fit in the TX FIFO.
+  pub fn write_blocking(&mut self, bytes: &[u8]) {
+    for &byte in bytes {
+      self.write_byte(byte);
+    }
+  }
+
+  /// Waits until the last byte has been shifted out, apart from its stop
+  /// bit.
+  pub fn flush(&mut self) {
+    let fdebug = unsafe { ptr::addr_of_mut!((*self.sm.pio_hw()).fdebug) };
+    let stall = 1 << (FDEBUG_TXSTALL_LSB + self.sm.index() as u32);
+
+    while !self.sm.is_tx_empty() {}
+
+    // The flag is cleared by writing 1, and set again once the state machine
+    // waits for the next byte
+    unsafe {
+      ptr::write_volatile(fdebug, stall);
+
+      while ptr::read_volatile(fdebug) & stall == 0 {}
+    }
+  }
+}
+
+impl fmt::Write for PioUartTx {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    self.write_blocking(s.as_bytes());
+    Ok(())
+  }
+}
+
+/// 8N1 UART receiver on a state machine
+///
+/// Framing errors and breaks are flagged with relative PIO IRQ 4, which is
+/// flag `4 + index` of the state machine.
+pub struct PioUartRx {
+  sm: StateMachine,
+  _program: LoadedProgram,
+  /// Error received after bytes which were already returned
+  error: Option<UartError>,
+}
+
+impl PioUartRx {
+  /// Receives on `pin` with PIO `pio` at `baudrate`, with the pull-up
+  /// enabled.
+  pub fn new(pio: u8, pin: u32, baudrate: u32) -> Option<Self> {
+    let (mut sm, program) = claim(pio, &programs::UART_RX)?;
+    let (integer, fraction) = uart_clkdiv(baudrate);
+    let config = program
+      .config()
+      .in_pins(pin)
+      .jmp_pin(pin)
+      .in_shift(true, false, 32)
+      .fifo_join(FifoJoin::Rx)
+      .clkdiv(integer, fraction);
+
+    init_pins(&mut sm, pin, 1, false);
+    unsafe { pico_sdk::gpio_set_pulls(pin, true, false) };
+    sm.init(&program, &config);
+    sm.start();
+
+    Some(Self {
+      sm,
+      _program: program,
+      error: None,
+    })
+  }
+
+  fn error_flag(&self) -> u8 {
+    4 + self.sm.index()
+  }
+
+  /// Returns `true` if a byte is in the RX FIFO.
+  pub fn is_readable(&self) -> bool {
+    !self.sm.is_rx_empty()
+  }
+
+  /// Reads a single byte if one is available, or reports a framing error
+  /// or break since the last read.
+  pub fn read_byte_nonblocking(&mut self) -> Option<Result<u8, UartError>> {
+    if let Some(error) = self.error.take() {
+      return Some(Err(error));
+    }
+
+    if self.sm.is_irq_set(self.error_flag()) {
+      self.sm.clear_irq(self.error_flag());
+
+      return Some(Err(UartError::Framing));
+    }
+
+    // The byte is in the top 8 bits of the ISR
+    self.sm.try_get().map(|word| Ok((word >> 24) as u8))
+  }
+
+  /// Reads a single byte, blocking until one is available.
+  pub fn read_byte(&mut self) -> Result<u8, UartError> {
+    loop {
+      if let Some(result) = self.read_byte_nonblocking() {
+        return result;
+      }
+
+      unsafe { pico_sdk::tight_loop_contents() };
+    }
+  }
+
+  /// Blocks until at least one byte is available and reads the available
+  /// bytes into `buffer`.
+  ///
+  /// An error after the first byte is returned by the next call instead.
+  pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, UartError> {
+    let mut read = 0;
+
+    for slot in buffer.iter_mut() {
+      let result = if read == 0 {
+        self.read_byte()
+      } else {
+        match self.read_byte_nonblocking() {
+          Some(result) => result,
+          None => break,
+        }
+      };
+
+      match result {
+        Ok(byte) => *slot = byte,
+        Err(error) if read > 0 => {
+          self.error = Some(error);
+          break;
+        }
+        Err(error) => return Err(error),
+      }
+
+      read += 1;
+    }
+
+    Ok(read)
+  }
+}
+
+/// SPI controller on a state machine, with SCK, MOSI and MISO on any pins
+///
+/// Chip select is left to the caller, for example as a GPIO output.
+pub struct PioSpi {
+  sm: StateMachine,
+  _program: LoadedProgram,
+  sck: u32,
+  miso: u32,
+  bits: u32,
+}
+
+impl PioSpi {
+  /// Runs SPI on the given pins with PIO `pio`, at `frequency` Hz with
+  /// frames of `bits` (1 to 32) bits.
+  ///
+  /// Returns `None` if `bits` is out of range or the PIO has no state
+  /// machine or space left.
+  pub fn new(
+    pio: u8,
+    sck: u32,
+    mosi: u32,
+    miso: u32,
+    mode: SpiMode,
+    bits: u32,
+    frequency: u32,
+  ) -> Option<Self> {
+    if !(1..=32).contains(&bits) {
+      return None;
+    }
+
+    let (mut sm, program) = if mode.cpha() {
+      claim(pio, &programs::SPI_CPHA1)?
+    } else {
+      claim(pio, &programs::SPI_CPHA0)?
+    };
+    let (integer, fraction) = clkdiv(frequency, programs::SPI_CYCLES_PER_BIT);
+    let config = program
+      .config()
+      .out_pins(mosi, 1)
+      .in_pins(miso)
+      .side_set_pins(sck)
+      .out_shift(false, true, bits)
+      .in_shift(false, true, bits)
+      .clkdiv(integer, fraction);
+    let outputs = 1 << sck | 1 << mosi;
+
+    sm.set_pins(0, outputs);
+    init_pins(&mut sm, sck, 1, true);
+    init_pins(&mut sm, mosi, 1, true);
+    init_pins(&mut sm, miso, 1, false);
+
+    // The clock polarity inverts SCK after the PIO
+    let polarity = if mode.cpol() {
+      pico_sdk::GPIO_OVERRIDE_INVERT
+    } else {
+      pico_sdk::GPIO_OVERRIDE_NORMAL
+    };
+
+    unsafe {
+      pico_sdk::gpio_set_outover(sck, polarity);
+
+      // MISO is sampled half a clock after SCK changes, which leaves no room
+      // for the input synchronizer at high clock rates
+      set_bits(
+        ptr::addr_of_mut!((*pio_ptr(pio)).input_sync_bypass),
+        1 << miso,
+      );
+    }
+
+    sm.init(&program, &config);
+    sm.start();
+
+    Some(Self {
+      sm,
+      _program: program,
+      sck,
+      miso,
+      bits,
+    })
+  }
+
+  /// Exchanges `len` frames, sending the frames returned by `write` and
+  /// passing the received ones to `read`.
+  fn exchange(
+    &mut self,
+    len: usize,
+    write: impl Fn(usize) -> u32,
+    mut read: impl FnMut(usize, u32),
+  ) {
+    let shift = 32 - self.bits;
+    let mask = u32::MAX >> shift;
+    let (mut sent, mut received) = (0, 0);
+
+    // Keep the FIFOs busy, but never more frames in flight than the RX FIFO
+    // takes
+    while received < len {
+      if sent < len && sent - received < 4 && self.sm.try_put(write(sent) << shift) {
+        sent += 1;
+      }
+
+      if let Some(word) = self.sm.try_get() {
+        read(received, word & mask);
+        received += 1;
+      }
+    }
+  }
+
+  /// Sends `frame` and returns the frame received at the same time.
+  pub fn transfer_frame(&mut self, frame: u32) -> u32 {
+    let mut received = 0;
+
+    self.exchange(1, |_| frame, |_, frame| received = frame);
+    received
+  }
+
+  /// Sends `bytes` and discards the received bytes.
+  pub fn write(&mut self, bytes: &[u8]) {
+    self.exchange(bytes.len(), |i| bytes[i] as u32, |_, _| {});
+  }
+
+  /// Receives into `bytes` while sending `fill`.
+  pub fn read(&mut self, bytes: &mut [u8], fill: u8) {
+    self.exchange(
+      bytes.len(),
+      |_| fill as u32,
+      |i, byte| bytes[i] = byte as u8,
+    );
+  }
+
+  /// Sends `bytes` and replaces them with the received bytes.
+  pub fn transfer_in_place(&mut self, bytes: &mut [u8]) {
+    let cells = Cell::from_mut(bytes).as_slice_of_cells();
+
+    self.exchange(
+      cells.len(),
+      |i| cells[i].get() as u32,
+      |i, byte| cells[i].set(byte as u8),
+    );
+  }
+}
+
+impl Drop for PioSpi {
+  fn drop(&mut self) {
+    unsafe {
+      pico_sdk::gpio_set_outover(self.sck, pico_sdk::GPIO_OVERRIDE_NORMAL);
+      clear_bits(
+        ptr::addr_of_mut!((*self.sm.pio_hw()).input_sync_bypass),
+        1 << self.miso,
+      );
+    }
+  }
+}
+
+/// Free-running clock for DVI or VGA style video, on one pin or as a
+/// differential pair on two
+pub struct PixelClock {
+  sm: StateMachine,
+  _program: LoadedProgram,
+}
+
+impl PixelClock {
+  /// Outputs a clock of `frequency` Hz on `pin` with PIO `pio`, and the
+  /// inverted clock on `pin + 1` if `differential`.
+  ///
+  /// The state machine runs at twice the frequency, so it can be at most
+  /// half the system clock.
+  pub fn new(pio: u8, pin: u32, frequency: u32, differential: bool) -> Option<Self> {
+    let (mut sm, program) = if differential {
+      claim(pio, &programs::PIXEL_CLOCK_DIFFERENTIAL)?
+    } else {
+      claim(pio, &programs::PIXEL_CLOCK)?
+    };
+    let (integer, fraction) = clkdiv(frequency, programs::PIXEL_CLOCK_CYCLES_PER_PERIOD);
+    let config = program
+      .config()
+      .side_set_pins(pin)
+      .clkdiv(integer, fraction);
+
+    init_pins(&mut sm, pin, if differential { 2 } else { 1 }, true);
+    sm.init(&program, &config);
+    sm.start();
+
+    Some(Self {
+      sm,
+      _program: program,
+    })
+  }
+
+  /// Changes the frequency, and restarts the clock divider so the clock
+  /// stays in phase with state machines restarted at the same time.
+  pub fn set_frequency(&mut self, frequency: u32) {
+    let (integer, fraction) = clkdiv(frequency, programs::PIXEL_CLOCK_CYCLES_PER_PERIOD);
+
+    self.sm.set_clkdiv(integer, fraction);
+    self.sm.restart_clock();
+  }
+
+  /// State machine of the clock, for example to synchronize it with the
+  /// ones shifting out pixels
+  pub fn state_machine(&mut self) -> &mut StateMachine {
+    &mut self.sm
+  }
+}
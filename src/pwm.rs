@@ -0,0 +1,435 @@
+//! PWM bindings
+//!
+//! The RP2040 has 8 PWM slices with two channels each. GPIO `n` is driven by
+//! channel `n & 1` (A for even, B for odd pins) of slice `(n >> 1) & 7`, see
+//! [`PwmPin`]. A [`Pwm`] owns one slice, and its frequency and duty cycle are
+//! set in hertz and nanoseconds; the counter wrap and clock divider are
+//! derived from `clk_sys`.
+//!
+//! ```ignore
+//! let pins = Pins::take().unwrap();
+//! let servo = pins.gpio16.into_function::<FunctionPwm>();
+//! let mut pwm = Pwm::for_pin(&servo).unwrap();
+//!
+//! pwm.set_frequency(50).unwrap();
+//! pwm.set_pulse_width_ns(servo.channel(), 1_500_000).unwrap();
+//! pwm.enable();
+//! ```
+//!
+//! The B channel of a slice can also be used as an input, in which case the
+//! slice becomes a [`PwmCounter`] that counts edges or measures how long the
+//! pin is high.
+
+use crate::gpio::{Function, FunctionPwm, Pin};
+use crate::{claim, pico_sdk};
+use core::ptr;
+use pico_sdk::pwm_hw_t;
+
+pub const PWM_PTR: *mut pwm_hw_t = 0x40050000u32 as _;
+
+/// Number of PWM slices
+pub const NUM_PWM_SLICES: u8 = 8;
+
+/// Largest wrap value used by [`Pwm`], one less than the maximum so that a
+/// level of `wrap + 1` (always high) still fits in 16 bits
+const MAX_WRAP: u32 = u16::MAX as u32 - 1;
+
+/// Divider in 1/16ths, the integer part is 8 bits and the fraction 4 bits
+const MIN_DIV16: u64 = 16;
+const MAX_DIV16: u64 = 255 * 16 + 15;
+
+const NS_PER_SECOND: u64 = 1_000_000_000;
+
+static mut PWM_CLAIMED: [bool; NUM_PWM_SLICES as usize] = [false; NUM_PWM_SLICES as usize];
+
+/// One of the two outputs of a slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Channel {
+  A = pico_sdk::PWM_CHAN_A,
+  B = pico_sdk::PWM_CHAN_B,
+}
+
+/// A pin in PWM function
+pub trait PwmPin {
+  /// Slice driving the pin
+  const SLICE: u8;
+  /// Channel of the slice driving the pin
+  const CHANNEL: Channel;
+
+  /// Returns the slice driving the pin.
+  fn slice(&self) -> u8 {
+    Self::SLICE
+  }
+
+  /// Returns the channel of the slice driving the pin.
+  fn channel(&self) -> Channel {
+    Self::CHANNEL
+  }
+}
+
+impl<const N: u8> PwmPin for Pin<N, Function<FunctionPwm>> {
+  const SLICE: u8 = ((Self::ID >> 1) & 7) as u8;
+  const CHANNEL: Channel = if Self::ID & 1 == 0 {
+    Channel::A
+  } else {
+    Channel::B
+  };
+}
+
+/// Errors reported when configuring a [`Pwm`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PwmError {
+  /// The frequency can't be reached with the current system clock
+  FrequencyOutOfRange,
+  /// The duty cycle or pulse width is longer than the period
+  DutyOutOfRange,
+}
+
+/// How a [`PwmCounter`] advances its counter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum InputMode {
+  /// Count clock cycles while the B pin is high
+  Gated = pico_sdk::PWM_DIV_B_HIGH,
+  /// Count rising edges of the B pin
+  RisingEdge = pico_sdk::PWM_DIV_B_RISING,
+  /// Count falling edges of the B pin
+  FallingEdge = pico_sdk::PWM_DIV_B_FALLING,
+}
+
+/// Divider (in 1/16ths) and wrap value producing `hz` from `sys_hz`
+///
+/// Picks the smallest divider, so the duty cycle has the finest resolution.
+const fn divider_for(sys_hz: u32, hz: u32, phase_correct: bool) -> Option<(u32, u16)> {
+  if hz == 0 {
+    return None;
+  }
+
+  let steps = if phase_correct { 2 } else { 1 };
+  // Length of a period in 1/16ths of a `clk_sys` cycle
+  let period16 = sys_hz as u64 * 16 / (hz as u64 * steps);
+  let mut div16 = period16.div_ceil(MAX_WRAP as u64 + 1);
+
+  if div16 < MIN_DIV16 {
+    div16 = MIN_DIV16;
+  }
+
+  let counts = (period16 + div16 / 2) / div16;
+
+  if div16 > MAX_DIV16 || counts < 2 {
+    return None;
+  }
+
+  Some((div16 as u32, (counts - 1) as u16))
+}
+
+/// A PWM slice
+pub struct Pwm {
+  slice: u8,
+  sys_hz: u32,
+  div16: u32,
+  wrap: u16,
+  phase_correct: bool,
+  inverted: [bool; 2],
+}
+
+impl Pwm {
+  /// Claims `slice` and resets it to a disabled, free running state.
+  ///
+  /// Returns `None` if the slice is already in use.
+  pub fn new(slice: u8) -> Option<Self> {
+    assert!(slice < NUM_PWM_SLICES, "RP2040 only has 8 PWM slices");
+
+    if !unsafe { claim::claim(ptr::addr_of_mut!(PWM_CLAIMED[slice as usize])) } {
+      return None;
+    }
+
+    let mut config = unsafe { pico_sdk::pwm_get_default_config() };
+    unsafe { pico_sdk::pwm_init(slice as _, &mut config, false) };
+
+    let mut pwm = Self {
+      slice,
+      sys_hz: unsafe { pico_sdk::clock_get_hz(pico_sdk::clk_sys) },
+      div16: MIN_DIV16 as u32,
+      wrap: 0,
+      phase_correct: false,
+      inverted: [false; 2],
+    };
+    pwm.set_wrap(MAX_WRAP as u16);
+
+    Some(pwm)
+  }
+
+  /// Claims the slice driving `pin`.
+  pub fn for_pin<P: PwmPin>(pin: &P) -> Option<Self> {
+    Self::new(pin.slice())
+  }
+
+  /// Disables the slice and releases it.
+  pub fn free(self) {
+    unsafe {
+      pico_sdk::pwm_set_enabled(self.slice as _, false);
+      claim::unclaim(ptr::addr_of_mut!(PWM_CLAIMED[self.slice as usize]));
+    }
+  }
+
+  /// Index of the slice
+  pub fn slice(&self) -> u8 {
+    self.slice
+  }
+
+  /// Returns the slice's registers.
+  pub fn hw(&self) -> *mut pico_sdk::pwm_slice_hw_t {
+    unsafe { ptr::addr_of_mut!((*PWM_PTR).slice[self.slice as usize]) }
+  }
+
+  fn steps(&self) -> u64 {
+    if self.phase_correct {
+      2
+    } else {
+      1
+    }
+  }
+
+  /// Sets the frequency of the outputs and returns the actual frequency.
+  ///
+  /// Channel levels are kept as-is, so the duty cycle must be set again
+  /// afterwards.
+  pub fn set_frequency(&mut self, hz: u32) -> Result<u32, PwmError> {
+    // The system clock may have been changed since the slice was claimed
+    self.sys_hz = unsafe { pico_sdk::clock_get_hz(pico_sdk::clk_sys) };
+
+    let (div16, wrap) =
+      divider_for(self.sys_hz, hz, self.phase_correct).ok_or(PwmError::FrequencyOutOfRange)?;
+    self.set_divider(div16);
+    self.set_wrap(wrap);
+
+    Ok(self.frequency())
+  }
+
+  /// Actual frequency of the outputs
+  pub fn frequency(&self) -> u32 {
+    let period16 = self.div16 as u64 * (self.wrap as u64 + 1) * self.steps();
+
+    (self.sys_hz as u64 * 16 / period16) as u32
+  }
+
+  /// Sets the clock divider in 1/16ths, between `16` and `4095`.
+  pub fn set_divider(&mut self, div16: u32) {
+    assert!((MIN_DIV16..=MAX_DIV16).contains(&(div16 as u64)));
+
+    self.div16 = div16;
+    unsafe {
+      pico_sdk::pwm_set_clkdiv_int_frac(self.slice as _, (div16 >> 4) as u8, (div16 & 15) as u8)
+    }
+  }
+
+  /// Sets the counter value at which the counter wraps.
+  pub fn set_wrap(&mut self, wrap: u16) {
+    self.wrap = wrap;
+    unsafe { pico_sdk::pwm_set_wrap(self.slice as _, wrap) }
+  }
+
+  /// Counter value at which the counter wraps; a level above it keeps the
+  /// channel high.
+  pub fn wrap(&self) -> u16 {
+    self.wrap
+  }
+
+  /// Sets the raw compare level of `channel`.
+  pub fn set_level(&mut self, channel: Channel, level: u16) {
+    unsafe { pico_sdk::pwm_set_chan_level(self.slice as _, channel as _, level) }
+  }
+
+  /// Sets the duty cycle of `channel` to `numerator / denominator`.
+  pub fn set_duty(
+    &mut self,
+    channel: Channel,
+    numerator: u32,
+    denominator: u32,
+  ) -> Result<(), PwmError> {
+    if denominator == 0 || numerator > denominator {
+      return Err(PwmError::DutyOutOfRange);
+    }
+
+    let level = (self.wrap as u64 + 1) * numerator as u64 / denominator as u64;
+    self.set_level(channel, level as u16);
+
+    Ok(())
+  }
+
+  /// Sets how long `channel` stays high in every period.
+  pub fn set_pulse_width_ns(&mut self, channel: Channel, ns: u32) -> Result<(), PwmError> {
+    let level =
+      ns as u64 * self.sys_hz as u64 * 16 / (self.div16 as u64 * self.steps() * NS_PER_SECOND);
+
+    if level > self.wrap as u64 + 1 {
+      return Err(PwmError::DutyOutOfRange);
+    }
+
+    self.set_level(channel, level as u16);
+
+    Ok(())
+  }
+
+  /// Enables or disables phase-correct mode, keeping the current frequency.
+  ///
+  /// In phase-correct mode the counter counts up and back down, so pulses
+  /// are centered in the period.
+  pub fn set_phase_correct(&mut self, phase_correct: bool) -> Result<u32, PwmError> {
+    let hz = self.frequency();
+    self.sys_hz = unsafe { pico_sdk::clock_get_hz(pico_sdk::clk_sys) };
+
+    // The slice is left unchanged if the frequency can't be kept
+    let (div16, wrap) =
+      divider_for(self.sys_hz, hz, phase_correct).ok_or(PwmError::FrequencyOutOfRange)?;
+
+    self.phase_correct = phase_correct;
+    unsafe { pico_sdk::pwm_set_phase_correct(self.slice as _, phase_correct) };
+    self.set_divider(div16);
+    self.set_wrap(wrap);
+
+    Ok(self.frequency())
+  }
+
+  /// Inverts the output of `channel`.
+  pub fn set_inverted(&mut self, channel: Channel, inverted: bool) {
+    self.inverted[channel as usize] = inverted;
+    unsafe {
+      pico_sdk::pwm_set_output_polarity(self.slice as _, self.inverted[0], self.inverted[1])
+    }
+  }
+
+  /// Starts the counter.
+  pub fn enable(&mut self) {
+    unsafe { pico_sdk::pwm_set_enabled(self.slice as _, true) }
+  }
+
+  /// Stops the counter, leaving the outputs at their current level.
+  pub fn disable(&mut self) {
+    unsafe { pico_sdk::pwm_set_enabled(self.slice as _, false) }
+  }
+
+  /// Returns `true` if the counter is running.
+  pub fn is_enabled(&self) -> bool {
+    unsafe { ptr::read_volatile(ptr::addr_of!((*PWM_PTR).en)) & (1 << self.slice) != 0 }
+  }
+
+  /// Current counter value
+  pub fn counter(&self) -> u16 {
+    unsafe { pico_sdk::pwm_get_counter(self.slice as _) }
+  }
+
+  /// Sets the counter value.
+  pub fn set_counter(&mut self, value: u16) {
+    unsafe { pico_sdk::pwm_set_counter(self.slice as _, value) }
+  }
+
+  /// Enables or disables the interrupt raised when the counter wraps.
+  pub fn set_irq_enabled(&mut self, enabled: bool) {
+    unsafe { pico_sdk::pwm_set_irq_enabled(self.slice as _, enabled) }
+  }
+
+  /// Clears the wrap interrupt of the slice.
+  pub fn clear_irq(&mut self) {
+    unsafe { pico_sdk::pwm_clear_irq(self.slice as _) }
+  }
+
+  /// Returns `true` if the wrap interrupt of the slice is pending.
+  pub fn is_irq_pending(&self) -> bool {
+    unsafe { pico_sdk::pwm_get_irq_status_mask() & (1 << self.slice) != 0 }
+  }
+
+  /// DMA request raised when the counter wraps
+  pub fn dreq(&self) -> u32 {
+    unsafe { pico_sdk::pwm_get_dreq(self.slice as _) }
+  }
+
+  /// Uses the slice's B pin as an input that advances the counter.
+  ///
+  /// The channel A output keeps working with the new counter.
+  pub fn into_counter<const N: u8>(
+    mut self,
+    pin: &Pin<N, Function<FunctionPwm>>,
+    mode: InputMode,
+  ) -> PwmCounter {
+    const {
+      assert!(N & 1 == 1, "Only channel B pins can be used as PWM input");
+    }
+    assert_eq!(pin.slice(), self.slice, "Pin isn't driven by this slice");
+
+    self.disable();
+    self.set_divider(MIN_DIV16 as u32);
+    self.set_wrap(u16::MAX);
+    self.set_counter(0);
+    unsafe { pico_sdk::pwm_set_clkdiv_mode(self.slice as _, mode as _) };
+
+    PwmCounter { pwm: self }
+  }
+}
+
+/// Starts the counters of all `slices` in the same clock cycle.
+///
+/// The counters are reset first, so the outputs are in phase. Slices which
+/// are already running and not in `slices` are left running.
+pub fn enable_synchronized(slices: &mut [&mut Pwm]) {
+  let mut mask = 0;
+
+  for pwm in slices.iter_mut() {
+    pwm.disable();
+    pwm.set_counter(0);
+    mask |= 1 << pwm.slice;
+  }
+
+  unsafe {
+    let enabled = ptr::read_volatile(ptr::addr_of!((*PWM_PTR).en));
+    pico_sdk::pwm_set_mask_enabled(enabled | mask);
+  }
+}
+
+/// A PWM slice counting on its B pin
+pub struct PwmCounter {
+  pwm: Pwm,
+}
+
+impl PwmCounter {
+  /// Current count
+  pub fn count(&self) -> u16 {
+    self.pwm.counter()
+  }
+
+  /// Resets the count to zero.
+  pub fn reset(&mut self) {
+    self.pwm.set_counter(0);
+  }
+
+  /// Starts counting.
+  pub fn enable(&mut self) {
+    self.pwm.enable();
+  }
+
+  /// Stops counting.
+  pub fn disable(&mut self) {
+    self.pwm.disable();
+  }
+
+  /// Advances the counter only every `div16 / 16` events.
+  pub fn set_divider(&mut self, div16: u32) {
+    self.pwm.set_divider(div16);
+  }
+
+  /// Returns the slice, for example to drive channel A from the count.
+  pub fn as_pwm(&mut self) -> &mut Pwm {
+    &mut self.pwm
+  }
+
+  /// Switches the slice back to free running PWM output.
+  pub fn into_pwm(mut self) -> Pwm {
+    self.pwm.disable();
+    unsafe { pico_sdk::pwm_set_clkdiv_mode(self.pwm.slice as _, pico_sdk::PWM_DIV_FREE_RUNNING) };
+    self.pwm.set_wrap(MAX_WRAP as u16);
+
+    self.pwm
+  }
+}
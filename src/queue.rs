@@ -0,0 +1,234 @@
+//! Queues for passing values between cores and interrupt handlers
+//!
+//! [`Queue`] wraps the SDK's `queue_t`, which may be used by any number of
+//! producers and consumers on both cores and in interrupt handlers. Unlike
+//! `queue_init`, the storage lives inside the `Queue`, so no heap is needed.
+//!
+//! ```ignore
+//! static EVENTS: Queue<u32, 16> = Queue::new();
+//!
+//! EVENTS.try_add(42).ok();
+//! let event = EVENTS.remove_blocking();
+//! ```
+//!
+//! [`SpscQueue`](crate::SpscQueue) is a lock-free ring buffer with the same
+//! operations for a single producer and a single consumer, e.g. an interrupt
+//! handler feeding the main loop. It lives in pico-sdk-util, which is tested
+//! on the host; [`ProducerTimeout`] and [`ConsumerTimeout`] add the timeout
+//! variants.
+
+use crate::pico_sdk;
+use crate::sync::{striped_lock_core, timeout_time};
+use core::cell::UnsafeCell;
+use core::ffi::c_void;
+use core::mem::{self, MaybeUninit};
+use core::ptr;
+use core::time::Duration;
+use pico_sdk::queue_t;
+use pico_sdk_util::{Consumer, Producer};
+
+/// Storage for `N + 1` elements, the SDK always leaves one slot empty
+#[repr(C)]
+struct Slots<T, const N: usize> {
+  elements: [MaybeUninit<T>; N],
+  spare: MaybeUninit<T>,
+}
+
+/// Multi-producer, multi-consumer queue of up to `N` values
+///
+/// Values are copied in and out by the SDK, hence `T: Copy`. A queue must not
+/// be moved while another core or an interrupt handler is using it, which is
+/// easiest to guarantee by placing it in a `static`.
+pub struct Queue<T: Copy, const N: usize> {
+  queue: UnsafeCell<queue_t>,
+  slots: UnsafeCell<Slots<T, N>>,
+}
+
+unsafe impl<T: Copy + Send, const N: usize> Send for Queue<T, N> {}
+unsafe impl<T: Copy + Send, const N: usize> Sync for Queue<T, N> {}
+
+impl<T: Copy, const N: usize> Queue<T, N> {
+  /// Creates an empty queue.
+  pub const fn new() -> Self {
+    assert!(mem::size_of::<T>() > 0 && mem::size_of::<T>() <= u16::MAX as usize);
+    assert!(N > 0 && N < u16::MAX as usize);
+
+    Self {
+      queue: UnsafeCell::new(queue_t {
+        core: striped_lock_core(),
+        // Set on first use, once the queue has its final address
+        data: ptr::null_mut(),
+        wptr: 0,
+        rptr: 0,
+        element_size: mem::size_of::<T>() as u16,
+        element_count: N as u16,
+      }),
+      slots: UnsafeCell::new(Slots {
+        elements: [const { MaybeUninit::uninit() }; N],
+        spare: MaybeUninit::uninit(),
+      }),
+    }
+  }
+
+  /// Returns the underlying SDK queue.
+  pub fn as_ptr(&self) -> *mut queue_t {
+    let queue = self.queue.get();
+    let slots = self.slots.get() as *mut u8;
+
+    unsafe {
+      let data = ptr::addr_of_mut!((*queue).data);
+
+      if ptr::read_volatile(data) != slots {
+        // Both cores may get here on first use, the lock keeps the write from
+        // racing with the SDK reading the pointer
+        let lock = (*queue).core.spin_lock;
+        let saved_irq = pico_sdk::spin_lock_blocking(lock);
+        ptr::write_volatile(data, slots);
+        pico_sdk::spin_unlock(lock, saved_irq);
+      }
+    }
+
+    queue
+  }
+
+  /// Maximum number of values in the queue
+  pub const fn capacity(&self) -> usize {
+    N
+  }
+
+  /// Number of values in the queue
+  pub fn len(&self) -> usize {
+    unsafe { pico_sdk::queue_get_level(self.as_ptr()) as usize }
+  }
+
+  /// Returns `true` if the queue holds no values.
+  pub fn is_empty(&self) -> bool {
+    unsafe { pico_sdk::queue_is_empty(self.as_ptr()) }
+  }
+
+  /// Returns `true` if no more values can be added.
+  pub fn is_full(&self) -> bool {
+    unsafe { pico_sdk::queue_is_full(self.as_ptr()) }
+  }
+
+  /// Adds `value` to the back of the queue, returning it if the queue is full.
+  pub fn try_add(&self, value: T) -> Result<(), T> {
+    if unsafe { pico_sdk::queue_try_add(self.as_ptr(), &value as *const T as *const c_void) } {
+      Ok(())
+    } else {
+      Err(value)
+    }
+  }
+
+  /// Adds `value` to the back of the queue, waiting for space.
+  pub fn add_blocking(&self, value: T) {
+    unsafe { pico_sdk::queue_add_blocking(self.as_ptr(), &value as *const T as *const c_void) }
+  }
+
+  /// Adds `value` to the back of the queue, waiting at most `timeout` for
+  /// space.
+  pub fn try_add_for(&self, mut value: T, timeout: Duration) -> Result<(), T> {
+    let until = timeout_time(timeout);
+
+    loop {
+      value = match self.try_add(value) {
+        Ok(()) => return Ok(()),
+        Err(value) => value,
+      };
+
+      // The SDK signals an event whenever a value is removed
+      if unsafe { pico_sdk::best_effort_wfe_or_timeout(until) } {
+        return self.try_add(value);
+      }
+    }
+  }
+
+  /// Removes the value at the front of the queue, `None` if it is empty.
+  pub fn try_remove(&self) -> Option<T> {
+    let mut value = MaybeUninit::<T>::uninit();
+
+    unsafe { pico_sdk::queue_try_remove(self.as_ptr(), value.as_mut_ptr() as *mut c_void) }
+      .then(|| unsafe { value.assume_init() })
+  }
+
+  /// Removes the value at the front of the queue, waiting for one.
+  pub fn remove_blocking(&self) -> T {
+    let mut value = MaybeUninit::<T>::uninit();
+
+    unsafe {
+      pico_sdk::queue_remove_blocking(self.as_ptr(), value.as_mut_ptr() as *mut c_void);
+      value.assume_init()
+    }
+  }
+
+  /// Removes the value at the front of the queue, waiting at most `timeout`
+  /// for one.
+  pub fn try_remove_for(&self, timeout: Duration) -> Option<T> {
+    let until = timeout_time(timeout);
+
+    loop {
+      if let Some(value) = self.try_remove() {
+        return Some(value);
+      }
+
+      if unsafe { pico_sdk::best_effort_wfe_or_timeout(until) } {
+        return self.try_remove();
+      }
+    }
+  }
+
+  /// Returns a copy of the value at the front of the queue without removing
+  /// it, `None` if the queue is empty.
+  pub fn peek(&self) -> Option<T> {
+    let mut value = MaybeUninit::<T>::uninit();
+
+    unsafe { pico_sdk::queue_try_peek(self.as_ptr(), value.as_mut_ptr() as *mut c_void) }
+      .then(|| unsafe { value.assume_init() })
+  }
+
+  /// Returns a copy of the value at the front of the queue, waiting for one.
+  pub fn peek_blocking(&self) -> T {
+    let mut value = MaybeUninit::<T>::uninit();
+
+    unsafe {
+      pico_sdk::queue_peek_blocking(self.as_ptr(), value.as_mut_ptr() as *mut c_void);
+      value.assume_init()
+    }
+  }
+}
+
+impl<T: Copy, const N: usize> Default for Queue<T, N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Timeout variant of [`Producer::try_add`]
+pub trait ProducerTimeout<T> {
+  /// Adds `value` to the back of the queue, waiting at most `timeout` for
+  /// space.
+  fn try_add_for(&mut self, value: T, timeout: Duration) -> Result<(), T>;
+}
+
+impl<T: Copy, const N: usize> ProducerTimeout<T> for Producer<'_, T, N> {
+  fn try_add_for(&mut self, value: T, timeout: Duration) -> Result<(), T> {
+    let until = timeout_time(timeout);
+
+    self.try_add_until(value, || unsafe { pico_sdk::time_reached(until) })
+  }
+}
+
+/// Timeout variant of [`Consumer::try_remove`]
+pub trait ConsumerTimeout<T> {
+  /// Removes the value at the front of the queue, waiting at most `timeout`
+  /// for one.
+  fn try_remove_for(&mut self, timeout: Duration) -> Option<T>;
+}
+
+impl<T: Copy, const N: usize> ConsumerTimeout<T> for Consumer<'_, T, N> {
+  fn try_remove_for(&mut self, timeout: Duration) -> Option<T> {
+    let until = timeout_time(timeout);
+
+    self.try_remove_until(|| unsafe { pico_sdk::time_reached(until) })
+  }
+}
@@ -0,0 +1,560 @@
+//! SPI bindings
+//!
+//! [`Spi`] owns one of the two SPI instances together with its pins. It runs
+//! as controller or peripheral in any of the 4 [`SpiMode`]s, with frames of 4
+//! to 16 bits sent as `u8` or `u16` words. Devices sharing a bus are accessed
+//! through [`SpiDevice`], which drives their chip select.
+//!
+//! ```ignore
+//! let pins = Pins::take().unwrap();
+//! let spi = Spi::new(
+//!   (pins.gpio18.into_function(), pins.gpio19.into_function(), pins.gpio16.into_function()),
+//!   SpiConfig::default(),
+//! )
+//! .unwrap();
+//! let bus = RefCell::new(spi);
+//! let mut flash = SpiDevice::new(&bus, pins.gpio17.into_output_high());
+//!
+//! let mut id = [0x9f, 0, 0, 0];
+//! flash.transaction(|spi| spi.transfer_in_place(&mut id));
+//! ```
+
+use crate::dma::{DmaChannel, DmaConfig, DmaRegister, DmaWord};
+use crate::gpio::{Function, FunctionSpi, Output, Pin};
+use crate::{claim, pico_sdk};
+use core::cell::RefCell;
+use core::ptr;
+use pico_sdk::{spi_hw_t, spi_inst_t};
+
+pub const SPI0_PTR: *mut spi_inst_t = 0x4003c000u32 as _;
+pub const SPI1_PTR: *mut spi_inst_t = 0x40040000u32 as _;
+
+const SSPCR0_DSS_LSB: u32 = 0;
+const SSPCR0_DSS_BITS: u32 = 0xf;
+const SSPCR0_SPO: u32 = 1 << 6;
+const SSPCR0_SPH: u32 = 1 << 7;
+const SSPCR1_SSE: u32 = 1 << 1;
+const SSPCR1_MS: u32 = 1 << 2;
+const SSPSR_RNE: u32 = 1 << 2;
+const SSPSR_BSY: u32 = 1 << 4;
+const SSPICR_RORIC: u32 = 1 << 0;
+
+static mut SPI_CLAIMED: [bool; 2] = [false; 2];
+
+/// One of the SPI instances
+pub trait SpiInstance {
+  const PTR: *mut spi_inst_t;
+  const INDEX: usize;
+}
+
+/// SPI0 instance
+pub struct Spi0;
+
+/// SPI1 instance
+pub struct Spi1;
+
+impl SpiInstance for Spi0 {
+  const PTR: *mut spi_inst_t = SPI0_PTR;
+  const INDEX: usize = 0;
+}
+
+impl SpiInstance for Spi1 {
+  const PTR: *mut spi_inst_t = SPI1_PTR;
+  const INDEX: usize = 1;
+}
+
+/// A pin which can be used as SPI clock
+pub trait SpiSckPin {
+  type Instance: SpiInstance;
+}
+
+/// A pin which can be used as SPI TX, which is MOSI for a controller and MISO
+/// for a peripheral
+pub trait SpiTxPin {
+  type Instance: SpiInstance;
+}
+
+/// A pin which can be used as SPI RX, which is MISO for a controller and MOSI
+/// for a peripheral
+pub trait SpiRxPin {
+  type Instance: SpiInstance;
+}
+
+/// A pin which can be used as the hardware chip select
+pub trait SpiCsPin {
+  type Instance: SpiInstance;
+}
+
+macro_rules! spi_pins {
+  ($($instance:ident: sck = [$($sck:literal),+], tx = [$($tx:literal),+], rx = [$($rx:literal),+], cs = [$($cs:literal),+];)+) => {
+    $(
+      $(impl SpiSckPin for Pin<$sck, Function<FunctionSpi>> {
+        type Instance = $instance;
+      })+
+      $(impl SpiTxPin for Pin<$tx, Function<FunctionSpi>> {
+        type Instance = $instance;
+      })+
+      $(impl SpiRxPin for Pin<$rx, Function<FunctionSpi>> {
+        type Instance = $instance;
+      })+
+      $(impl SpiCsPin for Pin<$cs, Function<FunctionSpi>> {
+        type Instance = $instance;
+      })+
+    )+
+  };
+}
+
+spi_pins! {
+  Spi0: sck = [2, 6, 18, 22], tx = [3, 7, 19, 23], rx = [0, 4, 16, 20], cs = [1, 5, 17, 21];
+  Spi1: sck = [10, 14, 26], tx = [11, 15, 27], rx = [8, 12, 24, 28], cs = [9, 13, 25, 29];
+}
+
+/// Pins belonging to the same SPI instance: clock and TX, optionally followed
+/// by RX and the hardware chip select
+pub trait SpiPins {
+  type Instance: SpiInstance;
+
+  /// Whether the hardware chip select is included, which the peripheral role
+  /// needs
+  const HAS_CS: bool = false;
+}
+
+impl<SCK: SpiSckPin, TX: SpiTxPin<Instance = SCK::Instance>> SpiPins for (SCK, TX) {
+  type Instance = SCK::Instance;
+}
+
+impl<SCK, TX, RX> SpiPins for (SCK, TX, RX)
+where
+  SCK: SpiSckPin,
+  TX: SpiTxPin<Instance = SCK::Instance>,
+  RX: SpiRxPin<Instance = SCK::Instance>,
+{
+  type Instance = SCK::Instance;
+}
+
+impl<SCK, TX, RX, CS> SpiPins for (SCK, TX, RX, CS)
+where
+  SCK: SpiSckPin,
+  TX: SpiTxPin<Instance = SCK::Instance>,
+  RX: SpiRxPin<Instance = SCK::Instance>,
+  CS: SpiCsPin<Instance = SCK::Instance>,
+{
+  type Instance = SCK::Instance;
+
+  const HAS_CS: bool = true;
+}
+
+/// Clock polarity and phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpiMode {
+  /// Idle low, sampling on the rising edge
+  #[default]
+  Mode0,
+  /// Idle low, sampling on the falling edge
+  Mode1,
+  /// Idle high, sampling on the falling edge
+  Mode2,
+  /// Idle high, sampling on the rising edge
+  Mode3,
+}
+
+impl SpiMode {
+  /// Returns `true` if the clock idles high.
+  pub const fn cpol(self) -> bool {
+    matches!(self, SpiMode::Mode2 | SpiMode::Mode3)
+  }
+
+  /// Returns `true` if data is sampled on the second clock edge.
+  pub const fn cpha(self) -> bool {
+    matches!(self, SpiMode::Mode1 | SpiMode::Mode3)
+  }
+}
+
+/// Whether the SPI drives the clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpiRole {
+  #[default]
+  Controller,
+  /// Clocked by another controller while the chip select is low, which
+  /// needs the pins to include the hardware chip select
+  Peripheral,
+}
+
+/// Format and role of a [`Spi`]
+///
+/// The default is a 1 MHz controller in mode 0 with 8 bit frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpiConfig {
+  pub baudrate: u32,
+  pub mode: SpiMode,
+  /// Bits per frame, 4 to 16
+  pub data_bits: u8,
+  pub role: SpiRole,
+}
+
+impl Default for SpiConfig {
+  fn default() -> Self {
+    Self {
+      baudrate: 1_000_000,
+      mode: SpiMode::default(),
+      data_bits: 8,
+      role: SpiRole::default(),
+    }
+  }
+}
+
+/// Word of a transfer, `u8` for frames of up to 8 bits and `u16` for longer
+/// frames
+pub trait SpiWord: DmaWord + Default + 'static {
+  #[doc(hidden)]
+  unsafe fn write_read(spi: *mut spi_inst_t, write: *const Self, read: *mut Self, len: usize);
+  #[doc(hidden)]
+  unsafe fn write(spi: *mut spi_inst_t, write: *const Self, len: usize);
+  #[doc(hidden)]
+  unsafe fn read(spi: *mut spi_inst_t, fill: Self, read: *mut Self, len: usize);
+}
+
+impl SpiWord for u8 {
+  unsafe fn write_read(spi: *mut spi_inst_t, write: *const u8, read: *mut u8, len: usize) {
+    pico_sdk::spi_write_read_blocking(spi, write, read, len);
+  }
+
+  unsafe fn write(spi: *mut spi_inst_t, write: *const u8, len: usize) {
+    pico_sdk::spi_write_blocking(spi, write, len);
+  }
+
+  unsafe fn read(spi: *mut spi_inst_t, fill: u8, read: *mut u8, len: usize) {
+    pico_sdk::spi_read_blocking(spi, fill, read, len);
+  }
+}
+
+impl SpiWord for u16 {
+  unsafe fn write_read(spi: *mut spi_inst_t, write: *const u16, read: *mut u16, len: usize) {
+    pico_sdk::spi_write16_read16_blocking(spi, write, read, len);
+  }
+
+  unsafe fn write(spi: *mut spi_inst_t, write: *const u16, len: usize) {
+    pico_sdk::spi_write16_blocking(spi, write, len);
+  }
+
+  unsafe fn read(spi: *mut spi_inst_t, fill: u16, read: *mut u16, len: usize) {
+    pico_sdk::spi_read16_blocking(spi, fill, read, len);
+  }
+}
+
+/// An SPI instance and its pins
+pub struct Spi<P: SpiPins> {
+  pins: P,
+  config: SpiConfig,
+  baudrate: u32,
+}
+
+impl<P: SpiPins> Spi<P> {
+  /// Initializes the SPI instance of `pins` with `config`.
+  ///
+  /// Returns `None` if the instance is already in use. Panics if
+  /// `config.role` is [`SpiRole::Peripheral`] but `pins` have no chip select.
+  pub fn new(pins: P, config: SpiConfig) -> Option<Self> {
+    Self::check_role(config.role);
+
+    if !unsafe { claim::claim(ptr::addr_of_mut!(SPI_CLAIMED[P::Instance::INDEX])) } {
+      return None;
+    }
+
+    let baudrate = unsafe { pico_sdk::spi_init(P::Instance::PTR, config.baudrate) };
+    let mut spi = Self {
+      pins,
+      config,
+      baudrate,
+    };
+    spi.set_format(config.mode, config.data_bits);
+    spi.set_role(config.role);
+
+    Some(spi)
+  }
+
+  /// Disables the SPI and returns its pins.
+  pub fn free(self) -> P {
+    unsafe {
+      pico_sdk::spi_deinit(P::Instance::PTR);
+      claim::unclaim(ptr::addr_of_mut!(SPI_CLAIMED[P::Instance::INDEX]));
+    }
+
+    self.pins
+  }
+
+  /// Returns the underlying SDK instance.
+  pub fn as_ptr(&self) -> *mut spi_inst_t {
+    P::Instance::PTR
+  }
+
+  /// Peripheral mode is only selected through the hardware chip select.
+  fn check_role(role: SpiRole) {
+    assert!(
+      role == SpiRole::Controller || P::HAS_CS,
+      "the SPI peripheral role needs the hardware chip select pin"
+    );
+  }
+
+  fn hw(&self) -> *mut spi_hw_t {
+    P::Instance::PTR as _
+  }
+
+  /// Runs `f` with the SPI disabled, as needed to change its format.
+  fn while_disabled(&mut self, f: impl FnOnce(*mut spi_hw_t)) {
+    let hw = self.hw();
+
+    unsafe {
+      let cr1 = ptr::read_volatile(ptr::addr_of!((*hw).cr1));
+
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).cr1), cr1 & !SSPCR1_SSE);
+      f(hw);
+      ptr::write_volatile(
+        ptr::addr_of_mut!((*hw).cr1),
+        ptr::read_volatile(ptr::addr_of!((*hw).cr1)) | (cr1 & SSPCR1_SSE),
+      );
+    }
+  }
+
+  /// Current settings, with the baudrate as requested
+  pub fn config(&self) -> SpiConfig {
+    self.config
+  }
+
+  /// Actual baudrate, which may differ from the requested one
+  pub fn baudrate(&self) -> u32 {
+    self.baudrate
+  }
+
+  /// Changes the baudrate and returns the actual baudrate.
+  pub fn set_baudrate(&mut self, baudrate: u32) -> u32 {
+    self.config.baudrate = baudrate;
+    self.baudrate = unsafe { pico_sdk::spi_set_baudrate(self.as_ptr(), baudrate) };
+    self.baudrate
+  }
+
+  /// Changes the clock mode and the number of bits per frame (4 to 16).
+  pub fn set_format(&mut self, mode: SpiMode, data_bits: u8) {
+    assert!(
+      (4..=16).contains(&data_bits),
+      "SPI frames must have 4 to 16 bits"
+    );
+
+    self.config.mode = mode;
+    self.config.data_bits = data_bits;
+    self.while_disabled(|hw| unsafe {
+      let cr0 = ptr::addr_of_mut!((*hw).cr0);
+      let mut value = ptr::read_volatile(cr0) & !(SSPCR0_DSS_BITS | SSPCR0_SPO | SSPCR0_SPH);
+
+      value |= (data_bits as u32 - 1) << SSPCR0_DSS_LSB;
+
+      if mode.cpol() {
+        value |= SSPCR0_SPO;
+      }
+
+      if mode.cpha() {
+        value |= SSPCR0_SPH;
+      }
+
+      ptr::write_volatile(cr0, value);
+    });
+  }
+
+  /// Switches between controller and peripheral.
+  ///
+  /// Panics if `role` is [`SpiRole::Peripheral`] but the pins have no chip
+  /// select.
+  pub fn set_role(&mut self, role: SpiRole) {
+    Self::check_role(role);
+    self.config.role = role;
+    self.while_disabled(|hw| unsafe {
+      let cr1 = ptr::addr_of_mut!((*hw).cr1);
+      let value = ptr::read_volatile(cr1) & !SSPCR1_MS;
+
+      match role {
+        SpiRole::Controller => ptr::write_volatile(cr1, value),
+        SpiRole::Peripheral => ptr::write_volatile(cr1, value | SSPCR1_MS),
+      }
+    });
+  }
+
+  /// Applies the baudrate, mode and frame size of `config` where they differ
+  /// from the current ones, keeping the role.
+  pub fn apply(&mut self, config: &SpiConfig) {
+    if config.baudrate != self.config.baudrate {
+      self.set_baudrate(config.baudrate);
+    }
+
+    if config.mode != self.config.mode || config.data_bits != self.config.data_bits {
+      self.set_format(config.mode, config.data_bits);
+    }
+  }
+
+  /// Returns `true` if there is space in the TX FIFO.
+  pub fn is_writable(&self) -> bool {
+    unsafe { pico_sdk::spi_is_writable(self.as_ptr()) }
+  }
+
+  /// Returns `true` if there is data in the RX FIFO.
+  pub fn is_readable(&self) -> bool {
+    unsafe { pico_sdk::spi_is_readable(self.as_ptr()) }
+  }
+
+  /// Returns `true` while a frame is being shifted or the TX FIFO isn't empty.
+  pub fn is_busy(&self) -> bool {
+    unsafe { pico_sdk::spi_is_busy(self.as_ptr()) }
+  }
+
+  /// Writes `words` and discards the received words.
+  pub fn write<W: SpiWord>(&mut self, words: &[W]) {
+    unsafe { W::write(self.as_ptr(), words.as_ptr(), words.len()) }
+  }
+
+  /// Reads into `words` while sending `fill`.
+  pub fn read<W: SpiWord>(&mut self, fill: W, words: &mut [W]) {
+    unsafe { W::read(self.as_ptr(), fill, words.as_mut_ptr(), words.len()) }
+  }
+
+  /// Sends `write` while receiving into `read`.
+  ///
+  /// If they differ in length, the shorter one is padded with zeros or the
+  /// extra words are discarded.
+  pub fn transfer<W: SpiWord>(&mut self, read: &mut [W], write: &[W]) {
+    let common = read.len().min(write.len());
+    let (read, read_rest) = read.split_at_mut(common);
+    let (write, write_rest) = write.split_at(common);
+
+    unsafe { W::write_read(self.as_ptr(), write.as_ptr(), read.as_mut_ptr(), common) };
+
+    self.write(write_rest);
+    self.read(W::default(), read_rest);
+  }
+
+  /// Sends `words` and replaces them with the received words.
+  pub fn transfer_in_place<W: SpiWord>(&mut self, words: &mut [W]) {
+    // The SDK always sends a word before it receives the word at the same index
+    let ptr = words.as_mut_ptr();
+    unsafe { W::write_read(self.as_ptr(), ptr, ptr, words.len()) };
+  }
+
+  /// Waits until the last frame has been shifted out.
+  pub fn flush(&mut self) {
+    while self.is_busy() {}
+  }
+
+  /// Data register as a DMA destination, paced by the TX FIFO
+  pub fn tx_register<W: SpiWord>(&self) -> DmaRegister<W> {
+    DmaRegister::spi_tx(P::Instance::INDEX as u8)
+  }
+
+  /// Data register as a DMA source, paced by the RX FIFO
+  pub fn rx_register<W: SpiWord>(&self) -> DmaRegister<W> {
+    DmaRegister::spi_rx(P::Instance::INDEX as u8)
+  }
+
+  /// Sends `write` with DMA on `tx` while receiving into `read` with DMA on
+  /// `rx`, and waits until both are done.
+  pub fn transfer_dma<W: SpiWord>(
+    &mut self,
+    tx: &mut DmaChannel,
+    rx: &mut DmaChannel,
+    read: &mut [W],
+    write: &[W],
+  ) {
+    assert!(
+      read.len() == write.len(),
+      "DMA transfers need buffers of equal length"
+    );
+
+    let config = DmaConfig::new();
+    let tx_register = self.tx_register();
+
+    // Words left over from earlier writes would be received first
+    self.drain_rx();
+
+    // Receiving starts first so that no word is missed
+    rx.scope(&config, self.rx_register(), read, |read| {
+      tx.transfer_blocking(&config, write, tx_register);
+      read.wait();
+    });
+  }
+
+  /// Sends `words` with DMA on `channel` and discards the received words.
+  pub fn write_dma<W: SpiWord>(&mut self, channel: &mut DmaChannel, words: &[W]) {
+    channel.transfer_blocking(&DmaConfig::new(), words, self.tx_register());
+    self.drain_rx();
+  }
+
+  /// Waits until the bus is idle, then drops the received words and the
+  /// overrun they caused.
+  fn drain_rx(&mut self) {
+    let hw = self.hw();
+
+    unsafe {
+      while ptr::read_volatile(ptr::addr_of!((*hw).sr)) & (SSPSR_RNE | SSPSR_BSY) != 0 {
+        ptr::read_volatile(ptr::addr_of!((*hw).dr));
+      }
+
+      ptr::write_volatile(ptr::addr_of_mut!((*hw).icr), SSPICR_RORIC);
+    }
+  }
+}
+
+/// A device on a shared [`Spi`] bus, selected by a GPIO chip select which is
+/// held low for each transaction
+///
+/// Devices created with [`SpiDevice::with_config`] switch the bus to their
+/// baudrate, mode and frame size first, so devices with different settings
+/// can share a bus.
+pub struct SpiDevice<'a, P: SpiPins, const CS: u8> {
+  bus: &'a RefCell<Spi<P>>,
+  cs: Pin<CS, Output>,
+  config: Option<SpiConfig>,
+}
+
+impl<'a, P: SpiPins, const CS: u8> SpiDevice<'a, P, CS> {
+  /// Device selected by `cs`, using the settings of the bus as they are.
+  pub fn new(bus: &'a RefCell<Spi<P>>, mut cs: Pin<CS, Output>) -> Self {
+    cs.set_high();
+
+    Self {
+      bus,
+      cs,
+      config: None,
+    }
+  }
+
+  /// Device selected by `cs`, with the baudrate, mode and frame size of
+  /// `config`.
+  pub fn with_config(bus: &'a RefCell<Spi<P>>, cs: Pin<CS, Output>, config: SpiConfig) -> Self {
+    Self {
+      config: Some(config),
+      ..Self::new(bus, cs)
+    }
+  }
+
+  /// Selects the device, runs `f` with the bus and deselects the device
+  /// once the last frame was sent.
+  ///
+  /// Panics if the bus is already in use, for example by a transaction of
+  /// another device in `f`.
+  pub fn transaction<R>(&mut self, f: impl FnOnce(&mut Spi<P>) -> R) -> R {
+    let mut bus = self.bus.borrow_mut();
+
+    if let Some(config) = &self.config {
+      bus.apply(config);
+    }
+
+    self.cs.set_low();
+    let result = f(&mut bus);
+    bus.flush();
+    self.cs.set_high();
+
+    result
+  }
+
+  /// Returns the chip select pin.
+  pub fn free(self) -> Pin<CS, Output> {
+    self.cs
+  }
+}
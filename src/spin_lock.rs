@@ -0,0 +1,249 @@
+//! Hardware spin lock primitives
+//!
+//! [`SpinLock`] guards data with one of the 32 SIO spin locks. Locking
+//! disables interrupts on the calling core, so the data is protected from the
+//! other core as well as from interrupt handlers.
+//!
+//! ```ignore
+//! static COUNTER: SpinLock<u32> = SpinLock::with_lock_num(PICO_SPINLOCK_ID_OS1, 0);
+//!
+//! *COUNTER.lock() += 1;
+//! ```
+//!
+//! With the `critical-section` feature, the [critical-section] crate is
+//! implemented on top of spin lock
+//! [PICO_SPINLOCK_ID_OS2](pico_sdk::PICO_SPINLOCK_ID_OS2), so crates like
+//! `heapless` and `defmt` can be used on both cores.
+//!
+//! [critical-section]: https://docs.rs/critical-section
+
+use crate::pico_sdk;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use pico_sdk::spin_lock_t;
+
+/// Number of hardware spin locks
+pub const NUM_SPIN_LOCKS: u32 = 32;
+
+const SPIN_LOCK_BASE: u32 = 0xd0000100;
+
+/// Returns the register of spin lock `lock_num`.
+pub const fn spin_lock_ptr(lock_num: u32) -> *mut spin_lock_t {
+  assert!(lock_num < NUM_SPIN_LOCKS, "RP2040 only has 32 spin locks");
+
+  (SPIN_LOCK_BASE + lock_num * 4) as _
+}
+
+/// Data guarded by a hardware spin lock
+///
+/// The lock isn't re-entrant, locking it again on the same core deadlocks.
+pub struct SpinLock<T> {
+  lock_num: u32,
+  claimed: bool,
+  data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for SpinLock<T> {}
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+  /// Claims an unused spin lock to guard `data`.
+  ///
+  /// Returns `None` if all spin locks are claimed. The lock is unclaimed
+  /// again when this is dropped.
+  pub fn new(data: T) -> Option<Self> {
+    let lock_num = unsafe { pico_sdk::spin_lock_claim_unused(false) };
+
+    if lock_num < 0 {
+      return None;
+    }
+
+    unsafe { pico_sdk::spin_lock_init(lock_num as u32) };
+
+    Some(Self {
+      lock_num: lock_num as u32,
+      claimed: true,
+      data: UnsafeCell::new(data),
+    })
+  }
+
+  /// Guards `data` with spin lock `lock_num`, which can be placed in a
+  /// `static`.
+  ///
+  /// The lock isn't claimed; use a number reserved for the application such
+  /// as [PICO_SPINLOCK_ID_OS1](pico_sdk::PICO_SPINLOCK_ID_OS1) or one that
+  /// was claimed with `spin_lock_claim`. `PICO_SPINLOCK_ID_OS2` is used by the
+  /// `critical-section` implementation and would deadlock
+  /// `critical_section::with` while held. Striped locks from
+  /// `next_striped_spin_lock_num` may be shared with other users, which is
+  /// fine as long as the lock is held briefly.
+  pub const fn with_lock_num(lock_num: u32, data: T) -> Self {
+    assert!(lock_num < NUM_SPIN_LOCKS, "RP2040 only has 32 spin locks");
+    #[cfg(feature = "critical-section")]
+    assert!(
+      lock_num != pico_sdk::PICO_SPINLOCK_ID_OS2,
+      "Spin lock is reserved for the critical section"
+    );
+
+    Self {
+      lock_num,
+      claimed: false,
+      data: UnsafeCell::new(data),
+    }
+  }
+
+  /// Number of the hardware spin lock
+  pub fn lock_num(&self) -> u32 {
+    self.lock_num
+  }
+
+  /// Returns the underlying hardware spin lock.
+  pub fn as_ptr(&self) -> *mut spin_lock_t {
+    spin_lock_ptr(self.lock_num)
+  }
+
+  /// Disables interrupts and waits for the lock.
+  pub fn lock(&self) -> SpinLockGuard<'_, T> {
+    let saved_irq = unsafe { pico_sdk::spin_lock_blocking(self.as_ptr()) };
+
+    SpinLockGuard {
+      lock: self,
+      saved_irq,
+      _not_send: PhantomData,
+    }
+  }
+
+  /// Takes the lock if it is free, without waiting.
+  pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+    unsafe {
+      let saved_irq = pico_sdk::save_and_disable_interrupts();
+
+      // Reading the register acquires the lock, a non-zero value means success
+      if ptr::read_volatile(self.as_ptr()) == 0 {
+        pico_sdk::restore_interrupts(saved_irq);
+        return None;
+      }
+
+      pico_sdk::__mem_fence_acquire();
+
+      Some(SpinLockGuard {
+        lock: self,
+        saved_irq,
+        _not_send: PhantomData,
+      })
+    }
+  }
+
+  /// Returns `true` if the lock is currently held by either core.
+  pub fn is_locked(&self) -> bool {
+    unsafe { pico_sdk::is_spin_locked(self.as_ptr()) }
+  }
+
+  /// Returns the data; no locking is needed since the borrow is exclusive.
+  pub fn get_mut(&mut self) -> &mut T {
+    self.data.get_mut()
+  }
+
+  /// Returns the data, unclaiming the lock if it was claimed by
+  /// [`SpinLock::new`].
+  pub fn into_inner(self) -> T {
+    let lock = core::mem::ManuallyDrop::new(self);
+    lock.unclaim();
+
+    unsafe { ptr::read(lock.data.get()) }
+  }
+
+  fn unclaim(&self) {
+    if self.claimed {
+      unsafe { pico_sdk::spin_lock_unclaim(self.lock_num) }
+    }
+  }
+}
+
+impl<T> Drop for SpinLock<T> {
+  fn drop(&mut self) {
+    self.unclaim();
+  }
+}
+
+/// Access to the data of a locked [`SpinLock`]
+///
+/// The lock is released and interrupts are restored when this is dropped,
+/// which must happen on the core that locked it.
+pub struct SpinLockGuard<'a, T> {
+  lock: &'a SpinLock<T>,
+  saved_irq: u32,
+  _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for SpinLockGuard<'_, T> {}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    unsafe { &*self.lock.data.get() }
+  }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+  fn deref_mut(&mut self) -> &mut T {
+    unsafe { &mut *self.lock.data.get() }
+  }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+  fn drop(&mut self) {
+    unsafe { pico_sdk::spin_unlock(self.lock.as_ptr(), self.saved_irq) }
+  }
+}
+
+#[cfg(feature = "critical-section")]
+mod critical_section_impl {
+  use super::spin_lock_ptr;
+  use crate::pico_sdk;
+  use core::ptr;
+
+  /// Spin lock reserved for the critical section, which no
+  /// [`SpinLock`](super::SpinLock) may use
+  const LOCK_NUM: u32 = pico_sdk::PICO_SPINLOCK_ID_OS2;
+
+  const UNOWNED: u8 = u8::MAX;
+
+  /// Restore state of a nested acquire, which must not release the lock
+  const NESTED: u32 = u32::MAX;
+
+  /// Core which currently holds the lock
+  static mut LOCK_OWNER: u8 = UNOWNED;
+
+  struct PicoCriticalSection;
+
+  critical_section::set_impl!(PicoCriticalSection);
+
+  unsafe impl critical_section::Impl for PicoCriticalSection {
+    unsafe fn acquire() -> u32 {
+      let core = pico_sdk::get_core_num() as u8;
+
+      // Only this core can store its own number, so this can't race
+      if ptr::read_volatile(ptr::addr_of!(LOCK_OWNER)) == core {
+        return NESTED;
+      }
+
+      let saved_irq = pico_sdk::spin_lock_blocking(spin_lock_ptr(LOCK_NUM));
+      ptr::write_volatile(ptr::addr_of_mut!(LOCK_OWNER), core);
+
+      saved_irq
+    }
+
+    unsafe fn release(saved_irq: u32) {
+      if saved_irq == NESTED {
+        return;
+      }
+
+      ptr::write_volatile(ptr::addr_of_mut!(LOCK_OWNER), UNOWNED);
+      pico_sdk::spin_unlock(spin_lock_ptr(LOCK_NUM), saved_irq);
+    }
+  }
+}
@@ -0,0 +1,118 @@
+//! Rust implemented stdio drivers
+//!
+//! The SDK's stdio (`printf`, `getchar`, [`println!`](crate::println), ...)
+//! fans out to a linked list of [stdio_driver_t]. Implementing
+//! [`StdioDriver`] for a type and putting a [`StdioDriverCell`] in a `static`
+//! adds it to that list, which allows routing output of C libraries to RTT, a
+//! ring buffer or any other transport.
+//!
+//! ```ignore
+//! struct Rtt;
+//!
+//! impl StdioDriver for Rtt {
+//!   fn out_chars(buf: &[u8]) {
+//!     rtt_write(buf);
+//!   }
+//! }
+//!
+//! static RTT: StdioDriverCell<Rtt> = StdioDriverCell::new();
+//!
+//! RTT.set_enabled(true);
+//! ```
+//!
+//! The SDK calls drivers without any context pointer, so drivers are
+//! implemented with associated functions and keep their state in statics.
+
+use crate::pico_sdk;
+use core::cell::UnsafeCell;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ptr;
+use pico_sdk::stdio_driver_t;
+use pico_sdk_util::{StdioDriver, StdioShim};
+
+/// Storage for the [stdio_driver_t] of a [`StdioDriver`]
+///
+/// The SDK keeps a pointer to the driver in its driver list, so this must be
+/// placed in a `static`.
+pub struct StdioDriverCell<D: StdioDriver> {
+  driver: UnsafeCell<stdio_driver_t>,
+  _driver: PhantomData<D>,
+}
+
+unsafe impl<D: StdioDriver> Sync for StdioDriverCell<D> {}
+
+impl<D: StdioDriver> StdioDriverCell<D> {
+  /// Creates the function table for `D`.
+  pub const fn new() -> Self {
+    Self {
+      driver: UnsafeCell::new(stdio_driver_t {
+        out_chars: Some(StdioShim::<D>::OUT_CHARS),
+        out_flush: Some(StdioShim::<D>::OUT_FLUSH),
+        in_chars: Some(StdioShim::<D>::IN_CHARS),
+        set_chars_available_callback: Some(StdioShim::<D>::SET_CHARS_AVAILABLE_CALLBACK),
+        next: ptr::null_mut(),
+        last_ended_with_cr: false,
+        crlf_enabled: D::CRLF_ENABLED,
+      }),
+      _driver: PhantomData,
+    }
+  }
+
+  /// Returns the underlying SDK driver.
+  pub fn as_ptr(&self) -> *mut stdio_driver_t {
+    self.driver.get()
+  }
+
+  /// Adds the driver to or removes it from the SDK's list of drivers.
+  pub fn set_enabled(&'static self, enabled: bool) {
+    unsafe { pico_sdk::stdio_set_driver_enabled(self.as_ptr(), enabled) }
+  }
+
+  /// Limits stdio to this driver only.
+  ///
+  /// The driver must be enabled. Use [`clear_stdio_filter`] to use all
+  /// enabled drivers again.
+  pub fn filter(&'static self) {
+    unsafe { pico_sdk::stdio_filter_driver(self.as_ptr()) }
+  }
+
+  /// Enables or disables translation of `\n` to `\r\n` on output.
+  pub fn set_translate_crlf(&'static self, translate: bool) {
+    unsafe { pico_sdk::stdio_set_translate_crlf(self.as_ptr(), translate) }
+  }
+}
+
+impl<D: StdioDriver> Default for StdioDriverCell<D> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Uses all enabled drivers for stdio again after [`StdioDriverCell::filter`].
+pub fn clear_stdio_filter() {
+  unsafe { pico_sdk::stdio_filter_driver(ptr::null_mut()) }
+}
+
+static mut CHARS_AVAILABLE: Option<fn()> = None;
+
+unsafe extern "C" fn chars_available(_param: *mut c_void) {
+  if let Some(callback) = ptr::read_volatile(ptr::addr_of!(CHARS_AVAILABLE)) {
+    callback();
+  }
+}
+
+/// Sets the function called whenever any driver has new input, or removes it
+/// with `None`.
+///
+/// The callback may run in interrupt context.
+pub fn set_chars_available_callback(callback: Option<fn()>) {
+  unsafe {
+    pico_sdk::stdio_set_chars_available_callback(None, ptr::null_mut());
+    ptr::write_volatile(ptr::addr_of_mut!(CHARS_AVAILABLE), callback);
+
+    if callback.is_some() {
+      pico_sdk::stdio_set_chars_available_callback(Some(chars_available), ptr::null_mut());
+    }
+  }
+}
@@ -0,0 +1,376 @@
+//! Wrappers for the SDK's `pico_sync` primitives
+//!
+//! [`Mutex`], [`RecursiveMutex`] and [`Semaphore`] sleep with
+//! `best_effort_wfe_or_timeout` while waiting instead of spinning with
+//! interrupts disabled, so they can be held for longer than a [`SpinLock`]
+//! but must not be used from interrupt handlers. [`CriticalSection`] is the
+//! SDK's spin lock plus disabled interrupts. All of them work across both
+//! cores and can be placed in `static`s.
+//!
+//! ```ignore
+//! static SENSORS: Mutex<[u16; 4]> = Mutex::new([0; 4]);
+//!
+//! if let Some(mut sensors) = SENSORS.try_lock_for(Duration::from_millis(10)) {
+//!   sensors[0] = 42;
+//! }
+//! ```
+//!
+//! [`SpinLock`]: crate::SpinLock
+
+use crate::pico_sdk;
+use crate::spin_lock::spin_lock_ptr;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::time::Duration;
+use pico_sdk::{critical_section_t, lock_core_t, mutex_t, recursive_mutex_t, semaphore_t};
+
+/// Owner id of a lock which isn't held
+const LOCK_INVALID_OWNER_ID: i8 = -1;
+
+/// Spin lock shared by the internal state of all primitives created in const
+/// context, which only hold it for a few instructions
+pub(crate) const fn striped_lock_core() -> lock_core_t {
+  lock_core_t {
+    spin_lock: spin_lock_ptr(pico_sdk::PICO_SPINLOCK_ID_STRIPED_FIRST),
+  }
+}
+
+pub(crate) fn timeout_time(timeout: Duration) -> pico_sdk::absolute_time_t {
+  let us = u64::try_from(timeout.as_micros()).unwrap_or(u64::MAX);
+
+  unsafe { pico_sdk::make_timeout_time_us(us) }
+}
+
+/// Mutual exclusion lock around `T`
+///
+/// The lock records the core holding it, see [`Mutex::owner`]. Locking it
+/// again from the owning core deadlocks, use [`RecursiveMutex`] for that.
+pub struct Mutex<T> {
+  mutex: UnsafeCell<mutex_t>,
+  data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+  /// Creates an unlocked mutex around `data`.
+  pub const fn new(data: T) -> Self {
+    Self {
+      mutex: UnsafeCell::new(mutex_t {
+        core: striped_lock_core(),
+        owner: LOCK_INVALID_OWNER_ID,
+      }),
+      data: UnsafeCell::new(data),
+    }
+  }
+
+  /// Returns the underlying SDK mutex.
+  pub fn as_ptr(&self) -> *mut mutex_t {
+    self.mutex.get()
+  }
+
+  /// Waits until the mutex is free and locks it.
+  pub fn lock(&self) -> MutexGuard<'_, T> {
+    unsafe { pico_sdk::mutex_enter_blocking(self.as_ptr()) };
+
+    MutexGuard { mutex: self }
+  }
+
+  /// Locks the mutex if it is free.
+  pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+    let mut owner = 0;
+
+    unsafe { pico_sdk::mutex_try_enter(self.as_ptr(), &mut owner) }
+      .then_some(MutexGuard { mutex: self })
+  }
+
+  /// Locks the mutex, waiting at most `timeout` for it to become free.
+  pub fn try_lock_for(&self, timeout: Duration) -> Option<MutexGuard<'_, T>> {
+    unsafe { pico_sdk::mutex_enter_block_until(self.as_ptr(), timeout_time(timeout)) }
+      .then_some(MutexGuard { mutex: self })
+  }
+
+  /// Core holding the mutex, `None` if it is unlocked.
+  pub fn owner(&self) -> Option<u32> {
+    let owner = unsafe { ptr::read_volatile(ptr::addr_of!((*self.as_ptr()).owner)) };
+
+    (owner != LOCK_INVALID_OWNER_ID).then_some(owner as u32)
+  }
+
+  /// Returns the data; no locking is needed since the borrow is exclusive.
+  pub fn get_mut(&mut self) -> &mut T {
+    self.data.get_mut()
+  }
+
+  /// Returns the data.
+  pub fn into_inner(self) -> T {
+    self.data.into_inner()
+  }
+}
+
+/// Access to the data of a locked [`Mutex`]
+pub struct MutexGuard<'a, T> {
+  mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    unsafe { &*self.mutex.data.get() }
+  }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+  fn deref_mut(&mut self) -> &mut T {
+    unsafe { &mut *self.mutex.data.get() }
+  }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+  fn drop(&mut self) {
+    unsafe { pico_sdk::mutex_exit(self.mutex.as_ptr()) }
+  }
+}
+
+/// Mutex which can be locked again by the core already holding it
+///
+/// Since the data can be reached through several guards at once, also from
+/// an interrupt handler preempting the owner, guards only give shared access
+/// and the data must be `Sync`; use atomics to mutate it.
+pub struct RecursiveMutex<T> {
+  mutex: UnsafeCell<recursive_mutex_t>,
+  data: T,
+}
+
+unsafe impl<T: Send> Send for RecursiveMutex<T> {}
+unsafe impl<T: Send + Sync> Sync for RecursiveMutex<T> {}
+
+impl<T> RecursiveMutex<T> {
+  /// Creates an unlocked mutex around `data`.
+  pub const fn new(data: T) -> Self {
+    Self {
+      mutex: UnsafeCell::new(recursive_mutex_t {
+        core: striped_lock_core(),
+        owner: LOCK_INVALID_OWNER_ID,
+        enter_count: 0,
+      }),
+      data,
+    }
+  }
+
+  /// Returns the underlying SDK mutex.
+  pub fn as_ptr(&self) -> *mut recursive_mutex_t {
+    self.mutex.get()
+  }
+
+  /// Waits until the mutex is free or held by this core and locks it.
+  pub fn lock(&self) -> RecursiveMutexGuard<'_, T> {
+    unsafe { pico_sdk::recursive_mutex_enter_blocking(self.as_ptr()) };
+
+    RecursiveMutexGuard { mutex: self }
+  }
+
+  /// Locks the mutex if it is free or held by this core.
+  pub fn try_lock(&self) -> Option<RecursiveMutexGuard<'_, T>> {
+    let mut owner = 0;
+
+    unsafe { pico_sdk::recursive_mutex_try_enter(self.as_ptr(), &mut owner) }
+      .then_some(RecursiveMutexGuard { mutex: self })
+  }
+
+  /// Locks the mutex, waiting at most `timeout` for it to become available.
+  pub fn try_lock_for(&self, timeout: Duration) -> Option<RecursiveMutexGuard<'_, T>> {
+    unsafe { pico_sdk::recursive_mutex_enter_block_until(self.as_ptr(), timeout_time(timeout)) }
+      .then_some(RecursiveMutexGuard { mutex: self })
+  }
+
+  /// Core holding the mutex, `None` if it is unlocked.
+  pub fn owner(&self) -> Option<u32> {
+    let owner = unsafe { ptr::read_volatile(ptr::addr_of!((*self.as_ptr()).owner)) };
+
+    (owner != LOCK_INVALID_OWNER_ID).then_some(owner as u32)
+  }
+
+  /// Returns the data; no locking is needed since the borrow is exclusive.
+  pub fn get_mut(&mut self) -> &mut T {
+    &mut self.data
+  }
+
+  /// Returns the data.
+  pub fn into_inner(self) -> T {
+    self.data
+  }
+}
+
+/// Shared access to the data of a locked [`RecursiveMutex`]
+pub struct RecursiveMutexGuard<'a, T> {
+  mutex: &'a RecursiveMutex<T>,
+}
+
+impl<T> Deref for RecursiveMutexGuard<'_, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    &self.mutex.data
+  }
+}
+
+impl<T> Drop for RecursiveMutexGuard<'_, T> {
+  fn drop(&mut self) {
+    unsafe { pico_sdk::recursive_mutex_exit(self.mutex.as_ptr()) }
+  }
+}
+
+/// Counting semaphore
+pub struct Semaphore {
+  semaphore: UnsafeCell<semaphore_t>,
+}
+
+unsafe impl Send for Semaphore {}
+unsafe impl Sync for Semaphore {}
+
+impl Semaphore {
+  /// Creates a semaphore with `permits` available out of `max_permits`.
+  pub const fn new(permits: i16, max_permits: i16) -> Self {
+    assert!(0 <= permits && permits <= max_permits);
+
+    Self {
+      semaphore: UnsafeCell::new(semaphore_t {
+        core: striped_lock_core(),
+        permits,
+        max_permits,
+      }),
+    }
+  }
+
+  /// Returns the underlying SDK semaphore.
+  pub fn as_ptr(&self) -> *mut semaphore_t {
+    self.semaphore.get()
+  }
+
+  /// Number of permits which can be acquired without waiting
+  pub fn available(&self) -> i32 {
+    unsafe { pico_sdk::sem_available(self.as_ptr()) }
+  }
+
+  /// Waits for a permit and takes it.
+  pub fn acquire(&self) {
+    unsafe { pico_sdk::sem_acquire_blocking(self.as_ptr()) }
+  }
+
+  /// Takes a permit if one is available.
+  pub fn try_acquire(&self) -> bool {
+    unsafe { pico_sdk::sem_try_acquire(self.as_ptr()) }
+  }
+
+  /// Takes a permit, waiting up to `timeout_ms` milliseconds for one.
+  pub fn acquire_timeout_ms(&self, timeout_ms: u32) -> bool {
+    unsafe { pico_sdk::sem_acquire_timeout_ms(self.as_ptr(), timeout_ms) }
+  }
+
+  /// Takes a permit, waiting up to `timeout_us` microseconds for one.
+  pub fn acquire_timeout_us(&self, timeout_us: u32) -> bool {
+    unsafe { pico_sdk::sem_acquire_timeout_us(self.as_ptr(), timeout_us) }
+  }
+
+  /// Returns a permit, waking a waiting core.
+  ///
+  /// Returns `false` if all permits were already available. This doesn't
+  /// wait, so it may be called from interrupt handlers.
+  pub fn release(&self) -> bool {
+    unsafe { pico_sdk::sem_release(self.as_ptr()) }
+  }
+
+  /// Sets the number of available permits.
+  pub fn reset(&self, permits: i16) {
+    unsafe { pico_sdk::sem_reset(self.as_ptr(), permits) }
+  }
+}
+
+/// A spin lock taken together with disabling interrupts, as a critical
+/// section across both cores
+///
+/// Critical sections aren't re-entrant.
+pub struct CriticalSection {
+  critical_section: UnsafeCell<critical_section_t>,
+  claimed: bool,
+}
+
+unsafe impl Send for CriticalSection {}
+unsafe impl Sync for CriticalSection {}
+
+impl CriticalSection {
+  /// Creates a critical section with its own claimed spin lock.
+  ///
+  /// Returns `None` if all spin locks are claimed.
+  pub fn new() -> Option<Self> {
+    let lock_num = unsafe { pico_sdk::spin_lock_claim_unused(false) };
+
+    if lock_num < 0 {
+      return None;
+    }
+
+    let mut critical_section = Self::with_lock_num(lock_num as u32);
+    critical_section.claimed = true;
+    unsafe {
+      pico_sdk::critical_section_init_with_lock_num(critical_section.as_ptr(), lock_num as u32)
+    };
+
+    Some(critical_section)
+  }
+
+  /// Creates a critical section using spin lock `lock_num`.
+  ///
+  /// The lock isn't claimed, see [`SpinLock::with_lock_num`](crate::SpinLock::with_lock_num).
+  pub const fn with_lock_num(lock_num: u32) -> Self {
+    Self {
+      critical_section: UnsafeCell::new(critical_section_t {
+        spin_lock: spin_lock_ptr(lock_num),
+        save: 0,
+      }),
+      claimed: false,
+    }
+  }
+
+  /// Returns the underlying SDK critical section.
+  pub fn as_ptr(&self) -> *mut critical_section_t {
+    self.critical_section.get()
+  }
+
+  /// Disables interrupts and waits for the spin lock.
+  pub fn enter(&self) -> CriticalSectionGuard<'_> {
+    unsafe { pico_sdk::critical_section_enter_blocking(self.as_ptr()) };
+
+    CriticalSectionGuard {
+      critical_section: self,
+    }
+  }
+
+  /// Runs `f` inside the critical section.
+  pub fn with<R>(&self, f: impl FnOnce() -> R) -> R {
+    let _guard = self.enter();
+    f()
+  }
+}
+
+impl Drop for CriticalSection {
+  fn drop(&mut self) {
+    if self.claimed {
+      unsafe { pico_sdk::critical_section_deinit(self.as_ptr()) }
+    }
+  }
+}
+
+/// An entered [`CriticalSection`], which is left when this is dropped
+pub struct CriticalSectionGuard<'a> {
+  critical_section: &'a CriticalSection,
+}
+
+impl Drop for CriticalSectionGuard<'_> {
+  fn drop(&mut self) {
+    unsafe { pico_sdk::critical_section_exit(self.critical_section.as_ptr()) }
+  }
+}
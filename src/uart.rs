@@ -0,0 +1,448 @@
+//! UART bindings
+//!
+//! [`Uart`] owns one of the two UART instances together with its TX and RX
+//! pins, and implements [`core::fmt::Write`] as well as the
+//! [`embedded-io`](embedded_io) traits when the `embedded-io` feature is on.
+//!
+//! ```ignore
+//! let pins = Pins::take().unwrap();
+//! let mut uart = Uart::new(
+//!   (pins.gpio0.into_function(), pins.gpio1.into_function()),
+//!   UartConfig::default(),
+//! )
+//! .unwrap();
+//!
+//! writeln!(uart, "Hello world!").unwrap();
+//! ```
+
+use crate::gpio::{Function, FunctionUart, Pin};
+use crate::{claim, pico_sdk};
+use core::{fmt, ptr};
+use pico_sdk::{uart_hw_t, uart_inst_t};
+
+pub const UART0_PTR: *mut uart_inst_t = 0x40034000u32 as _;
+pub const UART1_PTR: *mut uart_inst_t = 0x40038000u32 as _;
+
+const UART_UARTDR_DATA_BITS: u32 = 0x0ff;
+const UART_UARTDR_FE_BITS: u32 = 0x100;
+const UART_UARTDR_PE_BITS: u32 = 0x200;
+const UART_UARTDR_BE_BITS: u32 = 0x400;
+const UART_UARTDR_OE_BITS: u32 = 0x800;
+
+static mut UART_CLAIMED: [bool; 2] = [false; 2];
+
+/// One of the UART instances
+pub trait UartInstance {
+  const PTR: *mut uart_inst_t;
+  const INDEX: usize;
+}
+
+/// UART0 instance
+pub struct Uart0;
+
+/// UART1 instance
+pub struct Uart1;
+
+impl UartInstance for Uart0 {
+  const PTR: *mut uart_inst_t = UART0_PTR;
+  const INDEX: usize = 0;
+}
+
+impl UartInstance for Uart1 {
+  const PTR: *mut uart_inst_t = UART1_PTR;
+  const INDEX: usize = 1;
+}
+
+/// A pin which can be used as UART TX
+pub trait TxPin {
+  type Instance: UartInstance;
+}
+
+/// A pin which can be used as UART RX
+pub trait RxPin {
+  type Instance: UartInstance;
+}
+
+macro_rules! uart_pins {
+  ($($instance:ident: tx = [$($tx:literal),+], rx = [$($rx:literal),+];)+) => {
+    $(
+      $(impl TxPin for Pin<$tx, Function<FunctionUart>> {
+        type Instance = $instance;
+      })+
+      $(impl RxPin for Pin<$rx, Function<FunctionUart>> {
+        type Instance = $instance;
+      })+
+    )+
+  };
+}
+
+uart_pins! {
+  Uart0: tx = [0, 12, 16, 28], rx = [1, 13, 17, 29];
+  Uart1: tx = [4, 8, 20, 24], rx = [5, 9, 21, 25];
+}
+
+/// A TX and RX pin pair belonging to the same UART instance
+pub trait UartPins {
+  type Instance: UartInstance;
+}
+
+impl<TX: TxPin, RX: RxPin<Instance = TX::Instance>> UartPins for (TX, RX) {
+  type Instance = TX::Instance;
+}
+
+/// Number of data bits per character
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DataBits {
+  Five = 5,
+  Six = 6,
+  Seven = 7,
+  #[default]
+  Eight = 8,
+}
+
+/// Parity bit mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u32)]
+pub enum Parity {
+  #[default]
+  None = pico_sdk::UART_PARITY_NONE,
+  Even = pico_sdk::UART_PARITY_EVEN,
+  Odd = pico_sdk::UART_PARITY_ODD,
+}
+
+/// Number of stop bits per character
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StopBits {
+  #[default]
+  One = 1,
+  Two = 2,
+}
+
+/// Line format and behaviour of a [`Uart`]
+///
+/// The default is 115200 baud, 8N1 without CRLF translation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UartConfig {
+  pub baudrate: u32,
+  pub data_bits: DataBits,
+  pub parity: Parity,
+  pub stop_bits: StopBits,
+  /// Translate `\n` to `\r\n` when writing
+  pub translate_crlf: bool,
+}
+
+impl Default for UartConfig {
+  fn default() -> Self {
+    Self {
+      baudrate: 115200,
+      data_bits: DataBits::default(),
+      parity: Parity::default(),
+      stop_bits: StopBits::default(),
+      translate_crlf: false,
+    }
+  }
+}
+
+/// Errors reported while receiving
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UartError {
+  /// A character didn't have a valid stop bit
+  Framing,
+  /// A character's parity didn't match the configured parity
+  Parity,
+  /// The line was held low for longer than a character
+  Break,
+  /// The receive FIFO overflowed and data was lost
+  Overrun,
+  /// No data arrived before the timeout elapsed
+  Timeout,
+}
+
+/// A UART instance and its pins
+pub struct Uart<P: UartPins> {
+  pins: P,
+  baudrate: u32,
+  /// Error received after bytes which were already returned
+  error: Option<UartError>,
+}
+
+impl<P: UartPins> Uart<P> {
+  /// Initializes the UART instance of `pins` with `config`.
+  ///
+  /// Returns `None` if the instance is already in use.
+  pub fn new(pins: P, config: UartConfig) -> Option<Self> {
+    let index = P::Instance::INDEX;
+
+    if !unsafe { claim::claim(ptr::addr_of_mut!(UART_CLAIMED[index])) } {
+      return None;
+    }
+
+    let baudrate = unsafe { pico_sdk::uart_init(P::Instance::PTR, config.baudrate) };
+    let mut uart = Self {
+      pins,
+      baudrate,
+      error: None,
+    };
+    uart.set_format(config.data_bits, config.parity, config.stop_bits);
+    uart.set_translate_crlf(config.translate_crlf);
+
+    Some(uart)
+  }
+
+  /// Disables the UART and returns its pins.
+  pub fn free(self) -> P {
+    unsafe {
+      pico_sdk::uart_deinit(P::Instance::PTR);
+      claim::unclaim(ptr::addr_of_mut!(UART_CLAIMED[P::Instance::INDEX]));
+    }
+
+    self.pins
+  }
+
+  /// Returns the underlying SDK instance.
+  pub fn as_ptr(&self) -> *mut uart_inst_t {
+    P::Instance::PTR
+  }
+
+  fn hw(&self) -> *mut uart_hw_t {
+    P::Instance::PTR as _
+  }
+
+  /// Actual baudrate, which may differ slightly from the requested one
+  pub fn baudrate(&self) -> u32 {
+    self.baudrate
+  }
+
+  /// Changes the baudrate and returns the actual baudrate.
+  pub fn set_baudrate(&mut self, baudrate: u32) -> u32 {
+    self.baudrate = unsafe { pico_sdk::uart_set_baudrate(self.as_ptr(), baudrate) };
+    self.baudrate
+  }
+
+  /// Changes the line format.
+  pub fn set_format(&mut self, data_bits: DataBits, parity: Parity, stop_bits: StopBits) {
+    unsafe { pico_sdk::uart_set_format(self.as_ptr(), data_bits as _, stop_bits as _, parity as _) }
+  }
+
+  /// Enables or disables translation of `\n` to `\r\n` when writing.
+  pub fn set_translate_crlf(&mut self, translate: bool) {
+    unsafe { pico_sdk::uart_set_translate_crlf(self.as_ptr(), translate) }
+  }
+
+  /// Holds the TX line low while `enabled` is `true`.
+  pub fn set_break(&mut self, enabled: bool) {
+    unsafe { pico_sdk::uart_set_break(self.as_ptr(), enabled) }
+  }
+
+  /// Sends a break condition for `duration_us` microseconds.
+  pub fn send_break(&mut self, duration_us: u32) {
+    self.flush();
+    self.set_break(true);
+    unsafe { pico_sdk::busy_wait_us_32(duration_us) };
+    self.set_break(false);
+  }
+
+  /// Returns `true` if there is space in the TX FIFO.
+  pub fn is_writable(&self) -> bool {
+    unsafe { pico_sdk::uart_is_writable(self.as_ptr()) }
+  }
+
+  /// Returns `true` if there is data in the RX FIFO.
+  pub fn is_readable(&self) -> bool {
+    unsafe { pico_sdk::uart_is_readable(self.as_ptr()) }
+  }
+
+  /// Waits up to `us` microseconds for data to arrive.
+  pub fn is_readable_within_us(&self, us: u32) -> bool {
+    unsafe { pico_sdk::uart_is_readable_within_us(self.as_ptr(), us) }
+  }
+
+  /// Writes a single byte, blocking until there is space in the TX FIFO.
+  ///
+  /// `\n` is translated to `\r\n` if CRLF translation is enabled.
+  pub fn write_byte(&mut self, byte: u8) {
+    unsafe { pico_sdk::uart_putc(self.as_ptr(), byte as _) }
+  }
+
+  /// Writes all of `bytes`, blocking until they fit in the TX FIFO.
+  pub fn write_blocking(&mut self, bytes: &[u8]) {
+    for &byte in bytes {
+      self.write_byte(byte);
+    }
+  }
+
+  /// Writes as many bytes as fit in the TX FIFO without blocking.
+  pub fn write_nonblocking(&mut self, bytes: &[u8]) -> usize {
+    let mut written = 0;
+
+    for &byte in bytes {
+      if !self.is_writable() {
+        break;
+      }
+
+      self.write_byte(byte);
+      written += 1;
+    }
+
+    written
+  }
+
+  /// Waits until all data in the TX FIFO has been sent.
+  pub fn flush(&mut self) {
+    unsafe { pico_sdk::uart_tx_wait_blocking(self.as_ptr()) }
+  }
+
+  /// Reads a single byte if one is available.
+  pub fn read_byte_nonblocking(&mut self) -> Option<Result<u8, UartError>> {
+    if let Some(error) = self.error.take() {
+      return Some(Err(error));
+    }
+
+    if !self.is_readable() {
+      return None;
+    }
+
+    let data = unsafe { ptr::read_volatile(ptr::addr_of!((*self.hw()).dr)) };
+
+    Some(if data & UART_UARTDR_OE_BITS != 0 {
+      Err(UartError::Overrun)
+    } else if data & UART_UARTDR_BE_BITS != 0 {
+      Err(UartError::Break)
+    } else if data & UART_UARTDR_PE_BITS != 0 {
+      Err(UartError::Parity)
+    } else if data & UART_UARTDR_FE_BITS != 0 {
+      Err(UartError::Framing)
+    } else {
+      Ok((data & UART_UARTDR_DATA_BITS) as u8)
+    })
+  }
+
+  /// Reads a single byte, blocking until one is available.
+  pub fn read_byte(&mut self) -> Result<u8, UartError> {
+    loop {
+      if let Some(result) = self.read_byte_nonblocking() {
+        return result;
+      }
+
+      unsafe { pico_sdk::tight_loop_contents() };
+    }
+  }
+
+  /// Reads the bytes that are already in the RX FIFO into `buffer`.
+  ///
+  /// Returns the number of bytes read, which may be `0`. An error after the
+  /// first byte is returned by the next call instead.
+  pub fn read_nonblocking(&mut self, buffer: &mut [u8]) -> Result<usize, UartError> {
+    let mut read = 0;
+
+    for slot in buffer.iter_mut() {
+      match self.read_byte_nonblocking() {
+        Some(Ok(byte)) => *slot = byte,
+        Some(Err(error)) if read > 0 => {
+          self.error = Some(error);
+          break;
+        }
+        Some(Err(error)) => return Err(error),
+        None => break,
+      }
+
+      read += 1;
+    }
+
+    Ok(read)
+  }
+
+  /// Blocks until at least one byte is available and reads the available
+  /// bytes into `buffer`.
+  ///
+  /// An error after the first byte is returned by the next call instead.
+  pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, UartError> {
+    if buffer.is_empty() {
+      return Ok(0);
+    }
+
+    buffer[0] = self.read_byte()?;
+
+    let read = match self.read_nonblocking(&mut buffer[1..]) {
+      Ok(read) => read,
+      Err(error) => {
+        self.error = Some(error);
+        0
+      }
+    };
+
+    Ok(read + 1)
+  }
+
+  /// Like [`Uart::read`] but gives up with [`UartError::Timeout`] if nothing
+  /// arrives within `timeout_us` microseconds.
+  pub fn read_timeout(&mut self, buffer: &mut [u8], timeout_us: u32) -> Result<usize, UartError> {
+    if !buffer.is_empty() && self.error.is_none() && !self.is_readable_within_us(timeout_us) {
+      return Err(UartError::Timeout);
+    }
+
+    self.read(buffer)
+  }
+}
+
+impl<P: UartPins> fmt::Write for Uart<P> {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    self.write_blocking(s.as_bytes());
+    Ok(())
+  }
+}
+
+#[cfg(feature = "embedded-io")]
+mod io_impls {
+  use super::*;
+  use embedded_io::{ErrorKind, ErrorType, Read, ReadReady, Write, WriteReady};
+
+  impl embedded_io::Error for UartError {
+    fn kind(&self) -> ErrorKind {
+      match self {
+        UartError::Framing | UartError::Parity | UartError::Break => ErrorKind::InvalidData,
+        UartError::Overrun => ErrorKind::Other,
+        UartError::Timeout => ErrorKind::TimedOut,
+      }
+    }
+  }
+
+  impl<P: UartPins> ErrorType for Uart<P> {
+    type Error = UartError;
+  }
+
+  impl<P: UartPins> Read for Uart<P> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+      Uart::read(self, buf)
+    }
+  }
+
+  impl<P: UartPins> ReadReady for Uart<P> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+      Ok(self.error.is_some() || self.is_readable())
+    }
+  }
+
+  impl<P: UartPins> Write for Uart<P> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+      if buf.is_empty() {
+        return Ok(0);
+      }
+
+      self.write_byte(buf[0]);
+
+      Ok(self.write_nonblocking(&buf[1..]) + 1)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+      Uart::flush(self);
+      Ok(())
+    }
+  }
+
+  impl<P: UartPins> WriteReady for Uart<P> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+      Ok(self.is_writable())
+    }
+  }
+}
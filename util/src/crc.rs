@@ -0,0 +1,256 @@
+//! Software model of the DMA sniffer's checksums
+//!
+//! [`Crc`] computes the same CRCs, parity and sums as the RP2040 DMA
+//! sniffer, bit for bit. pico-sdk-sys uses it to configure the sniffer and
+//! as the fallback when the sniffer is busy.
+
+const CRC32_POLYNOMIAL: u32 = 0x04c11db7;
+const CRC16_CCITT_POLYNOMIAL: u32 = 0x1021;
+
+/// Calculation done by the sniffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum CrcAlgorithm {
+  /// CRC-32 with polynomial `0x04c11db7`, most significant bit first
+  Crc32 = 0x0,
+  /// [`CrcAlgorithm::Crc32`] over the bit-reversed data
+  Crc32Reversed = 0x1,
+  /// CRC-16-CCITT with polynomial `0x1021`, most significant bit first
+  Crc16Ccitt = 0x2,
+  /// [`CrcAlgorithm::Crc16Ccitt`] over the bit-reversed data
+  Crc16CcittReversed = 0x3,
+  /// Toggles bit 0 for every byte with an odd number of set bits
+  Parity = 0xe,
+  /// Sum of all bytes
+  Sum = 0xf,
+}
+
+impl CrcAlgorithm {
+  /// Number of bits in the result
+  pub const fn width(self) -> u32 {
+    match self {
+      Self::Crc16Ccitt | Self::Crc16CcittReversed => 16,
+      _ => 32,
+    }
+  }
+}
+
+/// A checksum as computed by the DMA sniffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc {
+  algorithm: CrcAlgorithm,
+  seed: u32,
+  reverse_output: bool,
+  invert_output: bool,
+}
+
+impl Crc {
+  /// `algorithm` starting from 0, with the result as computed.
+  pub const fn new(algorithm: CrcAlgorithm) -> Self {
+    Self {
+      algorithm,
+      seed: 0,
+      reverse_output: false,
+      invert_output: false,
+    }
+  }
+
+  /// The CRC-32 used by Ethernet, zlib and PNG
+  pub const fn crc32() -> Self {
+    Self::new(CrcAlgorithm::Crc32Reversed)
+      .seed(u32::MAX)
+      .reverse_output(true)
+      .invert_output(true)
+  }
+
+  /// CRC-32/MPEG-2, the CRC-32 without reflection or final inversion
+  pub const fn crc32_mpeg2() -> Self {
+    Self::new(CrcAlgorithm::Crc32).seed(u32::MAX)
+  }
+
+  /// CRC-16-CCITT starting from `0xffff`, also known as CRC-16/CCITT-FALSE
+  pub const fn crc16_ccitt() -> Self {
+    Self::new(CrcAlgorithm::Crc16Ccitt).seed(0xffff)
+  }
+
+  /// CRC-16/XMODEM, CRC-16-CCITT starting from 0
+  pub const fn crc16_xmodem() -> Self {
+    Self::new(CrcAlgorithm::Crc16Ccitt)
+  }
+
+  /// Sets the initial value.
+  pub const fn seed(mut self, seed: u32) -> Self {
+    self.seed = seed;
+    self
+  }
+
+  /// Reverses the bits of the result.
+  pub const fn reverse_output(mut self, enabled: bool) -> Self {
+    self.reverse_output = enabled;
+    self
+  }
+
+  /// Inverts the bits of the result.
+  pub const fn invert_output(mut self, enabled: bool) -> Self {
+    self.invert_output = enabled;
+    self
+  }
+
+  /// Algorithm of the checksum
+  pub const fn algorithm(&self) -> CrcAlgorithm {
+    self.algorithm
+  }
+
+  /// Returns `true` if the bits of the result are reversed.
+  pub const fn is_output_reversed(&self) -> bool {
+    self.reverse_output
+  }
+
+  /// Returns `true` if the bits of the result are inverted.
+  pub const fn is_output_inverted(&self) -> bool {
+    self.invert_output
+  }
+
+  /// Sniffer state before any data, which is the seed
+  pub const fn start(&self) -> u32 {
+    self.seed
+  }
+
+  /// Feeds `data` to the sniffer state `state`.
+  pub const fn update(&self, mut state: u32, data: &[u8]) -> u32 {
+    let mut index = 0;
+
+    while index < data.len() {
+      state = self.update_byte(state, data[index]);
+      index += 1;
+    }
+
+    state
+  }
+
+  /// Applies the output options to the sniffer state, giving the checksum.
+  pub const fn finish(&self, state: u32) -> u32 {
+    let width = self.algorithm.width();
+    let mask = u32::MAX >> (32 - width);
+    let mut result = state & mask;
+
+    if self.reverse_output {
+      result = result.reverse_bits() >> (32 - width);
+    }
+
+    if self.invert_output {
+      result = !result & mask;
+    }
+
+    result
+  }
+
+  /// Computes the checksum of `data`.
+  pub const fn compute(&self, data: &[u8]) -> u32 {
+    self.finish(self.update(self.start(), data))
+  }
+
+  const fn update_byte(&self, state: u32, byte: u8) -> u32 {
+    match self.algorithm {
+      CrcAlgorithm::Crc32 => crc32_byte(state, byte),
+      CrcAlgorithm::Crc32Reversed => crc32_byte(state, byte.reverse_bits()),
+      CrcAlgorithm::Crc16Ccitt => crc16_byte(state, byte),
+      CrcAlgorithm::Crc16CcittReversed => crc16_byte(state, byte.reverse_bits()),
+      CrcAlgorithm::Parity => state ^ (byte.count_ones() & 1),
+      CrcAlgorithm::Sum => state.wrapping_add(byte as u32),
+    }
+  }
+}
+
+const fn crc32_byte(mut state: u32, byte: u8) -> u32 {
+  state ^= (byte as u32) << 24;
+
+  let mut bit = 0;
+
+  while bit < 8 {
+    state = if state & (1 << 31) != 0 {
+      (state << 1) ^ CRC32_POLYNOMIAL
+    } else {
+      state << 1
+    };
+    bit += 1;
+  }
+
+  state
+}
+
+const fn crc16_byte(state: u32, byte: u8) -> u32 {
+  let mut state = (state ^ (byte as u32) << 8) & 0xffff;
+  let mut bit = 0;
+
+  while bit < 8 {
+    state = if state & (1 << 15) != 0 {
+      ((state << 1) ^ CRC16_CCITT_POLYNOMIAL) & 0xffff
+    } else {
+      state << 1
+    };
+    bit += 1;
+  }
+
+  state
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The input of the check values in the CRC catalogue
+  const CHECK: &[u8] = b"123456789";
+
+  #[test]
+  fn matches_presets() {
+    assert_eq!(Crc::crc32().compute(CHECK), 0xcbf43926);
+    assert_eq!(Crc::crc32_mpeg2().compute(CHECK), 0x0376e6e7);
+    assert_eq!(Crc::crc16_ccitt().compute(CHECK), 0x29b1);
+    assert_eq!(Crc::crc16_xmodem().compute(CHECK), 0x31c3);
+  }
+
+  #[test]
+  fn applies_output_options() {
+    // CRC-32/BZIP2
+    let bzip2 = Crc::new(CrcAlgorithm::Crc32)
+      .seed(u32::MAX)
+      .invert_output(true);
+    assert_eq!(bzip2.compute(CHECK), 0xfc891918);
+
+    // CRC-16/KERMIT
+    let kermit = Crc::new(CrcAlgorithm::Crc16CcittReversed).reverse_output(true);
+    assert_eq!(kermit.compute(CHECK), 0x2189);
+  }
+
+  #[test]
+  fn computes_parity_and_sum() {
+    let parity = Crc::new(CrcAlgorithm::Parity);
+    let sum = Crc::new(CrcAlgorithm::Sum);
+
+    // 5 of the digits have an odd number of set bits
+    assert_eq!(parity.compute(CHECK), 1);
+    assert_eq!(parity.compute(b"12"), 0);
+    assert_eq!(parity.compute(&[0x80]), 1);
+
+    assert_eq!(sum.compute(CHECK), 0x1dd);
+    assert_eq!(sum.compute(&[0xff; 4]), 0x3fc);
+    assert_eq!(sum.seed(u32::MAX).compute(&[2]), 1);
+  }
+
+  #[test]
+  fn updates_incrementally() {
+    for crc in [
+      Crc::crc32(),
+      Crc::crc16_ccitt(),
+      Crc::new(CrcAlgorithm::Sum),
+    ] {
+      let (head, tail) = CHECK.split_at(4);
+      let state = crc.update(crc.update(crc.start(), head), tail);
+
+      assert_eq!(crc.finish(state), crc.compute(CHECK));
+    }
+
+    assert_eq!(Crc::crc32().compute(&[]), 0);
+  }
+}
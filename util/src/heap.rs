@@ -0,0 +1,366 @@
+//! First-fit heap allocator
+//!
+//! [`Heap`] manages a single memory region without touching any hardware.
+//! pico-sdk-sys builds its per-core `RustHeap` global allocator on top of it.
+
+use core::alloc::Layout;
+use core::mem::{self, MaybeUninit};
+use core::ptr::{self, NonNull};
+
+/// Free block header, stored at the start of every free block
+struct Node {
+  size: usize,
+  next: *mut Node,
+}
+
+/// Allocation granularity, every block starts and ends on a multiple of this
+const UNIT: usize = mem::size_of::<Node>();
+
+const fn align_up(value: usize, align: usize) -> usize {
+  (value + align - 1) & !(align - 1)
+}
+
+/// Usage statistics of a [`Heap`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ArenaStats {
+  /// Total size of the arena
+  pub size: usize,
+  /// Bytes in use by allocations
+  pub used: usize,
+  /// Bytes available for allocations
+  pub free: usize,
+  /// Highest value of `used` since initialization
+  pub high_water_mark: usize,
+  /// Size of the largest allocation that can currently succeed
+  pub largest_free_block: usize,
+  /// Number of free blocks
+  pub free_blocks: usize,
+}
+
+impl ArenaStats {
+  /// Percentage of free memory that isn't part of the largest free block
+  pub fn fragmentation(&self) -> u8 {
+    (self.largest_free_block * 100)
+      .checked_div(self.free)
+      .map_or(0, |largest| (100 - largest) as u8)
+  }
+}
+
+/// First-fit heap over a single memory region
+///
+/// Free blocks are kept in an address ordered list and merged with their
+/// neighbours when freed.
+pub struct Heap {
+  head: *mut Node,
+  start: usize,
+  size: usize,
+  used: usize,
+  high_water_mark: usize,
+}
+
+unsafe impl Send for Heap {}
+
+impl Heap {
+  /// Creates a heap without any memory.
+  pub const fn empty() -> Self {
+    Self {
+      head: ptr::null_mut(),
+      start: 0,
+      size: 0,
+      used: 0,
+      high_water_mark: 0,
+    }
+  }
+
+  /// Creates a heap over `region`.
+  pub fn new(region: &'static mut [MaybeUninit<u8>]) -> Self {
+    let mut heap = Self::empty();
+    unsafe { heap.init(region.as_mut_ptr() as *mut u8, region.len()) };
+
+    heap
+  }
+
+  /// Hands `size` bytes at `start` to the heap, discarding its previous
+  /// memory.
+  ///
+  /// # Safety
+  ///
+  /// The region must be valid for writes, unused by anything else and must
+  /// outlive every allocation made from it.
+  pub unsafe fn init(&mut self, start: *mut u8, size: usize) {
+    let aligned = align_up(start as usize, UNIT);
+    let size = size.saturating_sub(aligned - start as usize) & !(UNIT - 1);
+
+    *self = Self::empty();
+    self.start = aligned;
+    self.size = size;
+
+    if size > 0 {
+      self.head = aligned as *mut Node;
+      self.head.write(Node {
+        size,
+        next: ptr::null_mut(),
+      });
+    }
+  }
+
+  /// Returns `true` if `ptr` points into the heap's memory.
+  pub fn contains(&self, ptr: *const u8) -> bool {
+    (self.start..self.start + self.size).contains(&(ptr as usize))
+  }
+
+  /// Returns the current usage statistics.
+  pub fn stats(&self) -> ArenaStats {
+    let mut stats = ArenaStats {
+      size: self.size,
+      used: self.used,
+      free: self.size - self.used,
+      high_water_mark: self.high_water_mark,
+      ..Default::default()
+    };
+    let mut node = self.head;
+
+    while let Some(current) = unsafe { node.as_ref() } {
+      stats.largest_free_block = stats.largest_free_block.max(current.size);
+      stats.free_blocks += 1;
+      node = current.next;
+    }
+
+    stats
+  }
+
+  fn block_size(layout: &Layout) -> usize {
+    align_up(layout.size().max(1), UNIT)
+  }
+
+  /// Allocates a block for `layout`.
+  pub fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+    let align = layout.align().max(UNIT);
+    let size = Self::block_size(&layout);
+    let mut previous: *mut *mut Node = &mut self.head;
+
+    unsafe {
+      while let Some(block) = (*previous).as_mut() {
+        let block_start = block as *mut Node as usize;
+        let block_end = block_start + block.size;
+        let next = block.next;
+        let mut start = align_up(block_start, align);
+
+        // The padding in front of the allocation must hold a free block
+        if start != block_start && start - block_start < UNIT {
+          start = align_up(block_start + UNIT, align);
+        }
+
+        let end = match start.checked_add(size) {
+          Some(end) if end <= block_end => end,
+          _ => {
+            previous = &mut block.next;
+            continue;
+          }
+        };
+
+        let mut rest = next;
+
+        if end < block_end {
+          rest = end as *mut Node;
+          rest.write(Node {
+            size: block_end - end,
+            next,
+          });
+        }
+
+        if start > block_start {
+          block.size = start - block_start;
+          block.next = rest;
+        } else {
+          *previous = rest;
+        }
+
+        self.used += size;
+        self.high_water_mark = self.high_water_mark.max(self.used);
+
+        return NonNull::new(start as *mut u8);
+      }
+    }
+
+    None
+  }
+
+  /// Returns a block to the heap.
+  ///
+  /// # Safety
+  ///
+  /// `ptr` must have been returned by [`Heap::alloc`] on this heap with the
+  /// same `layout` and must not be freed twice.
+  pub unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+    let start = ptr.as_ptr() as usize;
+    let size = Self::block_size(&layout);
+    let mut previous: *mut Node = ptr::null_mut();
+    let mut next = self.head;
+
+    while !next.is_null() && (next as usize) < start {
+      previous = next;
+      next = (*next).next;
+    }
+
+    let block = start as *mut Node;
+    block.write(Node { size, next });
+
+    // Merge with the following block
+    if !next.is_null() && start + size == next as usize {
+      (*block).size += (*next).size;
+      (*block).next = (*next).next;
+    }
+
+    // Merge with the preceding block
+    if previous.is_null() {
+      self.head = block;
+    } else if previous as usize + (*previous).size == start {
+      (*previous).size += (*block).size;
+      (*previous).next = (*block).next;
+    } else {
+      (*previous).next = block;
+    }
+
+    self.used -= size;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[repr(C, align(256))]
+  struct Region([MaybeUninit<u8>; 1024]);
+
+  /// Creates a heap over `size` bytes of a 256-byte aligned region.
+  fn heap(size: usize) -> Heap {
+    let region = Box::leak(Box::new(Region([MaybeUninit::uninit(); 1024])));
+    Heap::new(&mut region.0[..size])
+  }
+
+  fn layout(size: usize, align: usize) -> Layout {
+    Layout::from_size_align(size, align).unwrap()
+  }
+
+  #[test]
+  fn allocates_and_frees() {
+    let mut heap = heap(32 * UNIT);
+    let a = heap.alloc(layout(3, 1)).unwrap();
+    let b = heap.alloc(layout(2 * UNIT, 4)).unwrap();
+
+    assert!(heap.contains(a.as_ptr()) && heap.contains(b.as_ptr()));
+    assert!(b.as_ptr() as usize >= a.as_ptr() as usize + UNIT);
+    assert_eq!(heap.stats().used, 3 * UNIT);
+
+    unsafe {
+      a.as_ptr().write_bytes(0xaa, 3);
+      b.as_ptr().write_bytes(0xbb, 2 * UNIT);
+      heap.dealloc(a, layout(3, 1));
+      heap.dealloc(b, layout(2 * UNIT, 4));
+    }
+
+    let stats = heap.stats();
+    assert_eq!(stats.used, 0);
+    assert_eq!(stats.free_blocks, 1);
+    assert_eq!(stats.largest_free_block, 32 * UNIT);
+
+    // The freed memory is handed out again
+    assert_eq!(heap.alloc(layout(3, 1)), Some(a));
+  }
+
+  #[test]
+  fn coalesces_neighbours() {
+    let block = layout(4 * UNIT, 1);
+    let mut heap = heap(12 * UNIT);
+    let [a, b, c] = [(); 3].map(|_| heap.alloc(block).unwrap());
+
+    assert_eq!(heap.stats().free_blocks, 0);
+
+    unsafe {
+      heap.dealloc(a, block);
+      heap.dealloc(c, block);
+      assert_eq!(heap.stats().free_blocks, 2);
+
+      // Merges with the free blocks on both sides
+      heap.dealloc(b, block);
+    }
+
+    let stats = heap.stats();
+    assert_eq!(stats.free_blocks, 1);
+    assert_eq!(stats.largest_free_block, 12 * UNIT);
+    assert!(heap.alloc(layout(12 * UNIT, 1)).is_some());
+  }
+
+  #[test]
+  fn aligns_above_unit() {
+    let mut heap = heap(1024);
+    let small = heap.alloc(layout(1, 1)).unwrap();
+
+    for align in [32, 64, 256] {
+      let ptr = heap.alloc(layout(align, align)).unwrap();
+
+      assert_eq!(ptr.as_ptr() as usize % align, 0);
+      unsafe { heap.dealloc(ptr, layout(align, align)) };
+    }
+
+    unsafe { heap.dealloc(small, layout(1, 1)) };
+
+    let stats = heap.stats();
+    assert_eq!(stats.used, 0);
+    assert_eq!(stats.free_blocks, 1);
+    assert_eq!(stats.largest_free_block, 1024);
+  }
+
+  #[test]
+  fn reports_exhaustion() {
+    let mut heap = heap(8 * UNIT);
+
+    assert_eq!(heap.alloc(layout(9 * UNIT, 1)), None);
+    assert_eq!(heap.alloc(layout(isize::MAX as usize / 2, 1)), None);
+
+    let all = heap.alloc(layout(8 * UNIT, 1)).unwrap();
+    assert_eq!(heap.alloc(layout(1, 1)), None);
+    assert_eq!(heap.stats().free, 0);
+
+    unsafe { heap.dealloc(all, layout(8 * UNIT, 1)) };
+    assert!(heap.alloc(layout(1, 1)).is_some());
+
+    assert_eq!(Heap::empty().alloc(layout(1, 1)), None);
+  }
+
+  #[test]
+  fn reports_stats() {
+    let block = layout(4 * UNIT, 1);
+    let mut heap = heap(16 * UNIT);
+    let blocks = [(); 4].map(|_| heap.alloc(block).unwrap());
+
+    unsafe {
+      heap.dealloc(blocks[0], block);
+      heap.dealloc(blocks[2], block);
+    }
+
+    assert_eq!(
+      heap.stats(),
+      ArenaStats {
+        size: 16 * UNIT,
+        used: 8 * UNIT,
+        free: 8 * UNIT,
+        high_water_mark: 16 * UNIT,
+        largest_free_block: 4 * UNIT,
+        free_blocks: 2,
+      }
+    );
+    assert_eq!(heap.stats().fragmentation(), 50);
+
+    unsafe {
+      heap.dealloc(blocks[1], block);
+      heap.dealloc(blocks[3], block);
+    }
+
+    let stats = heap.stats();
+    assert_eq!(stats.high_water_mark, 16 * UNIT);
+    assert_eq!(stats.fragmentation(), 0);
+    assert_eq!(Heap::empty().stats(), ArenaStats::default());
+  }
+}
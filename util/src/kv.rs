@@ -0,0 +1,854 @@
+//! Wear-leveled key-value store
+//!
+//! [`KvStore`] keeps small values such as calibration data or credentials in
+//! a region of flash made of two or more sectors. Records are appended to a
+//! log in the current sector; when it is full, the latest value of every key
+//! is copied to the next sector, so erases rotate through the whole region.
+//!
+//! With the `FlashRegion` of pico-sdk-sys:
+//!
+//! ```ignore
+//! let mut flash = Flash::new().unwrap();
+//! let offset = flash.size() - 4 * FLASH_SECTOR_SIZE;
+//! let mut store = KvStore::mount(FlashRegion::new(&mut flash, offset, 4).unwrap()).unwrap();
+//!
+//! store.set("ssid", b"home").unwrap();
+//!
+//! let mut ssid = [0; 32];
+//! let len = store.get("ssid", &mut ssid).unwrap();
+//! ```
+//!
+//! # Format
+//!
+//! Each sector starts with a 12 byte header holding a magic number, a
+//! sequence number and the CRC-32 of both. The valid sector with the highest
+//! sequence number is the current one. Records follow the header, aligned to
+//! 4 bytes:
+//!
+//! | Bytes | Content                                  |
+//! |-------|------------------------------------------|
+//! | 1     | Key length                               |
+//! | 1     | Flags, bit 0 is cleared for deletions    |
+//! | 2     | Value length                             |
+//! | 4     | CRC-32 of the other header bytes, key and value |
+//! | n     | Key followed by the value                |
+//!
+//! Power loss is handled by never modifying data in place: a torn record
+//! fails its CRC and ends the log of its sector, and a sector only becomes
+//! current once its header is written, after all records were copied to it.
+//!
+//! The format only depends on the [`KvFlash`] trait, so it can be tested on
+//! the host with [`RamFlash`].
+
+use crate::crc::Crc;
+use core::convert::Infallible;
+
+/// Flash memory holding a [`KvStore`]
+///
+/// Flash behaves like NOR flash: erasing sets every byte of a sector to
+/// `0xff`, and writing can only clear bits.
+pub trait KvFlash {
+  /// Error reported by the flash, returned as [`KvError::Flash`]
+  type Error;
+
+  /// Size of an erasable sector in bytes
+  const SECTOR_SIZE: u32;
+
+  /// Number of sectors
+  fn sector_count(&self) -> u32;
+
+  /// Reads `buf.len()` bytes at `offset`.
+  fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+  /// Erases sector `index`.
+  fn erase(&mut self, index: u32) -> Result<(), Self::Error>;
+
+  /// Writes `data` at `offset`, which has been erased.
+  fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Errors reported by [`KvStore`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KvError<E> {
+  /// The key is empty or longer than [`MAX_KEY_LEN`]
+  InvalidKey,
+  /// The value doesn't fit into a sector
+  ValueTooLarge,
+  /// There isn't enough space left for the value
+  Full,
+  /// The buffer passed to [`KvStore::get`] is too small for the value
+  BufferTooSmall,
+  /// The region has fewer than 2 sectors
+  RegionTooSmall,
+  /// The flash failed
+  Flash(E),
+}
+
+impl<E> From<E> for KvError<E> {
+  fn from(error: E) -> Self {
+    Self::Flash(error)
+  }
+}
+
+/// Longest supported key in bytes
+pub const MAX_KEY_LEN: usize = 64;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"PKV1");
+const SECTOR_HEADER_LEN: u32 = 12;
+const RECORD_HEADER_LEN: u32 = 8;
+const FLAG_LIVE: u8 = 0x01;
+const ERASED: u8 = 0xff;
+
+/// Checksum of headers and records
+const CRC32: Crc = Crc::crc32();
+
+const fn align4(value: u32) -> u32 {
+  (value + 3) & !3
+}
+
+/// Header of a record in the log
+#[derive(Debug, Clone, Copy)]
+struct Record {
+  /// Offset of the record in its sector
+  offset: u32,
+  key_len: u8,
+  live: bool,
+  value_len: u16,
+}
+
+impl Record {
+  fn len(&self) -> u32 {
+    align4(RECORD_HEADER_LEN + self.key_len as u32 + self.value_len as u32)
+  }
+
+  fn value_offset(&self) -> u32 {
+    self.offset + RECORD_HEADER_LEN + self.key_len as u32
+  }
+}
+
+/// Result of reading a record header
+enum Entry {
+  Record(Record),
+  /// Erased flash, the log continues here
+  End,
+  /// A torn or corrupted record, nothing can be appended after it
+  Corrupt,
+}
+
+/// Log-structured, wear-leveled key-value store
+pub struct KvStore<F: KvFlash> {
+  flash: F,
+  current: u32,
+  sequence: u32,
+  /// Offset of the next record in the current sector, `None` if it can't be
+  /// appended to
+  write_offset: Option<u32>,
+}
+
+impl<F: KvFlash> KvStore<F> {
+  /// Opens the store in `flash`, formatting it if it holds no valid sector.
+  pub fn mount(flash: F) -> Result<Self, KvError<F::Error>> {
+    if flash.sector_count() < 2 {
+      return Err(KvError::RegionTooSmall);
+    }
+
+    let mut store = Self {
+      flash,
+      current: 0,
+      sequence: 0,
+      write_offset: None,
+    };
+    let mut found = false;
+
+    for sector in 0..store.flash.sector_count() {
+      if let Some(sequence) = store.read_sector_header(sector)? {
+        if !found || sequence.wrapping_sub(store.sequence) as i32 > 0 {
+          store.current = sector;
+          store.sequence = sequence;
+          found = true;
+        }
+      }
+    }
+
+    if found {
+      store.write_offset = store.find_write_offset()?;
+    } else {
+      store.format()?;
+    }
+
+    Ok(store)
+  }
+
+  /// Erases all keys.
+  pub fn format(&mut self) -> Result<(), KvError<F::Error>> {
+    for sector in 0..self.flash.sector_count() {
+      self.flash.erase(sector)?;
+    }
+
+    self.current = 0;
+    self.sequence = 0;
+    self.write_sector_header(0, 0)?;
+    self.write_offset = Some(SECTOR_HEADER_LEN);
+
+    Ok(())
+  }
+
+  /// Returns the underlying flash.
+  pub fn into_inner(self) -> F {
+    self.flash
+  }
+
+  /// Copies the value of `key` into `buf` and returns its length, `None` if
+  /// the key doesn't exist.
+  pub fn get(&mut self, key: &str, buf: &mut [u8]) -> Result<Option<usize>, KvError<F::Error>> {
+    Self::check_key(key)?;
+
+    let Some(record) = self.find(key)? else {
+      return Ok(None);
+    };
+    let len = record.value_len as usize;
+    let buf = buf.get_mut(..len).ok_or(KvError::BufferTooSmall)?;
+    self.flash.read(self.address(record.value_offset()), buf)?;
+
+    Ok(Some(len))
+  }
+
+  /// Returns `true` if `key` has a value.
+  pub fn contains(&mut self, key: &str) -> Result<bool, KvError<F::Error>> {
+    if Self::check_key(key).is_err() {
+      return Ok(false);
+    }
+
+    Ok(self.find(key)?.is_some())
+  }
+
+  /// Sets `key` to `value`.
+  pub fn set(&mut self, key: &str, value: &[u8]) -> Result<(), KvError<F::Error>> {
+    Self::check_key(key)?;
+
+    if value.len() > (F::SECTOR_SIZE - SECTOR_HEADER_LEN - RECORD_HEADER_LEN) as usize - key.len() {
+      return Err(KvError::ValueTooLarge);
+    }
+
+    if let Some(record) = self.find(key)? {
+      if self.value_equals(&record, value)? {
+        return Ok(());
+      }
+    }
+
+    self.append(key, value, true)
+  }
+
+  /// Deletes `key`.
+  pub fn remove(&mut self, key: &str) -> Result<(), KvError<F::Error>> {
+    Self::check_key(key)?;
+
+    if self.find(key)?.is_none() {
+      return Ok(());
+    }
+
+    self.append(key, &[], false)
+  }
+
+  /// Bytes used by records in the current sector, including outdated ones
+  pub fn used(&self) -> u32 {
+    self.write_offset.unwrap_or(F::SECTOR_SIZE) - SECTOR_HEADER_LEN
+  }
+
+  /// Calls `f` with every key and the length of its value.
+  pub fn for_each_key(&mut self, mut f: impl FnMut(&str, usize)) -> Result<(), KvError<F::Error>> {
+    let mut key = [0; MAX_KEY_LEN];
+
+    let mut next = SECTOR_HEADER_LEN;
+
+    while let Some(record) = self.next_record(&mut next)? {
+      let len = record.key_len as usize;
+
+      if record.live && self.is_latest(&record)? {
+        self.flash.read(
+          self.address(record.offset + RECORD_HEADER_LEN),
+          &mut key[..len],
+        )?;
+
+        if let Ok(key) = core::str::from_utf8(&key[..len]) {
+          f(key, record.value_len as usize);
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  fn check_key(key: &str) -> Result<(), KvError<F::Error>> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+      return Err(KvError::InvalidKey);
+    }
+
+    Ok(())
+  }
+
+  fn address(&self, offset: u32) -> u32 {
+    self.current * F::SECTOR_SIZE + offset
+  }
+
+  fn read_sector_header(&mut self, sector: u32) -> Result<Option<u32>, F::Error> {
+    let mut header = [0; SECTOR_HEADER_LEN as usize];
+    self.flash.read(sector * F::SECTOR_SIZE, &mut header)?;
+
+    let [magic, sequence, crc] =
+      [0, 4, 8].map(|i| u32::from_le_bytes(header[i..i + 4].try_into().unwrap()));
+
+    Ok((magic == MAGIC && crc == CRC32.compute(&header[..8])).then_some(sequence))
+  }
+
+  fn write_sector_header(&mut self, sector: u32, sequence: u32) -> Result<(), F::Error> {
+    let mut header = [0; SECTOR_HEADER_LEN as usize];
+    header[..4].copy_from_slice(&MAGIC.to_le_bytes());
+    header[4..8].copy_from_slice(&sequence.to_le_bytes());
+    let crc = CRC32.compute(&header[..8]);
+    header[8..].copy_from_slice(&crc.to_le_bytes());
+
+    self.flash.write(sector * F::SECTOR_SIZE, &header)
+  }
+
+  /// Reads and validates the record at `offset` of the current sector.
+  fn read_entry(&mut self, offset: u32) -> Result<Entry, F::Error> {
+    if offset + RECORD_HEADER_LEN > F::SECTOR_SIZE {
+      return Ok(Entry::Corrupt);
+    }
+
+    let mut header = [0; RECORD_HEADER_LEN as usize];
+    self.flash.read(self.address(offset), &mut header)?;
+
+    if header.iter().all(|byte| *byte == ERASED) {
+      return Ok(Entry::End);
+    }
+
+    let record = Record {
+      offset,
+      key_len: header[0],
+      live: header[1] & FLAG_LIVE != 0,
+      value_len: u16::from_le_bytes([header[2], header[3]]),
+    };
+    let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+    if record.key_len == 0
+      || record.key_len as usize > MAX_KEY_LEN
+      || offset + record.len() > F::SECTOR_SIZE
+    {
+      return Ok(Entry::Corrupt);
+    }
+
+    // The CRC covers the header without itself, the key and the value
+    let mut actual = CRC32.update(CRC32.start(), &header[..4]);
+    let mut chunk = [0; 32];
+    let mut position = offset + RECORD_HEADER_LEN;
+    let end = record.value_offset() + record.value_len as u32;
+
+    while position < end {
+      let len = ((end - position) as usize).min(chunk.len());
+      self.flash.read(self.address(position), &mut chunk[..len])?;
+      actual = CRC32.update(actual, &chunk[..len]);
+      position += len as u32;
+    }
+
+    if CRC32.finish(actual) != crc {
+      return Ok(Entry::Corrupt);
+    }
+
+    Ok(Entry::Record(record))
+  }
+
+  /// Returns the valid record at `offset` of the current sector and moves
+  /// `offset` past it.
+  fn next_record(&mut self, offset: &mut u32) -> Result<Option<Record>, F::Error> {
+    match self.read_entry(*offset)? {
+      Entry::Record(record) => {
+        *offset += record.len();
+        Ok(Some(record))
+      }
+      _ => Ok(None),
+    }
+  }
+
+  fn find_write_offset(&mut self) -> Result<Option<u32>, F::Error> {
+    let mut offset = SECTOR_HEADER_LEN;
+
+    loop {
+      match self.read_entry(offset)? {
+        Entry::Record(record) => offset += record.len(),
+        Entry::End => return Ok(Some(offset)),
+        Entry::Corrupt => return Ok(None),
+      }
+    }
+  }
+
+  fn key_equals(&mut self, record: &Record, key: &str) -> Result<bool, F::Error> {
+    let mut stored = [0; MAX_KEY_LEN];
+    let stored = &mut stored[..record.key_len as usize];
+    self
+      .flash
+      .read(self.address(record.offset + RECORD_HEADER_LEN), stored)?;
+
+    Ok(stored == key.as_bytes())
+  }
+
+  fn value_equals(&mut self, record: &Record, value: &[u8]) -> Result<bool, F::Error> {
+    if !record.live || record.value_len as usize != value.len() {
+      return Ok(false);
+    }
+
+    let mut chunk = [0; 32];
+    let mut offset = record.value_offset();
+
+    for expected in value.chunks(chunk.len()) {
+      let chunk = &mut chunk[..expected.len()];
+      self.flash.read(self.address(offset), chunk)?;
+
+      if chunk != expected {
+        return Ok(false);
+      }
+
+      offset += expected.len() as u32;
+    }
+
+    Ok(true)
+  }
+
+  /// Latest record of `key` in the current sector, if it isn't deleted
+  fn find(&mut self, key: &str) -> Result<Option<Record>, F::Error> {
+    let mut found = None;
+    let mut offset = SECTOR_HEADER_LEN;
+
+    while let Entry::Record(record) = self.read_entry(offset)? {
+      if record.key_len as usize == key.len() && self.key_equals(&record, key)? {
+        found = Some(record);
+      }
+
+      offset += record.len();
+    }
+
+    Ok(found.filter(|record| record.live))
+  }
+
+  /// Returns `true` if no later record in the current sector has the same
+  /// key as `record`.
+  fn is_latest(&mut self, record: &Record) -> Result<bool, F::Error> {
+    let mut key = [0; MAX_KEY_LEN];
+    let key = &mut key[..record.key_len as usize];
+    self
+      .flash
+      .read(self.address(record.offset + RECORD_HEADER_LEN), key)?;
+    // Keys are written from `&str`, so this only fails for corrupted flash
+    let Ok(key) = core::str::from_utf8(key) else {
+      return Ok(false);
+    };
+    let mut offset = record.offset + record.len();
+
+    while let Entry::Record(later) = self.read_entry(offset)? {
+      if later.key_len == record.key_len && self.key_equals(&later, key)? {
+        return Ok(false);
+      }
+
+      offset += later.len();
+    }
+
+    Ok(true)
+  }
+
+  fn write_record(
+    &mut self,
+    sector: u32,
+    offset: u32,
+    key: &str,
+    value: &[u8],
+    live: bool,
+  ) -> Result<(), F::Error> {
+    let mut header = [
+      key.len() as u8,
+      if live { ERASED } else { ERASED & !FLAG_LIVE },
+      0,
+      0,
+      0,
+      0,
+      0,
+      0,
+    ];
+    header[2..4].copy_from_slice(&(value.len() as u16).to_le_bytes());
+    let mut crc = CRC32.update(CRC32.start(), &header[..4]);
+    crc = CRC32.update(crc, key.as_bytes());
+    crc = CRC32.finish(CRC32.update(crc, value));
+    header[4..].copy_from_slice(&crc.to_le_bytes());
+
+    let address = sector * F::SECTOR_SIZE + offset;
+    self.flash.write(address, &header)?;
+    self
+      .flash
+      .write(address + RECORD_HEADER_LEN, key.as_bytes())?;
+    self
+      .flash
+      .write(address + RECORD_HEADER_LEN + key.len() as u32, value)
+  }
+
+  fn append(&mut self, key: &str, value: &[u8], live: bool) -> Result<(), KvError<F::Error>> {
+    let len = align4(RECORD_HEADER_LEN + key.len() as u32 + value.len() as u32);
+
+    if let Some(offset) = self.write_offset {
+      if offset + len <= F::SECTOR_SIZE {
+        self.write_record(self.current, offset, key, value, live)?;
+        self.write_offset = Some(offset + len);
+
+        return Ok(());
+      }
+    }
+
+    self.compact(key, value, live)
+  }
+
+  /// Moves the latest records to the next sector together with a new record
+  /// for `key`.
+  fn compact(&mut self, key: &str, value: &[u8], live: bool) -> Result<(), KvError<F::Error>> {
+    let mut needed = SECTOR_HEADER_LEN;
+
+    if live {
+      needed += align4(RECORD_HEADER_LEN + key.len() as u32 + value.len() as u32);
+    }
+
+    let mut next = SECTOR_HEADER_LEN;
+
+    while let Some(record) = self.next_record(&mut next)? {
+      if record.live && self.is_latest(&record)? && !self.key_equals(&record, key)? {
+        needed += record.len();
+      }
+    }
+
+    if needed > F::SECTOR_SIZE {
+      return Err(KvError::Full);
+    }
+
+    let target = (self.current + 1) % self.flash.sector_count();
+    let mut offset = SECTOR_HEADER_LEN;
+    self.flash.erase(target)?;
+
+    let mut next = SECTOR_HEADER_LEN;
+
+    while let Some(record) = self.next_record(&mut next)? {
+      if !record.live || !self.is_latest(&record)? || self.key_equals(&record, key)? {
+        continue;
+      }
+
+      let mut chunk = [0; 32];
+      let mut copied = 0;
+
+      while copied < record.len() {
+        let len = ((record.len() - copied) as usize).min(chunk.len());
+        self
+          .flash
+          .read(self.address(record.offset + copied), &mut chunk[..len])?;
+        self
+          .flash
+          .write(target * F::SECTOR_SIZE + offset + copied, &chunk[..len])?;
+        copied += len as u32;
+      }
+
+      offset += record.len();
+    }
+
+    if live {
+      self.write_record(target, offset, key, value, true)?;
+      offset += align4(RECORD_HEADER_LEN + key.len() as u32 + value.len() as u32);
+    }
+
+    // The new sector only becomes current once the header is written
+    self.sequence = self.sequence.wrapping_add(1);
+    self.write_sector_header(target, self.sequence)?;
+    self.current = target;
+    self.write_offset = Some(offset);
+
+    Ok(())
+  }
+}
+
+/// In-memory [`KvFlash`] with `SECTORS` sectors of `SECTOR_SIZE` bytes
+///
+/// Writes behave like NOR flash and can be cut off after a number of bytes
+/// to simulate power loss.
+pub struct RamFlash<const SECTOR_SIZE: usize, const SECTORS: usize> {
+  sectors: [[u8; SECTOR_SIZE]; SECTORS],
+  power_cut: Option<usize>,
+  erase_counts: [u32; SECTORS],
+}
+
+impl<const SECTOR_SIZE: usize, const SECTORS: usize> RamFlash<SECTOR_SIZE, SECTORS> {
+  /// Creates erased flash.
+  pub const fn new() -> Self {
+    Self {
+      sectors: [[ERASED; SECTOR_SIZE]; SECTORS],
+      power_cut: None,
+      erase_counts: [0; SECTORS],
+    }
+  }
+
+  /// Drops every write and erase after `bytes` more bytes were written, or
+  /// restores power with `None`.
+  pub fn set_power_cut(&mut self, bytes: Option<usize>) {
+    self.power_cut = bytes;
+  }
+
+  /// Number of times each sector was erased
+  pub fn erase_counts(&self) -> &[u32; SECTORS] {
+    &self.erase_counts
+  }
+}
+
+impl<const SECTOR_SIZE: usize, const SECTORS: usize> Default for RamFlash<SECTOR_SIZE, SECTORS> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<const SECTOR_SIZE: usize, const SECTORS: usize> KvFlash for RamFlash<SECTOR_SIZE, SECTORS> {
+  type Error = Infallible;
+
+  const SECTOR_SIZE: u32 = SECTOR_SIZE as u32;
+
+  fn sector_count(&self) -> u32 {
+    SECTORS as u32
+  }
+
+  fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Infallible> {
+    let flat = self.sectors.as_flattened();
+    buf.copy_from_slice(&flat[offset as usize..offset as usize + buf.len()]);
+
+    Ok(())
+  }
+
+  fn erase(&mut self, index: u32) -> Result<(), Infallible> {
+    if self.power_cut != Some(0) {
+      self.sectors[index as usize] = [ERASED; SECTOR_SIZE];
+      self.erase_counts[index as usize] += 1;
+    }
+
+    Ok(())
+  }
+
+  fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Infallible> {
+    let flat = self.sectors.as_flattened_mut();
+
+    for (byte, value) in flat[offset as usize..].iter_mut().zip(data) {
+      match &mut self.power_cut {
+        Some(0) => break,
+        Some(left) => *left -= 1,
+        None => {}
+      }
+
+      *byte &= *value;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  type Flash = RamFlash<256, 3>;
+
+  /// Bytes one record of [`fill`] takes
+  const RECORD_LEN: u32 = align4(RECORD_HEADER_LEN + 1 + 16);
+
+  fn get(store: &mut KvStore<Flash>, key: &str) -> Option<Vec<u8>> {
+    let mut buf = [0; 64];
+    let len = store.get(key, &mut buf).unwrap()?;
+
+    Some(buf[..len].to_vec())
+  }
+
+  fn remount(store: KvStore<Flash>) -> KvStore<Flash> {
+    let mut flash = store.into_inner();
+    flash.set_power_cut(None);
+
+    KvStore::mount(flash).unwrap()
+  }
+
+  /// Updates `a` and `b` in turn until the current sector is full, leaving
+  /// `a = [7; 16]` and `b = [8; 16]`.
+  fn fill() -> KvStore<Flash> {
+    let mut store = KvStore::mount(Flash::new()).unwrap();
+    let mut round = 1;
+
+    while store.used() + 2 * RECORD_LEN <= 256 - SECTOR_HEADER_LEN {
+      store.set("a", &[round; 16]).unwrap();
+      store.set("b", &[round + 1; 16]).unwrap();
+      round += 2;
+    }
+
+    store.set("a", &[7; 16]).unwrap();
+    store.set("b", &[8; 16]).unwrap();
+    assert!(store.used() + RECORD_LEN > 256 - SECTOR_HEADER_LEN);
+
+    store
+  }
+
+  #[test]
+  fn stores_values() {
+    let mut store = KvStore::mount(Flash::new()).unwrap();
+
+    store.set("ssid", b"home").unwrap();
+    store.set("pass", b"secret").unwrap();
+    store.set("ssid", b"work").unwrap();
+    store.remove("pass").unwrap();
+
+    let mut store = remount(store);
+    assert_eq!(get(&mut store, "ssid").as_deref(), Some(&b"work"[..]));
+    assert_eq!(get(&mut store, "pass"), None);
+    assert_eq!(store.get("ssid", &mut [0; 3]), Err(KvError::BufferTooSmall));
+    assert_eq!(store.set("", b""), Err(KvError::InvalidKey));
+    assert_eq!(store.set("big", &[0; 256]), Err(KvError::ValueTooLarge));
+
+    let mut keys = Vec::new();
+    store
+      .for_each_key(|key, len| keys.push((key.to_owned(), len)))
+      .unwrap();
+    assert_eq!(keys, [("ssid".to_owned(), 4)]);
+  }
+
+  #[test]
+  fn survives_power_cut_in_record() {
+    // The padding of a record isn't written
+    let len = (RECORD_HEADER_LEN + 3 + 8) as usize;
+
+    for cut in 0..=len {
+      let mut store = KvStore::mount(Flash::new()).unwrap();
+      store.set("old", b"value").unwrap();
+
+      let mut flash = store.into_inner();
+      flash.set_power_cut(Some(cut));
+      let mut store = KvStore::mount(flash).unwrap();
+      store.set("new", &[0x5a; 8]).unwrap();
+
+      let mut store = remount(store);
+      assert_eq!(get(&mut store, "old").as_deref(), Some(&b"value"[..]));
+
+      assert_eq!(
+        get(&mut store, "new"),
+        (cut >= len).then(|| vec![0x5a; 8]),
+        "{cut}"
+      );
+
+      store.set("new", &[0xa5; 8]).unwrap();
+      let mut store = remount(store);
+      assert_eq!(get(&mut store, "new"), Some(vec![0xa5; 8]));
+      assert_eq!(get(&mut store, "old").as_deref(), Some(&b"value"[..]));
+    }
+  }
+
+  #[test]
+  fn survives_power_cut_in_compaction() {
+    // The copy of `b`, the new record of `a` without its padding and the
+    // header
+    let len = (RECORD_LEN + RECORD_HEADER_LEN + 1 + 16 + SECTOR_HEADER_LEN) as usize;
+
+    for cut in 0..=len {
+      let mut flash = fill().into_inner();
+      flash.set_power_cut(Some(cut));
+      let mut store = KvStore::mount(flash).unwrap();
+      store.set("a", &[9; 16]).unwrap();
+
+      let mut store = remount(store);
+      let a = get(&mut store, "a").unwrap();
+
+      assert!(a == [7; 16] || a == [9; 16], "{cut}: {a:?}");
+      assert_eq!(a == [9; 16], cut == len, "{cut}");
+      assert_eq!(get(&mut store, "b"), Some(vec![8; 16]), "{cut}");
+
+      store.set("a", &[10; 16]).unwrap();
+      let mut store = remount(store);
+      assert_eq!(get(&mut store, "a"), Some(vec![10; 16]));
+      assert_eq!(get(&mut store, "b"), Some(vec![8; 16]));
+    }
+  }
+
+  #[test]
+  fn drops_torn_records_on_mount() {
+    let mut store = KvStore::mount(Flash::new()).unwrap();
+    store.set("a", b"kept").unwrap();
+    let used = store.used();
+
+    let mut flash = store.into_inner();
+    flash.set_power_cut(Some(RECORD_HEADER_LEN as usize + 2));
+    let mut store = KvStore::mount(flash).unwrap();
+    store.set("b", b"torn").unwrap();
+
+    // The torn record ends the log, so nothing is appended after it
+    let mut store = remount(store);
+    assert_eq!(get(&mut store, "b"), None);
+    assert_eq!(store.used(), 256 - SECTOR_HEADER_LEN);
+
+    // The next write moves the valid records to a clean sector
+    store.set("c", b"new").unwrap();
+    assert_eq!(store.used(), used + align4(RECORD_HEADER_LEN + 4));
+
+    let mut store = remount(store);
+    let mut keys = Vec::new();
+    store
+      .for_each_key(|key, _| keys.push(key.to_owned()))
+      .unwrap();
+    keys.sort();
+    assert_eq!(keys, ["a", "c"]);
+    assert_eq!(get(&mut store, "a").as_deref(), Some(&b"kept"[..]));
+  }
+
+  #[test]
+  fn rotates_erases_across_sectors() {
+    let mut store = KvStore::mount(RamFlash::<256, 4>::new()).unwrap();
+
+    for round in 0..500u32 {
+      store.set("counter", &round.to_le_bytes()).unwrap();
+    }
+
+    let flash = store.into_inner();
+    let counts = flash.erase_counts();
+    let min = counts.iter().min().unwrap();
+    let max = counts.iter().max().unwrap();
+
+    assert!(*min > 10, "{counts:?}");
+    assert!(max - min <= 1, "{counts:?}");
+
+    let mut store = KvStore::mount(flash).unwrap();
+    let mut buf = [0; 4];
+    store.get("counter", &mut buf).unwrap();
+    assert_eq!(u32::from_le_bytes(buf), 499);
+  }
+
+  #[test]
+  fn rejects_bad_crcs() {
+    let mut store = KvStore::mount(Flash::new()).unwrap();
+    store.set("a", &[0xff, 0x55]).unwrap();
+    store.set("b", b"after").unwrap();
+
+    // Clear one bit of the value of `a`
+    let mut flash = store.into_inner();
+    flash
+      .write(SECTOR_HEADER_LEN + RECORD_HEADER_LEN + 1, &[0xfe])
+      .unwrap();
+
+    // The record fails its CRC and ends the log of the sector
+    let mut store = KvStore::mount(flash).unwrap();
+    assert_eq!(get(&mut store, "a"), None);
+    assert_eq!(get(&mut store, "b"), None);
+
+    // A corrupted sector header makes the sector invalid
+    let mut flash = store.into_inner();
+    flash.write(0, &[0]).unwrap();
+
+    let mut store = KvStore::mount(flash).unwrap();
+    assert_eq!(store.used(), 0);
+    store.set("a", b"fresh").unwrap();
+    assert_eq!(
+      get(&mut remount(store), "a").as_deref(),
+      Some(&b"fresh"[..])
+    );
+  }
+}
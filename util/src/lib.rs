@@ -0,0 +1,20 @@
+//! Hardware independent building blocks for
+//! [pico-sdk-sys](https://docs.rs/pico-sdk-sys)
+//!
+//! This crate has no hardware dependencies, so it builds and is tested on the
+//! host. pico-sdk-sys re-exports all of it and wraps it where it needs the
+//! SDK.
+
+#![cfg_attr(not(test), no_std)]
+
+mod crc;
+mod heap;
+mod kv;
+mod spsc;
+mod stdio;
+
+pub use crc::*;
+pub use heap::*;
+pub use kv::*;
+pub use spsc::*;
+pub use stdio::*;
@@ -0,0 +1,357 @@
+//! Lock-free single-producer, single-consumer queue
+//!
+//! pico-sdk-sys adds waiting with a timeout on top of the `_until` variants.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Lock-free single-producer, single-consumer ring buffer of up to `N` values
+///
+/// [`SpscQueue::split`] hands out the [`Producer`] and [`Consumer`] halves,
+/// which may live on different cores or in an interrupt handler.
+pub struct SpscQueue<T: Copy, const N: usize> {
+  slots: UnsafeCell<[MaybeUninit<T>; N]>,
+  /// Read position in `0..2 * N`, only written by the consumer
+  head: AtomicUsize,
+  /// Write position in `0..2 * N`, only written by the producer
+  tail: AtomicUsize,
+}
+
+unsafe impl<T: Copy + Send, const N: usize> Sync for SpscQueue<T, N> {}
+
+impl<T: Copy, const N: usize> SpscQueue<T, N> {
+  /// Creates an empty queue.
+  pub const fn new() -> Self {
+    assert!(N > 0 && N <= usize::MAX / 4);
+
+    Self {
+      slots: UnsafeCell::new([const { MaybeUninit::uninit() }; N]),
+      head: AtomicUsize::new(0),
+      tail: AtomicUsize::new(0),
+    }
+  }
+
+  /// Maximum number of values in the queue
+  pub const fn capacity(&self) -> usize {
+    N
+  }
+
+  /// Number of values in the queue
+  pub fn len(&self) -> usize {
+    let head = self.head.load(Ordering::Acquire);
+    let tail = self.tail.load(Ordering::Acquire);
+
+    (tail + 2 * N - head) % (2 * N)
+  }
+
+  /// Returns `true` if the queue holds no values.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Returns `true` if no more values can be added.
+  pub fn is_full(&self) -> bool {
+    self.len() == N
+  }
+
+  /// Splits the queue into its producer and consumer halves.
+  pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+    let queue = &*self;
+
+    (Producer { queue }, Consumer { queue })
+  }
+
+  fn next(position: usize) -> usize {
+    if position + 1 == 2 * N {
+      0
+    } else {
+      position + 1
+    }
+  }
+
+  fn slot(&self, position: usize) -> *mut MaybeUninit<T> {
+    unsafe { (self.slots.get() as *mut MaybeUninit<T>).add(position % N) }
+  }
+}
+
+impl<T: Copy, const N: usize> Default for SpscQueue<T, N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Adding half of a [`SpscQueue`]
+pub struct Producer<'a, T: Copy, const N: usize> {
+  queue: &'a SpscQueue<T, N>,
+}
+
+unsafe impl<T: Copy + Send, const N: usize> Send for Producer<'_, T, N> {}
+
+impl<T: Copy, const N: usize> Producer<'_, T, N> {
+  /// Number of values in the queue
+  pub fn len(&self) -> usize {
+    self.queue.len()
+  }
+
+  /// Returns `true` if the queue holds no values.
+  pub fn is_empty(&self) -> bool {
+    self.queue.is_empty()
+  }
+
+  /// Returns `true` if no more values can be added.
+  pub fn is_full(&self) -> bool {
+    self.queue.is_full()
+  }
+
+  /// Adds `value` to the back of the queue, returning it if the queue is full.
+  pub fn try_add(&mut self, value: T) -> Result<(), T> {
+    if self.is_full() {
+      return Err(value);
+    }
+
+    let tail = self.queue.tail.load(Ordering::Relaxed);
+    unsafe { self.queue.slot(tail).write(MaybeUninit::new(value)) };
+    self
+      .queue
+      .tail
+      .store(SpscQueue::<T, N>::next(tail), Ordering::Release);
+
+    Ok(())
+  }
+
+  /// Adds `value` to the back of the queue, spinning until there is space.
+  pub fn add_blocking(&mut self, mut value: T) {
+    while let Err(rejected) = self.try_add(value) {
+      value = rejected;
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Adds `value` to the back of the queue, spinning until there is space
+  /// or `expired` returns `true`.
+  pub fn try_add_until(
+    &mut self,
+    mut value: T,
+    mut expired: impl FnMut() -> bool,
+  ) -> Result<(), T> {
+    loop {
+      value = match self.try_add(value) {
+        Ok(()) => return Ok(()),
+        Err(value) => value,
+      };
+
+      if expired() {
+        return self.try_add(value);
+      }
+
+      core::hint::spin_loop();
+    }
+  }
+}
+
+/// Removing half of a [`SpscQueue`]
+pub struct Consumer<'a, T: Copy, const N: usize> {
+  queue: &'a SpscQueue<T, N>,
+}
+
+unsafe impl<T: Copy + Send, const N: usize> Send for Consumer<'_, T, N> {}
+
+impl<T: Copy, const N: usize> Consumer<'_, T, N> {
+  /// Number of values in the queue
+  pub fn len(&self) -> usize {
+    self.queue.len()
+  }
+
+  /// Returns `true` if the queue holds no values.
+  pub fn is_empty(&self) -> bool {
+    self.queue.is_empty()
+  }
+
+  /// Returns `true` if no more values can be added.
+  pub fn is_full(&self) -> bool {
+    self.queue.is_full()
+  }
+
+  /// Removes the value at the front of the queue, `None` if it is empty.
+  pub fn try_remove(&mut self) -> Option<T> {
+    let value = self.peek()?;
+    let head = self.queue.head.load(Ordering::Relaxed);
+    self
+      .queue
+      .head
+      .store(SpscQueue::<T, N>::next(head), Ordering::Release);
+
+    Some(value)
+  }
+
+  /// Removes the value at the front of the queue, spinning until there is
+  /// one.
+  pub fn remove_blocking(&mut self) -> T {
+    loop {
+      if let Some(value) = self.try_remove() {
+        return value;
+      }
+
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Removes the value at the front of the queue, spinning until there is
+  /// one or `expired` returns `true`.
+  pub fn try_remove_until(&mut self, mut expired: impl FnMut() -> bool) -> Option<T> {
+    loop {
+      if let Some(value) = self.try_remove() {
+        return Some(value);
+      }
+
+      if expired() {
+        return self.try_remove();
+      }
+
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Returns a copy of the value at the front of the queue without removing
+  /// it, `None` if the queue is empty.
+  pub fn peek(&self) -> Option<T> {
+    if self.is_empty() {
+      return None;
+    }
+
+    let head = self.queue.head.load(Ordering::Relaxed);
+
+    Some(unsafe { (*self.queue.slot(head)).assume_init() })
+  }
+
+  /// Returns a copy of the value at the front of the queue, spinning until
+  /// there is one.
+  pub fn peek_blocking(&self) -> T {
+    loop {
+      if let Some(value) = self.peek() {
+        return value;
+      }
+
+      core::hint::spin_loop();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::thread;
+
+  #[test]
+  fn reports_full_and_empty() {
+    let mut queue = SpscQueue::<u8, 3>::new();
+    let (mut producer, mut consumer) = queue.split();
+
+    assert!(consumer.is_empty() && !consumer.is_full());
+    assert_eq!(consumer.try_remove(), None);
+    assert_eq!(consumer.peek(), None);
+
+    for value in 1..=3 {
+      assert_eq!(producer.try_add(value), Ok(()));
+    }
+
+    assert!(producer.is_full() && consumer.is_full());
+    assert_eq!(producer.len(), 3);
+    assert_eq!(producer.try_add(4), Err(4));
+
+    assert_eq!(consumer.peek(), Some(1));
+    assert_eq!(consumer.try_remove(), Some(1));
+    assert!(!producer.is_full());
+    assert_eq!(producer.try_add(4), Ok(()));
+
+    assert_eq!(
+      [(); 3].map(|_| consumer.try_remove()),
+      [Some(2), Some(3), Some(4)]
+    );
+    assert!(producer.is_empty());
+    assert_eq!(queue.len(), 0);
+  }
+
+  #[test]
+  fn wraps_around() {
+    let mut queue = SpscQueue::<u32, 4>::new();
+    let (mut producer, mut consumer) = queue.split();
+    let mut next = 0;
+
+    // Moves the positions around both halves of `0..2 * N` several times,
+    // with every fill level
+    for round in 0..40 {
+      let count = round % 5;
+
+      for value in next..next + count {
+        producer.try_add(value).unwrap();
+      }
+
+      assert_eq!(consumer.len(), count as usize);
+
+      for value in next..next + count {
+        assert_eq!(consumer.try_remove(), Some(value));
+      }
+
+      assert!(consumer.is_empty());
+      next += count;
+    }
+  }
+
+  #[test]
+  fn gives_up_when_expired() {
+    let mut queue = SpscQueue::<u8, 1>::new();
+    let (mut producer, mut consumer) = queue.split();
+    let mut polls = 0;
+
+    assert_eq!(
+      consumer.try_remove_until(|| {
+        polls += 1;
+        polls == 3
+      }),
+      None
+    );
+    assert_eq!(polls, 3);
+
+    assert_eq!(producer.try_add_until(1, || true), Ok(()));
+    assert_eq!(producer.try_add_until(2, || true), Err(2));
+    assert_eq!(consumer.try_remove_until(|| true), Some(1));
+  }
+
+  #[test]
+  fn passes_values_between_threads() {
+    const COUNT: u32 = 100_000;
+
+    let mut queue = SpscQueue::<u32, 8>::new();
+    let (mut producer, mut consumer) = queue.split();
+
+    // Yields instead of spinning, which would starve the other thread on a
+    // single CPU
+    thread::scope(|scope| {
+      scope.spawn(move || {
+        for value in 0..COUNT {
+          while producer.try_add(value).is_err() {
+            thread::yield_now();
+          }
+        }
+      });
+
+      scope.spawn(move || {
+        for expected in 0..COUNT {
+          let value = loop {
+            match consumer.peek() {
+              Some(value) => break value,
+              None => thread::yield_now(),
+            }
+          };
+
+          assert_eq!(value, expected);
+          assert_eq!(consumer.try_remove(), Some(expected));
+        }
+      });
+    });
+
+    assert!(queue.is_empty());
+  }
+}
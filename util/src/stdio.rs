@@ -0,0 +1,222 @@
+//! Rust side of SDK stdio drivers
+//!
+//! pico-sdk-sys puts the [`StdioShim`] entry points of a [`StdioDriver`] into
+//! the SDK's `stdio_driver_t`. They only deal with C types, so the glue is
+//! tested on the host.
+
+use core::ffi::{c_char, c_int, c_void};
+use core::marker::PhantomData;
+use core::slice;
+
+/// `PICO_ERROR_NO_DATA` from `pico/error.h`
+const PICO_ERROR_NO_DATA: c_int = -3;
+
+/// Callback which a driver invokes when new input is available
+#[derive(Debug, Clone, Copy)]
+pub struct CharsAvailableCallback {
+  callback: unsafe extern "C" fn(*mut c_void),
+  param: *mut c_void,
+}
+
+impl CharsAvailableCallback {
+  /// Notifies the SDK that input is available.
+  pub fn notify(&self) {
+    unsafe { (self.callback)(self.param) }
+  }
+}
+
+/// A stdio driver
+pub trait StdioDriver {
+  /// Whether `\n` is translated to `\r\n` when the driver is registered
+  const CRLF_ENABLED: bool = true;
+
+  /// Writes `buf` to the output.
+  fn out_chars(buf: &[u8]);
+
+  /// Waits until buffered output has been written.
+  fn out_flush() {}
+
+  /// Reads available input into `buf` without blocking.
+  ///
+  /// Returns the number of bytes read, `0` if no data is available.
+  fn in_chars(buf: &mut [u8]) -> usize {
+    let _ = buf;
+    0
+  }
+
+  /// Called by the SDK with the callback that must be notified whenever new
+  /// input arrives, or `None` if notifications are no longer wanted.
+  fn set_chars_available_callback(callback: Option<CharsAvailableCallback>) {
+    let _ = callback;
+  }
+}
+
+/// C entry points of a [`StdioDriver`], matching the fields of the SDK's
+/// `stdio_driver_t`
+pub struct StdioShim<D>(PhantomData<D>);
+
+impl<D: StdioDriver> StdioShim<D> {
+  /// `out_chars`, calls [`StdioDriver::out_chars`]
+  pub const OUT_CHARS: unsafe extern "C" fn(*const c_char, c_int) = out_chars::<D>;
+  /// `out_flush`, calls [`StdioDriver::out_flush`]
+  pub const OUT_FLUSH: unsafe extern "C" fn() = out_flush::<D>;
+  /// `in_chars`, calls [`StdioDriver::in_chars`]
+  pub const IN_CHARS: unsafe extern "C" fn(*mut c_char, c_int) -> c_int = in_chars::<D>;
+  /// `set_chars_available_callback`, calls
+  /// [`StdioDriver::set_chars_available_callback`]
+  pub const SET_CHARS_AVAILABLE_CALLBACK: unsafe extern "C" fn(
+    Option<unsafe extern "C" fn(*mut c_void)>,
+    *mut c_void,
+  ) = set_chars_available_callback::<D>;
+}
+
+unsafe extern "C" fn out_chars<D: StdioDriver>(buf: *const c_char, len: c_int) {
+  if !buf.is_null() && len > 0 {
+    D::out_chars(slice::from_raw_parts(buf as *const u8, len as usize));
+  }
+}
+
+unsafe extern "C" fn out_flush<D: StdioDriver>() {
+  D::out_flush();
+}
+
+unsafe extern "C" fn in_chars<D: StdioDriver>(buf: *mut c_char, len: c_int) -> c_int {
+  if buf.is_null() || len <= 0 {
+    return PICO_ERROR_NO_DATA;
+  }
+
+  match D::in_chars(slice::from_raw_parts_mut(buf as *mut u8, len as usize)) {
+    0 => PICO_ERROR_NO_DATA,
+    read => read.min(len as usize) as c_int,
+  }
+}
+
+unsafe extern "C" fn set_chars_available_callback<D: StdioDriver>(
+  callback: Option<unsafe extern "C" fn(*mut c_void)>,
+  param: *mut c_void,
+) {
+  D::set_chars_available_callback(
+    callback.map(|callback| CharsAvailableCallback { callback, param }),
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::{Cell, RefCell};
+  use std::collections::VecDeque;
+
+  /// The fields of `stdio_driver_t` which the SDK calls
+  struct Vtable {
+    out_chars: Option<unsafe extern "C" fn(*const c_char, c_int)>,
+    out_flush: Option<unsafe extern "C" fn()>,
+    in_chars: Option<unsafe extern "C" fn(*mut c_char, c_int) -> c_int>,
+    set_chars_available_callback:
+      Option<unsafe extern "C" fn(Option<unsafe extern "C" fn(*mut c_void)>, *mut c_void)>,
+  }
+
+  impl Vtable {
+    fn new<D: StdioDriver>() -> Self {
+      Self {
+        out_chars: Some(StdioShim::<D>::OUT_CHARS),
+        out_flush: Some(StdioShim::<D>::OUT_FLUSH),
+        in_chars: Some(StdioShim::<D>::IN_CHARS),
+        set_chars_available_callback: Some(StdioShim::<D>::SET_CHARS_AVAILABLE_CALLBACK),
+      }
+    }
+
+    fn out_chars(&self, bytes: &[u8]) {
+      unsafe { self.out_chars.unwrap()(bytes.as_ptr() as _, bytes.len() as _) }
+    }
+
+    fn in_chars(&self, buf: &mut [u8]) -> c_int {
+      unsafe { self.in_chars.unwrap()(buf.as_mut_ptr() as _, buf.len() as _) }
+    }
+  }
+
+  thread_local! {
+    static OUTPUT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static INPUT: RefCell<VecDeque<u8>> = const { RefCell::new(VecDeque::new()) };
+    static FLUSHES: Cell<usize> = const { Cell::new(0) };
+    static CALLBACK: Cell<Option<CharsAvailableCallback>> = const { Cell::new(None) };
+  }
+
+  struct Mock;
+
+  impl StdioDriver for Mock {
+    fn out_chars(buf: &[u8]) {
+      OUTPUT.with_borrow_mut(|output| output.extend_from_slice(buf));
+    }
+
+    fn out_flush() {
+      FLUSHES.set(FLUSHES.get() + 1);
+    }
+
+    fn in_chars(buf: &mut [u8]) -> usize {
+      INPUT.with_borrow_mut(|input| {
+        let read = buf.len().min(input.len());
+
+        for (slot, byte) in buf.iter_mut().zip(input.drain(..read)) {
+          *slot = byte;
+        }
+
+        read
+      })
+    }
+
+    fn set_chars_available_callback(callback: Option<CharsAvailableCallback>) {
+      CALLBACK.set(callback);
+    }
+  }
+
+  #[test]
+  fn writes_through_driver() {
+    let vtable = Vtable::new::<Mock>();
+
+    vtable.out_chars(b"hello ");
+    vtable.out_chars(b"");
+    vtable.out_chars(b"world\n");
+    unsafe { vtable.out_flush.unwrap()() };
+
+    assert_eq!(OUTPUT.take(), b"hello world\n");
+    assert_eq!(FLUSHES.get(), 1);
+  }
+
+  #[test]
+  fn reads_through_driver() {
+    let vtable = Vtable::new::<Mock>();
+    let mut buf = [0; 4];
+
+    assert_eq!(vtable.in_chars(&mut buf), PICO_ERROR_NO_DATA);
+
+    INPUT.with_borrow_mut(|input| input.extend(b"abcdef"));
+
+    assert_eq!(vtable.in_chars(&mut buf), 4);
+    assert_eq!(&buf, b"abcd");
+    assert_eq!(vtable.in_chars(&mut buf), 2);
+    assert_eq!(&buf[..2], b"ef");
+    assert_eq!(vtable.in_chars(&mut []), PICO_ERROR_NO_DATA);
+  }
+
+  #[test]
+  fn forwards_chars_available_callback() {
+    thread_local! {
+      static NOTIFIED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn notify(param: *mut c_void) {
+      assert_eq!(param as usize, 0x1234);
+      NOTIFIED.set(NOTIFIED.get() + 1);
+    }
+
+    let vtable = Vtable::new::<Mock>();
+    let set_callback = vtable.set_chars_available_callback.unwrap();
+
+    unsafe { set_callback(Some(notify), 0x1234 as _) };
+    CALLBACK.get().unwrap().notify();
+    assert_eq!(NOTIFIED.get(), 1);
+
+    unsafe { set_callback(None, core::ptr::null_mut()) };
+    assert!(CALLBACK.get().is_none());
+  }
+}